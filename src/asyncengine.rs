@@ -2,27 +2,50 @@
 
 // src/async_engine.rs
 use crate::basics::hmap::ShardedRwLockMap; // your new map
-use crate::datastr::account::Account;
+use crate::config::{
+    AccountCapacityPolicy, AccountColumn, AmountUnits, BoolFormat, CsvDialect, DepositCheckOrder,
+    DisputePolicy, EngineConfig, HistoricalDisputePolicy, LockedAccountPolicy, NegativeHeldPolicy,
+    NegativeTotalPolicy, ResolvePolicy, Rounding, TxUniqueness,
+};
+use crate::datastr::account::{
+    account_csv_header, account_csv_record, accounts_merkle_root, parse_overdraft_limit,
+    write_account_json, write_account_ndjson, Account, AccountLineWriter, OVERDRAFT_LIMIT_COLUMN,
+};
+use crate::datastr::deser::parse_bool;
 use crate::datastr::transaction::{
-    ClientId, Transaction, TransactionProcessingError, TransactionType, TxId,
+    ClientId, Transaction, TransactionError, TransactionProcessingError, TransactionType, TxId,
+    TxKey,
 };
 use csv::ReaderBuilder;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
+use async_compression::tokio::bufread::GzipDecoder;
 use csv_async::{AsyncReaderBuilder, AsyncWriterBuilder, Trim};
+use futures_util::future::join_all;
 use futures_util::stream::StreamExt;
 use tokio::fs::File;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 use tokio::sync::{mpsc, RwLockWriteGuard};
 use tokio::task;
 use tokio_util::io::SyncIoBridge;
 
 // Reuse the same errors
+use crate::engine::{
+    balance_violation, chargeback_amount, check_disputable, content_fingerprint, is_gzip,
+    is_max_duration_exceeded, is_unsettled, max_duration_exceeded, missing_header,
+    negative_held_rejected, new_account_deposit_client, normalize_input, reference_not_found,
+    transactions_reader_builder, Engine, InvariantViolation, TimestampOrdering,
+};
 pub use crate::engine::{EngineError, EngineSerDeserError};
 
+/// Name of the checkpoint file written in the checkpointing directory.
+pub const CHECKPOINT_FILE: &str = "checkpoint.csv";
+
 #[derive(Debug, Error)]
 pub enum AsycEngineSerDeserError {
     #[error("I/O error while reading session")]
@@ -73,12 +96,41 @@ trait AsycEngineStateTransitionFunctions {
     async fn process_dispute(&self, tx: &Transaction) -> Result<(), EngineError>;
     async fn process_resolve(&self, tx: &Transaction) -> Result<(), EngineError>;
     async fn process_chargeback(&self, tx: &Transaction) -> Result<(), EngineError>;
+    async fn process_hold(&self, tx: &Transaction) -> Result<(), EngineError>;
+    async fn process_release(&self, tx: &Transaction) -> Result<(), EngineError>;
+    async fn process_reversal(&self, tx: &Transaction) -> Result<(), EngineError>;
+    async fn process_freeze(&self, tx: &Transaction) -> Result<(), EngineError>;
+    async fn process_unfreeze(&self, tx: &Transaction) -> Result<(), EngineError>;
 }
 
 #[derive(Default)]
 pub struct AsyncEngine {
     pub accounts: Arc<ShardedRwLockMap<ClientId, Account>>,
-    pub transaction_log: Arc<ShardedRwLockMap<TxId, Transaction>>,
+    /// Deposits, withdrawals and holds, keyed as configured by `with_tx_uniqueness`.
+    pub transaction_log: Arc<ShardedRwLockMap<TxKey, Transaction>>,
+    /// Fees charged, keyed by the key of the deposit/withdrawal they were charged on.
+    pub fee_log: Arc<ShardedRwLockMap<TxKey, Transaction>>,
+    historical_transactions: Arc<ShardedRwLockMap<TxKey, ()>>,
+    /// Clients allowed to open an account in strict mode, see `with_strict_clients`.
+    registered_clients: Arc<ShardedRwLockMap<ClientId, ()>>,
+    /// Fingerprints of the files already processed, see `with_idempotent_file_reruns`.
+    processed_files: Arc<ShardedRwLockMap<u64, ()>>,
+    config: EngineConfig,
+    checkpointing: Option<Checkpointing>,
+}
+
+/// Where and how often `read_and_process_transactions` dumps a checkpoint.
+struct Checkpointing {
+    dir: PathBuf,
+    every_n: usize,
+}
+
+/// Progress of `read_and_process_transactions` stored in a checkpoint.
+struct CheckpointProgress {
+    /// Number of transactions read from the input (processed successfully or not).
+    processed: usize,
+    /// Id of the last transaction read, used to check that the input is the same on resume.
+    last_tx: TxId,
 }
 
 impl AsyncEngine {
@@ -86,7 +138,748 @@ impl AsyncEngine {
         Self {
             accounts: Arc::new(ShardedRwLockMap::new()),
             transaction_log: Arc::new(ShardedRwLockMap::new()),
+            fee_log: Arc::new(ShardedRwLockMap::new()),
+            historical_transactions: Arc::new(ShardedRwLockMap::new()),
+            registered_clients: Arc::new(ShardedRwLockMap::new()),
+            processed_files: Arc::new(ShardedRwLockMap::new()),
+            config: EngineConfig::default(),
+            checkpointing: None,
+        }
+    }
+
+    /// Creates an engine with the given `accounts` and logged `transactions`,
+    /// see `Engine::from_state`.
+    pub async fn from_state(
+        accounts: Vec<(ClientId, Account)>,
+        transactions: Vec<Transaction>,
+    ) -> Self {
+        let engine = Self::new();
+        let historical: Vec<(TxKey, ())> = transactions
+            .iter()
+            .map(|tx| (engine.tx_key(tx), ()))
+            .collect();
+        tokio::join!(
+            engine.historical_transactions.insert_many(historical),
+            engine
+                .transaction_log
+                .insert_many(transactions.into_iter().map(|tx| (engine.tx_key(&tx), tx))),
+            engine.accounts.insert_many(accounts),
+        );
+        engine
+    }
+
+    /// Replaces the whole configuration, e.g. with the one of a `ConfigFile`.
+    ///
+    /// # Panics
+    /// - If the `transaction_fee` of `config` is negative, see `with_transaction_fee`.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        assert!(
+            config.transaction_fee >= Decimal::ZERO,
+            "The transaction fee must not be negative"
+        );
+        self.config = config;
+        self
+    }
+
+    /// Sets the number of shards of the accounts and transactions maps (default `NUM_SHARDS`),
+    /// see `ShardedRwLockMap::with_shards`: more shards reduce the lock contention with many
+    /// clients processed on many cores. The maps are replaced, so it must be called before
+    /// processing or loading any transaction.
+    ///
+    /// # Panics
+    /// If `shards` is 0.
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        self.accounts = Arc::new(ShardedRwLockMap::with_shards(shards));
+        self.transaction_log = Arc::new(ShardedRwLockMap::with_shards(shards));
+        self.fee_log = Arc::new(ShardedRwLockMap::with_shards(shards));
+        self.historical_transactions = Arc::new(ShardedRwLockMap::with_shards(shards));
+        self
+    }
+
+    /// Sets the number of decimal places deposit/withdrawal amounts are rounded to
+    /// (default `4`), see `Engine::with_precision`.
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.config.precision = precision;
+        self
+    }
+
+    /// Sets the rounding of the deposit/withdrawal amounts to the precision, see
+    /// `Engine::with_rounding`.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.config.rounding = rounding;
+        self
+    }
+
+    /// Sets the policy applied when an operation would drive an account total negative.
+    pub fn with_negative_total_policy(mut self, policy: NegativeTotalPolicy) -> Self {
+        self.config.negative_total_policy = policy;
+        self
+    }
+
+    /// Sets the policy applied to disputes on transactions loaded from a previous session.
+    pub fn with_historical_dispute_policy(mut self, policy: HistoricalDisputePolicy) -> Self {
+        self.config.historical_dispute_policy = policy;
+        self
+    }
+
+    /// Sets the policy applied to resolves referring to a transaction that is not disputed.
+    pub fn with_resolve_policy(mut self, policy: ResolvePolicy) -> Self {
+        self.config.resolve_policy = policy;
+        self
+    }
+
+    /// Sets the policy applied when a resolve (or a dispute, see `NegativeHeldPolicy::Strict`)
+    /// would drive the account held funds below zero.
+    pub fn with_negative_held_policy(mut self, policy: NegativeHeldPolicy) -> Self {
+        self.config.negative_held_policy = policy;
+        self
+    }
+
+    /// Sets which transactions can be disputed, see `Engine::with_dispute_policy`.
+    pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.config.dispute_policy = policy;
+        self
+    }
+
+    /// Processes only the records of the given types read from a stream, see
+    /// `Engine::with_only_types`.
+    pub fn with_only_types(mut self, types: Option<HashSet<TransactionType>>) -> Self {
+        self.config.only_types = types;
+        self
+    }
+
+    /// Only lets known clients open an account, see `Engine::with_strict_clients`.
+    pub fn with_strict_clients(mut self, enabled: bool) -> Self {
+        self.config.strict_clients = enabled;
+        self
+    }
+
+    /// Registers `client`, see `Engine::register_client`.
+    pub async fn register_client(&self, client: ClientId) {
+        self.registered_clients.insert(client, ()).await;
+    }
+
+    /// Sets the transactions accepted on a locked account, see `Engine::with_locked_account_policy`.
+    pub fn with_locked_account_policy(mut self, policy: LockedAccountPolicy) -> Self {
+        self.config.locked_account_policy = policy;
+        self
+    }
+
+    /// Sets which check fails first on a deposit to a locked account with a repeated tx id.
+    pub fn with_deposit_check_order(mut self, order: DepositCheckOrder) -> Self {
+        self.config.deposit_check_order = order;
+        self
+    }
+
+    /// Sets whether tx ids are unique across all clients (default) or only per client, see
+    /// `Engine::with_tx_uniqueness`.
+    pub fn with_tx_uniqueness(mut self, uniqueness: TxUniqueness) -> Self {
+        self.config.tx_uniqueness = uniqueness;
+        self
+    }
+
+    // Helper: key of `tx` in the transaction log
+    #[inline]
+    fn tx_key(&self, tx: &Transaction) -> TxKey {
+        self.config.tx_uniqueness.key(tx.client, tx.tx)
+    }
+
+    /// Sets the representation of the `locked` column in `dump_account_to_csv`.
+    pub fn with_locked_format(mut self, format: BoolFormat) -> Self {
+        self.config.dump.locked_format = format;
+        self
+    }
+
+    /// Sets the units of the `amount` column of the processed transactions,
+    /// e.g. `AmountUnits::Minor(2)` reads `1050` as `10.50`.
+    pub fn with_amount_units(mut self, units: AmountUnits) -> Self {
+        self.config.amount_units = units;
+        self
+    }
+
+    /// Reads a deposit with a negative amount as a withdrawal of the absolute amount, for
+    /// upstreams encoding withdrawals as negative deposits (e.g. `deposit,1,1,-5` is a
+    /// withdrawal of 5). When disabled (default), such a deposit fails with `DepositAmountInvalid`.
+    pub fn with_signed_amounts(mut self, signed_amounts: bool) -> Self {
+        self.config.signed_amounts = signed_amounts;
+        self
+    }
+
+    /// Charges a flat `fee` on each deposit and withdrawal: it is deducted from the client
+    /// `available` and `total` funds and recorded as a `Fee` entry of the `fee_log`. The
+    /// transaction fails with `InsufficientFundsForFee` if the funds (after a deposit) cannot
+    /// cover the fee. Disputes and chargebacks move the transaction amount only: the fee is
+    /// never refunded.
+    ///
+    /// # Panics
+    /// - If `fee` is negative.
+    pub fn with_transaction_fee(mut self, fee: Decimal) -> Self {
+        assert!(
+            fee >= Decimal::ZERO,
+            "The transaction fee must not be negative"
+        );
+        self.config.transaction_fee = fee;
+        self
+    }
+
+    /// Distinguishes, among the disputes/resolves/chargebacks referring to an unknown transaction,
+    /// the rows with an amount: they are structured as a new transaction reusing a tx id, so
+    /// they fail with `TxIdCollision` instead of `TransactionNotFound`.
+    pub fn with_tx_id_collision_detection(mut self, enabled: bool) -> Self {
+        self.config.detect_tx_id_collisions = enabled;
+        self
+    }
+
+    /// Stops reading a stream once `max_duration` has elapsed since the start of the processing
+    /// call, keeping the state computed so far. The call then fails with a `MultipleErrors`
+    /// entry reporting the number of records processed, so the partial accounts can still be
+    /// dumped.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.config.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Processes the records of the input streams in the order of their `timestamp` column, by
+    /// chunks of `chunk_records` records, see `Engine::with_timestamp_ordering`.
+    pub fn with_timestamp_ordering(mut self, chunk_records: usize) -> Self {
+        self.config.timestamp_ordering = Some(chunk_records);
+        self
+    }
+
+    /// Limits the number of accounts, see `Engine::with_max_accounts`.
+    pub fn with_max_accounts(mut self, max_accounts: usize, policy: AccountCapacityPolicy) -> Self {
+        self.config.max_accounts = Some(max_accounts);
+        self.config.account_capacity_policy = policy;
+        self
+    }
+
+    /// Sets the dialect of the transactions CSV input, see `Engine::with_csv_dialect`.
+    pub fn with_csv_dialect(mut self, dialect: CsvDialect) -> Self {
+        self.config.csv_dialect = dialect;
+        self
+    }
+
+    /// Rejects any deposit or withdrawal whose amount exceeds `max_amount` with
+    /// `AmountExceedsLimit`, guarding against fat-finger errors (an amount equal to the limit is
+    /// accepted). By default the amount is unlimited.
+    pub fn with_max_amount(mut self, max_amount: Decimal) -> Self {
+        self.config.max_amount = Some(max_amount);
+        self
+    }
+
+    /// Makes processing a file already processed by this engine a successful no-op, see
+    /// `Engine::with_idempotent_file_reruns`.
+    pub fn with_idempotent_file_reruns(mut self, enabled: bool) -> Self {
+        self.config.idempotent_file_reruns = enabled;
+        self
+    }
+
+    /// Sets the units of the amount columns in `dump_account_to_csv`.
+    pub fn with_dump_amount_units(mut self, units: AmountUnits) -> Self {
+        self.config.dump.amount_units = units;
+        self
+    }
+
+    /// Skips the zero-balance, unlocked accounts in `dump_account_to_csv`.
+    pub fn with_omit_zero_accounts(mut self, omit_zero: bool) -> Self {
+        self.config.dump.omit_zero = omit_zero;
+        self
+    }
+
+    /// Writes only `columns`, in the given order, in `dump_account_to_csv`, see
+    /// `Engine::with_account_columns`.
+    pub fn with_account_columns(mut self, columns: Vec<AccountColumn>) -> Self {
+        self.config.dump.columns = Some(columns);
+        self
+    }
+
+    /// Rounds the amounts of the account dumps to `scale` decimal places, see
+    /// `Engine::with_dump_scale`.
+    pub fn with_dump_scale(mut self, scale: u32) -> Self {
+        self.config.dump.scale = Some(scale);
+        self
+    }
+
+    /// Sets the rounding of the amounts of the account dumps, applied with `with_dump_scale`.
+    pub fn with_dump_rounding(mut self, rounding: Rounding) -> Self {
+        self.config.dump.rounding = rounding;
+        self
+    }
+
+    /// Flushes the writer of `dump_account_to_csv` every `flush_every` records written (`0`
+    /// flushes only at the end), `DEFAULT_DUMP_FLUSH_EVERY` by default.
+    pub fn with_dump_flush_every(mut self, flush_every: usize) -> Self {
+        self.config.dump.flush_every = flush_every;
+        self
+    }
+
+    /// Makes `read_and_process_transactions` resumable after a crash.
+    ///
+    /// Every `every_n` transactions read (and at the end of the input) the accounts, the
+    /// transaction log and the progress on the input are dumped to `CHECKPOINT_FILE` in `dir`.
+    /// If the checkpoint is already there when `read_and_process_transactions` starts, the
+    /// engine state is restored from it and the transactions already read are skipped, so
+    /// the same input must be provided again. The errors reported before the checkpoint are
+    /// not reported again.
+    ///
+    /// Checkpointing assumes a single input stream: do not call `read_and_process_transactions`
+    /// concurrently on an engine with checkpointing.
+    pub fn with_checkpointing(mut self, dir: impl Into<PathBuf>, every_n: usize) -> Self {
+        self.checkpointing = Some(Checkpointing {
+            dir: dir.into(),
+            every_n: every_n.max(1),
+        });
+        self
+    }
+
+    /// Processes the CSV files at `paths` concurrently against the shared state, each one with
+    /// `read_and_process_transactions_from_csv`, e.g. the feeds of disjoint client groups. Files
+    /// sharing clients or tx ids are processed in no particular order with respect to each other,
+    /// see `Engine::process_streams` for a deterministic alternative.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the files are read and processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting the errors of every file, in the order of
+    ///   `paths`, each prefixed with the path of its file.
+    pub async fn process_files(
+        &self,
+        paths: &[String],
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        let results = join_all(
+            paths
+                .iter()
+                .map(|path| self.read_and_process_transactions_from_csv(path, buffer_size)),
+        )
+        .await;
+
+        let mut errors = Vec::new();
+        for (path, result) in paths.iter().zip(results) {
+            if let Err(TransactionProcessingError::MultipleErrors(file_errors)) = result {
+                errors.extend(file_errors.into_iter().map(|e| e.in_file(path)));
+            }
+        }
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Counts the transactions of the log by type, iterating over the shards of the log.
+    ///
+    /// Only deposits and withdrawals are stored in the log, so this is mostly a sanity check,
+    /// e.g. to verify what `load_from_previous_session_csvs` loaded.
+    pub async fn log_type_counts(&self) -> HashMap<TransactionType, usize> {
+        let mut counts = HashMap::new();
+        let mut iter = self.transaction_log.iter().await;
+        while let Some((tx_id, shard_guard)) = iter.next().await {
+            if let Some(tx) = shard_guard.get(&tx_id) {
+                *counts.entry(tx.ty.clone()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Returns a copy of the account of `client`, or `None` if the client has no account, see
+    /// `Engine::get_account`. The shard read lock is released before returning.
+    pub async fn get_account(&self, client: ClientId) -> Option<Account> {
+        self.accounts
+            .get(client)
+            .await
+            .and_then(|shard_guard| shard_guard.get(&client).cloned())
+    }
+
+    /// Returns the number of client accounts.
+    pub async fn total_clients(&self) -> usize {
+        self.accounts.len().await
+    }
+
+    /// Returns the number of transactions in the transaction log.
+    pub async fn total_transactions(&self) -> usize {
+        self.transaction_log.len().await
+    }
+
+    /// Returns a checksum of the accounts state: the sum of the account totals and the number of
+    /// accounts, see `Engine::state_checksum`.
+    pub async fn state_checksum(&self) -> (Decimal, usize) {
+        let mut sum = Decimal::ZERO;
+        let mut count = 0;
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(account) = shard_guard.get(&client_id) {
+                sum = sum.saturating_add(account.total);
+                count += 1;
+            }
+        }
+        (sum, count)
+    }
+
+    /// Checks that the balances of every account are consistent, see
+    /// `Engine::verify_invariants`. The shards of the accounts are scanned one at a time.
+    ///
+    /// # Errors
+    /// - `Vec<InvariantViolation>`: the violating accounts, sorted by client.
+    pub async fn verify_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = Vec::new();
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(violation) = shard_guard
+                .get(&client_id)
+                .and_then(|account| balance_violation(client_id, account, &self.config))
+            {
+                violations.push(violation);
+            }
+        }
+        if violations.is_empty() {
+            return Ok(());
+        }
+        violations.sort_unstable_by_key(|violation| violation.client);
+        Err(violations)
+    }
+
+    /// Returns the maximum amount that could be charged back, the sum of the amounts not charged
+    /// back yet of all the currently disputed transactions, see
+    /// `Engine::max_chargeback_exposure`. Only the transaction log is locked.
+    pub async fn max_chargeback_exposure(&self) -> Decimal {
+        let mut exposure = Decimal::ZERO;
+        let mut iter = self.transaction_log.iter().await;
+        while let Some((tx_id, shard_guard)) = iter.next().await {
+            let amount = shard_guard
+                .get(&tx_id)
+                .filter(|tx| tx.disputed)
+                .and_then(Transaction::remaining_amount);
+            if let Some(amount) = amount.filter(|amount| *amount > Decimal::ZERO) {
+                exposure = exposure.saturating_add(amount);
+            }
+        }
+        exposure
+    }
+
+    /// Returns the ids of all the transactions currently flagged as disputed, sorted, see
+    /// `Engine::disputed_transactions`. The shards of the log are scanned one at a time.
+    pub async fn disputed_transactions(&self) -> Vec<TxId> {
+        let mut disputed = Vec::new();
+        let mut iter = self.transaction_log.iter().await;
+        while let Some((tx_id, shard_guard)) = iter.next().await {
+            if shard_guard.get(&tx_id).is_some_and(|tx| tx.disputed) {
+                disputed.push(tx_id.tx);
+            }
+        }
+        disputed.sort_unstable();
+        disputed
+    }
+
+    /// Removes from the transaction log the transactions with a tx id lower than `older_than_tx`,
+    /// with their fees, unless one of them is still unsettled, see `Engine::prune_transactions`.
+    /// The shards are scanned one at a time.
+    pub async fn prune_transactions(&self, older_than_tx: TxId) -> Result<usize, EngineError> {
+        let mut locked = HashSet::new();
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if shard_guard
+                .get(&client_id)
+                .is_some_and(|account| account.locked)
+            {
+                locked.insert(client_id);
+            }
+        }
+
+        let mut keys = Vec::new();
+        let mut iter = self.transaction_log.iter().await;
+        while let Some((key, shard_guard)) = iter.next().await {
+            let Some(tx) = shard_guard.get(&key).filter(|tx| tx.tx < older_than_tx) else {
+                continue;
+            };
+            if is_unsettled(tx, locked.contains(&tx.client), &self.config) {
+                return Err(EngineError::TransactionUnsettled);
+            }
+            keys.push(key);
+        }
+        drop(iter);
+
+        let mut pruned = 0;
+        for key in keys {
+            if self.transaction_log.remove(key).await.is_some() {
+                self.fee_log.remove(key).await;
+                self.historical_transactions.remove(key).await;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Returns the root hash of a Merkle tree over the accounts sorted by client, see
+    /// `Engine::state_merkle_root`. The root is the same as the sync engine's for the same state.
+    pub async fn state_merkle_root(&self) -> [u8; 32] {
+        let mut accounts = Vec::new();
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(account) = shard_guard.get(&client_id) {
+                accounts.push((client_id, account.clone()));
+            }
+        }
+        accounts_merkle_root(accounts)
+    }
+
+    /// Dumps the current state of all accounts to a CSV writer in ascending client order, see
+    /// `Engine::dump_account_to_csv_sorted`. The accounts are first copied into a sorted
+    /// snapshot, so no shard lock is held while writing.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub async fn dump_account_to_csv_sorted<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut snapshot = BTreeMap::new();
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(account) = shard_guard.get(&client_id) {
+                snapshot.insert(client_id, account.clone());
+            }
+        }
+
+        let mut csv_writer = AsyncWriterBuilder::new()
+            .buffer_capacity(buffer_size)
+            .create_serializer(writer);
+        csv_writer
+            .serialize(account_csv_header(&self.config.dump))
+            .await?;
+        for (client_id, account) in &snapshot {
+            if self.config.dump.omit_zero && account.is_zero() {
+                continue;
+            }
+            csv_writer
+                .serialize(account_csv_record(*client_id, account, &self.config.dump)?)
+                .await?;
+        }
+        csv_writer.flush().await?;
+        Ok(())
+    }
+
+    /// Dumps the current state of all accounts as newline-delimited JSON, one object per line,
+    /// see `Engine::dump_account_to_ndjson`.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub async fn dump_account_to_ndjson<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.dump_account_lines(writer, buffer_size, |line, client, account, options| {
+            write_account_ndjson(line, client, account, options)
+        })
+        .await
+    }
+
+    /// Dumps the current state of all accounts as JSON Lines with the amounts as strings, one
+    /// object per line, see `Engine::dump_account_to_json`.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub async fn dump_account_to_json<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.dump_account_lines(writer, buffer_size, |line, client, account, options| {
+            write_account_json(line, client, account, options)
+        })
+        .await
+    }
+
+    /// Writes every account as a line built by `write_line`, skipping the zero accounts if
+    /// `omit_zero` is set.
+    async fn dump_account_lines<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+        write_line: AccountLineWriter<Vec<u8>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = BufWriter::with_capacity(buffer_size, writer);
+        let mut line = Vec::new();
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(account) = shard_guard.get(&client_id) {
+                if self.config.dump.omit_zero && account.is_zero() {
+                    continue;
+                }
+                line.clear();
+                write_line(&mut line, client_id, account, &self.config.dump)?;
+                writer.write_all(&line).await?;
+            }
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Dumps the checkpoint into `dir`, replacing the previous one.
+    ///
+    /// Same format as `Engine::dump_combined_csv` with three more record types:
+    /// - `P,processed,last_tx,,,` for the progress (first record),
+    /// - `H,type,client,tx,amount,disputed,charged_back,reversed,released` for the transactions
+    ///   loaded from a previous session,
+    /// - `F,client,tx,amount,,` for the fees of the `fee_log`.
+    ///
+    /// The checkpoint is written to a temporary file and then renamed, so a crash while
+    /// writing leaves the previous checkpoint in place.
+    async fn write_checkpoint(
+        &self,
+        dir: &Path,
+        progress: &CheckpointProgress,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut csv_writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(Vec::new());
+        csv_writer.serialize(("P", progress.processed, progress.last_tx, "", "", ""))?;
+
+        let mut iter = self.accounts.iter().await;
+        while let Some((client_id, shard_guard)) = iter.next().await {
+            if let Some(account) = shard_guard.get(&client_id) {
+                csv_writer.serialize((
+                    "A",
+                    client_id,
+                    account.available,
+                    account.held,
+                    account.total,
+                    account.locked,
+                ))?;
+            }
+        }
+
+        let mut iter = self.transaction_log.iter().await;
+        while let Some((tx_id, shard_guard)) = iter.next().await {
+            if let Some(tx) = shard_guard.get(&tx_id) {
+                let record_type = if self.historical_transactions.contains_key(tx_id).await {
+                    "H"
+                } else {
+                    "T"
+                };
+                csv_writer.serialize((
+                    record_type,
+                    tx.ty.clone(),
+                    tx.client,
+                    tx.tx,
+                    tx.amount,
+                    tx.disputed,
+                    tx.charged_back,
+                    tx.reversed,
+                    tx.released,
+                ))?;
+            }
+        }
+
+        let mut iter = self.fee_log.iter().await;
+        while let Some((key, shard_guard)) = iter.next().await {
+            if let Some(fee) = shard_guard.get(&key) {
+                csv_writer.serialize(("F", fee.client, fee.tx, fee.amount, "", ""))?;
+            }
         }
+        let data = csv_writer.into_inner().map_err(|e| e.into_error())?;
+
+        tokio::fs::create_dir_all(dir).await?;
+        let tmp_path = dir.join(format!("{CHECKPOINT_FILE}.tmp"));
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, dir.join(CHECKPOINT_FILE)).await?;
+        Ok(())
+    }
+
+    /// Restores the engine state from the checkpoint in `dir`, see `write_checkpoint`.
+    ///
+    /// # Returns
+    /// - `Ok(None)`: If there is no checkpoint in `dir`.
+    /// - `Ok(Some(progress))`: The progress on the input stored in the checkpoint.
+    ///
+    /// # Errors
+    /// - `Io`: If the checkpoint cannot be read.
+    /// - `Csv`: If a record cannot be read or a transaction cannot be parsed.
+    /// - `InvalidRecord`: If a record is malformed or the progress record is missing.
+    /// - `InvalidClientId`, `InvalidDecimal`, `InvalidBool`: If an account or a fee cannot be
+    ///   parsed.
+    async fn load_checkpoint(
+        &self,
+        dir: &Path,
+    ) -> Result<Option<CheckpointProgress>, EngineSerDeserError> {
+        let data = match tokio::fs::read(dir.join(CHECKPOINT_FILE)).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(EngineSerDeserError::Io(e)),
+        };
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_slice());
+        let transaction_headers = csv::StringRecord::from(vec![
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "disputed",
+            "charged_back",
+            "reversed",
+            "released",
+        ]);
+
+        let mut progress = None;
+        for result in rdr.records() {
+            let record = result.map_err(EngineSerDeserError::Csv)?;
+            // The transactions written before the `charged_back`, `reversed` and `released`
+            // columns have fewer fields.
+            let valid_len = match &record[0] {
+                "T" | "H" => matches!(record.len(), 6..=9),
+                _ => record.len() == 6,
+            };
+            if !valid_len {
+                return Err(EngineSerDeserError::InvalidRecord);
+            }
+            match &record[0] {
+                "P" => {
+                    progress = Some(CheckpointProgress {
+                        processed: record[1]
+                            .parse()
+                            .map_err(|_| EngineSerDeserError::InvalidRecord)?,
+                        last_tx: record[2]
+                            .parse()
+                            .map_err(|_| EngineSerDeserError::InvalidRecord)?,
+                    });
+                }
+                "A" => {
+                    let (client_id, account) = Engine::parse_account_record(&record, 1)?;
+                    self.accounts.insert(client_id, account).await;
+                }
+                record_type @ ("T" | "H") => {
+                    let fields: csv::StringRecord = record.iter().skip(1).collect();
+                    let transaction = fields
+                        .deserialize::<Transaction>(Some(&transaction_headers))
+                        .map_err(EngineSerDeserError::Csv)?;
+                    let key = self.tx_key(&transaction);
+                    if record_type == "H" {
+                        self.historical_transactions.insert(key, ()).await;
+                    }
+                    self.transaction_log.insert(key, transaction).await;
+                }
+                "F" => {
+                    let fee = Transaction::fee(
+                        record[1]
+                            .parse()
+                            .map_err(|_| EngineSerDeserError::InvalidClientId)?,
+                        record[2]
+                            .parse()
+                            .map_err(|_| EngineSerDeserError::InvalidRecord)?,
+                        record[3]
+                            .parse()
+                            .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
+                    );
+                    self.fee_log.insert(self.tx_key(&fee), fee).await;
+                }
+                _ => return Err(EngineSerDeserError::InvalidRecord),
+            }
+        }
+        progress.map(Some).ok_or(EngineSerDeserError::InvalidRecord)
     }
 
     // Helper: safe math (same as sync version)
@@ -98,6 +891,46 @@ impl AsyncEngine {
         a.checked_sub(b).ok_or(EngineError::SubtractionOverflow)
     }
 
+    // Helper: same negative total check as the sync version
+    fn check_total(&self, total: Decimal) -> Result<(), EngineError> {
+        if self.config.negative_total_policy == NegativeTotalPolicy::Reject && total < Decimal::ZERO
+        {
+            return Err(EngineError::NegativeTotal);
+        }
+        Ok(())
+    }
+
+    // Helper: same negative held check as the sync version
+    fn check_held(
+        &self,
+        tx: &Transaction,
+        held_before: Decimal,
+        held: Decimal,
+    ) -> Result<(), EngineError> {
+        if negative_held_rejected(tx, &self.config) && held < Decimal::ZERO && held < held_before {
+            return Err(EngineError::NegativeHeld);
+        }
+        Ok(())
+    }
+
+    // Helper: same amount limit check as the sync version
+    fn check_amount_limit(&self, amount: Decimal) -> Result<(), EngineError> {
+        if self.config.max_amount.is_some_and(|max| amount > max) {
+            return Err(EngineError::AmountExceedsLimit);
+        }
+        Ok(())
+    }
+
+    // Helper: same fee recording as the sync version
+    async fn record_fee(&self, tx: &Transaction) {
+        let fee = self.config.transaction_fee;
+        if fee > Decimal::ZERO {
+            self.fee_log
+                .insert(self.tx_key(tx), Transaction::fee(tx.client, tx.tx, fee))
+                .await;
+        }
+    }
+
     async fn try_get_account(
         &self,
         client: ClientId,
@@ -115,6 +948,61 @@ impl AsyncEngine {
             })
     }
 
+    /// See `Engine::try_get_dispute_account`.
+    async fn try_get_dispute_account(
+        &self,
+        client: ClientId,
+    ) -> Result<RwLockWriteGuard<'_, HashMap<ClientId, Account>>, EngineError> {
+        match self.config.locked_account_policy {
+            LockedAccountPolicy::Block => self.try_get_account(client).await,
+            LockedAccountPolicy::AllowDisputes => self
+                .accounts
+                .get_mut(client)
+                .await
+                .ok_or(EngineError::AccountNotFound),
+        }
+    }
+
+    /// Processes the transactions file at `input_path` read by `reader`, decompressing it if it
+    /// is gzip-compressed (see `is_gzip`).
+    async fn read_and_process_transactions_file<R>(
+        &self,
+        input_path: &str,
+        mut reader: BufReader<R>,
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let header = reader.fill_buf().await.map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error reading file: {}",
+                e
+            ))])
+        })?;
+        if is_gzip(input_path, header) {
+            let mut decoder = GzipDecoder::new(reader);
+            // Concatenated gzip members (e.g. appended dumps) are read as one stream.
+            decoder.multiple_members(true);
+            self.read_and_process_transactions(decoder, buffer_size)
+                .await
+        } else {
+            self.read_and_process_transactions(reader, buffer_size)
+                .await
+        }
+    }
+
+    /// See `Engine::check_reference_client`.
+    async fn check_reference_client(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let key = self.tx_key(tx);
+        match self.transaction_log.get(key).await {
+            Some(guard) if guard.get(&key).is_some_and(|o| o.client != tx.client) => {
+                Err(EngineError::DifferentClient)
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn check_transaction_semantic(
         tx: &Transaction,
         original_tx: &Transaction,
@@ -123,21 +1011,26 @@ impl AsyncEngine {
         if original_tx.client != tx.client {
             return Err(EngineError::DifferentClient);
         }
+        if original_tx.ty == TransactionType::Hold {
+            return Err(EngineError::HoldNotDisputable);
+        }
+        if original_tx.reversed {
+            return Err(EngineError::AlreadyReversed);
+        }
+        if original_tx.is_charged_back() {
+            return Err(EngineError::AlreadyChargedBack);
+        }
         match tx.ty {
-            TransactionType::Dispute => {
-                if original_tx.disputed {
-                    return Err(EngineError::TransactionAlreadyDisputed);
-                }
+            TransactionType::Dispute if original_tx.disputed => {
+                return Err(EngineError::TransactionAlreadyDisputed);
             }
-            TransactionType::Resolve | TransactionType::Chargeback => {
-                if !original_tx.disputed {
-                    return Err(EngineError::TransactionNotDisputed);
-                }
+            TransactionType::Resolve | TransactionType::Chargeback if !original_tx.disputed => {
+                return Err(EngineError::TransactionNotDisputed);
             }
             _ => {}
         }
         let mut amount = original_tx
-            .amount
+            .remaining_amount()
             .ok_or(EngineError::ReferredTransactionNoAmount)?;
         if original_tx.ty == TransactionType::Withdrawal {
             amount = -amount;
@@ -146,6 +1039,67 @@ impl AsyncEngine {
     }
 }
 
+/// Parses a transaction log dump (with its header), splitting the records in chunks parsed on
+/// parallel threads. Invalid records are reported on stderr and skipped.
+///
+/// The chunks are cut at line ends: the fields of a transaction log never contain line breaks.
+fn parse_transaction_log(data: &[u8]) -> Vec<Transaction> {
+    let header_end = data
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(data.len(), |pos| pos + 1);
+    let headers = ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(&data[..header_end])
+        .records()
+        .next()
+        .and_then(Result::ok)
+        .unwrap_or_default();
+
+    let mut chunks = Vec::new();
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = (data.len() - header_end).div_ceil(workers).max(1);
+    let mut start = header_end;
+    while start < data.len() {
+        let end = data[(start + chunk_size).min(data.len())..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |pos| start + chunk_size + pos + 1);
+        chunks.push(&data[start..end]);
+        start = end;
+    }
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let headers = &headers;
+                scope.spawn(move || {
+                    let mut rdr = ReaderBuilder::new()
+                        .has_headers(false)
+                        .trim(csv::Trim::All)
+                        .from_reader(chunk);
+                    let mut transactions = Vec::new();
+                    for result in rdr.records() {
+                        match result.and_then(|record| record.deserialize(Some(headers))) {
+                            Ok(tx) => transactions.push(tx),
+                            Err(e) => {
+                                eprintln!("Skipping invalid transaction record: {:?}", e)
+                            }
+                        }
+                    }
+                    transactions
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    })
+}
+
 impl AsyncEngineFunctions for AsyncEngine {
     async fn read_and_process_transactions<R>(
         &self,
@@ -157,8 +1111,9 @@ impl AsyncEngineFunctions for AsyncEngine {
     {
         // Channel to parallelize CSV reading (producer) and transaction processing (consumer)
         let (tx_sender, mut tx_receiver) = mpsc::unbounded_channel::<Transaction>();
-        let (err_sender, mut err_receiver) = mpsc::unbounded_channel::<String>();
+        let (err_sender, mut err_receiver) = mpsc::unbounded_channel::<TransactionError>();
 
+        let config = self.config.clone();
         let handle = task::spawn_blocking(move || {
             // We need to wrap the async stream in a SyncIoBridge to convert it to a sync stream
             // because the csv library only supports sync streams.
@@ -166,10 +1121,40 @@ impl AsyncEngineFunctions for AsyncEngine {
             // Finally, we create a CSV reader from the BufReader.
             let sync_stream = SyncIoBridge::new(stream);
             let mut reader = std::io::BufReader::with_capacity(buffer_size, sync_stream);
-            let mut csv_reader = ReaderBuilder::new()
-                .has_headers(true)
+            let mut csv_reader = transactions_reader_builder(&config)
                 .trim(csv::Trim::All)
                 .from_reader(&mut reader);
+            if csv_reader.headers().is_ok_and(|headers| headers.is_empty()) {
+                let _ = err_sender.send(missing_header());
+                return;
+            }
+
+            if let Some(chunk_records) = config.timestamp_ordering {
+                let ordering = match csv_reader.headers() {
+                    Ok(headers) => TimestampOrdering::new(headers, chunk_records),
+                    Err(e) => Err(Engine::format_read_error(&e)),
+                };
+                let mut ordering = match ordering {
+                    Ok(ordering) => ordering,
+                    Err(e) => {
+                        let _ = err_sender.send(e);
+                        return;
+                    }
+                };
+                while let Some(read) = ordering.next_transaction(&mut csv_reader) {
+                    match read {
+                        Ok((tx, _)) => {
+                            if tx_sender.send(tx).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            let _ = err_sender.send(e);
+                        }
+                    }
+                }
+                return;
+            }
 
             for result in csv_reader.deserialize::<Transaction>() {
                 match result {
@@ -179,21 +1164,103 @@ impl AsyncEngineFunctions for AsyncEngine {
                         }
                     }
                     Err(e) => {
-                        let msg = if e.to_string().contains("unknown variant") {
-                            "Error reading transaction record: unknown transaction type".to_string()
-                        } else {
-                            format!("Error reading transaction record: {}", e)
-                        };
-                        let _ = err_sender.send(msg);
+                        let _ = err_sender.send(Engine::format_read_error(&e));
                     }
                 }
             }
         });
         let mut errors = Vec::new();
 
-        while let Some(tx) = tx_receiver.recv().await {
-            if let Err(e) = self.process_transaction(&tx).await {
-                errors.push(format!("Error processing {tx:?}: {e}"));
+        let resumed = match &self.checkpointing {
+            Some(checkpointing) => self
+                .load_checkpoint(&checkpointing.dir)
+                .await
+                .map_err(|e| {
+                    TransactionProcessingError::MultipleErrors(vec![TransactionError::other(
+                        format!("Error loading checkpoint: {e}"),
+                    )])
+                })?,
+            None => None,
+        };
+        let mut progress = CheckpointProgress {
+            processed: 0,
+            last_tx: 0,
+        };
+
+        let deadline = self
+            .config
+            .max_duration
+            .map(|max_duration| (tokio::time::Instant::now() + max_duration, max_duration));
+
+        loop {
+            let next = match deadline {
+                Some((deadline, max_duration)) => {
+                    match tokio::time::timeout_at(deadline, tx_receiver.recv()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            errors.push(max_duration_exceeded(progress.processed, max_duration));
+                            break;
+                        }
+                    }
+                }
+                None => tx_receiver.recv().await,
+            };
+            let Some(tx) = next else {
+                break;
+            };
+            progress.processed += 1;
+            progress.last_tx = tx.tx;
+            if let Some(resumed) = &resumed {
+                if progress.processed < resumed.processed {
+                    continue;
+                }
+                if progress.processed == resumed.processed {
+                    if tx.tx != resumed.last_tx {
+                        errors.push(TransactionError::other(format!(
+                            "Error resuming from checkpoint: transaction {} read where {} was expected",
+                            tx.tx, resumed.last_tx
+                        )));
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            // The skipped records still count in the checkpointed progress of the stream.
+            if self.config.accepts_type(&tx.ty) {
+                if let Err(e) = self.process_transaction(&tx).await {
+                    errors.push(TransactionError::processing(&tx, e));
+                }
+            }
+
+            if let Some(checkpointing) = &self.checkpointing {
+                if progress.processed.is_multiple_of(checkpointing.every_n) {
+                    if let Err(e) = self.write_checkpoint(&checkpointing.dir, &progress).await {
+                        errors.push(TransactionError::other(format!(
+                            "Error writing checkpoint: {e}"
+                        )));
+                    }
+                }
+            }
+        }
+        // Dropping the receiver stops the parser if processing stopped early.
+        drop(tx_receiver);
+
+        if let Some(checkpointing) = &self.checkpointing {
+            let resumed_processed = resumed.as_ref().map_or(0, |resumed| resumed.processed);
+            if progress.processed < resumed_processed {
+                errors.push(TransactionError::other(format!(
+                    "Error resuming from checkpoint: {} transactions read where at least {} were expected",
+                    progress.processed, resumed_processed
+                )));
+            } else if progress.processed > resumed_processed
+                && !progress.processed.is_multiple_of(checkpointing.every_n)
+            {
+                if let Err(e) = self.write_checkpoint(&checkpointing.dir, &progress).await {
+                    errors.push(TransactionError::other(format!(
+                        "Error writing checkpoint: {e}"
+                    )));
+                }
             }
         }
 
@@ -202,7 +1269,7 @@ impl AsyncEngineFunctions for AsyncEngine {
         }
 
         if handle.await.is_err() {
-            errors.push("CSV parser panicked".to_string());
+            errors.push(TransactionError::other("CSV parser panicked"));
         }
 
         if errors.is_empty() {
@@ -217,13 +1284,42 @@ impl AsyncEngineFunctions for AsyncEngine {
         input_path: &str,
         buffer_size: usize,
     ) -> Result<(), TransactionProcessingError> {
-        let file = File::open(input_path).await.map_err(|e| {
-            TransactionProcessingError::MultipleErrors(vec![format!("Error opening file: {}", e)])
-        })?;
+        let open_error = |e: std::io::Error| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error opening file: {}",
+                e
+            ))])
+        };
+        if !self.config.idempotent_file_reruns {
+            let file = File::open(input_path).await.map_err(open_error)?;
+            let reader = BufReader::with_capacity(buffer_size, file);
+            return self
+                .read_and_process_transactions_file(input_path, reader, buffer_size)
+                .await;
+        }
 
-        let reader = BufReader::with_capacity(buffer_size, file);
-        self.read_and_process_transactions(reader, buffer_size)
-            .await
+        // The file is read once: the fingerprint is computed from the bytes then processed
+        let content = tokio::fs::read(input_path).await.map_err(open_error)?;
+        let (content, fingerprint) = task::spawn_blocking(move || {
+            let fingerprint = content_fingerprint(&content);
+            (content, fingerprint)
+        })
+        .await
+        .map_err(|e| open_error(std::io::Error::other(e)))?;
+        if self.processed_files.contains_key(fingerprint).await {
+            return Ok(());
+        }
+        let reader = BufReader::with_capacity(buffer_size, std::io::Cursor::new(content));
+        let result = self
+            .read_and_process_transactions_file(input_path, reader, buffer_size)
+            .await;
+
+        let stopped = matches!(&result, Err(TransactionProcessingError::MultipleErrors(errors))
+            if errors.iter().any(is_max_duration_exceeded));
+        if !stopped {
+            self.processed_files.insert(fingerprint, ()).await;
+        }
+        result
     }
 
     async fn load_from_previous_session_csvs(
@@ -231,27 +1327,27 @@ impl AsyncEngineFunctions for AsyncEngine {
         transactions_file: &str,
         accounts_file: &str,
     ) -> Result<(), AsycEngineSerDeserError> {
-        // Load transactions
+        // Load transactions: the whole log is parsed off the runtime on parallel threads, then
+        // bulk inserted with one lock acquisition per shard
         {
-            let file = File::open(transactions_file)
+            let data = tokio::fs::read(transactions_file)
                 .await
                 .map_err(AsycEngineSerDeserError::Io)?;
-            let mut rdr = AsyncReaderBuilder::new()
-                .has_headers(true)
-                .trim(Trim::All)
-                .create_deserializer(BufReader::new(file));
-
-            let mut records = rdr.deserialize::<Transaction>();
-            while let Some(result) = records.next().await {
-                if let Ok(tx) = result {
-                    self.transaction_log.insert(tx.tx, tx).await;
-                } else {
-                    eprintln!("Skipping invalid transaction record: {:?}", result);
-                }
-            }
+            let transactions = task::spawn_blocking(move || parse_transaction_log(&data))
+                .await
+                .map_err(|e| AsycEngineSerDeserError::Io(std::io::Error::other(e)))?;
+            let historical: Vec<(TxKey, ())> = transactions
+                .iter()
+                .map(|tx| (self.tx_key(tx), ()))
+                .collect();
+            tokio::join!(
+                self.historical_transactions.insert_many(historical),
+                self.transaction_log
+                    .insert_many(transactions.into_iter().map(|tx| (self.tx_key(&tx), tx))),
+            );
         }
 
-        // Load accounts (custom format: client,available,held,total,locked)
+        // Load accounts (custom format: client,available,held,total,locked[,overdraft_limit])
         {
             let file = File::open(accounts_file)
                 .await
@@ -259,16 +1355,39 @@ impl AsyncEngineFunctions for AsyncEngine {
 
             let mut reader = AsyncReaderBuilder::new()
                 .has_headers(true)
+                .flexible(true)
                 .trim(Trim::All)
+                .comment(Some(b'#'))
                 .create_deserializer(BufReader::new(file));
+            let has_overdraft_limit = reader
+                .headers()
+                .await
+                .map_err(AsycEngineSerDeserError::Csv)?
+                .get(5)
+                == Some(OVERDRAFT_LIMIT_COLUMN);
 
-            type AccountTuple = (ClientId, String, String, String, bool);
+            #[derive(serde::Deserialize)]
+            struct AccountRow(
+                ClientId,
+                String,
+                String,
+                String,
+                String,
+                #[serde(default)] Option<String>,
+            );
 
-            let mut records = reader.deserialize::<AccountTuple>();
+            let mut accounts = Vec::new();
+            let mut records = reader.deserialize::<AccountRow>();
 
             while let Some(result) = records.next().await {
-                let (client_id, available_str, held_str, total_str, locked) =
-                    result.map_err(AsycEngineSerDeserError::Csv)?;
+                let AccountRow(
+                    client_id,
+                    available_str,
+                    held_str,
+                    total_str,
+                    locked_str,
+                    overdraft_str,
+                ) = result.map_err(AsycEngineSerDeserError::Csv)?;
 
                 let to_dec = |s: String| -> Result<Decimal, _> {
                     s.parse::<Decimal>()
@@ -278,16 +1397,25 @@ impl AsyncEngineFunctions for AsyncEngine {
                 let available = to_dec(available_str)?;
                 let held = to_dec(held_str)?;
                 let total = to_dec(total_str)?;
+                let locked = parse_bool(&locked_str).ok_or(AsycEngineSerDeserError::InvalidBool)?;
+                let overdraft_limit = if has_overdraft_limit {
+                    parse_overdraft_limit(overdraft_str.as_deref())
+                        .ok_or(AsycEngineSerDeserError::InvalidDecimal)?
+                } else {
+                    Decimal::ZERO
+                };
 
                 let account = Account {
                     available,
                     held,
                     total,
                     locked,
+                    overdraft_limit,
                 };
 
-                self.accounts.insert(client_id, account).await;
+                accounts.push((client_id, account));
             }
+            self.accounts.insert_many(accounts).await;
         }
 
         Ok(())
@@ -304,25 +1432,25 @@ impl AsyncEngineFunctions for AsyncEngine {
 
         // Write header
         csv_writer
-            .serialize(("client", "available", "held", "total", "locked"))
+            .serialize(account_csv_header(&self.config.dump))
             .await?;
 
+        let flush_every = self.config.dump.flush_every;
+        let mut written = 0usize;
         let mut iter = self.accounts.iter().await;
         while let Some((client_id, shard_guard)) = iter.next().await {
             // Safety: we know key exists in this shard
             if let Some(account) = shard_guard.get(&client_id) {
+                if self.config.dump.omit_zero && account.is_zero() {
+                    continue;
+                }
                 csv_writer
-                    .serialize((
-                        client_id,
-                        account.available,
-                        account.held,
-                        account.total,
-                        account.locked,
-                    ))
+                    .serialize(account_csv_record(client_id, account, &self.config.dump)?)
                     .await?;
 
                 //flush every N records to reduce memory
-                if client_id % 1000 == 0 {
+                written += 1;
+                if flush_every > 0 && written.is_multiple_of(flush_every) {
                     csv_writer.flush().await?;
                 }
             }
@@ -348,7 +1476,16 @@ impl AsyncEngineFunctions for AsyncEngine {
 
         // Write header
         csv_writer
-            .serialize(("type", "client", "tx", "amount", "disputed"))
+            .serialize((
+                "type",
+                "client",
+                "tx",
+                "amount",
+                "disputed",
+                "charged_back",
+                "reversed",
+                "released",
+            ))
             .await?;
 
         let mut iter = self.transaction_log.iter().await;
@@ -362,6 +1499,9 @@ impl AsyncEngineFunctions for AsyncEngine {
                         transaction.tx,
                         transaction.amount,
                         transaction.disputed,
+                        transaction.charged_back,
+                        transaction.reversed,
+                        transaction.released,
                     ))
                     .await?;
             }
@@ -383,12 +1523,20 @@ impl AsyncEngineFunctions for AsyncEngine {
 
 impl AsycEngineStateTransitionFunctions for AsyncEngine {
     async fn process_transaction(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let tx = normalize_input(tx, &self.config)?;
+        let tx = tx.as_ref();
         match tx.ty {
             TransactionType::Deposit => self.process_deposit(tx).await,
             TransactionType::Withdrawal => self.process_withdrawal(tx).await,
             TransactionType::Dispute => self.process_dispute(tx).await,
             TransactionType::Resolve => self.process_resolve(tx).await,
             TransactionType::Chargeback => self.process_chargeback(tx).await,
+            TransactionType::Hold => self.process_hold(tx).await,
+            TransactionType::Release => self.process_release(tx).await,
+            TransactionType::Reversal => self.process_reversal(tx).await,
+            TransactionType::Freeze => self.process_freeze(tx).await,
+            TransactionType::Unfreeze => self.process_unfreeze(tx).await,
+            TransactionType::Fee => Err(EngineError::FeeNotProcessable),
         }
     }
 
@@ -397,21 +1545,68 @@ impl AsycEngineStateTransitionFunctions for AsyncEngine {
         if amount <= Decimal::ZERO {
             return Err(EngineError::DepositAmountInvalid);
         }
-        if self.transaction_log.contains_key(tx.tx).await {
+        self.check_amount_limit(amount)?;
+        if self.config.strict_clients
+            && !self.accounts.contains_key(tx.client).await
+            && !self.registered_clients.contains_key(tx.client).await
+        {
+            return Err(EngineError::UnknownClient);
+        }
+        let overflow;
+        let tx =
+            if self.config.max_accounts.is_some() && !self.accounts.contains_key(tx.client).await {
+                match new_account_deposit_client(tx, self.accounts.len().await, &self.config)? {
+                    client if client != tx.client => {
+                        overflow = Transaction {
+                            client,
+                            ..tx.clone()
+                        };
+                        &overflow
+                    }
+                    _ => tx,
+                }
+            } else {
+                tx
+            };
+        if self.config.deposit_check_order == DepositCheckOrder::LockedFirst {
+            if let Some(account_guard) = self.accounts.get(tx.client).await {
+                if account_guard.get(&tx.client).is_some_and(|a| a.locked) {
+                    return Err(EngineError::AccountLocked);
+                }
+            }
+        }
+        if self.transaction_log.contains_key(self.tx_key(tx)).await {
             return Err(EngineError::TransactionRepeated);
         }
+        let fee = self.config.transaction_fee;
 
-        let mut account_guard = self.accounts.entry(tx.client).await;
-        let account = account_guard.get_mut(&tx.client).unwrap();
+        let mut account_guard = self.accounts.shard_mut(tx.client).await;
+        // The account is not created if the deposit cannot cover the fee
+        if amount < fee && !account_guard.contains_key(&tx.client) {
+            return Err(EngineError::InsufficientFundsForFee);
+        }
+        let account = account_guard.entry(tx.client).or_default();
 
         if account.locked {
             return Err(EngineError::AccountLocked);
         }
 
-        account.available = Self::safe_add(account.available, amount)?;
-        account.total = Self::safe_add(account.total, amount)?;
+        let mut available = Self::safe_add(account.available, amount)?;
+        let mut total = Self::safe_add(account.total, amount)?;
+        if fee > Decimal::ZERO {
+            if available < fee {
+                return Err(EngineError::InsufficientFundsForFee);
+            }
+            available = Self::safe_sub(available, fee)?;
+            total = Self::safe_sub(total, fee)?;
+        }
+        account.available = available;
+        account.total = total;
 
-        self.transaction_log.insert(tx.tx, tx.clone()).await;
+        self.transaction_log
+            .insert(self.tx_key(tx), tx.clone())
+            .await;
+        self.record_fee(tx).await;
         Ok(())
     }
 
@@ -420,69 +1615,207 @@ impl AsycEngineStateTransitionFunctions for AsyncEngine {
         if amount <= Decimal::ZERO {
             return Err(EngineError::WithdrawalAmountInvalid);
         }
-        if self.transaction_log.contains_key(tx.tx).await {
+        self.check_amount_limit(amount)?;
+        if self.transaction_log.contains_key(self.tx_key(tx)).await {
             return Err(EngineError::TransactionRepeated);
         }
 
         let mut account_guard = self.try_get_account(tx.client).await?;
         let account = account_guard.get_mut(&tx.client).unwrap();
 
-        if account.available >= amount {
-            account.available = Self::safe_sub(account.available, amount)?;
-            account.total = Self::safe_sub(account.total, amount)?;
+        // The available funds can go down to the overdraft limit of the account
+        let available = Self::safe_sub(account.available, amount)?;
+        if available >= -account.overdraft_limit {
+            let fee = self.config.transaction_fee;
+            let available = Self::safe_sub(available, fee)?;
+            if available < -account.overdraft_limit {
+                return Err(EngineError::InsufficientFundsForFee);
+            }
+            let total = Self::safe_sub(Self::safe_sub(account.total, amount)?, fee)?;
+            self.check_total(total)?;
+            account.available = available;
+            account.total = total;
         } else {
             return Err(EngineError::InsufficientFunds);
         }
 
-        self.transaction_log.insert(tx.tx, tx.clone()).await;
+        self.transaction_log
+            .insert(self.tx_key(tx), tx.clone())
+            .await;
+        self.record_fee(tx).await;
         Ok(())
     }
 
     async fn process_dispute(&self, tx: &Transaction) -> Result<(), EngineError> {
-        let mut account_guard = self.try_get_account(tx.client).await?;
+        self.check_reference_client(tx).await?;
+        let mut account_guard = self.try_get_dispute_account(tx.client).await?;
         let account = account_guard.get_mut(&tx.client).unwrap();
 
-        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(tx.tx).await {
-            let original_tx = original_tx_guard.get_mut(&tx.tx).unwrap();
+        if self.config.historical_dispute_policy == HistoricalDisputePolicy::Reject
+            && self
+                .historical_transactions
+                .contains_key(self.tx_key(tx))
+                .await
+        {
+            return Err(EngineError::HistoricalTransaction);
+        }
+
+        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(self.tx_key(tx)).await {
+            let original_tx = original_tx_guard.get_mut(&self.tx_key(tx)).unwrap();
             let amount = Self::check_transaction_semantic(tx, original_tx)?;
-            account.available = Self::safe_sub(account.available, amount)?;
-            account.held = Self::safe_add(account.held, amount)?;
+            check_disputable(original_tx, &self.config)?;
+            let available = Self::safe_sub(account.available, amount)?;
+            let held = Self::safe_add(account.held, amount)?;
+            self.check_held(tx, account.held, held)?;
+            account.available = available;
+            account.held = held;
             original_tx.disputed = true;
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
         }
         Ok(())
     }
 
     async fn process_resolve(&self, tx: &Transaction) -> Result<(), EngineError> {
-        let mut account_guard = self.try_get_account(tx.client).await?;
+        self.check_reference_client(tx).await?;
+        let mut account_guard = self.try_get_dispute_account(tx.client).await?;
         let account = account_guard.get_mut(&tx.client).unwrap();
 
-        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(tx.tx).await {
-            let original_tx = original_tx_guard.get_mut(&tx.tx).unwrap();
+        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(self.tx_key(tx)).await {
+            let original_tx = original_tx_guard.get_mut(&self.tx_key(tx)).unwrap();
+            if self.config.resolve_policy == ResolvePolicy::IgnoreIfNotDisputed
+                && original_tx.client == tx.client
+                && !original_tx.disputed
+            {
+                return Ok(());
+            }
             let amount = Self::check_transaction_semantic(tx, original_tx)?;
-            account.available = Self::safe_add(account.available, amount)?;
-            account.held = Self::safe_sub(account.held, amount)?;
+            let available = Self::safe_add(account.available, amount)?;
+            let held = Self::safe_sub(account.held, amount)?;
+            self.check_held(tx, account.held, held)?;
+            account.available = available;
+            account.held = held;
             original_tx.disputed = false;
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
         }
         Ok(())
     }
 
     async fn process_chargeback(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx).await?;
+        let mut account_guard = self.try_get_dispute_account(tx.client).await?;
+        let account = account_guard.get_mut(&tx.client).unwrap();
+
+        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(self.tx_key(tx)).await {
+            let original_tx = original_tx_guard.get_mut(&self.tx_key(tx)).unwrap();
+            let disputed = Self::check_transaction_semantic(tx, original_tx)?;
+            let (amount, partial) = chargeback_amount(tx, disputed)?;
+            let total = Self::safe_sub(account.total, amount)?;
+            let held = Self::safe_sub(account.held, amount)?;
+            self.check_total(total)?;
+            account.total = total;
+            account.held = held;
+            original_tx.charged_back += amount.abs();
+            if !partial {
+                account.locked = true;
+            }
+        } else {
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+    async fn process_hold(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let amount = tx.amount.ok_or(EngineError::NoAmount)?;
+        if amount <= Decimal::ZERO {
+            return Err(EngineError::HoldAmountInvalid);
+        }
+        if self.transaction_log.contains_key(self.tx_key(tx)).await {
+            return Err(EngineError::TransactionRepeated);
+        }
+
         let mut account_guard = self.try_get_account(tx.client).await?;
         let account = account_guard.get_mut(&tx.client).unwrap();
 
-        if let Some(original_tx_guard) = self.transaction_log.get(tx.tx).await {
-            let original_tx = original_tx_guard.get(&tx.tx).unwrap();
-            let amount = Self::check_transaction_semantic(tx, original_tx)?;
-            account.total = Self::safe_sub(account.total, amount)?;
+        if account.available < amount {
+            return Err(EngineError::InsufficientFunds);
+        }
+        let available = Self::safe_sub(account.available, amount)?;
+        account.held = Self::safe_add(account.held, amount)?;
+        account.available = available;
+
+        self.transaction_log
+            .insert(self.tx_key(tx), tx.clone())
+            .await;
+        Ok(())
+    }
+
+    async fn process_release(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx).await?;
+        let mut account_guard = self.try_get_account(tx.client).await?;
+        let account = account_guard.get_mut(&tx.client).unwrap();
+
+        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(self.tx_key(tx)).await {
+            let original_tx = original_tx_guard.get_mut(&self.tx_key(tx)).unwrap();
+            let amount = Engine::check_release(tx, original_tx)?;
+            let available = Self::safe_add(account.available, amount)?;
             account.held = Self::safe_sub(account.held, amount)?;
-            account.locked = true;
+            account.available = available;
+            original_tx.released = true;
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+
+    // Same semantics as the sync version: undoes the deposit/withdrawal on available and total.
+    async fn process_reversal(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx).await?;
+        let mut account_guard = self.try_get_account(tx.client).await?;
+        let account = account_guard.get_mut(&tx.client).unwrap();
+
+        if let Some(mut original_tx_guard) = self.transaction_log.get_mut(self.tx_key(tx)).await {
+            let original_tx = original_tx_guard.get_mut(&self.tx_key(tx)).unwrap();
+            let amount = Engine::check_reversal(tx, original_tx)?;
+            let available = Self::safe_sub(account.available, amount)?;
+            let total = Self::safe_sub(account.total, amount)?;
+            self.check_total(total)?;
+            account.available = available;
+            account.total = total;
+            original_tx.reversed = true;
+        } else {
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+
+    // Same semantics as the sync version: locks the account without touching the balances.
+    async fn process_freeze(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let mut account_guard = self
+            .accounts
+            .get_mut(tx.client)
+            .await
+            .ok_or(EngineError::AccountNotFound)?;
+        let account = account_guard.get_mut(&tx.client).unwrap();
+        if account.locked {
+            return Err(EngineError::AccountAlreadyFrozen);
+        }
+        account.locked = true;
+        Ok(())
+    }
+
+    // Same semantics as the sync version: unlocks the account without touching the balances.
+    async fn process_unfreeze(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let mut account_guard = self
+            .accounts
+            .get_mut(tx.client)
+            .await
+            .ok_or(EngineError::AccountNotFound)?;
+        let account = account_guard.get_mut(&tx.client).unwrap();
+        if !account.locked {
+            return Err(EngineError::AccountNotFrozen);
         }
+        account.locked = false;
         Ok(())
     }
 }
@@ -0,0 +1,162 @@
+//! Bounded single-producer single-consumer feed with a configurable overflow policy, used by
+//! `Engine::subscribe_deltas_bounded` so that a slow subscriber cannot grow the memory without
+//! bound.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// What a bounded feed does when its subscriber falls `capacity` items behind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FeedOverflowPolicy {
+    /// The publisher waits until the subscriber receives an item: nothing is lost, but a slow
+    /// subscriber slows the publisher down.
+    #[default]
+    Block,
+    /// The oldest buffered item is discarded to make room: the publisher never waits, and the
+    /// subscriber only sees the latest `capacity` items (see `FeedReceiver::dropped`).
+    DropOldest,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    /// Number of items discarded by `FeedOverflowPolicy::DropOldest`.
+    dropped: u64,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: FeedOverflowPolicy,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Publishing side of a bounded feed, see `bounded`.
+pub struct FeedSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Subscribing side of a bounded feed, see `bounded`. Dropping it ends the subscription.
+pub struct FeedReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a single-producer single-consumer feed buffering at most `capacity` items (at least
+/// one), applying `policy` when the buffer is full.
+pub fn bounded<T>(capacity: usize, policy: FeedOverflowPolicy) -> (FeedSender<T>, FeedReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            items: VecDeque::with_capacity(capacity.max(1)),
+            dropped: 0,
+            sender_alive: true,
+            receiver_alive: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity: capacity.max(1),
+        policy,
+    });
+    (
+        FeedSender {
+            shared: Arc::clone(&shared),
+        },
+        FeedReceiver { shared },
+    )
+}
+
+impl<T> FeedSender<T> {
+    /// Sends `item`, waiting for room or discarding the oldest item if the buffer is full,
+    /// depending on the policy.
+    ///
+    /// # Returns
+    /// - `false` if the receiver was dropped (the item is discarded).
+    pub fn send(&self, item: T) -> bool {
+        let mut queue = self.shared.lock();
+        while queue.receiver_alive && queue.items.len() >= self.shared.capacity {
+            match self.shared.policy {
+                FeedOverflowPolicy::Block => {
+                    queue = self
+                        .shared
+                        .not_full
+                        .wait(queue)
+                        .unwrap_or_else(|e| e.into_inner());
+                }
+                FeedOverflowPolicy::DropOldest => {
+                    queue.items.pop_front();
+                    queue.dropped += 1;
+                }
+            }
+        }
+        if !queue.receiver_alive {
+            return false;
+        }
+        queue.items.push_back(item);
+        self.shared.not_empty.notify_one();
+        true
+    }
+}
+
+impl<T> Drop for FeedSender<T> {
+    fn drop(&mut self) {
+        self.shared.lock().sender_alive = false;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+impl<T> FeedReceiver<T> {
+    /// Receives the oldest buffered item, waiting for one.
+    ///
+    /// # Returns
+    /// - `None` once the buffer is empty and the sender was dropped.
+    pub fn recv(&self) -> Option<T> {
+        let mut queue = self.shared.lock();
+        loop {
+            if let Some(item) = queue.items.pop_front() {
+                self.shared.not_full.notify_one();
+                return Some(item);
+            }
+            if !queue.sender_alive {
+                return None;
+            }
+            queue = self
+                .shared
+                .not_empty
+                .wait(queue)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Receives the oldest buffered item, if any, without waiting.
+    pub fn try_recv(&self) -> Option<T> {
+        let item = self.shared.lock().items.pop_front();
+        if item.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        item
+    }
+
+    /// Returns an iterator over the items buffered so far, without waiting.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv())
+    }
+
+    /// Returns the number of items discarded by `FeedOverflowPolicy::DropOldest`.
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+}
+
+impl<T> Drop for FeedReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+        self.shared.not_full.notify_all();
+    }
+}
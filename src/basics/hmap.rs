@@ -1,37 +1,61 @@
+use futures_util::future::join_all;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::num::Wrapping;
+use std::pin::Pin;
+#[cfg(feature = "contention")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
 use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-const NUM_SHARDS: usize = 64;
+/// Default number of shards of a `ShardedRwLockMap`, see `ShardedRwLockMap::with_shards`.
+pub const NUM_SHARDS: usize = 64;
 
-/// Fast, deterministic shard selection without external crates
+/// Multiplier of the shard hash (the constant used in fxhash).
+const SHARD_HASH_MULTIPLIER: u64 = 0x517cc1b727220a95;
+
+/// Fast, deterministic shard selection without external crates: `shard` returns a hash of the
+/// key, the map routes the key to the shard `shard() % shard_count`.
 pub trait Shardable {
     fn shard(&self) -> usize;
 }
 
+/// Multiplicative hashing computed on 64 bits on every platform: the hash is taken from the
+/// top bits of the product, which depend on all the bits of the key (the low bits only depend
+/// on the low bits of the key, so keys sharing them would all land in the same shard).
+#[inline]
+fn shard_of(key: u64) -> usize {
+    let h = key.wrapping_mul(SHARD_HASH_MULTIPLIER);
+    (h >> 32) as usize
+}
+
 impl Shardable for u16 {
     #[inline]
     fn shard(&self) -> usize {
-        // Simple but good-enough hash (same constant used in fxhash)
-        let k = Wrapping(*self as usize);
-        let h = k * Wrapping(0x517cc1b727220a95u128 as usize);
-        (h.0) & (NUM_SHARDS - 1)
+        shard_of(*self as u64)
     }
 }
 
 impl Shardable for u32 {
     #[inline]
     fn shard(&self) -> usize {
-        let k = Wrapping(*self as usize);
-        let h = k * Wrapping(0x517cc1b727220a95u128 as usize);
-        (h.0) & (NUM_SHARDS - 1)
+        shard_of(*self as u64)
+    }
+}
+
+impl Shardable for u64 {
+    #[inline]
+    fn shard(&self) -> usize {
+        shard_of(*self)
     }
 }
 
 /// Async-safe sharded HashMap using tokio::sync::RwLock
 pub struct ShardedRwLockMap<K, V> {
-    shards: Box<[RwLock<HashMap<K, V>>; NUM_SHARDS]>,
+    shards: Box<[RwLock<HashMap<K, V>>]>,
+    /// Per shard number of write lock acquisitions that had to wait.
+    #[cfg(feature = "contention")]
+    contention: Box<[AtomicU64]>,
 }
 
 impl<K, V> Default for ShardedRwLockMap<K, V>
@@ -49,22 +73,60 @@ where
     K: Eq + Hash + Shardable + Copy,
     V: Send + Sync,
 {
+    /// Creates a map with `NUM_SHARDS` shards.
     pub fn new() -> Self {
-        // Helper to create an array of RwLock<HashMap<..>>
-        let mut shards = Vec::with_capacity(NUM_SHARDS);
-        for _ in 0..NUM_SHARDS {
-            shards.push(RwLock::new(HashMap::new()));
-        }
-        // SAFETY: we just created exactly NUM_SHARDS elements
-        let shards: [_; NUM_SHARDS] = shards.try_into().unwrap_or_else(|_| unreachable!());
+        Self::with_shards(NUM_SHARDS)
+    }
+
+    /// Creates a map with `shards` shards (any count, not only powers of two). More shards
+    /// mean less contention between writers of different keys, e.g. with many clients on
+    /// many cores, at the cost of slower whole-map scans (`len`, `iter`).
+    ///
+    /// # Panics
+    /// If `shards` is 0.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "a ShardedRwLockMap needs at least one shard");
         Self {
-            shards: Box::new(shards),
+            shards: (0..shards).map(|_| RwLock::new(HashMap::new())).collect(),
+            #[cfg(feature = "contention")]
+            contention: (0..shards).map(|_| AtomicU64::new(0)).collect(),
         }
     }
 
+    /// Returns the number of shards of the map.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard containing `key`.
     #[inline]
-    fn shard_for(&self, key: &K) -> usize {
-        key.shard()
+    pub fn shard_for(&self, key: &K) -> usize {
+        key.shard() % self.shards.len()
+    }
+
+    /// Acquires the write lock of the shard containing the key
+    #[inline]
+    async fn write_shard(&self, key: &K) -> RwLockWriteGuard<'_, HashMap<K, V>> {
+        let shard_idx = self.shard_for(key);
+        #[cfg(feature = "contention")]
+        match self.shards[shard_idx].try_write() {
+            Ok(lock) => return lock,
+            Err(_) => {
+                self.contention[shard_idx].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.shards[shard_idx].write().await
+    }
+
+    /// Returns, for each shard, how many write lock acquisitions had to wait because the lock
+    /// was held by someone else. High counts identify hot shards (and so hot keys), useful to
+    /// tune the shard count (`with_shards`) and the shard function.
+    #[cfg(feature = "contention")]
+    pub fn contention_report(&self) -> Vec<u64> {
+        self.contention
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect()
     }
 
     /// Get a read guard to the shard containing the key (if the key exists)
@@ -80,8 +142,7 @@ where
 
     /// Get a write guard to the shard containing the key (if the key exists)
     pub async fn get_mut(&self, key: K) -> Option<RwLockWriteGuard<'_, HashMap<K, V>>> {
-        let shard = &self.shards[self.shard_for(&key)];
-        let lock = shard.write().await;
+        let lock = self.write_shard(&key).await;
         if lock.contains_key(&key) {
             Some(lock)
         } else {
@@ -89,28 +150,51 @@ where
         }
     }
 
+    /// Get a write guard to the shard containing the key, whether or not the key exists
+    pub async fn shard_mut(&self, key: K) -> RwLockWriteGuard<'_, HashMap<K, V>> {
+        self.write_shard(&key).await
+    }
+
     /// Get or create an entry (very common pattern for accounts)
     pub async fn entry(&self, key: K) -> RwLockWriteGuard<'_, HashMap<K, V>>
     where
         V: Default,
     {
-        let shard = &self.shards[self.shard_for(&key)];
-        let mut lock = shard.write().await;
+        let mut lock = self.write_shard(&key).await;
         lock.entry(key).or_default();
         lock
     }
 
     /// Insert a value
     pub async fn insert(&self, key: K, value: V) -> Option<V> {
-        let shard = &self.shards[self.shard_for(&key)];
-        let mut lock = shard.write().await;
+        let mut lock = self.write_shard(&key).await;
         lock.insert(key, value)
     }
 
+    /// Insert many values, acquiring the write lock of each shard only once: the entries are
+    /// bucketed by shard first, then the shards are filled concurrently. Much faster than
+    /// `insert` in a loop for bulk loads. If a key appears more than once, the last value wins.
+    pub async fn insert_many<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut buckets: Vec<Vec<(K, V)>> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+        for (key, value) in entries {
+            buckets[self.shard_for(&key)].push((key, value));
+        }
+        join_all(buckets.into_iter().filter(|bucket| !bucket.is_empty()).map(
+            |bucket| async move {
+                let mut lock = self.write_shard(&bucket[0].0).await;
+                lock.reserve(bucket.len());
+                lock.extend(bucket);
+            },
+        ))
+        .await;
+    }
+
     /// Remove a value
     pub async fn remove(&self, key: K) -> Option<V> {
-        let shard = &self.shards[self.shard_for(&key)];
-        let mut lock = shard.write().await;
+        let mut lock = self.write_shard(&key).await;
         lock.remove(&key)
     }
 
@@ -126,6 +210,29 @@ where
         ShardedIter {
             map: self,
             shard_idx: 0,
+            pending_keys: Vec::new(),
+        }
+    }
+
+    /// Returns a `Stream` of copies of all the entries, to be composed with the `StreamExt`
+    /// combinators (e.g. `.filter(..).collect().await`). The entries of a shard are cloned
+    /// under its read lock when the stream reaches it, and the lock is released before they
+    /// are yielded. Use `iter` to read the entries in place without cloning them.
+    pub fn stream(&self) -> ShardedStream<'_, K, V>
+    where
+        K: Send + Sync,
+        V: Clone,
+    {
+        let entries = stream::iter(self.shards.iter())
+            .then(|shard| async move {
+                let lock = shard.read().await;
+                lock.iter()
+                    .map(|(key, value)| (*key, value.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .flat_map(stream::iter);
+        ShardedStream {
+            entries: entries.boxed(),
         }
     }
 
@@ -141,6 +248,25 @@ where
         total
     }
 
+    /// Returns the total number of entries in the map as of a single point in time.
+    ///
+    /// Unlike `len`, which reads the shards one after the other (so a concurrent move of a key
+    /// between shards can be counted twice or missed, giving a total that never existed), this
+    /// function acquires the read locks of all the shards, in shard order, before summing.
+    /// It is slower and blocks every writer until the sum is done.
+    ///
+    /// # Deadlocks
+    /// Do not call it while holding a guard returned by this map (e.g. by `get_mut` or
+    /// `entry`): it would wait for that shard forever. Writers of this map only lock one
+    /// shard at a time, so they cannot deadlock with it.
+    pub async fn len_consistent(&self) -> usize {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.read().await);
+        }
+        guards.iter().map(|guard| guard.len()).sum()
+    }
+
     /// Check if all shards are empty
     ///
     /// Iterate over all shards and check if they are empty.
@@ -157,9 +283,15 @@ where
 }
 
 /// Async iterator over all key-value pairs
+///
+/// The keys of a shard are collected when the iterator reaches it; each call to `next` then
+/// returns one of them together with a fresh read guard of its shard (keys removed meanwhile
+/// are skipped).
 pub struct ShardedIter<'a, K, V> {
     map: &'a ShardedRwLockMap<K, V>,
     shard_idx: usize,
+    /// Keys of shard `shard_idx - 1` not yet returned.
+    pending_keys: Vec<K>,
 }
 
 impl<'a, K, V> ShardedIter<'a, K, V>
@@ -169,22 +301,35 @@ where
 {
     pub async fn next(&mut self) -> Option<(K, RwLockReadGuard<'a, HashMap<K, V>>)> {
         loop {
-            if self.shard_idx >= NUM_SHARDS {
-                return None;
+            if let Some(key) = self.pending_keys.pop() {
+                // Caller must not hold this guard longer than needed
+                let read_guard = self.map.shards[self.shard_idx - 1].read().await;
+                if read_guard.contains_key(&key) {
+                    return Some((key, read_guard));
+                }
+                continue;
             }
 
-            let shard = &self.map.shards[self.shard_idx];
-            let read_guard = shard.read().await;
-
-            if !read_guard.is_empty() {
-                // Return the first key and the guard that protects the whole shard
-                // Caller must not hold this guard longer than needed
-                let key = *read_guard.keys().next()?;
-                self.shard_idx += 1; // move to next shard on next call
-                return Some((key, read_guard));
+            if self.shard_idx >= self.map.shards.len() {
+                return None;
             }
 
+            let read_guard = self.map.shards[self.shard_idx].read().await;
+            self.pending_keys = read_guard.keys().copied().collect();
             self.shard_idx += 1;
         }
     }
 }
+
+/// `Stream` of cloned key-value pairs, see `ShardedRwLockMap::stream`.
+pub struct ShardedStream<'a, K, V> {
+    entries: BoxStream<'a, (K, V)>,
+}
+
+impl<K, V> Stream for ShardedStream<'_, K, V> {
+    type Item = (K, V);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.entries.poll_next_unpin(cx)
+    }
+}
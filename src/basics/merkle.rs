@@ -0,0 +1,49 @@
+//! Binary Merkle tree over SHA-256, with the leaf/node domain separation of RFC 6962 so that a
+//! leaf can never be mistaken for an internal node.
+
+use sha2::{Digest, Sha256};
+
+/// Hash of a Merkle tree node or leaf.
+pub type Hash = [u8; 32];
+
+/// Returns the hash of the leaf with content `data`: `SHA-256(0x00 || data)`.
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0x00])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+/// Returns the hash of the internal node with children `left` and `right`:
+/// `SHA-256(0x01 || left || right)`.
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([0x01])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Returns the root of the Merkle tree with the given leaf hashes, in order.
+///
+/// The leaves are paired level by level; the last node of a level with an odd number of nodes
+/// is promoted unchanged to the next level. The root of an empty tree is `SHA-256("")`.
+pub fn merkle_root(leaves: Vec<Hash>) -> Hash {
+    if leaves.is_empty() {
+        return Sha256::digest([]).into();
+    }
+    let mut level = leaves;
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!("chunks(2) yields one or two nodes"),
+            })
+            .collect();
+    }
+    level[0]
+}
@@ -0,0 +1,162 @@
+//! Processing counters of the engine and their snapshot, see `Engine::metrics`.
+
+use crate::datastr::error::EngineError;
+use crate::datastr::transaction::TransactionType;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters updated by the engine on every processed transaction.
+#[derive(Debug, Default)]
+pub(crate) struct MetricsCounters {
+    deposits: AtomicU64,
+    withdrawals: AtomicU64,
+    disputes: AtomicU64,
+    resolves: AtomicU64,
+    chargebacks: AtomicU64,
+    /// Rejected transactions by `EngineError` variant.
+    rejected: DashMap<&'static str, u64>,
+}
+
+impl MetricsCounters {
+    /// Counts the outcome of processing a transaction of type `ty`.
+    pub(crate) fn record(&self, ty: &TransactionType, result: &Result<(), EngineError>) {
+        let counter = match (ty, result) {
+            (_, Err(error)) => return self.record_rejection(error),
+            (TransactionType::Deposit, Ok(())) => &self.deposits,
+            (TransactionType::Withdrawal, Ok(())) => &self.withdrawals,
+            (TransactionType::Dispute, Ok(())) => &self.disputes,
+            (TransactionType::Resolve, Ok(())) => &self.resolves,
+            (TransactionType::Chargeback, Ok(())) => &self.chargebacks,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a transaction rejected with `error`.
+    pub(crate) fn record_rejection(&self, error: &EngineError) {
+        *self.rejected.entry(error_kind(error)).or_default() += 1;
+    }
+
+    /// Returns a snapshot of the counters, with `accounts_locked` locked accounts.
+    pub(crate) fn snapshot(&self, accounts_locked: u64) -> EngineMetrics {
+        EngineMetrics {
+            deposits_processed: self.deposits.load(Ordering::Relaxed),
+            withdrawals_processed: self.withdrawals.load(Ordering::Relaxed),
+            disputes: self.disputes.load(Ordering::Relaxed),
+            resolves: self.resolves.load(Ordering::Relaxed),
+            chargebacks: self.chargebacks.load(Ordering::Relaxed),
+            rejected_by_kind: self
+                .rejected
+                .iter()
+                .map(|entry| (entry.key().to_string(), *entry.value()))
+                .collect(),
+            accounts_locked,
+        }
+    }
+}
+
+/// Returns the name of the `EngineError` variant of `error`, e.g. `InsufficientFunds`.
+fn error_kind(error: &EngineError) -> &'static str {
+    match error {
+        EngineError::DifferentClient => "DifferentClient",
+        EngineError::NoAmount => "NoAmount",
+        EngineError::ReferredTransactionNoAmount => "ReferredTransactionNoAmount",
+        EngineError::DepositAmountInvalid => "DepositAmountInvalid",
+        EngineError::WithdrawalAmountInvalid => "WithdrawalAmountInvalid",
+        EngineError::TransactionRepeated => "TransactionRepeated",
+        EngineError::InsufficientFunds => "InsufficientFunds",
+        EngineError::AccountNotFound => "AccountNotFound",
+        EngineError::TransactionNotFound => "TransactionNotFound",
+        EngineError::AdditionOverflow => "AdditionOverflow",
+        EngineError::SubtractionOverflow => "SubtractionOverflow",
+        EngineError::AccountLocked => "AccountLocked",
+        EngineError::TransactionAlreadyDisputed => "TransactionAlreadyDisputed",
+        EngineError::TransactionNotDisputed => "TransactionNotDisputed",
+        EngineError::NegativeTotal => "NegativeTotal",
+        EngineError::NegativeHeld => "NegativeHeld",
+        EngineError::HistoricalTransaction => "HistoricalTransaction",
+        EngineError::InvalidMinorUnits => "InvalidMinorUnits",
+        EngineError::InsufficientFundsForFee => "InsufficientFundsForFee",
+        EngineError::FeeNotProcessable => "FeeNotProcessable",
+        EngineError::TxIdCollision => "TxIdCollision",
+        EngineError::AmountExceedsLimit => "AmountExceedsLimit",
+        EngineError::HoldAmountInvalid => "HoldAmountInvalid",
+        EngineError::NotAHold => "NotAHold",
+        EngineError::HoldAlreadyReleased => "HoldAlreadyReleased",
+        EngineError::HoldNotDisputable => "HoldNotDisputable",
+        EngineError::AccountAlreadyFrozen => "AccountAlreadyFrozen",
+        EngineError::AccountNotFrozen => "AccountNotFrozen",
+        EngineError::AccountCapacityReached => "AccountCapacityReached",
+        EngineError::DepositDropped => "DepositDropped",
+        EngineError::TransactionUnsettled => "TransactionUnsettled",
+        EngineError::ChargebackAmountInvalid => "ChargebackAmountInvalid",
+        EngineError::ChargebackExceedsHeld => "ChargebackExceedsHeld",
+        EngineError::WithdrawalNotDisputable => "WithdrawalNotDisputable",
+        EngineError::AlreadyReversed => "AlreadyReversed",
+        EngineError::CannotReverseDisputed => "CannotReverseDisputed",
+        EngineError::HoldNotReversible => "HoldNotReversible",
+        EngineError::AlreadyChargedBack => "AlreadyChargedBack",
+        EngineError::UnknownClient => "UnknownClient",
+        EngineError::SchemaViolation(_) => "SchemaViolation",
+        EngineError::WalWriteFailed(_) => "WalWriteFailed",
+    }
+}
+
+/// Snapshot of the processing counters of an engine, see `Engine::metrics`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EngineMetrics {
+    /// Deposits processed successfully.
+    pub deposits_processed: u64,
+    /// Withdrawals processed successfully.
+    pub withdrawals_processed: u64,
+    /// Disputes processed successfully.
+    pub disputes: u64,
+    /// Resolves processed successfully.
+    pub resolves: u64,
+    /// Chargebacks (partial or final) processed successfully.
+    pub chargebacks: u64,
+    /// Rejected transactions by `EngineError` variant name, e.g. `InsufficientFunds`.
+    pub rejected_by_kind: BTreeMap<String, u64>,
+    /// Accounts currently locked (charged back or frozen).
+    pub accounts_locked: u64,
+}
+
+impl EngineMetrics {
+    /// Formats the metrics in the Prometheus text exposition format, e.g. to be served on a
+    /// `/metrics` endpoint.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP txn_engine_transactions_processed_total Transactions processed successfully, by type.\n",
+        );
+        out.push_str("# TYPE txn_engine_transactions_processed_total counter\n");
+        for (ty, count) in [
+            ("deposit", self.deposits_processed),
+            ("withdrawal", self.withdrawals_processed),
+            ("dispute", self.disputes),
+            ("resolve", self.resolves),
+            ("chargeback", self.chargebacks),
+        ] {
+            let _ = writeln!(
+                out,
+                "txn_engine_transactions_processed_total{{type=\"{ty}\"}} {count}"
+            );
+        }
+        out.push_str(
+            "# HELP txn_engine_transactions_rejected_total Transactions rejected by the engine, by error.\n",
+        );
+        out.push_str("# TYPE txn_engine_transactions_rejected_total counter\n");
+        for (kind, count) in &self.rejected_by_kind {
+            let _ = writeln!(
+                out,
+                "txn_engine_transactions_rejected_total{{kind=\"{kind}\"}} {count}"
+            );
+        }
+        out.push_str("# HELP txn_engine_accounts_locked Accounts currently locked.\n");
+        out.push_str("# TYPE txn_engine_accounts_locked gauge\n");
+        let _ = writeln!(out, "txn_engine_accounts_locked {}", self.accounts_locked);
+        out
+    }
+}
@@ -1 +1,7 @@
+pub mod feed;
 pub mod hmap;
+pub mod merkle;
+pub mod metrics;
+pub mod stats;
+#[cfg(feature = "timing")]
+pub mod timing;
@@ -0,0 +1,31 @@
+use rust_decimal::Decimal;
+
+/// Running count/sum/min/max of transaction amounts.
+///
+/// Maintained incrementally while processing so the memory footprint is constant.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AmountStats {
+    pub count: u64,
+    pub sum: Decimal,
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+}
+
+impl AmountStats {
+    /// Adds a new amount to the statistics. The sum saturates instead of overflowing.
+    pub fn record(&mut self, amount: Decimal) {
+        self.count += 1;
+        self.sum = self.sum.saturating_add(amount);
+        self.min = Some(self.min.map_or(amount, |min| min.min(amount)));
+        self.max = Some(self.max.map_or(amount, |max| max.max(amount)));
+    }
+
+    /// Returns the mean of the recorded amounts, or `None` if no amount was recorded.
+    pub fn mean(&self) -> Option<Decimal> {
+        if self.count == 0 {
+            None
+        } else {
+            self.sum.checked_div(Decimal::from(self.count))
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+/// Running min/max/avg of processing durations for a single transaction type.
+///
+/// Samples are accumulated incrementally so the memory footprint is constant
+/// regardless of the number of processed transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TimingStats {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl TimingStats {
+    /// Adds a new duration sample to the statistics.
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.count == 0 || elapsed < self.min {
+            self.min = elapsed;
+        }
+        if elapsed > self.max {
+            self.max = elapsed;
+        }
+        self.total += elapsed;
+        self.count += 1;
+    }
+
+    /// Returns the average duration of the recorded samples, or `Duration::ZERO` if none were recorded.
+    pub fn avg(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64)
+        }
+    }
+}
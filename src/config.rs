@@ -0,0 +1,651 @@
+use crate::datastr::transaction::{ClientId, TransactionType, TxId, TxKey};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+/// Policy applied when an operation would drive an account `total` below zero.
+///
+/// In this model `available` and `held` can legitimately go negative (e.g. disputing a deposit whose
+/// funds were already withdrawn), while a negative `total` is almost always the sign of corrupted input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeTotalPolicy {
+    /// The operation is applied (default, historical behavior).
+    #[default]
+    Allow,
+    /// The operation is rejected with `EngineError::NegativeTotal` and the account is left unchanged.
+    Reject,
+}
+
+/// Policy applied when a resolve (or, with `Strict`, a dispute) would drive the account `held`
+/// funds below zero.
+///
+/// `held` goes negative disputing a withdrawal; resolving a disputed deposit while such a
+/// withdrawal is still disputed can overdraw it further.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeHeldPolicy {
+    /// The resolve is applied (default, historical behavior).
+    #[default]
+    Allow,
+    /// A resolve lowering `held` below zero is rejected with `EngineError::NegativeHeld` and the
+    /// account is left unchanged. Resolves raising a negative `held` (e.g. the resolve of a
+    /// disputed withdrawal) are still accepted.
+    Reject,
+    /// As `Reject`, and a dispute driving `held` below zero (the dispute of a withdrawal, which
+    /// would claw back funds the client already took) is rejected as well: `held` never goes
+    /// negative.
+    Strict,
+}
+
+/// Policy applied to disputes referring to transactions loaded from a previous session
+/// (see `load_from_previous_session_csvs`), as opposed to the ones processed in the current session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoricalDisputePolicy {
+    /// Historical transactions can be disputed (default, historical behavior).
+    #[default]
+    Allow,
+    /// Disputes on historical transactions are rejected with `EngineError::HistoricalTransaction`.
+    /// Resolves and chargebacks of historical transactions already disputed are still accepted.
+    Reject,
+}
+
+/// Policy applied to resolves referring to a transaction that is not disputed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolvePolicy {
+    /// The resolve fails with `EngineError::TransactionNotDisputed` (default, historical behavior).
+    #[default]
+    Error,
+    /// The resolve is a successful no-op, for upstreams sending resolves idempotently.
+    IgnoreIfNotDisputed,
+}
+
+/// Order of the checks performed by a deposit on a locked account with a repeated tx id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositCheckOrder {
+    /// The repeated tx id is checked first (default, historical behavior): such a deposit fails
+    /// with `EngineError::TransactionRepeated`, also on a nonexistent account, which is then
+    /// not created.
+    #[default]
+    RepeatedFirst,
+    /// The account lock is checked first: such a deposit fails with `EngineError::AccountLocked`.
+    LockedFirst,
+}
+
+/// Transactions accepted on a locked account (frozen or charged back).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockedAccountPolicy {
+    /// Every transaction fails with `EngineError::AccountLocked` (default, historical behavior).
+    #[default]
+    Block,
+    /// Disputes, resolves and chargebacks are still applied, so that the funds held by the other
+    /// disputes of a charged back account can be released; deposits, withdrawals, holds,
+    /// releases and reversals are still rejected. A fully charged back transaction cannot be
+    /// disputed, resolved or charged back again (`EngineError::AlreadyChargedBack`).
+    AllowDisputes,
+}
+
+/// Scope in which a transaction id identifies a transaction, i.e. the key of the transaction log.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxUniqueness {
+    /// Tx ids are unique across all clients (default, historical behavior): a deposit reusing the
+    /// tx id of another client fails with `EngineError::TransactionRepeated`.
+    #[default]
+    Global,
+    /// Tx ids are only unique per client: transactions are keyed on the `(client, tx)` pair, and
+    /// a dispute/resolve/chargeback refers to the transaction with its tx id of the same client.
+    PerClient,
+}
+
+impl TxUniqueness {
+    /// Returns the transaction log key of the transaction `tx` of `client`.
+    #[inline]
+    pub fn key(self, client: ClientId, tx: TxId) -> TxKey {
+        match self {
+            TxUniqueness::Global => TxKey::global(tx),
+            TxUniqueness::PerClient => TxKey::per_client(client, tx),
+        }
+    }
+}
+
+/// What a deposit does when it would create an account beyond `EngineConfig::max_accounts`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountCapacityPolicy {
+    /// The deposit fails with `EngineError::AccountCapacityReached` (default).
+    #[default]
+    Reject,
+    /// The deposit is credited to the shared overflow account of the given client id (e.g.
+    /// `{ overflow = 65535 }` in the config file), which is created even beyond the limit. The
+    /// transaction is logged under the overflow client, so it can only be disputed by it.
+    Overflow(ClientId),
+    /// The deposit is skipped and reported with `EngineError::DepositDropped`, telling it from the
+    /// deposits rejected with `AccountCapacityReached`.
+    Drop,
+}
+
+/// What a dump does when the transaction log and the accounts disagree on the held funds, see
+/// `Engine::held_mismatches`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreDumpVerification {
+    /// The dump is written without any check (default).
+    #[default]
+    Off,
+    /// The dump is written anyway and the mismatches are kept as warnings for the caller, see
+    /// `Engine::pre_dump_warnings`.
+    Warn,
+    /// The dump fails instead of persisting the corrupted state further.
+    Strict,
+}
+
+/// Dialect of the transactions CSV input. A UTF-8 byte order mark at the start of the input is
+/// always stripped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CsvDialect {
+    /// Field delimiter (`b','` by default), e.g. `b'\t'` for tab-separated files. In the config
+    /// file, a single ASCII character, e.g. `delimiter = "\t"`.
+    #[serde(deserialize_with = "deserialize_delimiter")]
+    pub delimiter: u8,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self { delimiter: b',' }
+    }
+}
+
+impl CsvDialect {
+    /// Returns the dialect of tab-separated files.
+    pub fn tab_separated() -> Self {
+        Self { delimiter: b'\t' }
+    }
+}
+
+/// Which transactions can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    /// Whether withdrawals can be disputed (`true` by default): the dispute of a withdrawal
+    /// moves its amount from the held to the available funds, driving `held` negative. When
+    /// `false`, only deposits are disputable and the dispute of a withdrawal fails with
+    /// `EngineError::WithdrawalNotDisputable`.
+    pub allow_withdrawal_disputes: bool,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        Self {
+            allow_withdrawal_disputes: true,
+        }
+    }
+}
+
+/// Deserializes a delimiter from a single ASCII character.
+fn deserialize_delimiter<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u8, D::Error> {
+    let delimiter = String::deserialize(deserializer)?;
+    match delimiter.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(serde::de::Error::custom(format!(
+            "invalid delimiter {delimiter:?}, expected a single ASCII character"
+        ))),
+    }
+}
+
+/// Deserializes a `transaction_fee`, rejecting negative fees (they would credit the client on
+/// every withdrawal).
+fn deserialize_transaction_fee<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Decimal, D::Error> {
+    let fee = <Decimal as Deserialize>::deserialize(deserializer)?;
+    if fee < Decimal::ZERO {
+        return Err(serde::de::Error::custom(format!(
+            "invalid transaction fee {fee}, expected a non-negative amount"
+        )));
+    }
+    Ok(fee)
+}
+
+/// Textual representation of booleans (e.g. the account `locked` column) in CSV dumps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoolFormat {
+    /// `true` / `false` (default).
+    #[default]
+    TrueFalse,
+    /// `1` / `0`.
+    OneZero,
+    /// `Y` / `N`.
+    YesNo,
+}
+
+impl BoolFormat {
+    /// Returns the representation of `value` in this format.
+    pub fn format(&self, value: bool) -> &'static str {
+        match (self, value) {
+            (BoolFormat::TrueFalse, true) => "true",
+            (BoolFormat::TrueFalse, false) => "false",
+            (BoolFormat::OneZero, true) => "1",
+            (BoolFormat::OneZero, false) => "0",
+            (BoolFormat::YesNo, true) => "Y",
+            (BoolFormat::YesNo, false) => "N",
+        }
+    }
+}
+
+/// Representation of amounts in input streams and dumps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountUnits {
+    /// Decimal amounts, e.g. `10.50` (default).
+    #[default]
+    Decimal,
+    /// Integer number of minor units (cents, satoshis, ...) at the given scale,
+    /// e.g. `1050` at scale 2 is `10.50`.
+    Minor(u32),
+}
+
+impl AmountUnits {
+    /// Converts an amount expressed in these units into a decimal amount.
+    ///
+    /// Returns `None` if a minor-unit amount is not an integer or the scale exceeds the
+    /// `Decimal` maximum (28).
+    pub fn units_to_decimal(self, amount: Decimal) -> Option<Decimal> {
+        match self {
+            AmountUnits::Decimal => Some(amount),
+            AmountUnits::Minor(scale) => {
+                if !amount.fract().is_zero() {
+                    return None;
+                }
+                let mut value = amount.trunc();
+                value.rescale(0);
+                value.set_scale(scale).ok()?;
+                Some(value)
+            }
+        }
+    }
+
+    /// Converts a decimal amount into these units, rounding minor units to the nearest
+    /// integer (midpoint away from zero).
+    ///
+    /// Returns `None` if the conversion overflows.
+    pub fn decimal_to_units(self, amount: Decimal) -> Option<Decimal> {
+        match self {
+            AmountUnits::Decimal => Some(amount),
+            AmountUnits::Minor(scale) => {
+                let factor = Decimal::from_i128_with_scale(10i128.checked_pow(scale)?, 0);
+                amount
+                    .checked_mul(factor)
+                    .map(|v| v.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero))
+            }
+        }
+    }
+}
+
+/// Rounding strategy, named as on the command line (e.g. `half-even`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Midpoint away from zero, e.g. `1.25` to `1.3` (default).
+    #[default]
+    HalfUp,
+    /// Midpoint to the nearest even digit (banker's rounding), e.g. `1.25` to `1.2`.
+    HalfEven,
+    /// Midpoint toward zero, e.g. `1.25` to `1.2`.
+    HalfDown,
+    /// Toward zero (truncation).
+    Down,
+    /// Away from zero.
+    Up,
+    /// Toward negative infinity.
+    Floor,
+    /// Toward positive infinity.
+    Ceiling,
+}
+
+impl Rounding {
+    /// All the strategies.
+    pub const ALL: [Rounding; 7] = [
+        Rounding::HalfUp,
+        Rounding::HalfEven,
+        Rounding::HalfDown,
+        Rounding::Down,
+        Rounding::Up,
+        Rounding::Floor,
+        Rounding::Ceiling,
+    ];
+
+    /// Returns the name of the strategy.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rounding::HalfUp => "half-up",
+            Rounding::HalfEven => "half-even",
+            Rounding::HalfDown => "half-down",
+            Rounding::Down => "down",
+            Rounding::Up => "up",
+            Rounding::Floor => "floor",
+            Rounding::Ceiling => "ceiling",
+        }
+    }
+
+    /// Parses the name of a strategy, e.g. `half-even`.
+    ///
+    /// # Errors
+    /// - If the name is not a known strategy.
+    pub fn parse(name: &str) -> Result<Rounding, String> {
+        let name = name.trim();
+        Rounding::ALL
+            .into_iter()
+            .find(|rounding| rounding.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown rounding `{name}` (expected half-up, half-even, half-down, down, up, floor or ceiling)"
+                )
+            })
+    }
+
+    /// Returns the `rust_decimal` strategy.
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfDown => RoundingStrategy::MidpointTowardZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+            Rounding::Floor => RoundingStrategy::ToNegativeInfinity,
+            Rounding::Ceiling => RoundingStrategy::ToPositiveInfinity,
+        }
+    }
+}
+
+/// Column of the accounts CSV dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountColumn {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+impl AccountColumn {
+    /// All the columns, in the default dump order.
+    pub const ALL: [AccountColumn; 5] = [
+        AccountColumn::Client,
+        AccountColumn::Available,
+        AccountColumn::Held,
+        AccountColumn::Total,
+        AccountColumn::Locked,
+    ];
+
+    /// Returns the header name of the column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountColumn::Client => "client",
+            AccountColumn::Available => "available",
+            AccountColumn::Held => "held",
+            AccountColumn::Total => "total",
+            AccountColumn::Locked => "locked",
+        }
+    }
+
+    /// Parses a comma-separated list of column names, e.g. `client,total,locked`.
+    ///
+    /// # Errors
+    /// - If a name is not a known column (e.g. in an empty list) or a column is repeated.
+    pub fn parse_list(list: &str) -> Result<Vec<AccountColumn>, String> {
+        let mut columns = Vec::new();
+        for name in list.split(',').map(str::trim) {
+            let column = AccountColumn::ALL
+                .into_iter()
+                .find(|column| column.name() == name)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown account column `{name}` (expected client, available, held, total or locked)"
+                    )
+                })?;
+            if columns.contains(&column) {
+                return Err(format!("Account column `{name}` is repeated"));
+            }
+            columns.push(column);
+        }
+        Ok(columns)
+    }
+}
+
+/// Default number of records written between two flushes of the async accounts dump.
+pub const DEFAULT_DUMP_FLUSH_EVERY: usize = 1000;
+
+/// Output options applied when dumping the engine state.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DumpOptions {
+    pub locked_format: BoolFormat,
+    /// Units of the `available`, `held` and `total` columns.
+    pub amount_units: AmountUnits,
+    /// Skips the accounts with zero `available`, `held` and `total` that are not locked.
+    pub omit_zero: bool,
+    /// Columns of the accounts CSV dump, in order (`None`, the default, is all of them).
+    pub columns: Option<Vec<AccountColumn>>,
+    /// Number of decimal places of the dumped amounts, padded with zeros if needed (`None`, the
+    /// default, dumps the amounts as processed).
+    pub scale: Option<u32>,
+    /// Rounding applied to the dumped amounts when a `scale` is set.
+    pub rounding: Rounding,
+    /// Number of records the async accounts CSV dump writes between two flushes of its writer
+    /// (`0` flushes only once all the accounts are written).
+    pub flush_every: usize,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            locked_format: BoolFormat::default(),
+            amount_units: AmountUnits::default(),
+            omit_zero: false,
+            columns: None,
+            scale: None,
+            rounding: Rounding::default(),
+            flush_every: DEFAULT_DUMP_FLUSH_EVERY,
+        }
+    }
+}
+
+impl DumpOptions {
+    /// Converts `amount` into its dumped value: rounded to `scale` decimal places with
+    /// `rounding`, if a scale is set, then converted into `amount_units`.
+    ///
+    /// Returns `None` if the conversion overflows.
+    pub fn dump_amount(&self, amount: Decimal) -> Option<Decimal> {
+        let amount = match self.scale {
+            Some(scale) => {
+                let mut rounded = amount.round_dp_with_strategy(scale, self.rounding.strategy());
+                rounded.rescale(scale);
+                rounded
+            }
+            None => amount,
+        };
+        self.amount_units.decimal_to_units(amount)
+    }
+
+    /// Returns the columns of the accounts CSV dump, in order.
+    pub fn account_columns(&self) -> &[AccountColumn] {
+        self.columns.as_deref().unwrap_or(&AccountColumn::ALL)
+    }
+}
+
+/// Configuration shared by `Engine` and `AsyncEngine`.
+///
+/// The default configuration reproduces the historical behavior of the engines.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Number of decimal places the deposit/withdrawal amounts are rounded to (with `rounding`)
+    /// before being processed.
+    pub precision: u32,
+    /// Rounding of the amounts to `precision` (midpoint away from zero by default).
+    pub rounding: Rounding,
+    pub negative_total_policy: NegativeTotalPolicy,
+    pub historical_dispute_policy: HistoricalDisputePolicy,
+    pub resolve_policy: ResolvePolicy,
+    pub negative_held_policy: NegativeHeldPolicy,
+    pub dispute_policy: DisputePolicy,
+    pub locked_account_policy: LockedAccountPolicy,
+    pub deposit_check_order: DepositCheckOrder,
+    pub tx_uniqueness: TxUniqueness,
+    /// Number of records a dispute/resolve/chargeback referring to an unknown transaction is held
+    /// back for while reading a stream with `Engine` (`0` disables the buffering).
+    pub dispute_reorder_window: usize,
+    /// Number of records of a stream sorted by their `timestamp` column before being processed
+    /// (`None`, the default, processes the records in file order).
+    pub timestamp_ordering: Option<usize>,
+    /// Units of the `amount` column of the processed transactions.
+    pub amount_units: AmountUnits,
+    /// Reads a deposit with a negative amount as a withdrawal of the absolute amount.
+    pub signed_amounts: bool,
+    /// Reports a dispute/resolve/chargeback with an amount referring to an unknown transaction
+    /// as `EngineError::TxIdCollision` instead of `EngineError::TransactionNotFound`.
+    pub detect_tx_id_collisions: bool,
+    /// Flat fee deducted from the client funds on each deposit and withdrawal (`0` disables it,
+    /// negative fees are rejected).
+    #[serde(deserialize_with = "deserialize_transaction_fee")]
+    pub transaction_fee: Decimal,
+    /// Largest amount accepted for a single deposit or withdrawal (`None`, the default, is
+    /// unlimited). Also accepted as `max_transaction_amount` in the config file.
+    #[serde(alias = "max_transaction_amount")]
+    pub max_amount: Option<Decimal>,
+    /// Largest number of accounts: a deposit creating a new account beyond it is handled by
+    /// `account_capacity_policy` (`None`, the default, is unlimited).
+    pub max_accounts: Option<usize>,
+    pub account_capacity_policy: AccountCapacityPolicy,
+    /// Makes re-processing a file already processed by the engine a successful no-op (files are
+    /// recognized by content), for idempotent re-runs of retried jobs.
+    pub idempotent_file_reruns: bool,
+    /// Routes every client to a fixed worker in `Engine::process_slice_parallel`, so that the
+    /// transactions of a client are processed in input order (`Engine` only).
+    pub per_client_ordering: bool,
+    /// Rejects the deposits of clients that have no account and were not registered (see
+    /// `Engine::register_client`) with `EngineError::UnknownClient`, instead of creating their
+    /// account.
+    pub strict_clients: bool,
+    /// Keeps the input `amount` strings of the logged transactions (`Engine` streams only).
+    pub preserve_raw_amount: bool,
+    /// Types of the records processed when reading a stream, the records of the other types
+    /// being skipped without errors (`None`, the default, processes all of them).
+    pub only_types: Option<HashSet<TransactionType>>,
+    /// Checks that the transaction log and the accounts agree on the held funds before every
+    /// dump (`Engine` only).
+    pub pre_dump_verification: PreDumpVerification,
+    /// Scans a whole stream before processing it and refuses it, processing nothing, if a
+    /// dispute/resolve/chargeback refers to no earlier deposit/withdrawal (`Engine` only).
+    pub reference_precheck: bool,
+    /// Dialect of the transactions CSV input (comma-separated by default).
+    pub csv_dialect: CsvDialect,
+    /// Wall-clock budget of a stream processing call: once exceeded, the remaining records are
+    /// left unread and the call reports how many records were processed. Not read from the
+    /// config file (see `--max-duration`).
+    #[serde(skip)]
+    pub max_duration: Option<Duration>,
+    pub dump: DumpOptions,
+}
+
+/// Default `EngineConfig::precision`: amounts are processed with four decimal places.
+pub const DEFAULT_PRECISION: u32 = 4;
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            rounding: Rounding::default(),
+            negative_total_policy: NegativeTotalPolicy::default(),
+            historical_dispute_policy: HistoricalDisputePolicy::default(),
+            resolve_policy: ResolvePolicy::default(),
+            negative_held_policy: NegativeHeldPolicy::default(),
+            dispute_policy: DisputePolicy::default(),
+            locked_account_policy: LockedAccountPolicy::default(),
+            deposit_check_order: DepositCheckOrder::default(),
+            tx_uniqueness: TxUniqueness::default(),
+            dispute_reorder_window: 0,
+            timestamp_ordering: None,
+            amount_units: AmountUnits::default(),
+            signed_amounts: false,
+            detect_tx_id_collisions: false,
+            transaction_fee: Decimal::ZERO,
+            max_amount: None,
+            max_accounts: None,
+            account_capacity_policy: AccountCapacityPolicy::default(),
+            idempotent_file_reruns: false,
+            per_client_ordering: false,
+            strict_clients: false,
+            preserve_raw_amount: false,
+            only_types: None,
+            pre_dump_verification: PreDumpVerification::Off,
+            reference_precheck: false,
+            csv_dialect: CsvDialect::default(),
+            max_duration: None,
+            dump: DumpOptions::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Returns whether the records of type `ty` read from a stream are processed, see
+    /// `only_types`.
+    pub fn accepts_type(&self, ty: &TransactionType) -> bool {
+        self.only_types
+            .as_ref()
+            .is_none_or(|types| types.contains(ty))
+    }
+}
+
+/// Settings read from a TOML config file (`--config=PATH` or `--config PATH`), e.g.:
+///
+/// ```toml
+/// worker_threads = 4
+/// shards = 256
+///
+/// [engine]
+/// precision = 2
+/// negative_total_policy = "reject"
+/// amount_units = { minor = 2 }
+/// transaction_fee = "0.5"
+///
+/// [engine.dump]
+/// locked_format = "one_zero"
+/// ```
+///
+/// Every setting is optional and defaults to the historical behavior; unknown settings are
+/// rejected.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Worker threads of the async runtime (a single-threaded runtime is used if not set).
+    pub worker_threads: Option<NonZeroUsize>,
+    /// Number of shards of the async engine maps (`NUM_SHARDS` if not set), see
+    /// `AsyncEngine::with_shards`.
+    pub shards: Option<NonZeroUsize>,
+    pub engine: EngineConfig,
+}
+
+impl ConfigFile {
+    /// Parses a TOML config.
+    pub fn from_toml_str(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Reads and parses the TOML config file at `path`.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::from_toml_str(&std::fs::read_to_string(path)?)?)
+    }
+}
@@ -5,9 +5,11 @@ use serde::{Deserialize, Serialize};
 use std::io::Write;
 
 use super::{
-    deser::{deserialize_account_amount, deserialize_trimmed_string},
+    deser::{deserialize_account_amount, deserialize_trimmed_bool},
     transaction::ClientId,
 };
+use crate::basics::merkle;
+use crate::config::{AccountColumn, DumpOptions};
 
 // Represents an account
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
@@ -18,8 +20,101 @@ pub struct Account {
     pub held: Decimal,
     #[serde(deserialize_with = "deserialize_account_amount")]
     pub total: Decimal,
-    #[serde(deserialize_with = "deserialize_trimmed_string::<bool,_>")]
+    #[serde(deserialize_with = "deserialize_trimmed_bool")]
     pub locked: bool,
+    /// Approved credit line: withdrawals can drive `available` down to `-overdraft_limit`.
+    #[serde(default, deserialize_with = "deserialize_account_amount")]
+    pub overdraft_limit: Decimal,
+}
+
+/// Header of the optional trailing column of an accounts file holding `Account::overdraft_limit`.
+pub const OVERDRAFT_LIMIT_COLUMN: &str = "overdraft_limit";
+
+/// Parses an `overdraft_limit` field, an empty or missing field meaning no overdraft.
+pub fn parse_overdraft_limit(field: Option<&str>) -> Option<Decimal> {
+    match field.map(str::trim) {
+        None | Some("") => Some(Decimal::ZERO),
+        Some(limit) => limit.parse().ok(),
+    }
+}
+
+/// Change of the balances of an account produced by a processed transaction, see
+/// `Engine::subscribe_deltas`. Summing the deltas of a client gives its balances.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AccountDelta {
+    pub client: ClientId,
+    pub delta_available: Decimal,
+    pub delta_held: Decimal,
+    pub delta_total: Decimal,
+}
+
+/// Account state written by the NDJSON dumps, see `write_account_ndjson`.
+#[derive(Serialize)]
+struct AccountRecord {
+    client: ClientId,
+    #[serde(with = "rust_decimal::serde::arbitrary_precision")]
+    available: Decimal,
+    #[serde(with = "rust_decimal::serde::arbitrary_precision")]
+    held: Decimal,
+    #[serde(with = "rust_decimal::serde::arbitrary_precision")]
+    total: Decimal,
+    locked: bool,
+}
+
+/// Account state written by the JSON Lines dumps, see `write_account_json`.
+#[derive(Serialize)]
+struct AccountStringRecord {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl AccountDelta {
+    /// Returns the change of the account of `client` from `before` to `after`.
+    pub fn between(client: ClientId, before: &Account, after: &Account) -> Self {
+        AccountDelta {
+            client,
+            delta_available: after.available - before.available,
+            delta_held: after.held - before.held,
+            delta_total: after.total - before.total,
+        }
+    }
+}
+
+impl Account {
+    /// Returns `true` if the account is not locked and all its balances are zero,
+    /// e.g. a pass-through client whose deposits were fully withdrawn.
+    pub fn is_zero(&self) -> bool {
+        !self.locked && self.available.is_zero() && self.held.is_zero() && self.total.is_zero()
+    }
+}
+
+/// Returns the Merkle root (see `basics::merkle`) of `accounts`, sorted by client.
+///
+/// Each leaf is the account encoded as `client,available,held,total,locked`, with the amounts
+/// normalized (`1.50` and `1.5` are the same balance), so that the root depends only on the
+/// state of the accounts, not on the order they are given in or on the scale of the amounts.
+pub fn accounts_merkle_root(mut accounts: Vec<(ClientId, Account)>) -> merkle::Hash {
+    accounts.sort_unstable_by_key(|(client, _)| *client);
+    let leaves = accounts
+        .iter()
+        .map(|(client, account)| {
+            merkle::leaf_hash(
+                format!(
+                    "{},{},{},{},{}",
+                    client,
+                    account.available.normalize(),
+                    account.held.normalize(),
+                    account.total.normalize(),
+                    account.locked
+                )
+                .as_bytes(),
+            )
+        })
+        .collect();
+    merkle::merkle_root(leaves)
 }
 
 /// Writes the final state of all accounts to stdout as a CSV file.
@@ -38,21 +133,195 @@ pub fn serialize_account_balances_csv<W: Write>(
     accounts: &DashMap<ClientId, Account>,
     writer: W,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut csv_writer = Writer::from_writer(writer);
+    serialize_account_balances_csv_with_options(accounts, writer, &DumpOptions::default())
+}
 
+/// Writes the final state of all accounts as CSV records, formatted according to `options`.
+///
+/// Same as `serialize_account_balances_csv`, but e.g. only the `options.columns` are written, the
+/// `locked` column using `options.locked_format`, the amounts in `options.amount_units`, and zero
+/// accounts are skipped if `options.omit_zero` is set (see `account_csv_record`).
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any errors occur while writing.
+pub fn serialize_account_balances_csv_with_options<W: Write>(
+    accounts: &DashMap<ClientId, Account>,
+    writer: W,
+    options: &DumpOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv_writer = Writer::from_writer(writer);
     for entry in accounts.iter() {
         let client_id = *entry.key();
         let account = entry.value();
+        if options.omit_zero && account.is_zero() {
+            continue;
+        }
 
         // Write a record to the CSV file
-        csv_writer.serialize((
-            client_id,
-            account.available,
-            account.held,
-            account.total,
-            account.locked,
-        ))?;
+        csv_writer.write_record(account_csv_record(client_id, account, options)?)?;
     }
     csv_writer.flush()?;
     Ok(())
 }
+
+/// Returns the header of the accounts CSV dump: the names of the `options` columns.
+pub fn account_csv_header(options: &DumpOptions) -> Vec<&'static str> {
+    options
+        .account_columns()
+        .iter()
+        .map(AccountColumn::name)
+        .collect()
+}
+
+/// Returns the fields of the CSV record of the account of `client`, in the `options` columns:
+/// the amounts as converted by `options.dump_amount` and `locked` in `options.locked_format`.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if an amount cannot be converted to the dump units.
+pub fn account_csv_record(
+    client: ClientId,
+    account: &Account,
+    options: &DumpOptions,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let units = |amount: Decimal| {
+        options
+            .dump_amount(amount)
+            .map(|amount| amount.to_string())
+            .ok_or("Amount overflow converting to the dump units")
+    };
+    options
+        .account_columns()
+        .iter()
+        .map(|column| {
+            Ok(match column {
+                AccountColumn::Client => client.to_string(),
+                AccountColumn::Available => units(account.available)?,
+                AccountColumn::Held => units(account.held)?,
+                AccountColumn::Total => units(account.total)?,
+                AccountColumn::Locked => options.locked_format.format(account.locked).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Writes the account of `client` as a JSON object on a single line (one NDJSON record), e.g.
+/// `{"client":1,"available":1.5,"held":0,"total":1.5,"locked":false}`.
+///
+/// The amounts are written in `options.amount_units` as JSON numbers, without loss of precision;
+/// `locked` is always a JSON boolean.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if an amount cannot be converted or the writing fails.
+pub fn write_account_ndjson<W: Write>(
+    mut writer: W,
+    client: ClientId,
+    account: &Account,
+    options: &DumpOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let units = |amount: Decimal| {
+        options
+            .dump_amount(amount)
+            .ok_or("Amount overflow converting to the dump units")
+    };
+    let record = AccountRecord {
+        client,
+        available: units(account.available)?,
+        held: units(account.held)?,
+        total: units(account.total)?,
+        locked: account.locked,
+    };
+    serde_json::to_writer(&mut writer, &record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Writes the account of `client` as a JSON object on a single line (one JSON Lines record)
+/// with the amounts as strings, e.g.
+/// `{"client":1,"available":"5.0000","held":"0.0000","total":"5.0000","locked":false}`.
+///
+/// Unlike `write_account_ndjson`, the amounts survive consumers parsing JSON numbers as floats
+/// and the record deserializes back into an `Account`.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if an amount cannot be converted or the writing fails.
+pub fn write_account_json<W: Write>(
+    mut writer: W,
+    client: ClientId,
+    account: &Account,
+    options: &DumpOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let units = |amount: Decimal| {
+        options
+            .dump_amount(amount)
+            .map(|amount| amount.to_string())
+            .ok_or("Amount overflow converting to the dump units")
+    };
+    let record = AccountStringRecord {
+        client,
+        available: units(account.available)?,
+        held: units(account.held)?,
+        total: units(account.total)?,
+        locked: account.locked,
+    };
+    serde_json::to_writer(&mut writer, &record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Writes one account as a line of `W`, e.g. `write_account_ndjson` or `write_account_json`.
+pub type AccountLineWriter<W> =
+    fn(&mut W, ClientId, &Account, &DumpOptions) -> Result<(), Box<dyn std::error::Error>>;
+
+/// Writes every account with `write_line`, skipping the zero accounts if `options.omit_zero`
+/// is set.
+fn serialize_account_lines<W: Write>(
+    accounts: &DashMap<ClientId, Account>,
+    mut writer: W,
+    options: &DumpOptions,
+    write_line: AccountLineWriter<W>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in accounts.iter() {
+        if options.omit_zero && entry.value().is_zero() {
+            continue;
+        }
+        write_line(&mut writer, *entry.key(), entry.value(), options)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the final state of all accounts as newline-delimited JSON, one object per account
+/// (see `write_account_ndjson`), skipping the zero accounts if `options.omit_zero` is set.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any errors occur while writing.
+pub fn serialize_account_balances_ndjson_with_options<W: Write>(
+    accounts: &DashMap<ClientId, Account>,
+    writer: W,
+    options: &DumpOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    serialize_account_lines(
+        accounts,
+        writer,
+        options,
+        |writer, client, account, options| write_account_ndjson(writer, client, account, options),
+    )
+}
+
+/// Writes the final state of all accounts as JSON Lines with the amounts as strings, one object
+/// per account (see `write_account_json`), skipping the zero accounts if `options.omit_zero` is set.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any errors occur while writing.
+pub fn serialize_account_balances_json_with_options<W: Write>(
+    accounts: &DashMap<ClientId, Account>,
+    writer: W,
+    options: &DumpOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    serialize_account_lines(
+        accounts,
+        writer,
+        options,
+        |writer, client, account, options| write_account_json(writer, client, account, options),
+    )
+}
@@ -0,0 +1,158 @@
+//! Parquet export of accounts and transactions (feature `parquet`).
+//!
+//! Amounts are written as `Decimal128(38, scale)`, where `scale` is the largest scale of the
+//! exported amounts, so that no amount is rounded.
+
+use std::fs::File;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Decimal128Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use rust_decimal::Decimal;
+
+use super::account::Account;
+use super::transaction::{ClientId, Transaction};
+
+/// Precision of the `Decimal128` amount columns (the maximum).
+const AMOUNT_PRECISION: u8 = 38;
+
+/// Returns the largest scale of `amounts` (0 if there are none).
+fn amount_scale<'a>(amounts: impl Iterator<Item = &'a Decimal>) -> u32 {
+    amounts.map(Decimal::scale).max().unwrap_or(0)
+}
+
+/// Builds a `Decimal128(38, scale)` column from `amounts`.
+///
+/// # Errors
+/// - If an amount cannot be represented at `scale` in 38 digits.
+fn decimal_column(
+    amounts: impl Iterator<Item = Option<Decimal>>,
+    scale: u32,
+) -> Result<ArrayRef, Box<dyn std::error::Error>> {
+    let values = amounts
+        .map(|amount| {
+            amount
+                .map(|amount| {
+                    10i128
+                        .checked_pow(scale - amount.scale())
+                        .and_then(|factor| amount.mantissa().checked_mul(factor))
+                        .ok_or("Amount overflow converting to Decimal128")
+                })
+                .transpose()
+        })
+        .collect::<Result<Decimal128Array, _>>()?
+        .with_precision_and_scale(AMOUNT_PRECISION, scale as i8)?;
+    Ok(Arc::new(values))
+}
+
+/// Writes `batch` to a new Parquet file at `path`.
+fn write_batch(path: &str, batch: &RecordBatch) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes `accounts` to a Parquet file at `path` with columns
+/// `client` (UInt16), `available`, `held`, `total` (Decimal128) and `locked` (Boolean).
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if an amount cannot be converted or the file cannot be written.
+pub fn write_accounts_parquet(
+    accounts: &[(ClientId, Account)],
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scale = amount_scale(
+        accounts
+            .iter()
+            .flat_map(|(_, a)| [&a.available, &a.held, &a.total]),
+    );
+    let amount_type = DataType::Decimal128(AMOUNT_PRECISION, scale as i8);
+    let schema = Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", amount_type.clone(), false),
+        Field::new("held", amount_type.clone(), false),
+        Field::new("total", amount_type, false),
+        Field::new("locked", DataType::Boolean, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(UInt16Array::from_iter_values(
+                accounts.iter().map(|(client, _)| *client),
+            )),
+            decimal_column(accounts.iter().map(|(_, a)| Some(a.available)), scale)?,
+            decimal_column(accounts.iter().map(|(_, a)| Some(a.held)), scale)?,
+            decimal_column(accounts.iter().map(|(_, a)| Some(a.total)), scale)?,
+            Arc::new(BooleanArray::from_iter(
+                accounts.iter().map(|(_, a)| Some(a.locked)),
+            )),
+        ],
+    )?;
+    write_batch(path, &batch)
+}
+
+/// Writes `transactions` to a Parquet file at `path` with columns `type` (Utf8),
+/// `client` (UInt16), `tx` (UInt32), `amount` (nullable Decimal128), `disputed` (Boolean),
+/// `charged_back` (Decimal128, at the scale of `amount`), `reversed` (Boolean) and `released`
+/// (Boolean).
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if an amount cannot be converted or the file cannot be written.
+pub fn write_transactions_parquet(
+    transactions: &[Transaction],
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scale = amount_scale(
+        transactions
+            .iter()
+            .flat_map(|tx| tx.amount.iter().chain([&tx.charged_back])),
+    );
+    let schema = Schema::new(vec![
+        Field::new("type", DataType::Utf8, false),
+        Field::new("client", DataType::UInt16, false),
+        Field::new("tx", DataType::UInt32, false),
+        Field::new(
+            "amount",
+            DataType::Decimal128(AMOUNT_PRECISION, scale as i8),
+            true,
+        ),
+        Field::new("disputed", DataType::Boolean, false),
+        Field::new(
+            "charged_back",
+            DataType::Decimal128(AMOUNT_PRECISION, scale as i8),
+            false,
+        ),
+        Field::new("reversed", DataType::Boolean, false),
+        Field::new("released", DataType::Boolean, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from_iter_values(
+                transactions.iter().map(|tx| tx.ty.to_string()),
+            )),
+            Arc::new(UInt16Array::from_iter_values(
+                transactions.iter().map(|tx| tx.client),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                transactions.iter().map(|tx| tx.tx),
+            )),
+            decimal_column(transactions.iter().map(|tx| tx.amount), scale)?,
+            Arc::new(BooleanArray::from_iter(
+                transactions.iter().map(|tx| Some(tx.disputed)),
+            )),
+            decimal_column(transactions.iter().map(|tx| Some(tx.charged_back)), scale)?,
+            Arc::new(BooleanArray::from_iter(
+                transactions.iter().map(|tx| Some(tx.reversed)),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                transactions.iter().map(|tx| Some(tx.released)),
+            )),
+        ],
+    )?;
+    write_batch(path, &batch)
+}
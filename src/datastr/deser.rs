@@ -1,12 +1,22 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
 use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{de, Deserialize, Deserializer};
 use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DeserError {
+    #[error("Amount cannot be an empty string")]
+    EmptyAmount,
+    #[error("Invalid amount '{0}'")]
+    InvalidAmount(String),
+}
 
 /// Deserialize an amount from a CSV string.
 ///
 /// If the string is empty, the result is `None`. Otherwise, the amount is parsed
-/// from the string and rounded to four decimal places using the midpoint away
-/// from zero rounding strategy. If parsing fails, an error is returned.
+/// from the string as is: the engines round it to their configured precision
+/// (`EngineConfig::precision`) when processing it. If parsing fails, an error is returned.
 pub fn deserialize_amount<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
 where
     D: Deserializer<'de>,
@@ -14,17 +24,41 @@ where
     let s: Option<String> = Option::deserialize(deserializer)?;
     match s {
         Some(ref v) if !v.trim().is_empty() => Decimal::from_str(v.trim())
-            .map(|mut d| {
-                d = d.round_dp_with_strategy(4, RoundingStrategy::MidpointAwayFromZero);
-                Some(d)
-            })
+            .map(Some)
             .map_err(de::Error::custom),
         _ => Ok(None),
     }
 }
 
-/// Deserialize an amount from a CSV string and round it to four decimal places using the midpoint away
-/// from zero rounding strategy. If the string is empty, return an error instead of Option::None.
+/// Parses the input amount `raw` and rounds it to `precision` decimal places with `strategy`,
+/// as the engines do before processing it (see `EngineConfig::precision`), e.g. to preview
+/// whether an amount will be altered: `1.12345` becomes `1.1235` with precision 4 and
+/// `RoundingStrategy::MidpointAwayFromZero`. Amounts with at most `precision` decimal places are
+/// returned unchanged.
+///
+/// # Errors
+/// - `EmptyAmount`: If `raw` is empty (surrounding whitespace ignored).
+/// - `InvalidAmount`: If `raw` is not a decimal number.
+pub fn normalize_amount(
+    raw: &str,
+    precision: u32,
+    strategy: RoundingStrategy,
+) -> Result<Decimal, DeserError> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Err(DeserError::EmptyAmount);
+    }
+    let amount = Decimal::from_str(raw).map_err(|_| DeserError::InvalidAmount(raw.to_string()))?;
+    if amount.scale() > precision {
+        Ok(amount.round_dp_with_strategy(precision, strategy))
+    } else {
+        Ok(amount)
+    }
+}
+
+/// Deserialize an account amount from a CSV string, as is: account dumps are already at the
+/// precision of the engine that wrote them. If the string is empty, return an error instead of
+/// Option::None.
 ///
 /// The input string is trimmed before parsing. If parsing fails, an error is returned.
 pub fn deserialize_account_amount<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
@@ -37,12 +71,7 @@ where
         return Err(de::Error::custom("Amount cannot be an empty string"));
     }
 
-    Decimal::from_str(s.trim())
-        .map(|mut d| {
-            d = d.round_dp_with_strategy(4, RoundingStrategy::MidpointAwayFromZero);
-            d
-        })
-        .map_err(de::Error::custom)
+    Decimal::from_str(s.trim()).map_err(de::Error::custom)
 }
 
 // Helper function to deserialize and trim strings for any type T that can be FromStr
@@ -55,3 +84,83 @@ where
     let s = String::deserialize(deserializer)?;
     T::from_str(s.trim()).map_err(de::Error::custom)
 }
+
+/// Parses a timestamp given either as an ISO-8601 date and time, e.g. `2024-05-01T10:00:00Z`
+/// (UTC if it has no offset), or as an integer number of seconds since the Unix epoch
+/// (surrounding whitespace ignored).
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").map(|t| t.and_utc()))
+        .ok()
+}
+
+/// Deserialize an optional timestamp from a CSV string, see `parse_timestamp`.
+///
+/// If the string is empty, the result is `None`. If parsing fails, an error is returned.
+pub fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    match s {
+        Some(ref v) if !v.trim().is_empty() => parse_timestamp(v)
+            .map(Some)
+            .ok_or_else(|| de::Error::custom(format!("invalid timestamp '{}'", v.trim()))),
+        _ => Ok(None),
+    }
+}
+
+/// Parses a boolean accepting the representations produced by `BoolFormat`:
+/// `true`/`false`, `1`/`0` and `Y`/`N` (case insensitive, surrounding whitespace ignored).
+pub fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "y" => Some(true),
+        "false" | "0" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+/// Deserialize a boolean from a CSV string accepting every representation handled by `parse_bool`,
+/// or from a native boolean (e.g. the `locked` field of a JSON account dump).
+pub fn deserialize_trimmed_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct BoolVisitor;
+
+    impl de::Visitor<'_> for BoolVisitor {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a boolean")
+        }
+
+        fn visit_bool<E: de::Error>(self, v: bool) -> Result<bool, E> {
+            Ok(v)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<bool, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<bool, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<bool, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<bool, E> {
+            parse_bool(s).ok_or_else(|| E::custom(format!("invalid boolean: {}", s.trim())))
+        }
+    }
+
+    // CSV deserializers infer the type of the field from its text, so `1` reaches `visit_u64`.
+    deserializer.deserialize_any(BoolVisitor)
+}
@@ -0,0 +1,87 @@
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EngineError {
+    #[error("Cannot dispute/resolve/chargeback transaction from a different client")]
+    DifferentClient,
+    #[error("Transaction must have an amount")]
+    NoAmount,
+    #[error("Referred Transaction must have an amount")]
+    ReferredTransactionNoAmount,
+    #[error("Deposit amount must be greater than 0")]
+    DepositAmountInvalid,
+    #[error("Withdrawal amount must be greater than 0")]
+    WithdrawalAmountInvalid,
+    #[error("Transaction id already processed in this session - cannot be repeated.")]
+    TransactionRepeated,
+    #[error("Insufficient funds")]
+    InsufficientFunds,
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("Transaction not found")]
+    TransactionNotFound,
+    #[error("Addition overflow")]
+    AdditionOverflow,
+    #[error("Subtraction overflow")]
+    SubtractionOverflow,
+    #[error("Account is locked")]
+    AccountLocked,
+    #[error("Transaction already disputed")]
+    TransactionAlreadyDisputed,
+    #[error("Transaction not disputed")]
+    TransactionNotDisputed,
+    #[error("Operation would drive the account total negative")]
+    NegativeTotal,
+    #[error("Dispute or resolve would drive the account held funds negative")]
+    NegativeHeld,
+    #[error("Transaction loaded from a previous session cannot be disputed")]
+    HistoricalTransaction,
+    #[error("Amount in minor units must be an integer")]
+    InvalidMinorUnits,
+    #[error("Insufficient funds to cover the transaction fee")]
+    InsufficientFundsForFee,
+    #[error("Fees are charged by the engine and cannot be processed as transactions")]
+    FeeNotProcessable,
+    #[error("Transaction id not found and the dispute/resolve/chargeback has an amount - it looks like a new transaction")]
+    TxIdCollision,
+    #[error("Amount exceeds the maximum single-transaction amount")]
+    AmountExceedsLimit,
+    #[error("Hold amount must be greater than 0")]
+    HoldAmountInvalid,
+    #[error("Released transaction is not a hold")]
+    NotAHold,
+    #[error("Hold already released")]
+    HoldAlreadyReleased,
+    #[error("Holds cannot be disputed/resolved/chargedback")]
+    HoldNotDisputable,
+    #[error("Account already frozen")]
+    AccountAlreadyFrozen,
+    #[error("Account not frozen")]
+    AccountNotFrozen,
+    #[error("Maximum number of accounts reached")]
+    AccountCapacityReached,
+    #[error("Deposit dropped: maximum number of accounts reached")]
+    DepositDropped,
+    #[error("Transaction is disputed or an active hold and cannot be pruned")]
+    TransactionUnsettled,
+    #[error("Chargeback amount must be greater than 0")]
+    ChargebackAmountInvalid,
+    #[error("Chargeback amount exceeds the disputed held amount")]
+    ChargebackExceedsHeld,
+    #[error("Withdrawals cannot be disputed")]
+    WithdrawalNotDisputable,
+    #[error("Transaction already reversed")]
+    AlreadyReversed,
+    #[error("Disputed transaction cannot be reversed")]
+    CannotReverseDisputed,
+    #[error("Holds cannot be reversed")]
+    HoldNotReversible,
+    #[error("Transaction already charged back")]
+    AlreadyChargedBack,
+    #[error("Client not registered")]
+    UnknownClient,
+    #[error("Transaction violates the JSON schema: {0}")]
+    SchemaViolation(String),
+    #[error("Transaction applied but not written to the write-ahead log: {0}")]
+    WalWriteFailed(String),
+}
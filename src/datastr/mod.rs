@@ -1,3 +1,6 @@
 pub mod account;
+#[cfg(feature = "parquet")]
+pub mod columnar;
 pub mod deser;
+pub mod error;
 pub mod transaction;
@@ -1,16 +1,55 @@
+use chrono::{DateTime, Utc};
 use csv::Writer;
 use dashmap::DashMap;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::{fmt, io::Write};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::Write,
+};
 use thiserror::Error;
 
-use super::deser::{deserialize_amount, deserialize_trimmed_string};
+use super::deser::{deserialize_amount, deserialize_timestamp, deserialize_trimmed_string};
+use super::error::EngineError;
+use crate::basics::hmap::Shardable;
 
 pub type TxId = u32;
 pub type ClientId = u16;
 
-#[derive(PartialEq, Debug, Clone, Serialize)]
+/// Key of the transaction log: the tx id, qualified by the client id when tx ids are only unique
+/// per client (see `TxUniqueness`).
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct TxKey {
+    /// `None` when tx ids are unique across all clients.
+    pub client: Option<ClientId>,
+    pub tx: TxId,
+}
+
+impl TxKey {
+    /// Key of the transaction `tx` when tx ids are unique across all clients.
+    pub fn global(tx: TxId) -> Self {
+        TxKey { client: None, tx }
+    }
+
+    /// Key of the transaction `tx` of `client` when tx ids are only unique per client.
+    pub fn per_client(client: ClientId, tx: TxId) -> Self {
+        TxKey {
+            client: Some(client),
+            tx,
+        }
+    }
+}
+
+impl Shardable for TxKey {
+    #[inline]
+    fn shard(&self) -> usize {
+        let client = self.client.map_or(0, |client| client as u64 + 1);
+        ((client << 32) | self.tx as u64).shard()
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize)]
 #[serde(rename_all = "lowercase")] // This will convert enum variant names to lowercase for serialization
 pub enum TransactionType {
     Deposit,
@@ -18,6 +57,25 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Authorization hold (e.g. a card pre-auth): moves the amount from the available to the
+    /// held funds, independently of the dispute flow.
+    Hold,
+    /// Release of the hold with the same tx id: moves its amount back to the available funds.
+    /// The logged hold keeps its type and is flagged as `released`.
+    Release,
+    /// Reversal of the deposit or withdrawal with the same tx id, e.g. a mistaken deposit: undoes
+    /// its effect on the available and total funds. The logged transaction keeps its type and is
+    /// flagged as `reversed`, so it cannot be disputed or reversed again.
+    Reversal,
+    /// Compliance freeze of the account of the client: locks it without touching the balances.
+    /// The tx id does not refer to any transaction.
+    Freeze,
+    /// Lifts the lock of the account of the client (including a lock set by a chargeback),
+    /// without touching the balances. The tx id does not refer to any transaction.
+    Unfreeze,
+    /// Fee charged by the engine on a deposit or withdrawal, see `Engine::with_transaction_fee`.
+    /// Never read from an input stream.
+    Fee,
 }
 
 impl fmt::Display for TransactionType {
@@ -28,8 +86,46 @@ impl fmt::Display for TransactionType {
             TransactionType::Dispute => write!(f, "dispute"),
             TransactionType::Resolve => write!(f, "resolve"),
             TransactionType::Chargeback => write!(f, "chargeback"),
+            TransactionType::Hold => write!(f, "hold"),
+            TransactionType::Release => write!(f, "release"),
+            TransactionType::Reversal => write!(f, "reversal"),
+            TransactionType::Freeze => write!(f, "freeze"),
+            TransactionType::Unfreeze => write!(f, "unfreeze"),
+            TransactionType::Fee => write!(f, "fee"),
+        }
+    }
+}
+
+impl TransactionType {
+    /// Parses the name of a type read from an input stream, e.g. `deposit`.
+    pub fn parse(name: &str) -> Option<TransactionType> {
+        match name.trim() {
+            "deposit" => Some(TransactionType::Deposit),
+            "withdrawal" => Some(TransactionType::Withdrawal),
+            "dispute" => Some(TransactionType::Dispute),
+            "resolve" => Some(TransactionType::Resolve),
+            "chargeback" => Some(TransactionType::Chargeback),
+            "hold" => Some(TransactionType::Hold),
+            "release" => Some(TransactionType::Release),
+            "reversal" => Some(TransactionType::Reversal),
+            "freeze" => Some(TransactionType::Freeze),
+            "unfreeze" => Some(TransactionType::Unfreeze),
+            _ => None,
         }
     }
+
+    /// Parses a comma-separated list of type names, e.g. `deposit,withdrawal`.
+    ///
+    /// # Errors
+    /// - If a name is not a known type (e.g. in an empty list).
+    pub fn parse_list(list: &str) -> Result<HashSet<TransactionType>, String> {
+        list.split(',')
+            .map(|name| {
+                TransactionType::parse(name)
+                    .ok_or_else(|| format!("Unknown transaction type `{}`", name.trim()))
+            })
+            .collect()
+    }
 }
 
 // Custom Deserialize implementation for TransactionType
@@ -39,21 +135,12 @@ impl<'de> Deserialize<'de> for TransactionType {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match s.as_str().trim() {
-            "deposit" => Ok(TransactionType::Deposit),
-            "withdrawal" => Ok(TransactionType::Withdrawal),
-            "dispute" => Ok(TransactionType::Dispute),
-            "resolve" => Ok(TransactionType::Resolve),
-            "chargeback" => Ok(TransactionType::Chargeback),
-            _ => Err(serde::de::Error::custom(format!(
-                "Unknown transaction type: {}",
-                s
-            ))),
-        }
+        TransactionType::parse(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("Unknown transaction type: {}", s)))
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub ty: TransactionType,
@@ -61,16 +148,269 @@ pub struct Transaction {
     pub client: u16,
     #[serde(deserialize_with = "deserialize_trimmed_string::<u32,_>")]
     pub tx: u32,
-    #[serde(deserialize_with = "deserialize_amount")]
+    #[serde(default, deserialize_with = "deserialize_amount")]
     pub amount: Option<Decimal>,
     #[serde(default)]
     pub disputed: bool,
+    /// Part of the amount of a logged deposit or withdrawal already charged back (always
+    /// positive): a partial chargeback leaves `amount` unchanged and the rest disputed.
+    #[serde(default)]
+    pub charged_back: Decimal,
+    /// Whether a logged deposit or withdrawal has been reversed, see `TransactionType::Reversal`.
+    #[serde(default)]
+    pub reversed: bool,
+    /// Whether a logged hold has been released, see `TransactionType::Release`.
+    #[serde(default)]
+    pub released: bool,
+    /// Optional `timestamp` column of the input stream, see `Engine::with_timestamp_ordering`.
+    /// Not kept in the transaction log dumps.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+// The fields below `disputed` are only shown when set, so that the messages of the errors
+// (see `TransactionError::processing`) are unchanged for the transactions not using them.
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Transaction");
+        debug
+            .field("ty", &self.ty)
+            .field("client", &self.client)
+            .field("tx", &self.tx)
+            .field("amount", &self.amount)
+            .field("disputed", &self.disputed);
+        if !self.charged_back.is_zero() {
+            debug.field("charged_back", &self.charged_back);
+        }
+        if self.reversed {
+            debug.field("reversed", &self.reversed);
+        }
+        if self.released {
+            debug.field("released", &self.released);
+        }
+        if let Some(timestamp) = &self.timestamp {
+            debug.field("timestamp", timestamp);
+        }
+        debug.finish()
+    }
+}
+
+impl Transaction {
+    /// Creates a deposit of `amount` for `client`.
+    pub fn deposit(client: ClientId, tx: TxId, amount: Decimal) -> Self {
+        Transaction::new(TransactionType::Deposit, client, tx, Some(amount))
+    }
+
+    /// Creates a withdrawal of `amount` for `client`.
+    pub fn withdrawal(client: ClientId, tx: TxId, amount: Decimal) -> Self {
+        Transaction::new(TransactionType::Withdrawal, client, tx, Some(amount))
+    }
+
+    /// Creates a dispute of the transaction `tx` of `client`.
+    pub fn dispute(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Dispute, client, tx, None)
+    }
+
+    /// Creates a resolve of the disputed transaction `tx` of `client`.
+    pub fn resolve(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Resolve, client, tx, None)
+    }
+
+    /// Creates a chargeback of the disputed transaction `tx` of `client`.
+    pub fn chargeback(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Chargeback, client, tx, None)
+    }
+
+    /// Creates an authorization hold of `amount` on the funds of `client`.
+    pub fn hold(client: ClientId, tx: TxId, amount: Decimal) -> Self {
+        Transaction::new(TransactionType::Hold, client, tx, Some(amount))
+    }
+
+    /// Creates a release of the hold `tx` of `client`.
+    pub fn release(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Release, client, tx, None)
+    }
+
+    /// Creates a reversal of the deposit or withdrawal `tx` of `client`.
+    pub fn reversal(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Reversal, client, tx, None)
+    }
+
+    /// Creates a freeze of the account of `client`.
+    pub fn freeze(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Freeze, client, tx, None)
+    }
+
+    /// Creates an unfreeze of the account of `client`.
+    pub fn unfreeze(client: ClientId, tx: TxId) -> Self {
+        Transaction::new(TransactionType::Unfreeze, client, tx, None)
+    }
+
+    /// Creates the fee of `amount` charged to `client` on the deposit or withdrawal `tx`.
+    pub fn fee(client: ClientId, tx: TxId, amount: Decimal) -> Self {
+        Transaction::new(TransactionType::Fee, client, tx, Some(amount))
+    }
+
+    fn new(ty: TransactionType, client: ClientId, tx: TxId, amount: Option<Decimal>) -> Self {
+        Transaction {
+            ty,
+            client,
+            tx,
+            amount,
+            disputed: false,
+            charged_back: Decimal::ZERO,
+            reversed: false,
+            released: false,
+            timestamp: None,
+        }
+    }
+
+    /// Returns whether the whole amount has been charged back, see `charged_back`.
+    pub fn is_charged_back(&self) -> bool {
+        self.amount
+            .is_some_and(|amount| !self.charged_back.is_zero() && self.charged_back >= amount)
+    }
+
+    /// Returns the part of the amount not charged back yet, see `charged_back`.
+    pub fn remaining_amount(&self) -> Option<Decimal> {
+        self.amount.map(|amount| amount - self.charged_back)
+    }
 }
 
+/// Cause of a `TransactionError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionErrorKind {
+    /// The transaction was rejected by the engine.
+    Engine(EngineError),
+    /// The record could not be read (malformed CSV, unknown transaction type, ...).
+    Read,
+    /// The processing call itself failed (opening the input, checkpointing, ...).
+    Other,
+    /// The processing call stopped before the end of the stream (time budget exceeded).
+    Stopped,
+}
+
+/// An error collected while processing a stream of transactions.
+///
+/// `Display` reproduces the message reported before the errors were structured, e.g.
+/// `Error processing Transaction { .. }: Insufficient funds`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionError {
+    /// Line of the input record the error refers to (1-based, the header is line 1), if known.
+    pub record_line: Option<u64>,
+    /// Id of the failed transaction, if the record could be read.
+    pub tx: Option<TxId>,
+    /// Client of the failed transaction, if the record could be read.
+    pub client: Option<ClientId>,
+    pub kind: TransactionErrorKind,
+    message: String,
+}
+
+impl TransactionError {
+    /// Returns the error of `tx` rejected by the engine with `error`.
+    pub fn processing(tx: &Transaction, error: EngineError) -> Self {
+        TransactionError {
+            record_line: None,
+            tx: Some(tx.tx),
+            client: Some(tx.client),
+            message: format!("Error processing {tx:?}: {error}"),
+            kind: TransactionErrorKind::Engine(error),
+        }
+    }
+
+    /// Returns the error of a record that could not be read.
+    pub fn read(message: impl Into<String>) -> Self {
+        TransactionError::new(TransactionErrorKind::Read, message.into())
+    }
+
+    /// Returns an error of the processing call not related to a single record.
+    pub fn other(message: impl Into<String>) -> Self {
+        TransactionError::new(TransactionErrorKind::Other, message.into())
+    }
+
+    /// Returns the error of a processing call stopped before the end of the stream.
+    pub fn stopped(message: impl Into<String>) -> Self {
+        TransactionError::new(TransactionErrorKind::Stopped, message.into())
+    }
+
+    fn new(kind: TransactionErrorKind, message: String) -> Self {
+        TransactionError {
+            record_line: None,
+            tx: None,
+            client: None,
+            kind,
+            message,
+        }
+    }
+
+    /// Sets the line of the input record the error refers to.
+    pub fn at_line(mut self, line: Option<u64>) -> Self {
+        self.record_line = line;
+        self
+    }
+
+    /// Prefixes the message with the path of the file the error comes from, e.g.
+    /// `feed_a.csv: Error reading transaction record: ...`.
+    pub fn in_file(mut self, path: &str) -> Self {
+        self.message = format!("{path}: {}", self.message);
+        self
+    }
+
+    /// Returns the engine error, if the transaction was rejected by the engine.
+    pub fn engine_error(&self) -> Option<&EngineError> {
+        match &self.kind {
+            TransactionErrorKind::Engine(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns the error message, see `Display`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
 #[derive(Debug, Error)]
 pub enum TransactionProcessingError {
-    #[error("Transaction processing encountered multiple errors: {0:?}")]
-    MultipleErrors(Vec<String>),
+    #[error("Transaction processing encountered multiple errors: {:?}", .0.iter().map(TransactionError::message).collect::<Vec<_>>())]
+    MultipleErrors(Vec<TransactionError>),
+}
+
+impl TransactionProcessingError {
+    /// Aggregates identical error causes into `(message, count)` pairs, in order of first
+    /// occurrence. Errors of transactions rejected by the engine are grouped by cause, regardless
+    /// of the transaction; any other message is grouped as is.
+    pub fn grouped(&self) -> Vec<(String, usize)> {
+        let TransactionProcessingError::MultipleErrors(errors) = self;
+        let mut groups: Vec<(String, usize)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for error in errors {
+            let message = match error.engine_error() {
+                Some(cause) => cause.to_string(),
+                None => error.message().to_string(),
+            };
+            match positions.get(&message) {
+                Some(&pos) => groups[pos].1 += 1,
+                None => {
+                    positions.insert(message.clone(), groups.len());
+                    groups.push((message, 1));
+                }
+            }
+        }
+        groups
+    }
 }
 
 // impl fmt::Display for TransactionProcessingError {
@@ -103,11 +443,14 @@ pub enum TransactionProcessingError {
 /// - tx: The transaction ID.
 /// - amount: The amount of the transaction.
 /// - disputed: Whether the transaction is disputed.
+/// - charged_back: The part of the amount already charged back.
+/// - reversed: Whether the transaction has been reversed.
+/// - released: Whether the hold has been released.
 ///
 /// # Errors
 /// - `Box<dyn std::error::Error>` if any errors occur while writing to the CSV file.
 pub fn serialize_transcation_log_csv<W: Write>(
-    transaction_log: &DashMap<TxId, Transaction>,
+    transaction_log: &DashMap<TxKey, Transaction>,
     writer: W,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut csv_writer = Writer::from_writer(writer);
@@ -122,6 +465,45 @@ pub fn serialize_transcation_log_csv<W: Write>(
             transaction.tx,
             transaction.amount,
             transaction.disputed,
+            transaction.charged_back,
+            transaction.reversed,
+            transaction.released,
+        ))?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Writes the transaction log to a CSV file, like `serialize_transcation_log_csv`, with an
+/// additional column before `charged_back` holding the input amount string of each transaction
+/// found in `raw_amounts` (empty otherwise).
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any errors occur while writing to the CSV file.
+pub fn serialize_transcation_log_csv_with_raw_amounts<W: Write>(
+    transaction_log: &DashMap<TxKey, Transaction>,
+    raw_amounts: &DashMap<TxKey, String>,
+    writer: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv_writer = Writer::from_writer(writer);
+
+    for entry in transaction_log.iter() {
+        let transaction = entry.value();
+        let raw_amount = raw_amounts.get(entry.key());
+
+        csv_writer.serialize((
+            transaction.ty.clone(),
+            transaction.client,
+            transaction.tx,
+            transaction.amount,
+            transaction.disputed,
+            raw_amount
+                .as_deref()
+                .map(String::as_str)
+                .unwrap_or_default(),
+            transaction.charged_back,
+            transaction.reversed,
+            transaction.released,
         ))?;
     }
     csv_writer.flush()?;
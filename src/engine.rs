@@ -1,47 +1,52 @@
-use crate::datastr::account::{serialize_account_balances_csv, Account};
+use crate::basics::feed::{self, FeedOverflowPolicy, FeedReceiver, FeedSender};
+use crate::basics::metrics::{EngineMetrics, MetricsCounters};
+use crate::basics::stats::AmountStats;
+#[cfg(feature = "timing")]
+use crate::basics::timing::TimingStats;
+use crate::config::{
+    AccountCapacityPolicy, AccountColumn, AmountUnits, BoolFormat, CsvDialect, DepositCheckOrder,
+    DisputePolicy, EngineConfig, HistoricalDisputePolicy, LockedAccountPolicy, NegativeHeldPolicy,
+    NegativeTotalPolicy, PreDumpVerification, ResolvePolicy, Rounding, TxUniqueness,
+};
+use crate::datastr::account::{
+    account_csv_header, account_csv_record, accounts_merkle_root, parse_overdraft_limit,
+    serialize_account_balances_csv_with_options, serialize_account_balances_json_with_options,
+    serialize_account_balances_ndjson_with_options, Account, AccountDelta, OVERDRAFT_LIMIT_COLUMN,
+};
+#[cfg(feature = "parquet")]
+use crate::datastr::columnar;
+use crate::datastr::deser::parse_bool;
 use crate::datastr::transaction::{
-    serialize_transcation_log_csv, ClientId, Transaction, TransactionProcessingError,
-    TransactionType, TxId,
+    serialize_transcation_log_csv, serialize_transcation_log_csv_with_raw_amounts, ClientId,
+    Transaction, TransactionError, TransactionErrorKind, TransactionProcessingError,
+    TransactionType, TxId, TxKey,
 };
-use dashmap::DashMap;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::{DashMap, DashSet};
+use flate2::read::MultiGzDecoder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 use csv::{ReaderBuilder, Trim};
 use rust_decimal::Decimal;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
-#[derive(Debug, Error)]
-pub enum EngineError {
-    #[error("Cannot dispute/resolve/chargeback transaction from a different client")]
-    DifferentClient,
-    #[error("Transaction must have an amount")]
-    NoAmount,
-    #[error("Referred Transaction must have an amount")]
-    ReferredTransactionNoAmount,
-    #[error("Deposit amount must be greater than 0")]
-    DepositAmountInvalid,
-    #[error("Withdrawal amount must be greater than 0")]
-    WithdrawalAmountInvalid,
-    #[error("Transaction id already processed in this session - cannot be repeated.")]
-    TransactionRepeated,
-    #[error("Insufficient funds")]
-    InsufficientFunds,
-    #[error("Account not found")]
-    AccountNotFound,
-    #[error("Transaction not found")]
-    TransactionNotFound,
-    #[error("Addition overflow")]
-    AdditionOverflow,
-    #[error("Subtraction overflow")]
-    SubtractionOverflow,
-    #[error("Account is locked")]
-    AccountLocked,
-    #[error("Transaction already disputed")]
-    TransactionAlreadyDisputed,
-    #[error("Transaction not disputed")]
-    TransactionNotDisputed,
-}
+pub use crate::datastr::error::EngineError;
 
 #[derive(Debug, Error)]
 pub enum EngineSerDeserError {
@@ -55,6 +60,19 @@ pub enum EngineSerDeserError {
     InvalidDecimal,
     #[error("Parsing error while reading session csv - InvalidBool")]
     InvalidBool,
+    #[error("Parsing error while reading combined csv - InvalidRecord")]
+    InvalidRecord,
+    #[error("Parsing error while reading checkpoint manifest")]
+    InvalidManifest(serde_json::Error),
+    #[error("Checkpoint mismatch: the manifest records {expected_accounts} accounts and {expected_transactions} transactions, {accounts} and {transactions} were loaded")]
+    CheckpointMismatch {
+        expected_accounts: usize,
+        expected_transactions: usize,
+        accounts: usize,
+        transactions: usize,
+    },
+    #[error("Checkpoint mismatch: the SHA-256 of {0} differs from the manifest")]
+    CheckpointChecksumMismatch(String),
 }
 
 impl From<std::io::Error> for EngineSerDeserError {
@@ -105,99 +123,3116 @@ trait EngineStateTransitionFunctions {
     fn process_dispute(&self, tx: &Transaction) -> Result<(), EngineError>;
     fn process_resolve(&self, tx: &Transaction) -> Result<(), EngineError>;
     fn process_chargeback(&self, tx: &Transaction) -> Result<(), EngineError>;
+    fn process_hold(&self, tx: &Transaction) -> Result<(), EngineError>;
+    fn process_release(&self, tx: &Transaction) -> Result<(), EngineError>;
+    fn process_reversal(&self, tx: &Transaction) -> Result<(), EngineError>;
+    fn process_freeze(&self, tx: &Transaction) -> Result<(), EngineError>;
+    fn process_unfreeze(&self, tx: &Transaction) -> Result<(), EngineError>;
+}
+
+/// Returns whether the logged transaction `tx` can still be referred to by a later dispute
+/// resolution or release, and so cannot be pruned: an active hold, or a disputed transaction not
+/// fully charged back (a charged back transaction stays flagged as disputed), unless its account
+/// is locked and the `LockedAccountPolicy` of `config` blocks the disputes of locked accounts.
+pub(crate) fn is_unsettled(tx: &Transaction, account_locked: bool, config: &EngineConfig) -> bool {
+    (tx.ty == TransactionType::Hold && !tx.released)
+        || (tx.disputed
+            && !tx.is_charged_back()
+            && (!account_locked
+                || config.locked_account_policy == LockedAccountPolicy::AllowDisputes))
+}
+
+/// Converts `tx` from the input conventions of `config` into the form processed by the engine,
+/// borrowing `tx` unchanged when no conversion applies:
+/// - the amount is converted from the input `amount_units` into a decimal amount,
+/// - the amount is rounded to `precision` decimal places with the `rounding` strategy,
+/// - with `signed_amounts`, a deposit with a negative amount becomes a withdrawal of the
+///   absolute amount.
+///
+/// # Errors
+/// - `InvalidMinorUnits`: If a minor-unit amount is not an integer.
+pub(crate) fn normalize_input<'a>(
+    tx: &'a Transaction,
+    config: &EngineConfig,
+) -> Result<Cow<'a, Transaction>, EngineError> {
+    let mut tx = Cow::Borrowed(tx);
+    if let (AmountUnits::Minor(_), Some(amount)) = (config.amount_units, tx.amount) {
+        let amount = config
+            .amount_units
+            .units_to_decimal(amount)
+            .ok_or(EngineError::InvalidMinorUnits)?;
+        tx.to_mut().amount = Some(amount);
+    }
+    if let Some(amount) = tx.amount.filter(|amount| amount.scale() > config.precision) {
+        tx.to_mut().amount =
+            Some(amount.round_dp_with_strategy(config.precision, config.rounding.strategy()));
+    }
+    if config.signed_amounts && tx.ty == TransactionType::Deposit {
+        if let Some(amount) = tx.amount.filter(|amount| *amount < Decimal::ZERO) {
+            let tx = tx.to_mut();
+            tx.ty = TransactionType::Withdrawal;
+            tx.amount = Some(-amount);
+        }
+    }
+    Ok(tx)
+}
+
+/// Returns the amount reversed by the chargeback `tx` of a transaction whose disputed amount is
+/// `disputed` (negative for a withdrawal, see `check_transaction_semantic`), and whether the
+/// chargeback is partial.
+///
+/// A chargeback without an amount, or with the whole disputed amount, is final. A smaller amount
+/// only reverses that portion, with the sign of `disputed`: the remainder stays disputed.
+///
+/// # Errors
+/// - `ChargebackAmountInvalid`: If the amount is not greater than 0.
+/// - `ChargebackExceedsHeld`: If the amount is greater than the disputed amount.
+pub(crate) fn chargeback_amount(
+    tx: &Transaction,
+    disputed: Decimal,
+) -> Result<(Decimal, bool), EngineError> {
+    let Some(amount) = tx.amount else {
+        return Ok((disputed, false));
+    };
+    if amount <= Decimal::ZERO {
+        return Err(EngineError::ChargebackAmountInvalid);
+    }
+    match amount.cmp(&disputed.abs()) {
+        std::cmp::Ordering::Greater => Err(EngineError::ChargebackExceedsHeld),
+        std::cmp::Ordering::Equal => Ok((disputed, false)),
+        std::cmp::Ordering::Less if disputed.is_sign_negative() => Ok((-amount, true)),
+        std::cmp::Ordering::Less => Ok((amount, true)),
+    }
+}
+
+/// Returns a builder of CSV readers of transactions (with headers) in the dialect of `config`.
+///
+/// The records are deserialized by header name, so the columns can come in any order and
+/// unknown columns are ignored; the header names are trimmed (e.g. `type, client, tx, amount`).
+pub(crate) fn transactions_reader_builder(config: &EngineConfig) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .has_headers(true)
+        .trim(Trim::Headers)
+        .delimiter(config.csv_dialect.delimiter);
+    builder
+}
+
+/// Returns the error of a dispute/resolve/chargeback `tx` referring to a transaction not found
+/// in the log, see `Engine::with_tx_id_collision_detection`.
+pub(crate) fn reference_not_found(tx: &Transaction, config: &EngineConfig) -> EngineError {
+    if config.detect_tx_id_collisions && tx.amount.is_some() {
+        EngineError::TxIdCollision
+    } else {
+        EngineError::TransactionNotFound
+    }
+}
+
+/// Returns the client a deposit of `tx` creating a new account is credited to, given the number
+/// of accounts of the engine, see `Engine::with_max_accounts`.
+///
+/// # Returns
+/// - `tx.client`, or the overflow account if `tx` would exceed the limit.
+///
+/// # Errors
+/// - `AccountCapacityReached`: If `tx` would exceed the limit and the policy rejects it.
+/// - `DepositDropped`: If `tx` would exceed the limit and the policy drops it.
+pub(crate) fn new_account_deposit_client(
+    tx: &Transaction,
+    accounts: usize,
+    config: &EngineConfig,
+) -> Result<ClientId, EngineError> {
+    if config.max_accounts.is_none_or(|max| accounts < max) {
+        return Ok(tx.client);
+    }
+    match config.account_capacity_policy {
+        AccountCapacityPolicy::Reject => Err(EngineError::AccountCapacityReached),
+        AccountCapacityPolicy::Overflow(client) => Ok(client),
+        AccountCapacityPolicy::Drop => Err(EngineError::DepositDropped),
+    }
+}
+
+/// Magic bytes starting every gzip member, see `is_gzip`.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns `true` if the file at `input_path` is gzip-compressed: its name ends with `.gz` or
+/// its content (starting with `header`) starts with the gzip magic bytes.
+pub(crate) fn is_gzip(input_path: &str, header: &[u8]) -> bool {
+    input_path.ends_with(".gz") || header.starts_with(&GZIP_MAGIC)
+}
+
+/// Opens the transactions file at `input_path` for reading, decompressing it if it is
+/// gzip-compressed (see `is_gzip`).
+///
+/// # Errors
+/// - `TransactionProcessingError` if the file cannot be opened or read.
+fn open_transactions_file(input_path: &str) -> Result<Box<dyn Read>, TransactionProcessingError> {
+    let io_error = |what: &str, e: std::io::Error| {
+        TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+            "Error {} file: {}",
+            what, e
+        ))])
+    };
+    let file = File::open(input_path).map_err(|e| io_error("opening", e))?;
+    let mut reader = BufReader::new(file);
+    let header = reader.fill_buf().map_err(|e| io_error("reading", e))?;
+    if is_gzip(input_path, header) {
+        // Concatenated gzip members (e.g. appended dumps) are read as one stream.
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Returns the error reported when a stream processing call stops after `processed` records
+/// because `max_duration` was exceeded, see `Engine::with_max_duration`.
+pub(crate) fn max_duration_exceeded(processed: usize, max_duration: Duration) -> TransactionError {
+    TransactionError::stopped(format!(
+        "Processing stopped after {processed} records: maximum duration of {max_duration:?} exceeded"
+    ))
+}
+
+/// Returns the error reported when a transactions stream is empty, without even a header line
+/// (a stream with a header and no record is processed as a successful no-op).
+pub(crate) fn missing_header() -> TransactionError {
+    TransactionError::other("empty input: missing header")
+}
+
+/// Returns `true` if `error` is the one reported by a processing call stopped by
+/// `max_duration_exceeded`.
+pub(crate) fn is_max_duration_exceeded(error: &TransactionError) -> bool {
+    error.kind == TransactionErrorKind::Stopped
+}
+
+/// Returns a fingerprint of the `content` of a transactions file, identifying the files already
+/// processed, see `Engine::with_idempotent_file_reruns`.
+pub(crate) fn content_fingerprint(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(content);
+    hasher.finish()
+}
+
+/// Step of a client ledger trace, see `Engine::trace_client`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerStep {
+    /// The transaction of the client, as read from the input.
+    pub transaction: Transaction,
+    /// The error processing the transaction, if it failed.
+    pub error: Option<String>,
+    /// The client account after the transaction (`None` while the client has no account).
+    pub account: Option<Account>,
+}
+
+/// Account whose `held` balance disagrees with the transaction log, see `Engine::held_mismatches`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeldMismatch {
+    pub client: ClientId,
+    /// The `held` balance of the account.
+    pub held: Decimal,
+    /// The funds held according to the transaction log.
+    pub expected: Decimal,
+}
+
+impl std::fmt::Display for HeldMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "client {} held {} expected {}",
+            self.client, self.held, self.expected
+        )
+    }
+}
+
+/// Account whose balances break the `available + held == total` invariant, see
+/// `Engine::verify_invariants`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvariantViolation {
+    pub client: ClientId,
+    /// `available + held - total`, `None` if `available + held` overflows.
+    pub discrepancy: Option<Decimal>,
+}
+
+/// Transaction logged by both merged engines, see `Engine::merge`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Transaction {tx} is logged by both merged engines")]
+pub struct MergeConflict {
+    pub tx: TxId,
+}
+
+/// Checks the balances of the account of `client`: `available + held` must equal `total` at the
+/// `precision` of `config` (i.e. once the discrepancy is rounded to it), see
+/// `Engine::verify_invariants`.
+pub(crate) fn balance_violation(
+    client: ClientId,
+    account: &Account,
+    config: &EngineConfig,
+) -> Option<InvariantViolation> {
+    let discrepancy = account
+        .available
+        .checked_add(account.held)
+        .and_then(|sum| sum.checked_sub(account.total));
+    let violated = discrepancy.is_none_or(|discrepancy| {
+        !discrepancy
+            .round_dp_with_strategy(config.precision, config.rounding.strategy())
+            .is_zero()
+    });
+    violated.then_some(InvariantViolation {
+        client,
+        discrepancy,
+    })
+}
+
+/// Name of the accounts file of a checkpoint, see `Engine::checkpoint`.
+pub const CHECKPOINT_ACCOUNTS: &str = "accounts.csv";
+/// Name of the transaction log file of a checkpoint, see `Engine::checkpoint`.
+pub const CHECKPOINT_TRANSACTIONS: &str = "transactions.csv";
+/// Name of the fee log file of a checkpoint, see `Engine::checkpoint`.
+pub const CHECKPOINT_FEES: &str = "fees.csv";
+/// Name of the manifest file of a checkpoint, see `Engine::checkpoint`.
+pub const CHECKPOINT_MANIFEST: &str = "manifest.json";
+/// Buffer size of the checkpoint files writers.
+const CHECKPOINT_BUFFER_SIZE: usize = 64 * 1024;
+/// Number of records read before processing them, see
+/// `Engine::read_and_process_transactions_parallel`.
+pub const PARALLEL_CHUNK_RECORDS: usize = 65_536;
+
+/// Returns a pool of `workers` threads processing the batches of the parallel entry points.
+///
+/// # Errors
+/// - `TransactionProcessingError` if the threads cannot be spawned.
+fn worker_pool(workers: usize) -> Result<rayon::ThreadPool, TransactionProcessingError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error starting the worker threads: {}",
+                e
+            ))])
+        })
+}
+
+/// Returns the SHA-256 of the content of the file at `path`, in lowercase hex.
+fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Content of the `manifest.json` of a checkpoint, see `Engine::checkpoint`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointManifest {
+    /// Number of rows of `accounts.csv`.
+    pub accounts: usize,
+    /// Number of rows of `transactions.csv`.
+    pub transactions: usize,
+    /// Number of rows of `fees.csv`.
+    pub fees: usize,
+    /// SHA-256 of `accounts.csv` (lowercase hex).
+    pub accounts_sha256: String,
+    /// SHA-256 of `transactions.csv` (lowercase hex).
+    pub transactions_sha256: String,
+    /// SHA-256 of `fees.csv` (lowercase hex).
+    pub fees_sha256: String,
+    /// Creation time of the checkpoint (RFC 3339, UTC).
+    pub timestamp: String,
+}
+
+/// Transaction held back by the dispute reorder window, see `Engine::with_dispute_reorder_window`.
+struct PendingTransaction {
+    tx: Transaction,
+    /// Index of the input record the transaction was read at.
+    read_at: usize,
+    /// Line of the input record, see `TransactionError::record_line`.
+    line: Option<u64>,
+    /// Last error returned processing the transaction, reported if it falls out of the window.
+    error: EngineError,
+}
+
+impl PendingTransaction {
+    /// Returns the error reported when the transaction falls out of the reorder window.
+    fn into_error(self) -> TransactionError {
+        TransactionError::processing(&self.tx, self.error).at_line(self.line)
+    }
+}
+
+/// Converts the first `columns` values of a SQLite row into a CSV record, see
+/// `Engine::read_and_process_transactions_from_sqlite`.
+///
+/// # Errors
+/// - If a value is a blob or not valid UTF-8 text.
+#[cfg(feature = "sqlite")]
+fn sqlite_record(row: &rusqlite::Row<'_>, columns: usize) -> Result<csv::StringRecord, String> {
+    use rusqlite::types::ValueRef;
+
+    let mut record = csv::StringRecord::with_capacity(64, columns);
+    for idx in 0..columns {
+        match row.get_ref(idx).map_err(|e| e.to_string())? {
+            ValueRef::Null => record.push_field(""),
+            ValueRef::Integer(value) => record.push_field(&value.to_string()),
+            ValueRef::Real(value) => record.push_field(&value.to_string()),
+            ValueRef::Text(text) => {
+                record.push_field(std::str::from_utf8(text).map_err(|e| e.to_string())?)
+            }
+            ValueRef::Blob(_) => return Err(format!("unexpected blob in column {}", idx)),
+        }
+    }
+    Ok(record)
+}
+
+/// Checks that the logged transaction `original_tx` can be disputed under the configured
+/// `DisputePolicy`.
+///
+/// # Errors
+/// - `WithdrawalNotDisputable`: If it is a withdrawal and withdrawal disputes are not allowed.
+pub(crate) fn check_disputable(
+    original_tx: &Transaction,
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    if original_tx.ty == TransactionType::Withdrawal
+        && !config.dispute_policy.allow_withdrawal_disputes
+    {
+        return Err(EngineError::WithdrawalNotDisputable);
+    }
+    Ok(())
+}
+
+/// Returns whether the configured `NegativeHeldPolicy` rejects the dispute or resolve `tx`
+/// driving the held funds below zero.
+pub(crate) fn negative_held_rejected(tx: &Transaction, config: &EngineConfig) -> bool {
+    match config.negative_held_policy {
+        NegativeHeldPolicy::Allow => false,
+        NegativeHeldPolicy::Reject => tx.ty == TransactionType::Resolve,
+        NegativeHeldPolicy::Strict => true,
+    }
+}
+
+/// Transactions of an input stream read by chunks and sorted by their `timestamp`, see
+/// `Engine::with_timestamp_ordering`.
+pub(crate) struct TimestampOrdering {
+    headers: csv::StringRecord,
+    chunk_records: usize,
+    /// Sorted transactions of the current chunk with their records, preceded by the read errors
+    /// of the chunk.
+    chunk: VecDeque<Result<(Transaction, csv::StringRecord), TransactionError>>,
+}
+
+impl TimestampOrdering {
+    /// # Errors
+    /// - If `headers` has no `timestamp` column.
+    pub(crate) fn new(
+        headers: &csv::StringRecord,
+        chunk_records: usize,
+    ) -> Result<Self, TransactionError> {
+        if !headers.iter().any(|h| h.trim() == "timestamp") {
+            return Err(TransactionError::read(
+                "Error reading transaction record: missing timestamp column",
+            ));
+        }
+        Ok(TimestampOrdering {
+            headers: headers.clone(),
+            chunk_records: chunk_records.max(1),
+            chunk: VecDeque::new(),
+        })
+    }
+
+    /// Returns the next transaction, in timestamp order, with its record, reading the next chunk
+    /// of `csv_reader` if needed. Returns `None` at the end of the input.
+    pub(crate) fn next_transaction<R: Read>(
+        &mut self,
+        csv_reader: &mut csv::Reader<R>,
+    ) -> Option<Result<(Transaction, csv::StringRecord), TransactionError>> {
+        if self.chunk.is_empty() {
+            self.read_chunk(csv_reader);
+        }
+        self.chunk.pop_front()
+    }
+
+    /// Reads up to `chunk_records` records and sorts their transactions by timestamp, keeping
+    /// the input order of equal timestamps. Unreadable records, including invalid or missing
+    /// timestamps, are reported as read errors.
+    fn read_chunk<R: Read>(&mut self, csv_reader: &mut csv::Reader<R>) {
+        let mut transactions = Vec::with_capacity(self.chunk_records);
+        for _ in 0..self.chunk_records {
+            let mut record = csv::StringRecord::new();
+            match csv_reader.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    self.chunk.push_back(Err(Engine::format_read_error(&e)));
+                    continue;
+                }
+            }
+            match record.deserialize::<Transaction>(Some(&self.headers)) {
+                Ok(tx) if tx.timestamp.is_none() => {
+                    let error = TransactionError::read(
+                        "Error reading transaction record: missing timestamp",
+                    );
+                    let line = record.position().map(csv::Position::line);
+                    self.chunk.push_back(Err(error.at_line(line)));
+                }
+                Ok(tx) => transactions.push((tx, record)),
+                Err(e) => self.chunk.push_back(Err(Engine::format_read_error(&e))),
+            }
+        }
+        transactions.sort_by_key(|(tx, _)| tx.timestamp);
+        self.chunk.extend(transactions.into_iter().map(Ok));
+    }
+}
+
+/// Heap payload used by `Engine::process_merged`: ordering is fully determined by the
+/// (tx id, stream index) pair preceding it in the heap tuple, so entries always compare equal.
+struct MergeEntry(Transaction);
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for MergeEntry {}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, _: &Self) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+/// Subscriber of the account balance changes, see `subscribe_deltas`.
+enum DeltaSubscriber {
+    Unbounded(Sender<AccountDelta>),
+    /// See `subscribe_deltas_bounded`.
+    Bounded(FeedSender<AccountDelta>),
+}
+
+impl DeltaSubscriber {
+    fn send(&self, delta: AccountDelta) {
+        // A dropped receiver just stops receiving.
+        match self {
+            DeltaSubscriber::Unbounded(sender) => {
+                let _ = sender.send(delta);
+            }
+            DeltaSubscriber::Bounded(sender) => {
+                sender.send(delta);
+            }
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct Engine {
     pub accounts: DashMap<ClientId, Account>,
-    pub transaction_log: DashMap<TxId, Transaction>,
+    /// Deposits, withdrawals and holds, keyed as configured by `with_tx_uniqueness`.
+    pub transaction_log: DashMap<TxKey, Transaction>,
+    /// Fees charged, keyed by the key of the deposit/withdrawal they were charged on.
+    pub fee_log: DashMap<TxKey, Transaction>,
+    historical_transactions: DashSet<TxKey>,
+    /// Clients allowed to open an account in strict mode, see `with_strict_clients`.
+    registered_clients: DashSet<ClientId>,
+    /// Input `amount` strings of the logged transactions, see `with_preserve_raw_amount`.
+    raw_amounts: DashMap<TxKey, String>,
+    /// Fingerprints of the files already processed, see `with_idempotent_file_reruns`.
+    processed_files: DashSet<u64>,
+    config: EngineConfig,
+    /// Subscribers of the account balance changes, see `subscribe_deltas`.
+    delta_subscribers: RwLock<Vec<DeltaSubscriber>>,
+    amount_stats: DashMap<TransactionType, AmountStats>,
+    /// Processing counters, see `metrics`.
+    metrics: MetricsCounters,
+    /// Write-ahead log of the applied transactions, see `enable_wal`.
+    wal: Mutex<Option<csv::Writer<File>>>,
+    /// Mismatches found by the last dump, see `pre_dump_warnings`.
+    pre_dump_warnings: Mutex<Vec<HeldMismatch>>,
+    #[cfg(feature = "timing")]
+    timings: DashMap<TransactionType, TimingStats>,
+    /// Schema the transactions are validated against, see `with_json_schema`.
+    #[cfg(feature = "jsonschema")]
+    json_schema: Option<std::sync::Arc<jsonschema::Validator>>,
 }
 
-impl Engine {
-    pub fn new() -> Self {
-        Engine {
-            accounts: DashMap::new(),
-            transaction_log: DashMap::new(),
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            accounts: DashMap::new(),
+            transaction_log: DashMap::new(),
+            fee_log: DashMap::new(),
+            historical_transactions: DashSet::new(),
+            registered_clients: DashSet::new(),
+            raw_amounts: DashMap::new(),
+            processed_files: DashSet::new(),
+            config: EngineConfig::default(),
+            delta_subscribers: RwLock::new(Vec::new()),
+            amount_stats: DashMap::new(),
+            metrics: MetricsCounters::default(),
+            wal: Mutex::new(None),
+            pre_dump_warnings: Mutex::new(Vec::new()),
+            #[cfg(feature = "timing")]
+            timings: DashMap::new(),
+            #[cfg(feature = "jsonschema")]
+            json_schema: None,
+        }
+    }
+
+    /// Creates an engine with the given `accounts` and logged `transactions` (deposits,
+    /// withdrawals and holds), as `load_from_previous_session_csvs` would load them from files,
+    /// e.g. to embed the engine in another service or to set up a test state.
+    ///
+    /// The transactions are keyed with the default `TxUniqueness`.
+    pub fn from_state(accounts: Vec<(ClientId, Account)>, transactions: Vec<Transaction>) -> Self {
+        let engine = Engine::new();
+        for transaction in transactions {
+            let key = engine.tx_key(&transaction);
+            engine.historical_transactions.insert(key);
+            engine.transaction_log.insert(key, transaction);
+        }
+        for (client_id, account) in accounts {
+            engine.accounts.insert(client_id, account);
+        }
+        engine
+    }
+
+    /// Replaces the whole configuration, e.g. with the one of a `ConfigFile`.
+    ///
+    /// # Panics
+    /// - If the `transaction_fee` of `config` is negative, see `with_transaction_fee`.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        assert!(
+            config.transaction_fee >= Decimal::ZERO,
+            "The transaction fee must not be negative"
+        );
+        self.config = config;
+        self
+    }
+
+    /// Sets the number of decimal places deposit/withdrawal amounts are rounded to
+    /// (default `4`), e.g. `2` for instruments settling in cents.
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.config.precision = precision;
+        self
+    }
+
+    /// Sets the rounding of the deposit/withdrawal amounts to the precision (default
+    /// `Rounding::HalfUp`, midpoint away from zero), e.g. `Rounding::HalfEven` (banker's
+    /// rounding) for regulatory reporting.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.config.rounding = rounding;
+        self
+    }
+
+    /// Sets the policy applied when an operation would drive an account total negative.
+    pub fn with_negative_total_policy(mut self, policy: NegativeTotalPolicy) -> Self {
+        self.config.negative_total_policy = policy;
+        self
+    }
+
+    /// Holds back disputes, resolves and chargebacks referring to a transaction not yet seen for
+    /// up to `window` records of the input stream, retrying them as deposits and withdrawals are
+    /// processed. Useful when upstream reordering may move a dispute before its deposit.
+    /// `0` (default) disables the buffering.
+    pub fn with_dispute_reorder_window(mut self, window: usize) -> Self {
+        self.config.dispute_reorder_window = window;
+        self
+    }
+
+    /// Processes the records of the input streams in the order of their `timestamp` column
+    /// (an ISO-8601 date and time, or seconds since the Unix epoch, see `Transaction::timestamp`)
+    /// instead of the file order, within chunks of `chunk_records` records: each chunk is read
+    /// and sorted before being processed, so a dispute arriving before its deposit in the same
+    /// chunk is processed after it. Records with the same timestamp keep their input order.
+    ///
+    /// The streams must then have a `timestamp` column; records with an invalid or missing
+    /// timestamp are reported as read errors. By default the records are processed in file
+    /// order. `AsyncEngine::with_timestamp_ordering` orders its streams the same way;
+    /// `read_and_process_transactions_parallel` does not support it.
+    pub fn with_timestamp_ordering(mut self, chunk_records: usize) -> Self {
+        self.config.timestamp_ordering = Some(chunk_records);
+        self
+    }
+
+    /// Sets the policy applied to disputes on transactions loaded from a previous session.
+    pub fn with_historical_dispute_policy(mut self, policy: HistoricalDisputePolicy) -> Self {
+        self.config.historical_dispute_policy = policy;
+        self
+    }
+
+    /// Sets the policy applied to resolves referring to a transaction that is not disputed.
+    pub fn with_resolve_policy(mut self, policy: ResolvePolicy) -> Self {
+        self.config.resolve_policy = policy;
+        self
+    }
+
+    /// Sets the policy applied when a resolve (or a dispute, see `NegativeHeldPolicy::Strict`)
+    /// would drive the account held funds below zero.
+    pub fn with_negative_held_policy(mut self, policy: NegativeHeldPolicy) -> Self {
+        self.config.negative_held_policy = policy;
+        self
+    }
+
+    /// Sets which transactions can be disputed, e.g. only deposits with
+    /// `DisputePolicy { allow_withdrawal_disputes: false }` (by default withdrawals can be
+    /// disputed too).
+    pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.config.dispute_policy = policy;
+        self
+    }
+
+    /// Sets the transactions accepted on a locked account, e.g.
+    /// `LockedAccountPolicy::AllowDisputes` to still resolve the other disputes of a charged back
+    /// account (by default every transaction is rejected with `AccountLocked`).
+    pub fn with_locked_account_policy(mut self, policy: LockedAccountPolicy) -> Self {
+        self.config.locked_account_policy = policy;
+        self
+    }
+
+    /// Sets which check fails first on a deposit to a locked account with a repeated tx id.
+    pub fn with_deposit_check_order(mut self, order: DepositCheckOrder) -> Self {
+        self.config.deposit_check_order = order;
+        self
+    }
+
+    /// Sets whether tx ids are unique across all clients (default) or only per client, in which
+    /// case different clients can reuse the same tx id and disputes, resolves and chargebacks
+    /// refer to the transaction of their own client.
+    ///
+    /// The transaction log is keyed accordingly: set it before processing or loading anything.
+    pub fn with_tx_uniqueness(mut self, uniqueness: TxUniqueness) -> Self {
+        self.config.tx_uniqueness = uniqueness;
+        self
+    }
+
+    /// Validates every transaction, as read and before any processing, against the JSON `schema`
+    /// (e.g. requiring an amount or bounding it), rejecting the violations with
+    /// `SchemaViolation`. The transactions are validated in their JSON form, e.g.
+    /// `{"type":"deposit","client":1,"tx":1,"amount":1.5,"disputed":false}` (the `amount` is
+    /// `null` when absent).
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if `schema` is not a valid JSON schema.
+    #[cfg(feature = "jsonschema")]
+    pub fn with_json_schema(
+        mut self,
+        schema: &serde_json::Value,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        self.json_schema = Some(std::sync::Arc::new(jsonschema::validator_for(schema)?));
+        Ok(self)
+    }
+
+    /// Checks `tx` against the schema set with `with_json_schema`, if any.
+    #[cfg(feature = "jsonschema")]
+    fn validate_schema(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let Some(validator) = &self.json_schema else {
+            return Ok(());
+        };
+        let mut instance =
+            serde_json::to_value(tx).map_err(|e| EngineError::SchemaViolation(e.to_string()))?;
+        // Amounts serialize as strings: validate them as numbers, so that ranges can be checked
+        if let Some(amount) = tx.amount {
+            let amount = amount
+                .to_string()
+                .parse::<serde_json::Number>()
+                .map_err(|e| EngineError::SchemaViolation(e.to_string()))?;
+            instance["amount"] = serde_json::Value::Number(amount);
+        }
+        validator
+            .validate(&instance)
+            .map_err(|e| EngineError::SchemaViolation(e.to_string()))
+    }
+
+    /// Returns the key of `tx` in the transaction log.
+    #[inline]
+    fn tx_key(&self, tx: &Transaction) -> TxKey {
+        self.config.tx_uniqueness.key(tx.client, tx.tx)
+    }
+
+    /// Sets the representation of the `locked` column in `dump_account_to_csv`.
+    pub fn with_locked_format(mut self, format: BoolFormat) -> Self {
+        self.config.dump.locked_format = format;
+        self
+    }
+
+    /// Sets the units of the `amount` column of the processed transactions,
+    /// e.g. `AmountUnits::Minor(2)` reads `1050` as `10.50`.
+    pub fn with_amount_units(mut self, units: AmountUnits) -> Self {
+        self.config.amount_units = units;
+        self
+    }
+
+    /// Reads a deposit with a negative amount as a withdrawal of the absolute amount, for
+    /// upstreams encoding withdrawals as negative deposits (e.g. `deposit,1,1,-5` is a
+    /// withdrawal of 5). When disabled (default), such a deposit fails with `DepositAmountInvalid`.
+    pub fn with_signed_amounts(mut self, signed_amounts: bool) -> Self {
+        self.config.signed_amounts = signed_amounts;
+        self
+    }
+
+    /// Charges a flat `fee` on each deposit and withdrawal: it is deducted from the client
+    /// `available` and `total` funds and recorded as a `Fee` entry of the `fee_log`. The
+    /// transaction fails with `InsufficientFundsForFee` if the funds (after a deposit) cannot
+    /// cover the fee. Disputes and chargebacks move the transaction amount only: the fee is
+    /// never refunded.
+    ///
+    /// # Panics
+    /// - If `fee` is negative, as it would credit the client on every withdrawal.
+    pub fn with_transaction_fee(mut self, fee: Decimal) -> Self {
+        assert!(
+            fee >= Decimal::ZERO,
+            "The transaction fee must not be negative"
+        );
+        self.config.transaction_fee = fee;
+        self
+    }
+
+    /// Distinguishes, among the disputes/resolves/chargebacks referring to an unknown transaction,
+    /// the rows with an amount: they are structured as a new transaction reusing a tx id, so
+    /// they fail with `TxIdCollision` instead of `TransactionNotFound`.
+    pub fn with_tx_id_collision_detection(mut self, enabled: bool) -> Self {
+        self.config.detect_tx_id_collisions = enabled;
+        self
+    }
+
+    /// Scans every stream before processing it, checking that each dispute, resolve and
+    /// chargeback refers to a deposit or withdrawal read earlier in the same stream or already in
+    /// the transaction log (e.g. loaded from a previous session). If any reference is missing,
+    /// nothing is processed and the call fails with a `TransactionNotFound` error per dangling
+    /// reference, reported up front instead of mid-stream.
+    ///
+    /// The stream is buffered in memory for the two passes.
+    pub fn with_reference_precheck(mut self, enabled: bool) -> Self {
+        self.config.reference_precheck = enabled;
+        self
+    }
+
+    /// Sets the dialect of the transactions CSV input, e.g. `CsvDialect::tab_separated()` for
+    /// tab-separated exports. Session dumps are always read and written comma-separated.
+    pub fn with_csv_dialect(mut self, dialect: CsvDialect) -> Self {
+        self.config.csv_dialect = dialect;
+        self
+    }
+
+    /// Stops reading a stream once `max_duration` has elapsed since the start of the processing
+    /// call, keeping the state computed so far. The call then fails with a `MultipleErrors`
+    /// entry reporting the number of records processed, so the partial accounts can still be
+    /// dumped.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.config.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Limits the number of accounts to `max_accounts`: a deposit that would create a new account
+    /// beyond it is handled by `policy` (rejected, credited to a shared overflow account or
+    /// dropped). By default the number of accounts is unlimited.
+    pub fn with_max_accounts(mut self, max_accounts: usize, policy: AccountCapacityPolicy) -> Self {
+        self.config.max_accounts = Some(max_accounts);
+        self.config.account_capacity_policy = policy;
+        self
+    }
+
+    /// Rejects any deposit or withdrawal whose amount exceeds `max_amount` with
+    /// `AmountExceedsLimit`, guarding against fat-finger errors (an amount equal to the limit is
+    /// accepted). By default the amount is unlimited.
+    pub fn with_max_amount(mut self, max_amount: Decimal) -> Self {
+        self.config.max_amount = Some(max_amount);
+        self
+    }
+
+    /// Makes processing a file already processed by this engine a successful no-op instead of a
+    /// flood of `TransactionRepeated` errors, for idempotent re-runs (e.g. retried jobs).
+    ///
+    /// Files are recognized by content: a file counts as processed once a
+    /// `read_and_process_transactions_from_csv` call on it completed, even if some of its
+    /// transactions were rejected (they would be rejected again); a call stopped by
+    /// `with_max_duration` does not count. A file processed only partially, e.g. by a crashed
+    /// job, is processed again.
+    ///
+    /// When enabled, each file is read in memory once and both fingerprinted and processed from
+    /// those bytes, so a file modified while it is processed cannot be recorded under the
+    /// fingerprint of a different content.
+    pub fn with_idempotent_file_reruns(mut self, enabled: bool) -> Self {
+        self.config.idempotent_file_reruns = enabled;
+        self
+    }
+
+    /// Sets the units of the amount columns in `dump_account_to_csv`.
+    pub fn with_dump_amount_units(mut self, units: AmountUnits) -> Self {
+        self.config.dump.amount_units = units;
+        self
+    }
+
+    /// Makes `process_slice_parallel` process the transactions of each client in input order,
+    /// by routing every client to a fixed worker. Without it, the transactions of a client may be
+    /// processed by different workers in any order (e.g. a dispute before its deposit).
+    pub fn with_per_client_ordering(mut self, enabled: bool) -> Self {
+        self.config.per_client_ordering = enabled;
+        self
+    }
+
+    /// Only lets known clients open an account, for closed systems where clients must be
+    /// pre-registered: a deposit of a client without an account fails with `UnknownClient`
+    /// unless the client was registered with `register_client`. The clients of the accounts
+    /// loaded from a previous session (or already created) are known. By default an account is
+    /// created on the first deposit of any client.
+    pub fn with_strict_clients(mut self, enabled: bool) -> Self {
+        self.config.strict_clients = enabled;
+        self
+    }
+
+    /// Registers `client`, allowing it to open an account with a deposit in strict mode (see
+    /// `with_strict_clients`).
+    pub fn register_client(&self, client: ClientId) {
+        self.registered_clients.insert(client);
+    }
+
+    /// Processes only the records of the given types read from a stream (`None`, the default,
+    /// processes all of them), e.g. only the deposits and withdrawals when debugging a replay.
+    /// The records of the other types are skipped without errors.
+    pub fn with_only_types(mut self, types: Option<HashSet<TransactionType>>) -> Self {
+        self.config.only_types = types;
+        self
+    }
+
+    /// Keeps the input `amount` string of every deposit and withdrawal read from a stream
+    /// alongside the rounded amount actually applied (see `raw_amount`), and adds a
+    /// `raw_amount` column to `dump_transaction_log_to_csv` for auditing.
+    pub fn with_preserve_raw_amount(mut self, preserve: bool) -> Self {
+        self.config.preserve_raw_amount = preserve;
+        self
+    }
+
+    /// Returns the input `amount` string of the transaction `tx` of `client`, if raw amounts are
+    /// preserved.
+    pub fn raw_amount(&self, client: ClientId, tx: TxId) -> Option<String> {
+        self.raw_amounts
+            .get(&self.config.tx_uniqueness.key(client, tx))
+            .map(|raw| raw.clone())
+    }
+
+    /// Skips the zero-balance, unlocked accounts in `dump_account_to_csv`.
+    pub fn with_omit_zero_accounts(mut self, omit_zero: bool) -> Self {
+        self.config.dump.omit_zero = omit_zero;
+        self
+    }
+
+    /// Writes only `columns`, in the given order, in `dump_account_to_csv`, e.g.
+    /// `[Client, Total, Locked]` for importers expecting `client,total,locked`. A subset dump
+    /// cannot be reloaded with `load_from_previous_session_csvs`.
+    pub fn with_account_columns(mut self, columns: Vec<AccountColumn>) -> Self {
+        self.config.dump.columns = Some(columns);
+        self
+    }
+
+    /// Rounds the amounts of the account dumps to `scale` decimal places, padding them with zeros
+    /// if needed (e.g. `1.5` is dumped as `1.50` with scale 2), independently of the processing
+    /// precision.
+    pub fn with_dump_scale(mut self, scale: u32) -> Self {
+        self.config.dump.scale = Some(scale);
+        self
+    }
+
+    /// Sets the rounding of the amounts of the account dumps, applied with `with_dump_scale`.
+    pub fn with_dump_rounding(mut self, rounding: Rounding) -> Self {
+        self.config.dump.rounding = rounding;
+        self
+    }
+
+    /// Writes a checkpoint of the engine state to `dir` (created if needed): the accounts to
+    /// `accounts.csv` (with their overdraft limits), the transaction log to `transactions.csv`,
+    /// the fee log to `fees.csv` (`client,tx,amount`) and their row counts and SHA-256, with the creation time, to
+    /// `manifest.json`. It can be loaded back with `restore`.
+    ///
+    /// The files are written to a staging directory inside `dir` and then renamed into place,
+    /// the manifest last: a crash leaves either the previous checkpoint or files whose checksums
+    /// disagree with the manifest, which `restore` detects. The accounts are written with all their
+    /// columns whatever the dump options. The checkpoint is meant to be taken when no
+    /// transaction is in flight.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any file cannot be written or renamed, or the pre-dump
+    ///   verification fails (see `with_pre_dump_verification`).
+    pub fn checkpoint(&self, dir: &Path) -> Result<CheckpointManifest, Box<dyn std::error::Error>> {
+        let staging = dir.join(".checkpoint.tmp");
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
+
+        self.dump_transaction_log_to_csv(
+            &staging.join(CHECKPOINT_TRANSACTIONS).to_string_lossy(),
+            CHECKPOINT_BUFFER_SIZE,
+        )?;
+        {
+            let file = File::create(staging.join(CHECKPOINT_ACCOUNTS))?;
+            let mut csv_writer =
+                csv::Writer::from_writer(BufWriter::with_capacity(CHECKPOINT_BUFFER_SIZE, file));
+            csv_writer.write_record([
+                "client",
+                "available",
+                "held",
+                "total",
+                "locked",
+                OVERDRAFT_LIMIT_COLUMN,
+            ])?;
+            for entry in self.accounts.iter() {
+                let account = entry.value();
+                csv_writer.serialize((
+                    *entry.key(),
+                    account.available,
+                    account.held,
+                    account.total,
+                    account.locked,
+                    account.overdraft_limit,
+                ))?;
+            }
+            csv_writer.flush()?;
+        }
+        {
+            let file = File::create(staging.join(CHECKPOINT_FEES))?;
+            let mut csv_writer =
+                csv::Writer::from_writer(BufWriter::with_capacity(CHECKPOINT_BUFFER_SIZE, file));
+            csv_writer.write_record(["client", "tx", "amount"])?;
+            for entry in self.fee_log.iter() {
+                let fee = entry.value();
+                csv_writer.serialize((fee.client, fee.tx, fee.amount))?;
+            }
+            csv_writer.flush()?;
+        }
+
+        let manifest = CheckpointManifest {
+            accounts: self.accounts.len(),
+            transactions: self.transaction_log.len(),
+            fees: self.fee_log.len(),
+            accounts_sha256: file_sha256(&staging.join(CHECKPOINT_ACCOUNTS))?,
+            transactions_sha256: file_sha256(&staging.join(CHECKPOINT_TRANSACTIONS))?,
+            fees_sha256: file_sha256(&staging.join(CHECKPOINT_FEES))?,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        {
+            let mut buf_writer = BufWriter::new(File::create(staging.join(CHECKPOINT_MANIFEST))?);
+            serde_json::to_writer_pretty(&mut buf_writer, &manifest)?;
+            buf_writer.flush()?;
+        }
+
+        for name in [
+            CHECKPOINT_ACCOUNTS,
+            CHECKPOINT_TRANSACTIONS,
+            CHECKPOINT_FEES,
+            CHECKPOINT_MANIFEST,
+        ] {
+            std::fs::rename(staging.join(name), dir.join(name))?;
+        }
+        std::fs::remove_dir(&staging)?;
+        Ok(manifest)
+    }
+
+    /// Loads a checkpoint written by `checkpoint` from `dir`, as `load_from_previous_session_csvs`
+    /// does, with its fee log, then checks that the number of accounts and transactions loaded
+    /// matches the manifest. The SHA-256 of every file is checked against the manifest before
+    /// anything is loaded, so files from different checkpoints are rejected even when their row
+    /// counts agree.
+    ///
+    /// # Errors
+    /// - `Io`, `InvalidManifest`: If the manifest cannot be read.
+    /// - `CheckpointChecksumMismatch`: If a file was changed or belongs to another checkpoint;
+    ///   nothing is loaded.
+    /// - The errors of `load_from_previous_session_csvs`, and `Csv` if a fee cannot be parsed.
+    /// - `CheckpointMismatch`: If the files disagree with the manifest (e.g. a transaction could
+    ///   not be parsed); the rows read are left loaded.
+    pub fn restore(&mut self, dir: &Path) -> Result<CheckpointManifest, EngineSerDeserError> {
+        let file = File::open(dir.join(CHECKPOINT_MANIFEST))?;
+        let manifest: CheckpointManifest = serde_json::from_reader(BufReader::new(file))
+            .map_err(EngineSerDeserError::InvalidManifest)?;
+        for (name, expected) in [
+            (CHECKPOINT_ACCOUNTS, &manifest.accounts_sha256),
+            (CHECKPOINT_TRANSACTIONS, &manifest.transactions_sha256),
+            (CHECKPOINT_FEES, &manifest.fees_sha256),
+        ] {
+            if file_sha256(&dir.join(name))? != *expected {
+                return Err(EngineSerDeserError::CheckpointChecksumMismatch(
+                    name.to_string(),
+                ));
+            }
+        }
+
+        let accounts_before = self.accounts.len();
+        let transactions_before = self.transaction_log.len();
+        self.load_from_previous_session_csvs(
+            &dir.join(CHECKPOINT_TRANSACTIONS).to_string_lossy(),
+            &dir.join(CHECKPOINT_ACCOUNTS).to_string_lossy(),
+        )?;
+        let mut rdr = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(BufReader::new(File::open(dir.join(CHECKPOINT_FEES))?));
+        for record in rdr.deserialize::<(ClientId, TxId, Decimal)>() {
+            let (client, tx, amount) = record?;
+            let fee = Transaction::fee(client, tx, amount);
+            self.fee_log.insert(self.tx_key(&fee), fee);
+        }
+        let accounts = self.accounts.len() - accounts_before;
+        let transactions = self.transaction_log.len() - transactions_before;
+        if accounts != manifest.accounts || transactions != manifest.transactions {
+            return Err(EngineSerDeserError::CheckpointMismatch {
+                expected_accounts: manifest.accounts,
+                expected_transactions: manifest.transactions,
+                accounts,
+                transactions,
+            });
+        }
+        Ok(manifest)
+    }
+
+    /// Appends every transaction successfully applied from now on, in the order it is applied
+    /// and including disputes, resolves and chargebacks, to the write-ahead log at `path`
+    /// (created if needed, appended to otherwise), so that the state can be rebuilt after a crash
+    /// with `recover_from_wal`. Unlike the dumps, which are point-in-time snapshots, the log
+    /// records the whole history of the session.
+    ///
+    /// The log is a transactions CSV (`type,client,tx,amount`) holding the transactions as
+    /// read, before any conversion by the engine configuration. Each transaction is flushed to
+    /// the file as soon as it is applied; a transaction that cannot be written fails with
+    /// `WalWriteFailed`, although it was applied.
+    ///
+    /// # Errors
+    /// - `std::io::Error` if the file cannot be opened or the header cannot be written.
+    pub fn enable_wal(&self, path: &Path) -> std::io::Result<()> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        if empty {
+            writer.write_record(["type", "client", "tx", "amount"])?;
+            writer.flush()?;
+        }
+        *self.wal.lock().unwrap_or_else(|e| e.into_inner()) = Some(writer);
+        Ok(())
+    }
+
+    /// Appends the applied transaction `tx` to the write-ahead log, if enabled.
+    ///
+    /// # Errors
+    /// - `WalWriteFailed`: If the transaction cannot be written.
+    fn append_to_wal(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let mut wal = self.wal.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(writer) = wal.as_mut() else {
+            return Ok(());
+        };
+        writer
+            .serialize((&tx.ty, tx.client, tx.tx, tx.amount))
+            .map_err(|e| e.to_string())
+            .and_then(|()| writer.flush().map_err(|e| e.to_string()))
+            .map_err(EngineError::WalWriteFailed)
+    }
+
+    /// Replays the write-ahead log at `path` (see `enable_wal`) into this engine, meant to be a
+    /// fresh engine configured as the one that wrote the log, rebuilding its accounts and its
+    /// transaction log, dispute state included. Recover before enabling the write-ahead log of
+    /// this engine, or the replayed transactions are logged again.
+    ///
+    /// # Returns
+    /// - `Ok(())` if every logged transaction is applied again.
+    /// - `Err(TransactionProcessingError)` collecting the records that cannot be read or
+    ///   applied, e.g. of a log truncated by the crash.
+    pub fn recover_from_wal(&self, path: &Path) -> Result<(), TransactionProcessingError> {
+        let file = File::open(path).map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error opening file: {}",
+                e
+            ))])
+        })?;
+        let mut csv_reader = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(BufReader::new(file));
+        let mut errors = Vec::new();
+        for record in csv_reader.deserialize::<Transaction>() {
+            match record {
+                Ok(tx) => {
+                    if let Err(e) = self.process_transaction(&tx) {
+                        errors.push(TransactionError::processing(&tx, e));
+                    }
+                }
+                Err(e) => errors.push(Engine::format_read_error(&e)),
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns a copy of all the accounts that can be taken while transactions are being
+    /// processed concurrently.
+    ///
+    /// Every state transition updates an account (`available`, `held`, `total`, `locked`) while
+    /// holding the write lock of its `DashMap` entry, and each account is copied here under the
+    /// read lock of the same entry: a snapshot account is never torn (e.g. `available` updated
+    /// but not `total`). The guarantee is per account: accounts are copied one shard at a time,
+    /// so the snapshot may reflect different points of the stream for different clients.
+    pub fn consistent_snapshot(&self) -> HashMap<ClientId, Account> {
+        self.accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Returns a copy of the account of `client`, or `None` if the client has no account.
+    ///
+    /// Unlike reading `accounts` directly, no guard of the map escapes: holding the returned
+    /// account never blocks the processing of the client (nor deadlocks a caller that processes
+    /// transactions meanwhile), e.g. to serve balance queries while the engine is running.
+    pub fn get_account(&self, client: ClientId) -> Option<Account> {
+        self.accounts
+            .get(&client)
+            .map(|entry| entry.value().clone())
+    }
+
+    /// Returns the number of client accounts.
+    pub fn total_clients(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Returns the number of transactions in the transaction log.
+    pub fn total_transactions(&self) -> usize {
+        self.transaction_log.len()
+    }
+
+    /// Returns the root hash of a Merkle tree over the accounts sorted by client (see
+    /// `datastr::account::accounts_merkle_root`).
+    ///
+    /// Two engines with the same account state have the same root, whatever the order the
+    /// transactions were processed in: comparing the roots is a cheap cross-check between the
+    /// sync and async engines or between distributed shards. The accounts are read with
+    /// `consistent_snapshot`, so the root is only meaningful when no transaction is in flight.
+    pub fn state_merkle_root(&self) -> [u8; 32] {
+        accounts_merkle_root(self.consistent_snapshot().into_iter().collect())
+    }
+
+    /// Parses an account dumped as `client,available,held,total,locked`, starting at the
+    /// field `offset` of `record`.
+    pub(crate) fn parse_account_record(
+        record: &csv::StringRecord,
+        offset: usize,
+    ) -> Result<(ClientId, Account), EngineSerDeserError> {
+        let client_id: u16 = record[offset]
+            .parse()
+            .map_err(|_| EngineSerDeserError::InvalidClientId)?;
+        let account = Account {
+            available: record[offset + 1]
+                .parse()
+                .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
+            held: record[offset + 2]
+                .parse()
+                .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
+            total: record[offset + 3]
+                .parse()
+                .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
+            locked: parse_bool(&record[offset + 4]).ok_or(EngineSerDeserError::InvalidBool)?,
+            overdraft_limit: Decimal::ZERO,
+        };
+        Ok((client_id, account))
+    }
+
+    /// Exports the accounts, sorted by client, as Parquet to `accounts_path` and optionally
+    /// the transaction log, sorted by tx id, to `transactions_path`, for columnar analysis.
+    /// Amounts are `Decimal128` columns, see `datastr::columnar`.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if an amount cannot be converted or a file cannot be written.
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(
+        &self,
+        accounts_path: &str,
+        transactions_path: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
+        let mut accounts: Vec<(ClientId, Account)> =
+            self.consistent_snapshot().into_iter().collect();
+        accounts.sort_unstable_by_key(|(client, _)| *client);
+        columnar::write_accounts_parquet(&accounts, accounts_path)?;
+
+        if let Some(transactions_path) = transactions_path {
+            let mut transactions: Vec<Transaction> = self
+                .transaction_log
+                .iter()
+                .map(|entry| entry.value().clone())
+                .collect();
+            transactions.sort_unstable_by_key(|tx| (tx.tx, tx.client));
+            columnar::write_transactions_parquet(&transactions, transactions_path)?;
+        }
+        Ok(())
+    }
+
+    /// Dumps the current state of all accounts to a CSV writer like `dump_account_to_csv`, in
+    /// ascending client order instead of the map order, so that the dumps of two runs can be
+    /// diffed. The client ids are collected and sorted first; every account is then read when
+    /// its row is written.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub fn dump_account_to_csv_sorted<W: Write>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
+        let mut clients: Vec<ClientId> = self.accounts.iter().map(|entry| *entry.key()).collect();
+        clients.sort_unstable();
+
+        let mut buf_writer = BufWriter::with_capacity(buffer_size, writer);
+        writeln!(
+            buf_writer,
+            "{}",
+            account_csv_header(&self.config.dump).join(",")
+        )?;
+        let mut csv_writer = csv::Writer::from_writer(&mut buf_writer);
+        for client in clients {
+            let Some(account) = self.accounts.get(&client) else {
+                continue;
+            };
+            if self.config.dump.omit_zero && account.is_zero() {
+                continue;
+            }
+            csv_writer.write_record(account_csv_record(client, &account, &self.config.dump)?)?;
+        }
+        csv_writer.flush()?;
+        drop(csv_writer);
+        buf_writer.flush()?;
+        Ok(())
+    }
+
+    /// Dumps the current state of all accounts as newline-delimited JSON, one object per line
+    /// (`{"client":1,"available":1.5,"held":0,"total":1.5,"locked":false}`), for streaming
+    /// consumers such as `jq` or a log ingester. The dump options apply as in
+    /// `dump_account_to_csv`, except `locked` which is always a JSON boolean.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub fn dump_account_to_ndjson<W: Write>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
+        serialize_account_balances_ndjson_with_options(
+            &self.accounts,
+            BufWriter::with_capacity(buffer_size, writer),
+            &self.config.dump,
+        )
+    }
+
+    /// Dumps the current state of all accounts as JSON Lines, one object per line with the amounts
+    /// as strings (`{"client":1,"available":"5.0000","held":"0.0000","total":"5.0000","locked":false}`),
+    /// for consumers that would lose precision parsing JSON numbers. The dump options apply as
+    /// in `dump_account_to_ndjson`.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing to the writer.
+    pub fn dump_account_to_json<W: Write>(
+        &self,
+        writer: W,
+        buffer_size: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
+        serialize_account_balances_json_with_options(
+            &self.accounts,
+            BufWriter::with_capacity(buffer_size, writer),
+            &self.config.dump,
+        )
+    }
+
+    /// Dumps accounts and transaction log into a single CSV, avoiding to manage two files
+    /// (and the atomicity problem between them). There is no header row and the first column
+    /// discriminates the record type:
+    /// - `A,client,available,held,total,locked` for accounts,
+    /// - `T,type,client,tx,amount,disputed,charged_back,reversed,released` for transactions.
+    ///
+    /// # Errors
+    /// - `Box<dyn std::error::Error>` if any errors occur while writing.
+    pub fn dump_combined_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
+        // The transaction records have one more field than the account ones
+        let mut csv_writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+
+        for entry in self.accounts.iter() {
+            let account = entry.value();
+            csv_writer.serialize((
+                "A",
+                *entry.key(),
+                account.available,
+                account.held,
+                account.total,
+                account.locked,
+            ))?;
+        }
+        for entry in self.transaction_log.iter() {
+            let tx = entry.value();
+            csv_writer.serialize((
+                "T",
+                tx.ty.clone(),
+                tx.client,
+                tx.tx,
+                tx.amount,
+                tx.disputed,
+                tx.charged_back,
+                tx.reversed,
+                tx.released,
+            ))?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads accounts and transactions dumped by `dump_combined_csv`.
+    ///
+    /// As `load_from_previous_session_csvs`, no semantic/consistency check is performed on the
+    /// input data and the loaded transactions are considered historical.
+    ///
+    /// # Errors
+    /// - `Csv`: If a record cannot be read or a transaction cannot be parsed.
+    /// - `InvalidRecord`: If a record has an unknown type or a wrong number of fields.
+    /// - `InvalidClientId`, `InvalidDecimal`, `InvalidBool`: If an account cannot be parsed.
+    pub fn load_combined_csv<R: Read>(&mut self, reader: R) -> Result<(), EngineSerDeserError> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(BufReader::new(reader));
+        let transaction_headers = csv::StringRecord::from(vec![
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "disputed",
+            "charged_back",
+            "reversed",
+            "released",
+        ]);
+
+        for result in rdr.records() {
+            let record = result.map_err(EngineSerDeserError::Csv)?;
+            // The transactions dumped before the `charged_back`, `reversed` and `released`
+            // columns have fewer fields.
+            let valid_len = match &record[0] {
+                "T" => matches!(record.len(), 6..=9),
+                _ => record.len() == 6,
+            };
+            if !valid_len {
+                return Err(EngineSerDeserError::InvalidRecord);
+            }
+            match &record[0] {
+                "A" => {
+                    let (client_id, account) = Engine::parse_account_record(&record, 1)?;
+                    self.accounts.insert(client_id, account);
+                }
+                "T" => {
+                    let fields: csv::StringRecord = record.iter().skip(1).collect();
+                    let transaction = fields
+                        .deserialize::<Transaction>(Some(&transaction_headers))
+                        .map_err(EngineSerDeserError::Csv)?;
+                    let key = self.tx_key(&transaction);
+                    self.historical_transactions.insert(key);
+                    self.transaction_log.insert(key, transaction);
+                }
+                _ => return Err(EngineSerDeserError::InvalidRecord),
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays the transactions of the CSV file at `input_path` on a new engine with the
+    /// configuration of this one, recording for each transaction of `client` the outcome and the
+    /// account state right after it. Meant for support investigations: this engine is not
+    /// modified.
+    ///
+    /// The records are processed strictly in order (the dispute reorder window is not applied)
+    /// and the records that cannot be parsed are skipped.
+    ///
+    /// # Errors
+    /// - `TransactionProcessingError` if the file cannot be opened.
+    pub fn trace_client(
+        &self,
+        client: ClientId,
+        input_path: &str,
+    ) -> Result<Vec<LedgerStep>, TransactionProcessingError> {
+        let file = File::open(input_path).map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error opening file: {}",
+                e
+            ))])
+        })?;
+        let replay = Engine::new().with_config(self.config.clone());
+        let mut csv_reader = transactions_reader_builder(&self.config)
+            .trim(Trim::All)
+            .from_reader(BufReader::new(file));
+
+        let mut steps = Vec::new();
+        for transaction in csv_reader.deserialize::<Transaction>().flatten() {
+            let result = replay.process_transaction(&transaction);
+            if transaction.client == client {
+                steps.push(LedgerStep {
+                    error: result.err().map(|e| e.to_string()),
+                    account: replay.accounts.get(&client).map(|account| account.clone()),
+                    transaction,
+                });
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Compares the transaction log of this engine with the one of `other`.
+    ///
+    /// Useful to investigate nondeterminism, e.g. comparing sync vs async runs or sequential vs
+    /// concurrent processing of the same input.
+    ///
+    /// # Returns
+    /// - `Vec<TxId>`: the sorted ids of the transactions present in only one of the two logs, or
+    ///   present in both but with a different content (e.g. a different `disputed` state).
+    pub fn log_diff(&self, other: &Engine) -> Vec<TxId> {
+        let mut diff: Vec<TxId> = self
+            .transaction_log
+            .iter()
+            .filter(|entry| {
+                other
+                    .transaction_log
+                    .get(entry.key())
+                    .is_none_or(|other_tx| *other_tx != *entry.value())
+            })
+            .map(|entry| entry.key().tx)
+            .collect();
+
+        diff.extend(
+            other
+                .transaction_log
+                .iter()
+                .filter(|entry| !self.transaction_log.contains_key(entry.key()))
+                .map(|entry| entry.key().tx),
+        );
+
+        diff.sort_unstable();
+        diff.dedup();
+        diff
+    }
+
+    /// Folds the state of `other` into this engine, e.g. to combine engines that processed
+    /// disjoint partitions of the input.
+    ///
+    /// The accounts of the clients known to only one engine are taken as they are. For a client
+    /// known to both, `available`, `held` and `total` are summed (saturating on overflow), so the
+    /// funds held by the disputes of either engine stay held and can still be resolved or charged
+    /// back; the account is locked if either is locked and keeps the larger `overdraft_limit`.
+    ///
+    /// The logged transactions and fees are united, so the engines must have processed disjoint
+    /// sets of transactions: a transaction logged by both (even with the same content) is a
+    /// conflict, as its effect is already part of the balances of both accounts and summing them
+    /// would count it twice.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the engines are merged.
+    /// - `Err(MergeConflict)` with the lowest conflicting transaction id, this engine is left
+    ///   untouched.
+    pub fn merge(&mut self, other: Engine) -> Result<(), MergeConflict> {
+        if let Some(tx) = other
+            .transaction_log
+            .iter()
+            .filter(|entry| self.transaction_log.contains_key(entry.key()))
+            .map(|entry| entry.key().tx)
+            .min()
+        {
+            return Err(MergeConflict { tx });
+        }
+
+        for (client, account) in other.accounts {
+            match self.accounts.entry(client) {
+                Entry::Occupied(mut entry) => {
+                    let own = entry.get_mut();
+                    own.available = own.available.saturating_add(account.available);
+                    own.held = own.held.saturating_add(account.held);
+                    own.total = own.total.saturating_add(account.total);
+                    own.locked |= account.locked;
+                    own.overdraft_limit = own.overdraft_limit.max(account.overdraft_limit);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(account);
+                }
+            }
+        }
+        self.transaction_log.extend(other.transaction_log);
+        for (key, fee) in other.fee_log {
+            self.fee_log.entry(key).or_insert(fee);
+        }
+        for (key, raw) in other.raw_amounts {
+            self.raw_amounts.entry(key).or_insert(raw);
+        }
+        for key in other.historical_transactions {
+            self.historical_transactions.insert(key);
+        }
+        for client in other.registered_clients {
+            self.registered_clients.insert(client);
+        }
+        Ok(())
+    }
+
+    /// Returns count, sum, min, max and mean of the amounts of the deposits and withdrawals
+    /// successfully processed so far, per transaction type.
+    ///
+    /// Rejected transactions are not accounted. The statistics are maintained incrementally
+    /// during processing, so this call is cheap.
+    ///
+    /// # Returns
+    /// - `HashMap<TransactionType, AmountStats>`: an entry for `Deposit` and/or `Withdrawal` if at least one was processed.
+    pub fn amount_stats(&self) -> HashMap<TransactionType, AmountStats> {
+        self.amount_stats
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    /// Returns a snapshot of the processing counters, e.g. for production monitoring (see
+    /// `EngineMetrics::render_prometheus`): the deposits, withdrawals, disputes, resolves and
+    /// chargebacks processed successfully, the rejected transactions by error, and the number of
+    /// locked accounts.
+    ///
+    /// The counters are maintained during processing; a transaction held back by the dispute
+    /// reorder window is counted as rejected on each failed attempt.
+    pub fn metrics(&self) -> EngineMetrics {
+        let accounts_locked = self
+            .accounts
+            .iter()
+            .filter(|entry| entry.value().locked)
+            .count();
+        self.metrics.snapshot(accounts_locked as u64)
+    }
+
+    /// Subscribes to the changes of the account balances, for incremental replication of the
+    /// accounts to a read model instead of dumping their full state.
+    ///
+    /// Every transaction processed successfully from now on sends the `AccountDelta` of its
+    /// account (deposits, withdrawals and chargebacks change `total`, disputes and resolves move
+    /// funds between `available` and `held`). The deltas of a client are sent in processing
+    /// order, while holding the lock of its account, so summing them gives its balances
+    /// (starting from the balances at subscription time). Deltas are buffered in the channel
+    /// until received; dropping the receiver ends the subscription.
+    pub fn subscribe_deltas(&self) -> Receiver<AccountDelta> {
+        let (sender, receiver) = channel();
+        self.delta_subscribers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(DeltaSubscriber::Unbounded(sender));
+        receiver
+    }
+
+    /// Subscribes to the changes of the account balances like `subscribe_deltas`, buffering at
+    /// most `capacity` deltas so that a slow subscriber cannot grow the memory of the engine
+    /// without bound. When the buffer is full, `policy` applies:
+    /// - `FeedOverflowPolicy::Block`: the processing waits for the subscriber (while holding the
+    ///   lock of the account), so every delta is delivered.
+    /// - `FeedOverflowPolicy::DropOldest`: the oldest delta is discarded, so the sums of the
+    ///   deltas no longer give the balances (see `FeedReceiver::dropped`).
+    pub fn subscribe_deltas_bounded(
+        &self,
+        capacity: usize,
+        policy: FeedOverflowPolicy,
+    ) -> FeedReceiver<AccountDelta> {
+        let (sender, receiver) = feed::bounded(capacity, policy);
+        self.delta_subscribers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(DeltaSubscriber::Bounded(sender));
+        receiver
+    }
+
+    /// Sends the change of the account of `client` from `before` to `after` to the subscribers.
+    fn publish_delta(&self, client: ClientId, before: &Account, after: &Account) {
+        let subscribers = self
+            .delta_subscribers
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        if subscribers.is_empty() {
+            return;
+        }
+        let delta = AccountDelta::between(client, before, after);
+        for subscriber in subscribers.iter() {
+            subscriber.send(delta.clone());
+        }
+    }
+
+    /// Counts the transactions of the log by type.
+    ///
+    /// Only deposits and withdrawals are stored in the log, so this is mostly a sanity check,
+    /// e.g. to verify what `load_from_previous_session_csvs` loaded.
+    pub fn log_type_counts(&self) -> HashMap<TransactionType, usize> {
+        let mut counts = HashMap::new();
+        for entry in self.transaction_log.iter() {
+            *counts.entry(entry.value().ty.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Returns a checksum of the accounts state: the sum of the account totals and the number of
+    /// accounts.
+    ///
+    /// Meant for benchmarks, which can consume it (e.g. with `black_box`) so that the processing
+    /// work is not optimized away. The sum saturates instead of overflowing.
+    pub fn state_checksum(&self) -> (Decimal, usize) {
+        let mut sum = Decimal::ZERO;
+        let mut count = 0;
+        for entry in self.accounts.iter() {
+            sum = sum.saturating_add(entry.value().total);
+            count += 1;
+        }
+        (sum, count)
+    }
+
+    /// Returns the maximum amount that could be charged back: the sum of the amounts not charged
+    /// back yet of all the currently disputed transactions, across accounts (for risk reporting).
+    ///
+    /// A charged back transaction stays flagged as disputed with nothing left to charge back, so
+    /// it is not counted; the disputed transactions of locked accounts are, as
+    /// `LockedAccountPolicy::AllowDisputes` can still charge them back. The amounts of disputed
+    /// withdrawals are counted as logged (positive), i.e. the exposure measures the funds that a
+    /// chargeback could move. The sum saturates instead of overflowing.
+    ///
+    /// Only the transaction log is locked (a shard at a time), so the report can run while
+    /// transactions are processed.
+    pub fn max_chargeback_exposure(&self) -> Decimal {
+        self.transaction_log
+            .iter()
+            .filter(|entry| entry.value().disputed)
+            .filter_map(|entry| entry.value().remaining_amount())
+            .filter(|amount| *amount > Decimal::ZERO)
+            .fold(Decimal::ZERO, Decimal::saturating_add)
+    }
+
+    /// Returns the net flow of `client`: the sum of its logged deposits minus the sum of its
+    /// logged withdrawals, reversed ones excluded (for cash-flow reporting). Unlike the balance,
+    /// it ignores disputes, chargebacks, holds and fees. The transaction log is scanned, and the sums saturate
+    /// instead of overflowing.
+    ///
+    /// # Returns
+    /// - `None` if the client has no account.
+    pub fn net_flow(&self, client: ClientId) -> Option<Decimal> {
+        self.accounts.get(&client)?;
+        Some(
+            self.transaction_log
+                .iter()
+                .filter(|entry| entry.value().client == client && !entry.value().reversed)
+                .fold(Decimal::ZERO, |flow, entry| {
+                    let tx = entry.value();
+                    let amount = tx.amount.unwrap_or_default();
+                    match tx.ty {
+                        TransactionType::Deposit => flow.saturating_add(amount),
+                        TransactionType::Withdrawal => flow.saturating_sub(amount),
+                        _ => flow,
+                    }
+                }),
+        )
+    }
+
+    /// Makes every dump (`dump_account_to_csv`, `dump_account_to_ndjson`, `dump_account_to_json`,
+    /// `dump_transaction_log_to_csv`, `dump_combined_csv`, `export_parquet`) check first that the
+    /// transaction log and the accounts agree on the held funds (see `held_mismatches`), e.g.
+    /// after loading inconsistent session files.
+    ///
+    /// With `PreDumpVerification::Warn` the dump is written anyway and the mismatches are kept
+    /// for `pre_dump_warnings`; with `PreDumpVerification::Strict` the dump fails instead of
+    /// persisting the corrupted state further.
+    pub fn with_pre_dump_verification(mut self, verification: PreDumpVerification) -> Self {
+        self.config.pre_dump_verification = verification;
+        self
+    }
+
+    /// Returns the mismatches found by the last dump with `PreDumpVerification::Warn` (see
+    /// `with_pre_dump_verification`), empty if the state was consistent.
+    pub fn pre_dump_warnings(&self) -> Vec<HeldMismatch> {
+        self.pre_dump_warnings.lock().unwrap().clone()
+    }
+
+    /// Returns the accounts, sorted by client, whose `held` balance differs from the funds held
+    /// according to the transaction log: the amounts of the disputed deposits, minus the amounts of
+    /// the disputed withdrawals, plus the amounts of the active holds.
+    ///
+    /// Locked accounts are skipped, as a charged back transaction stays flagged as disputed while
+    /// its funds are no longer held. The check is only meaningful when no transaction is in flight.
+    pub fn held_mismatches(&self) -> Vec<HeldMismatch> {
+        let mut expected: HashMap<ClientId, Decimal> = HashMap::new();
+        for entry in self.transaction_log.iter() {
+            let tx = entry.value();
+            let held = match (&tx.ty, tx.remaining_amount()) {
+                (TransactionType::Deposit, Some(amount)) if tx.disputed => amount,
+                (TransactionType::Withdrawal, Some(amount)) if tx.disputed => -amount,
+                (TransactionType::Hold, Some(amount)) if !tx.released => amount,
+                _ => continue,
+            };
+            let sum = expected.entry(tx.client).or_default();
+            *sum = sum.saturating_add(held);
+        }
+        let mut mismatches: Vec<HeldMismatch> = self
+            .accounts
+            .iter()
+            .filter(|entry| !entry.value().locked)
+            .filter_map(|entry| {
+                let expected = expected.get(entry.key()).copied().unwrap_or_default();
+                (entry.value().held != expected).then(|| HeldMismatch {
+                    client: *entry.key(),
+                    held: entry.value().held,
+                    expected,
+                })
+            })
+            .collect();
+        mismatches.sort_unstable_by_key(|mismatch| mismatch.client);
+        mismatches
+    }
+
+    /// Checks that the balances of every account are consistent: `available + held == total`
+    /// at the configured precision, and `available + held` does not overflow. Meant as a
+    /// post-processing assertion (e.g. in stress tests, or after loading session files); the
+    /// check is only meaningful when no transaction is in flight.
+    ///
+    /// # Errors
+    /// - `Vec<InvariantViolation>`: the violating accounts, sorted by client, with their
+    ///   discrepancy.
+    pub fn verify_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations: Vec<InvariantViolation> = self
+            .accounts
+            .iter()
+            .filter_map(|entry| balance_violation(*entry.key(), entry.value(), &self.config))
+            .collect();
+        if violations.is_empty() {
+            return Ok(());
+        }
+        violations.sort_unstable_by_key(|violation| violation.client);
+        Err(violations)
+    }
+
+    /// Runs the pre-dump verification, see `with_pre_dump_verification`: the mismatches found by
+    /// `held_mismatches` are kept as warnings or, in strict mode, fail the dump.
+    fn verify_before_dump(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mismatches = match self.config.pre_dump_verification {
+            PreDumpVerification::Off => return Ok(()),
+            PreDumpVerification::Warn => {
+                *self.pre_dump_warnings.lock().unwrap() = self.held_mismatches();
+                return Ok(());
+            }
+            PreDumpVerification::Strict => self.held_mismatches(),
+        };
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let details: Vec<String> = mismatches.iter().map(HeldMismatch::to_string).collect();
+        Err(format!(
+            "Pre-dump verification failed, the transaction log and the accounts disagree: {}",
+            details.join(", ")
+        )
+        .into())
+    }
+
+    /// Returns the ids of all the transactions currently flagged as disputed, sorted, e.g. for a
+    /// disputes dashboard. Charged back transactions stay flagged and are included.
+    pub fn disputed_transactions(&self) -> Vec<TxId> {
+        let mut disputed: Vec<TxId> = self
+            .transaction_log
+            .iter()
+            .filter(|entry| entry.value().disputed)
+            .map(|entry| entry.key().tx)
+            .collect();
+        disputed.sort_unstable();
+        disputed
+    }
+
+    /// Removes from the transaction log the transactions with a tx id lower than `older_than_tx`,
+    /// to bound the memory of long-running sessions. The pruned transactions can no longer be
+    /// disputed (the disputes fail with `TransactionNotFound`) and their tx ids can be reused;
+    /// their fees and raw amounts are removed as well.
+    ///
+    /// Nothing is pruned if any of those transactions is still unsettled, i.e. an active hold or
+    /// disputed and not fully charged back, on an unlocked account or under
+    /// `LockedAccountPolicy::AllowDisputes`. The pruning is meant to run between streams, not
+    /// concurrently with the processing.
+    ///
+    /// # Returns
+    /// - `Ok(usize)`: The number of pruned transactions.
+    ///
+    /// # Errors
+    /// - `TransactionUnsettled`: If a transaction older than `older_than_tx` is unsettled.
+    pub fn prune_transactions(&self, older_than_tx: TxId) -> Result<usize, EngineError> {
+        let locked: HashSet<ClientId> = self
+            .accounts
+            .iter()
+            .filter(|entry| entry.value().locked)
+            .map(|entry| *entry.key())
+            .collect();
+        let mut keys = Vec::new();
+        for entry in self.transaction_log.iter() {
+            let tx = entry.value();
+            if tx.tx >= older_than_tx {
+                continue;
+            }
+            if is_unsettled(tx, locked.contains(&tx.client), &self.config) {
+                return Err(EngineError::TransactionUnsettled);
+            }
+            keys.push(*entry.key());
+        }
+
+        let mut pruned = 0;
+        for key in keys {
+            if self.transaction_log.remove(&key).is_some() {
+                self.fee_log.remove(&key);
+                self.raw_amounts.remove(&key);
+                self.historical_transactions.remove(&key);
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Returns the processing time statistics collected so far, per transaction type.
+    ///
+    /// Only the time spent in the state transition (`process_transaction`) is measured,
+    /// CSV parsing is excluded. Failed transactions are measured as well.
+    ///
+    /// # Returns
+    /// - `HashMap<TransactionType, TimingStats>`: min/max/avg durations for every type processed at least once.
+    #[cfg(feature = "timing")]
+    pub fn timing_report(&self) -> HashMap<TransactionType, TimingStats> {
+        self.timings
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    /// Attempts to retrieve a mutable reference to an account associated with a given client ID.
+    ///
+    /// # Parameters
+    /// - `client`: The client ID whose account is to be retrieved.
+    ///
+    /// # Returns
+    /// - `Ok(RefMut<Account>)`: A mutable reference to the account if it exists and is not locked.
+    /// - `Err(EngineError)`: If the account is not found or is locked.
+    ///
+    /// # Errors
+    /// - `AccountNotFound`: If the account associated with the given client ID does not exist.
+    /// - `AccountLocked`: If the account is locked.
+    fn try_get_account(
+        &self,
+        client: ClientId,
+    ) -> Result<dashmap::mapref::one::RefMut<'_, u16, Account>, EngineError> {
+        self.accounts
+            .get_mut(&client)
+            .ok_or(EngineError::AccountNotFound)
+            .and_then(|account| {
+                if account.locked {
+                    Err(EngineError::AccountLocked)
+                } else {
+                    Ok(account)
+                }
+            })
+    }
+
+    /// Like `try_get_account`, for disputes, resolves and chargebacks: the account is returned
+    /// even if locked under `LockedAccountPolicy::AllowDisputes`.
+    ///
+    /// # Errors
+    /// - `AccountNotFound`: If the account associated with the given client ID does not exist.
+    /// - `AccountLocked`: If the account is locked and the policy is `LockedAccountPolicy::Block`.
+    fn try_get_dispute_account(
+        &self,
+        client: ClientId,
+    ) -> Result<dashmap::mapref::one::RefMut<'_, u16, Account>, EngineError> {
+        match self.config.locked_account_policy {
+            LockedAccountPolicy::Block => self.try_get_account(client),
+            LockedAccountPolicy::AllowDisputes => self
+                .accounts
+                .get_mut(&client)
+                .ok_or(EngineError::AccountNotFound),
+        }
+    }
+
+    /// Verifies that the transaction referred to by `tx`, if logged, belongs to the client of
+    /// `tx`. Checked before looking up the account, so that a reference to the transaction of
+    /// another client is reported as such even if the client of `tx` has no account.
+    ///
+    /// # Errors
+    /// - `DifferentClient`: If the referred transaction belongs to a different client.
+    fn check_reference_client(&self, tx: &Transaction) -> Result<(), EngineError> {
+        match self.transaction_log.get(&self.tx_key(tx)) {
+            Some(original_tx) if original_tx.client != tx.client => {
+                Err(EngineError::DifferentClient)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Verifies the semantic validity of a transaction in relation to its original transaction.
+    ///
+    /// # Parameters
+    /// - `tx`: The transaction to be checked.
+    /// - `original_tx`: The original transaction that `tx` is related to.
+    ///
+    /// # Returns
+    /// - `Ok(Decimal)`: The amount associated with the original transaction, POSSIBLY WITH A NEGATIVE SIGN if
+    ///   the original transaction was a withdrawal.
+    /// - `Err(EngineError)`: An error if the transactions have different clients, the transaction
+    ///   type requires a disputed status that doesn't match, or if the original transaction lacks an amount.
+    ///
+    /// # Errors
+    /// - `DifferentClient`: If the transactions are from different clients.
+    /// - `HoldNotDisputable`: If the original transaction is a hold (released or not).
+    /// - `AlreadyReversed`: If the original transaction has been reversed.
+    /// - `AlreadyChargedBack`: If the whole amount of the original transaction has been charged
+    ///   back (see `LockedAccountPolicy::AllowDisputes`).
+    /// - `TransactionAlreadyDisputed`: If a dispute is attempted on an already disputed transaction.
+    /// - `TransactionNotDisputed`: If a resolve or chargeback is attempted on a non-disputed transaction.
+    /// - `ReferredTransactionNoAmount`: If the original transaction does not have an amount.
+    fn check_transaction_semantic(
+        tx: &Transaction,
+        original_tx: &Transaction,
+    ) -> Result<Decimal, EngineError> {
+        if original_tx.client != tx.client {
+            return Err(EngineError::DifferentClient);
+        }
+        if original_tx.ty == TransactionType::Hold {
+            return Err(EngineError::HoldNotDisputable);
+        }
+        if original_tx.reversed {
+            return Err(EngineError::AlreadyReversed);
+        }
+        if original_tx.is_charged_back() {
+            return Err(EngineError::AlreadyChargedBack);
+        }
+        match tx.ty {
+            TransactionType::Dispute if original_tx.disputed => {
+                return Err(EngineError::TransactionAlreadyDisputed);
+            }
+            TransactionType::Resolve | TransactionType::Chargeback if !original_tx.disputed => {
+                return Err(EngineError::TransactionNotDisputed);
+            }
+            _ => {}
+        }
+        // This error condition should never happen as it is guaranteed only deposits and withdrawals
+        // with valid amount are stored in the transactions_log. But in general the process could read
+        // the content from a previous session from file if the file is corrupted some deposits or
+        // withdrawals without amount could occur.
+        let mut amount = original_tx
+            .remaining_amount()
+            .ok_or(EngineError::ReferredTransactionNoAmount)?;
+
+        if original_tx.ty == TransactionType::Withdrawal {
+            amount = -amount;
+        }
+        Ok(amount)
+    }
+
+    /// Verifies that `tx` releases the hold `original_tx` and returns the held amount.
+    ///
+    /// # Errors
+    /// - `DifferentClient`: If the transactions are from different clients.
+    /// - `HoldAlreadyReleased`: If the hold has already been released.
+    /// - `NotAHold`: If the original transaction is not a hold.
+    /// - `ReferredTransactionNoAmount`: If the original transaction does not have an amount.
+    pub(crate) fn check_release(
+        tx: &Transaction,
+        original_tx: &Transaction,
+    ) -> Result<Decimal, EngineError> {
+        if original_tx.client != tx.client {
+            return Err(EngineError::DifferentClient);
+        }
+        if original_tx.ty != TransactionType::Hold {
+            return Err(EngineError::NotAHold);
+        }
+        if original_tx.released {
+            return Err(EngineError::HoldAlreadyReleased);
+        }
+        original_tx
+            .amount
+            .ok_or(EngineError::ReferredTransactionNoAmount)
+    }
+
+    /// Verifies that `tx` can reverse `original_tx` and returns the amount to subtract from the
+    /// available and total funds: the deposited amount, or the withdrawn amount WITH A NEGATIVE
+    /// SIGN.
+    ///
+    /// # Errors
+    /// - `DifferentClient`: If the transactions are from different clients.
+    /// - `AlreadyReversed`: If the original transaction has already been reversed.
+    /// - `AlreadyChargedBack`: If the original transaction has been charged back.
+    /// - `HoldNotReversible`: If the original transaction is a hold (released or not).
+    /// - `CannotReverseDisputed`: If the original transaction is disputed (or partially charged
+    ///   back).
+    /// - `ReferredTransactionNoAmount`: If the original transaction does not have an amount.
+    pub(crate) fn check_reversal(
+        tx: &Transaction,
+        original_tx: &Transaction,
+    ) -> Result<Decimal, EngineError> {
+        if original_tx.client != tx.client {
+            return Err(EngineError::DifferentClient);
+        }
+        if original_tx.reversed {
+            return Err(EngineError::AlreadyReversed);
+        }
+        if original_tx.is_charged_back() {
+            return Err(EngineError::AlreadyChargedBack);
+        }
+        let sign = match original_tx.ty {
+            TransactionType::Deposit => Decimal::ONE,
+            TransactionType::Withdrawal => Decimal::NEGATIVE_ONE,
+            _ => return Err(EngineError::HoldNotReversible),
+        };
+        if original_tx.disputed {
+            return Err(EngineError::CannotReverseDisputed);
+        }
+        let amount = original_tx
+            .amount
+            .ok_or(EngineError::ReferredTransactionNoAmount)?;
+        Ok(amount * sign)
+    }
+
+    /// Checks the new `total` of an account against the configured `NegativeTotalPolicy`.
+    ///
+    /// # Errors
+    /// - `NegativeTotal`: If the total is negative and the policy is `NegativeTotalPolicy::Reject`.
+    fn check_total(&self, total: &Decimal) -> Result<(), EngineError> {
+        if self.config.negative_total_policy == NegativeTotalPolicy::Reject
+            && *total < Decimal::ZERO
+        {
+            return Err(EngineError::NegativeTotal);
+        }
+        Ok(())
+    }
+
+    /// Checks the new `held` funds of an account processing the dispute or resolve `tx` against
+    /// the configured `NegativeHeldPolicy`.
+    ///
+    /// # Errors
+    /// - `NegativeHeld`: If a resolve lowers the held funds below zero and the policy is
+    ///   `NegativeHeldPolicy::Reject` or `Strict`, or a dispute does and the policy is `Strict`.
+    fn check_held(
+        &self,
+        tx: &Transaction,
+        held_before: &Decimal,
+        held: &Decimal,
+    ) -> Result<(), EngineError> {
+        if negative_held_rejected(tx, &self.config) && *held < Decimal::ZERO && held < held_before {
+            return Err(EngineError::NegativeHeld);
+        }
+        Ok(())
+    }
+
+    /// Checks a deposit/withdrawal `amount` against the limit set with `with_max_amount`.
+    ///
+    /// # Errors
+    /// - `AmountExceedsLimit`: If the amount is greater than the limit.
+    fn check_amount_limit(&self, amount: Decimal) -> Result<(), EngineError> {
+        if self.config.max_amount.is_some_and(|max| amount > max) {
+            return Err(EngineError::AmountExceedsLimit);
+        }
+        Ok(())
+    }
+
+    /// Records in the `fee_log` the transaction fee charged on the deposit/withdrawal `tx`.
+    fn record_fee(&self, tx: &Transaction) {
+        let fee = self.config.transaction_fee;
+        if fee > Decimal::ZERO {
+            self.fee_log
+                .insert(self.tx_key(tx), Transaction::fee(tx.client, tx.tx, fee));
+        }
+    }
+
+    /// Formats a CSV deserialization error into the error reported in `MultipleErrors`.
+    ///
+    /// The csv error is reported as is, including the record/line/byte position, so that
+    /// the sync and async readers report the same message.
+    pub(crate) fn format_read_error(e: &csv::Error) -> TransactionError {
+        TransactionError::read(format!("Error reading transaction record: {}", e))
+            .at_line(e.position().map(csv::Position::line))
+    }
+
+    /// Returns a `TransactionNotFound` error for each dispute/resolve/chargeback of the CSV `data`
+    /// not referring to a deposit/withdrawal read earlier in `data` or in the transaction log,
+    /// see `with_reference_precheck`. Unreadable records are left to the processing.
+    fn check_references(&self, data: &[u8]) -> Vec<TransactionError> {
+        let mut csv_reader = transactions_reader_builder(&self.config).from_reader(data);
+        let Ok(headers) = csv_reader.headers().cloned() else {
+            return Vec::new();
+        };
+        let in_log = |key: TxKey| {
+            self.transaction_log.get(&key).is_some_and(|logged| {
+                matches!(
+                    logged.ty,
+                    TransactionType::Deposit | TransactionType::Withdrawal
+                )
+            })
+        };
+        let mut seen: HashSet<TxKey> = HashSet::new();
+        let mut errors = Vec::new();
+        let mut string_record = csv::StringRecord::new();
+        while let Ok(true) = csv_reader.read_record(&mut string_record) {
+            let Ok(record) = string_record.deserialize::<Transaction>(Some(&headers)) else {
+                continue;
+            };
+            let key = self.tx_key(&record);
+            match record.ty {
+                TransactionType::Deposit | TransactionType::Withdrawal => {
+                    seen.insert(key);
+                }
+                TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Reversal
+                    if !seen.contains(&key) && !in_log(key) =>
+                {
+                    let line = string_record.position().map(csv::Position::line);
+                    errors.push(
+                        TransactionError::processing(&record, EngineError::TransactionNotFound)
+                            .at_line(line),
+                    );
+                }
+                _ => {}
+            }
+        }
+        errors
+    }
+
+    /// Runs `check_references` on `data` if the reference precheck is enabled, failing with the
+    /// dangling references.
+    fn precheck_references(&self, data: &[u8]) -> Result<(), TransactionProcessingError> {
+        if !self.config.reference_precheck {
+            return Ok(());
+        }
+        let errors = self.check_references(data);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        }
+    }
+
+    /// Deserializes and processes every record of `csv_reader`, collecting all errors.
+    ///
+    /// If a dispute reorder window is configured (see `with_dispute_reorder_window`), disputes,
+    /// resolves and chargebacks failing with `TransactionNotFound` (or `AccountNotFound`, when
+    /// they precede the first deposit of the client) are held back and retried after every
+    /// subsequent deposit or withdrawal, for at most `window` records; then the last error is
+    /// reported.
+    ///
+    /// `sink` is called with the outcome of every transaction read, once it is final (a held
+    /// back transaction only when it is processed or falls out of the window).
+    fn process_csv_records<R: Read, F: FnMut(&Transaction, Result<(), EngineError>)>(
+        &self,
+        csv_reader: &mut csv::Reader<R>,
+        sink: &mut F,
+    ) -> Result<(), TransactionProcessingError> {
+        let window = self.config.dispute_reorder_window;
+        let mut pending: VecDeque<PendingTransaction> = VecDeque::new();
+
+        let mut errors = Vec::with_capacity(1000);
+
+        let headers = csv_reader
+            .headers()
+            .map_err(|e| {
+                TransactionProcessingError::MultipleErrors(vec![Engine::format_read_error(&e)])
+            })?
+            .clone();
+        if headers.is_empty() {
+            return Err(TransactionProcessingError::MultipleErrors(vec![
+                missing_header(),
+            ]));
+        }
+        let amount_idx = if self.config.preserve_raw_amount {
+            headers.iter().position(|h| h.trim() == "amount")
+        } else {
+            None
+        };
+        let mut ordering = match self.config.timestamp_ordering {
+            Some(chunk_records) => Some(
+                TimestampOrdering::new(&headers, chunk_records)
+                    .map_err(|e| TransactionProcessingError::MultipleErrors(vec![e]))?,
+            ),
+            None => None,
+        };
+        let mut string_record = csv::StringRecord::new();
+        let deadline = self
+            .config
+            .max_duration
+            .map(|max_duration| (Instant::now() + max_duration, max_duration));
+
+        let mut index = 0;
+        loop {
+            if let Some((deadline, max_duration)) = deadline {
+                if Instant::now() >= deadline {
+                    errors.push(max_duration_exceeded(index, max_duration));
+                    break;
+                }
+            }
+            let next = match ordering.as_mut() {
+                Some(ordering) => ordering.next_transaction(csv_reader).map(|read| {
+                    read.map(|(tx, record)| {
+                        string_record = record;
+                        tx
+                    })
+                }),
+                None => match csv_reader.read_record(&mut string_record) {
+                    Ok(true) => Some(
+                        string_record
+                            .deserialize::<Transaction>(Some(&headers))
+                            .map_err(|e| Engine::format_read_error(&e)),
+                    ),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(Engine::format_read_error(&e))),
+                },
+            };
+            // The raw amounts are only kept if requested.
+            let next = next.map(|read| {
+                read.map(|tx| {
+                    let raw_amount = amount_idx
+                        .and_then(|idx| string_record.get(idx))
+                        .map(str::trim)
+                        .filter(|raw| !raw.is_empty())
+                        .map(String::from);
+                    (tx, raw_amount)
+                })
+            });
+            match next {
+                Some(Ok((record, _))) if !self.config.accepts_type(&record.ty) => continue,
+                Some(Ok((record, raw_amount))) => {
+                    let line = string_record.position().map(csv::Position::line);
+                    index += 1;
+                    let refers_to_log = matches!(
+                        record.ty,
+                        TransactionType::Dispute
+                            | TransactionType::Resolve
+                            | TransactionType::Chargeback
+                            | TransactionType::Release
+                            | TransactionType::Reversal
+                    );
+                    // Keep the relative order of the operations on a held back transaction.
+                    if refers_to_log
+                        && pending
+                            .iter()
+                            .any(|p| self.tx_key(&p.tx) == self.tx_key(&record))
+                    {
+                        pending.push_back(PendingTransaction {
+                            tx: record,
+                            read_at: index,
+                            line,
+                            error: EngineError::TransactionNotFound,
+                        });
+                    } else {
+                        match self.process_transaction(&record) {
+                            Ok(()) if !refers_to_log => {
+                                sink(&record, Ok(()));
+                                if let Some(raw_amount) = raw_amount {
+                                    self.raw_amounts.insert(self.tx_key(&record), raw_amount);
+                                }
+                                if !pending.is_empty() {
+                                    self.retry_pending(&mut pending, &mut errors, sink);
+                                }
+                            }
+                            Ok(()) => sink(&record, Ok(())),
+                            Err(
+                                e @ (EngineError::TransactionNotFound
+                                | EngineError::AccountNotFound),
+                            ) if refers_to_log && window > 0 => {
+                                pending.push_back(PendingTransaction {
+                                    tx: record,
+                                    read_at: index,
+                                    line,
+                                    error: e,
+                                });
+                            }
+                            Err(e) => {
+                                sink(&record, Err(e.clone()));
+                                errors.push(TransactionError::processing(&record, e).at_line(line))
+                            }
+                        }
+                    }
+
+                    while pending.front().is_some_and(|p| index - p.read_at >= window) {
+                        let p = pending.pop_front().unwrap();
+                        sink(&p.tx, Err(p.error.clone()));
+                        errors.push(p.into_error());
+                    }
+                }
+                Some(Err(e)) => {
+                    errors.push(e);
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        for p in pending {
+            sink(&p.tx, Err(p.error.clone()));
+            errors.push(p.into_error());
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs `process` on the file at `input_path` unless idempotent file re-runs are enabled
+    /// and the file was already processed, see `with_idempotent_file_reruns`.
+    ///
+    /// With idempotent file re-runs the file is read once and `process` gets its content, the
+    /// bytes the fingerprint is computed from; otherwise it gets `None` and reads the file itself.
+    fn process_file_once(
+        &self,
+        input_path: &str,
+        process: impl FnOnce(&Self, Option<&[u8]>) -> Result<(), TransactionProcessingError>,
+    ) -> Result<(), TransactionProcessingError> {
+        if !self.config.idempotent_file_reruns {
+            return process(self, None);
+        }
+        let content = std::fs::read(input_path).map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error opening file: {}",
+                e
+            ))])
+        })?;
+        let fingerprint = content_fingerprint(&content);
+        if self.processed_files.contains(&fingerprint) {
+            return Ok(());
+        }
+        let result = process(self, Some(&content));
+        let stopped = matches!(&result, Err(TransactionProcessingError::MultipleErrors(errors))
+            if errors.iter().any(is_max_duration_exceeded));
+        if !stopped {
+            self.processed_files.insert(fingerprint);
+        }
+        result
+    }
+
+    /// Processes the in-memory `content` of the transactions file at `input_path`, decompressing
+    /// it if it is gzip-compressed (see `is_gzip`).
+    fn process_transactions_content(
+        &self,
+        input_path: &str,
+        content: &[u8],
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        if is_gzip(input_path, content) {
+            return self.read_and_process_transactions(MultiGzDecoder::new(content), buffer_size);
+        }
+        self.precheck_references(content)?;
+        let mut csv_reader = transactions_reader_builder(&self.config)
+            .buffer_capacity(buffer_size)
+            .from_reader(content);
+        self.process_csv_records(&mut csv_reader, &mut |_, _| {})
+    }
+
+    /// Retries the held back transactions in order, keeping the ones still referring to an
+    /// unknown transaction or account.
+    fn retry_pending<F: FnMut(&Transaction, Result<(), EngineError>)>(
+        &self,
+        pending: &mut VecDeque<PendingTransaction>,
+        errors: &mut Vec<TransactionError>,
+        sink: &mut F,
+    ) {
+        let mut still_pending: VecDeque<PendingTransaction> =
+            VecDeque::with_capacity(pending.len());
+        for mut p in pending.drain(..) {
+            if still_pending
+                .iter()
+                .any(|other| self.tx_key(&other.tx) == self.tx_key(&p.tx))
+            {
+                still_pending.push_back(p);
+                continue;
+            }
+            match self.process_transaction(&p.tx) {
+                Ok(()) => sink(&p.tx, Ok(())),
+                Err(e @ (EngineError::TransactionNotFound | EngineError::AccountNotFound)) => {
+                    p.error = e;
+                    still_pending.push_back(p);
+                }
+                Err(e) => {
+                    sink(&p.tx, Err(e.clone()));
+                    errors.push(TransactionError::processing(&p.tx, e).at_line(p.line))
+                }
+            }
+        }
+        *pending = still_pending;
+    }
+
+    /// Reads and processes transactions from a CSV stream like `read_and_process_transactions`,
+    /// calling `sink` with the outcome of each transaction once it is processed, e.g. to log the
+    /// accepted/rejected transactions live or to report the progress of a long file. The errors
+    /// are still collected and returned at the end; records that cannot be read are not passed
+    /// to `sink` (there is no transaction).
+    pub fn read_and_process_transactions_with_sink<R, F>(
+        &self,
+        stream: R,
+        buffer_size: usize,
+        mut sink: F,
+    ) -> Result<(), TransactionProcessingError>
+    where
+        R: Read,
+        F: FnMut(&Transaction, Result<(), EngineError>),
+    {
+        let mut reader = BufReader::with_capacity(buffer_size, stream);
+
+        if self.config.reference_precheck {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).map_err(|e| {
+                TransactionProcessingError::MultipleErrors(vec![TransactionError::read(format!(
+                    "Error reading transaction record: {}",
+                    e
+                ))])
+            })?;
+            self.precheck_references(&data)?;
+            let mut csv_reader =
+                transactions_reader_builder(&self.config).from_reader(data.as_slice());
+            return self.process_csv_records(&mut csv_reader, &mut sink);
+        }
+
+        let mut csv_reader = transactions_reader_builder(&self.config).from_reader(reader);
+
+        self.process_csv_records(&mut csv_reader, &mut sink)
+    }
+
+    /// Reads and processes transactions from a CSV stream like `read_and_process_transactions`,
+    /// also returning the clients whose account was changed by the stream, e.g. to invalidate
+    /// cached balances after a batch.
+    ///
+    /// A client is touched by every transaction processed successfully for it (a resolve
+    /// ignored by `ResolvePolicy::IgnoreIfNotDisputed` included); a failed transaction does not
+    /// touch it. A deposit credited to the overflow account (see `AccountCapacityPolicy`)
+    /// touches the overflow client, and a dropped one touches nothing.
+    ///
+    /// # Returns
+    /// The touched clients, also when some transactions fail, along with the outcome of
+    /// `read_and_process_transactions`.
+    pub fn read_and_process_transactions_tracked<R: Read>(
+        &self,
+        stream: R,
+        buffer_size: usize,
+    ) -> (HashSet<ClientId>, Result<(), TransactionProcessingError>) {
+        let mut touched = HashSet::new();
+        let result =
+            self.read_and_process_transactions_with_sink(stream, buffer_size, |tx, result| {
+                if result.is_err() {
+                    return;
+                }
+                let client = match self.config.account_capacity_policy {
+                    _ if tx.ty != TransactionType::Deposit
+                        || self.accounts.contains_key(&tx.client) =>
+                    {
+                        tx.client
+                    }
+                    AccountCapacityPolicy::Overflow(overflow) => overflow,
+                    AccountCapacityPolicy::Reject | AccountCapacityPolicy::Drop => return,
+                };
+                touched.insert(client);
+            });
+        (touched, result)
+    }
+
+    /// Validates a CSV stream of transactions without changing the state of the engine (dry run),
+    /// e.g. to check a batch before committing it.
+    ///
+    /// The transactions go through the whole processing pipeline (amount checks, referred
+    /// transactions, locked accounts, ...) on a scratch copy of the accounts and of the
+    /// transaction log, so that disputes, resolves and chargebacks are checked against the
+    /// current state and the earlier transactions of the stream. The copy is discarded, and no
+    /// balance change is sent to the subscribers.
+    ///
+    /// # Parameters
+    /// - `stream`: A reader of the CSV transactions.
+    /// - `buffer_size`: The capacity of the CSV parser internal buffer.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the transactions would be processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting every reading/processing error.
+    pub fn validate_transactions<R: Read>(
+        &self,
+        stream: R,
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        self.scratch_copy()
+            .read_and_process_transactions(stream, buffer_size)
+    }
+
+    /// Validates the transactions of the CSV file at `input_path` (optionally gzip-compressed)
+    /// without changing the state of the engine, see `validate_transactions`.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the transactions would be processed without errors.
+    /// - `Err(TransactionProcessingError)` if the file cannot be opened, collecting every
+    ///   reading/processing error otherwise.
+    pub fn validate_transactions_from_csv(
+        &self,
+        input_path: &str,
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        self.validate_transactions(open_transactions_file(input_path)?, buffer_size)
+    }
+
+    /// Returns an engine with the same configuration and a copy of the accounts and of the logs,
+    /// without subscribers or statistics, see `validate_transactions`.
+    fn scratch_copy(&self) -> Engine {
+        Engine {
+            accounts: self.accounts.clone(),
+            transaction_log: self.transaction_log.clone(),
+            fee_log: self.fee_log.clone(),
+            historical_transactions: self.historical_transactions.clone(),
+            registered_clients: self.registered_clients.clone(),
+            config: self.config.clone(),
+            #[cfg(feature = "jsonschema")]
+            json_schema: self.json_schema.clone(),
+            ..Engine::new()
+        }
+    }
+
+    /// Processes an in-memory sequence of transactions in order, e.g. built with the
+    /// `Transaction::deposit`/`withdrawal`/`dispute`/`resolve`/`chargeback` constructors.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all transactions are processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting the errors of the failed transactions.
+    pub fn process_slice(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<(), TransactionProcessingError> {
+        let errors: Vec<TransactionError> = transactions
+            .iter()
+            .filter_map(|tx| {
+                self.process_transaction(tx)
+                    .err()
+                    .map(|e| TransactionError::processing(tx, e))
+            })
+            .collect();
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Processes an in-memory sequence of transactions on `workers` threads.
+    ///
+    /// By default the sequence is split into `workers` contiguous chunks processed concurrently,
+    /// so the transactions of a client spanning several chunks may be applied out of input order.
+    /// With `with_per_client_ordering`, each client is routed to the worker `client % workers`
+    /// instead: the transactions of a client are then processed in input order, as by
+    /// `process_slice`, while different clients are still processed in parallel.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all transactions are processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting the errors of the failed transactions,
+    ///   grouped by worker.
+    pub fn process_slice_parallel(
+        &self,
+        transactions: &[Transaction],
+        workers: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        let workers = workers.max(1);
+        let batches: Vec<Vec<(&Transaction, Option<u64>)>> = if self.config.per_client_ordering {
+            Engine::batches_by_client(transactions.iter().map(|tx| (tx, None)), workers)
+        } else {
+            transactions
+                .chunks(transactions.len().div_ceil(workers).max(1))
+                .map(|chunk| chunk.iter().map(|tx| (tx, None)).collect())
+                .collect()
+        };
+
+        let errors = self.process_batches(&worker_pool(workers)?, &batches);
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Processes several CSV streams concurrently on `workers` threads, with a deterministic
+    /// outcome: the final state of every client is the one of processing the streams one after
+    /// the other, in the given order, whatever the thread scheduling.
+    ///
+    /// The streams are read concurrently and held in memory, then the mutations of each client
+    /// are serialized on a single worker (`client % workers`), in stream order and in input order
+    /// within a stream: two streams depositing, withdrawing or disputing on the same client never
+    /// race. Different clients are still processed in parallel.
+    ///
+    /// The guarantee assumes that different clients do not reuse the same tx id, or that tx ids
+    /// are unique per client (see `with_tx_uniqueness`): otherwise, which of the two transactions
+    /// is rejected as repeated depends on the scheduling.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the records are read and processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting the read errors, stream by stream, then the
+    ///   errors of the failed transactions, grouped by worker.
+    pub fn process_streams<R: Read + Send>(
+        &self,
+        streams: Vec<R>,
+        workers: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        let mut parsed: Vec<(Vec<Transaction>, Vec<TransactionError>)> =
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = streams
+                    .into_iter()
+                    .map(|stream| {
+                        scope.spawn(move || {
+                            let mut transactions = Vec::new();
+                            let mut errors = Vec::new();
+                            let mut csv_reader =
+                                transactions_reader_builder(&self.config).from_reader(stream);
+                            for result in csv_reader.deserialize::<Transaction>() {
+                                match result {
+                                    Ok(tx) => transactions.push(tx),
+                                    Err(e) => errors.push(Engine::format_read_error(&e)),
+                                }
+                            }
+                            (transactions, errors)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("Reader thread panicked"))
+                    .collect()
+            });
+
+        let mut errors = Vec::new();
+        for (_, read_errors) in parsed.iter_mut() {
+            errors.append(read_errors);
+        }
+        let workers = workers.max(1);
+        let batches = Engine::batches_by_client(
+            parsed
+                .iter()
+                .flat_map(|(transactions, _)| transactions.iter().map(|tx| (tx, None))),
+            workers,
+        );
+        errors.extend(self.process_batches(&worker_pool(workers)?, &batches));
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads transactions from a CSV stream and processes them on `workers` threads, sharding
+    /// the clients: each client is routed to the shard `client % workers`, so the transactions
+    /// of a client are processed in input order, as by `read_and_process_transactions`, while
+    /// different clients are processed in parallel. The final state is the one of the serial
+    /// processing provided that tx ids are unique per client (see `with_tx_uniqueness`) or
+    /// across clients.
+    ///
+    /// The records are read in chunks of `PARALLEL_CHUNK_RECORDS`, each processed before the
+    /// next one is read, so the memory does not grow with the stream. The chunks are processed
+    /// by the same pool of `workers` threads, started once per call.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the records are read and processed without errors.
+    /// - `Err(TransactionProcessingError)` collecting the read errors and the errors of the
+    ///   failed transactions, chunk by chunk and grouped by shard within a chunk. Nothing is
+    ///   processed if a dispute reorder window (see `with_dispute_reorder_window`), the
+    ///   reference precheck (see `with_reference_precheck`) or the timestamp ordering (see
+    ///   `with_timestamp_ordering`) is configured, as none is supported by the sharded
+    ///   processing.
+    pub fn read_and_process_transactions_parallel<R: Read>(
+        &self,
+        stream: R,
+        buffer_size: usize,
+        workers: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        if self.config.dispute_reorder_window > 0
+            || self.config.reference_precheck
+            || self.config.timestamp_ordering.is_some()
+        {
+            return Err(TransactionProcessingError::MultipleErrors(vec![
+                TransactionError::other(
+                    "The dispute reorder window, the reference precheck and the timestamp \
+                     ordering are not supported by the parallel reader",
+                ),
+            ]));
+        }
+        let workers = workers.max(1);
+        let pool = worker_pool(workers)?;
+        let mut csv_reader = transactions_reader_builder(&self.config)
+            .buffer_capacity(buffer_size.max(1))
+            .from_reader(stream);
+        let headers = csv_reader
+            .headers()
+            .map_err(|e| {
+                TransactionProcessingError::MultipleErrors(vec![Engine::format_read_error(&e)])
+            })?
+            .clone();
+        if headers.is_empty() {
+            return Err(TransactionProcessingError::MultipleErrors(vec![
+                missing_header(),
+            ]));
+        }
+
+        let mut errors = Vec::new();
+        let mut record = csv::StringRecord::new();
+        let mut chunk: Vec<(Transaction, Option<u64>)> = Vec::with_capacity(PARALLEL_CHUNK_RECORDS);
+        loop {
+            let more = match csv_reader.read_record(&mut record) {
+                Ok(true) => {
+                    match record.deserialize::<Transaction>(Some(&headers)) {
+                        Ok(tx) if self.config.accepts_type(&tx.ty) => {
+                            chunk.push((tx, record.position().map(csv::Position::line)))
+                        }
+                        Ok(_) => {}
+                        Err(e) => errors.push(Engine::format_read_error(&e)),
+                    }
+                    true
+                }
+                Ok(false) => false,
+                Err(e) => {
+                    errors.push(Engine::format_read_error(&e));
+                    true
+                }
+            };
+            if !more || chunk.len() >= PARALLEL_CHUNK_RECORDS {
+                let batches =
+                    Engine::batches_by_client(chunk.iter().map(|(tx, line)| (tx, *line)), workers);
+                errors.extend(self.process_batches(&pool, &batches));
+                chunk.clear();
+            }
+            if !more {
+                break;
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Routes every client to the batch `client % workers`, keeping the order of `transactions`
+    /// (paired with their input line, if known) within each batch.
+    fn batches_by_client<'a>(
+        transactions: impl IntoIterator<Item = (&'a Transaction, Option<u64>)>,
+        workers: usize,
+    ) -> Vec<Vec<(&'a Transaction, Option<u64>)>> {
+        let mut batches = vec![Vec::new(); workers];
+        for (tx, line) in transactions {
+            batches[tx.client as usize % workers].push((tx, line));
+        }
+        batches
+    }
+
+    /// Processes the batches concurrently on `pool`, each batch in order, returning the errors of
+    /// the failed transactions grouped by batch.
+    fn process_batches(
+        &self,
+        pool: &rayon::ThreadPool,
+        batches: &[Vec<(&Transaction, Option<u64>)>],
+    ) -> Vec<TransactionError> {
+        pool.install(|| {
+            batches
+                .par_iter()
+                .map(|batch| {
+                    batch
+                        .iter()
+                        .filter_map(|&(tx, line)| {
+                            self.process_transaction(tx)
+                                .err()
+                                .map(|e| TransactionError::processing(tx, e).at_line(line))
+                        })
+                        .collect::<Vec<TransactionError>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Reads transactions from a CSV file with a `timestamp` column and processes only the ones
+    /// with `timestamp <= cutoff`, silently skipping the later ones. This reconstructs the account
+    /// state as of a point in time. The timestamps are read as for `with_timestamp_ordering`
+    /// (epoch seconds or ISO-8601).
+    ///
+    /// # Parameters
+    /// - `input_path`: The path to the CSV file containing the transactions.
+    /// - `cutoff`: The last timestamp to process (inclusive).
+    ///
+    /// # Returns
+    /// - `Ok(())` if all the transactions up to the cutoff are processed without errors.
+    /// - `Err(TransactionProcessingError)` if the file has no `timestamp` column, or collecting
+    ///   the reading/processing errors (including invalid or missing timestamps).
+    pub fn process_as_of(
+        &self,
+        input_path: &str,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), TransactionProcessingError> {
+        let file = File::open(input_path).map_err(|e| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error opening file: {}",
+                e
+            ))])
+        })?;
+        let mut csv_reader =
+            transactions_reader_builder(&self.config).from_reader(BufReader::new(file));
+
+        let headers = csv_reader
+            .headers()
+            .map_err(|e| {
+                TransactionProcessingError::MultipleErrors(vec![Engine::format_read_error(&e)])
+            })?
+            .clone();
+        if !headers.iter().any(|h| h.trim() == "timestamp") {
+            return Err(TransactionProcessingError::MultipleErrors(vec![
+                TransactionError::read(
+                    "Error reading transaction record: missing timestamp column",
+                ),
+            ]));
+        }
+
+        let mut errors = Vec::new();
+        for result in csv_reader.records() {
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => {
+                    errors.push(Engine::format_read_error(&e));
+                    continue;
+                }
+            };
+            let line = record.position().map(csv::Position::line);
+            match record.deserialize::<Transaction>(Some(&headers)) {
+                Ok(tx) => match tx.timestamp {
+                    Some(timestamp) if timestamp > cutoff => {}
+                    Some(_) => {
+                        if let Err(e) = self.process_transaction(&tx) {
+                            errors.push(TransactionError::processing(&tx, e).at_line(line));
+                        }
+                    }
+                    None => errors.push(
+                        TransactionError::read(
+                            "Error reading transaction record: missing timestamp",
+                        )
+                        .at_line(line),
+                    ),
+                },
+                Err(e) => errors.push(Engine::format_read_error(&e)),
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
         }
     }
 
-    /// Attempts to retrieve a mutable reference to an account associated with a given client ID.
+    /// Reads transactions from a CSV file through a read-only memory map and processes them.
+    ///
+    /// The mapped bytes are fed straight to the CSV parser, avoiding the intermediate `BufReader`
+    /// copy of the buffered path; this pays off on multi-gigabyte files. If the file cannot be
+    /// mapped (e.g. special files or platforms without mmap support) it falls back to the
+    /// buffered path.
+    ///
+    /// NOTE: the file must not be modified or truncated by other processes while it is processed.
     ///
     /// # Parameters
-    /// - `client`: The client ID whose account is to be retrieved.
+    /// - `input_path`: The path to the CSV file containing the transactions.
+    /// - `buffer_size`: The capacity of the CSV parser internal buffer.
     ///
     /// # Returns
-    /// - `Ok(RefMut<Account>)`: A mutable reference to the account if it exists and is not locked.
-    /// - `Err(EngineError)`: If the account is not found or is locked.
+    /// - `Ok(())` if all transactions are processed without errors.
+    /// - `Err(TransactionProcessingError)` if any errors occur during processing or reading.
+    #[cfg(feature = "mmap")]
+    pub fn read_and_process_transactions_from_mmap(
+        &self,
+        input_path: &str,
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        self.process_file_once(input_path, |engine, content| {
+            if let Some(content) = content {
+                return engine.process_transactions_content(input_path, content, buffer_size);
+            }
+            let file = File::open(input_path).map_err(|e| {
+                TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                    "Error opening file: {}",
+                    e
+                ))])
+            })?;
+
+            // SAFETY: the mapping is read-only and the caller guarantees the file is not modified
+            // while it is mapped.
+            match unsafe { memmap2::Mmap::map(&file) } {
+                Ok(mmap) => engine.process_transactions_content(input_path, &mmap, buffer_size),
+                Err(_) => engine.read_and_process_transactions(file, buffer_size),
+            }
+        })
+    }
+
+    /// Reads transactions from the rows of a SQLite query and processes them.
     ///
-    /// # Errors
-    /// - `AccountNotFound`: If the account associated with the given client ID does not exist.
-    /// - `AccountLocked`: If the account is locked.
-    fn try_get_account(
+    /// The query must return the columns `type`, `client`, `tx` and `amount` (in any order,
+    /// named as in the CSV header, e.g. `SELECT kind AS type, ...`); every row goes through the
+    /// same parsing and validation as a CSV record, with `NULL` read as an empty field. The
+    /// `record_line` of the errors is the 1-based number of the row.
+    ///
+    /// # Parameters
+    /// - `db_path`: The path (or SQLite URI) of the database.
+    /// - `query`: The query returning the transactions, in processing order.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all transactions are processed without errors.
+    /// - `Err(TransactionProcessingError)` if the database cannot be opened or the query fails,
+    ///   or collecting the reading/processing errors of the rows.
+    #[cfg(feature = "sqlite")]
+    pub fn read_and_process_transactions_from_sqlite(
         &self,
-        client: ClientId,
-    ) -> Result<dashmap::mapref::one::RefMut<'_, u16, Account>, EngineError> {
-        self.accounts
-            .get_mut(&client)
-            .ok_or(EngineError::AccountNotFound)
-            .and_then(|account| {
-                if account.locked {
-                    Err(EngineError::AccountLocked)
-                } else {
-                    Ok(account)
+        db_path: &str,
+        query: &str,
+    ) -> Result<(), TransactionProcessingError> {
+        let sqlite_error = |e: rusqlite::Error| {
+            TransactionProcessingError::MultipleErrors(vec![TransactionError::other(format!(
+                "Error reading SQLite database: {}",
+                e
+            ))])
+        };
+        let connection = rusqlite::Connection::open(db_path).map_err(sqlite_error)?;
+        let mut statement = connection.prepare(query).map_err(sqlite_error)?;
+        let headers = csv::StringRecord::from(statement.column_names());
+        let mut rows = statement.query([]).map_err(sqlite_error)?;
+
+        let mut errors = Vec::new();
+        let mut line = 0;
+        loop {
+            let row = match rows.next() {
+                Ok(Some(row)) => row,
+                Ok(None) => break,
+                Err(e) => {
+                    errors.push(TransactionError::read(format!(
+                        "Error reading transaction record: {}",
+                        e
+                    )));
+                    break;
                 }
-            })
+            };
+            line += 1;
+            let record = match sqlite_record(row, headers.len()) {
+                Ok(record) => record,
+                Err(e) => {
+                    errors.push(
+                        TransactionError::read(format!("Error reading transaction record: {}", e))
+                            .at_line(Some(line)),
+                    );
+                    continue;
+                }
+            };
+            match record.deserialize::<Transaction>(Some(&headers)) {
+                Ok(tx) => {
+                    if let Err(e) = self.process_transaction(&tx) {
+                        errors.push(TransactionError::processing(&tx, e).at_line(Some(line)));
+                    }
+                }
+                Err(e) => errors.push(Engine::format_read_error(&e).at_line(Some(line))),
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
+        }
     }
 
-    /// Verifies the semantic validity of a transaction in relation to its original transaction.
+    /// Processes multiple transaction streams in global transaction id order.
+    ///
+    /// Every stream must be individually sorted by transaction id (non-decreasing, so disputes,
+    /// resolves and chargebacks may directly follow the transaction they refer to). The streams
+    /// are k-way merged: at every step the pending record with the lowest transaction id is
+    /// processed, ties are broken by the position of the stream in `readers`.
+    /// This allows disputes in one stream to refer to transactions of another stream without
+    /// reading the inputs twice.
     ///
     /// # Parameters
-    /// - `tx`: The transaction to be checked.
-    /// - `original_tx`: The original transaction that `tx` is related to.
+    /// - `readers`: The input streams, each sorted by transaction id.
+    /// - `buffer_size`: # of bytes of the read buffer of each stream.
     ///
     /// # Returns
-    /// - `Ok(Decimal)`: The amount associated with the original transaction, POSSIBLY WITH A NEGATIVE SIGN if
-    ///   the original transaction was a withdrawal.
-    /// - `Err(EngineError)`: An error if the transactions have different clients, the transaction
-    ///   type requires a disputed status that doesn't match, or if the original transaction lacks an amount.
-    ///
-    /// # Errors
-    /// - `DifferentClient`: If the transactions are from different clients.
-    /// - `TransactionAlreadyDisputed`: If a dispute is attempted on an already disputed transaction.
-    /// - `TransactionNotDisputed`: If a resolve or chargeback is attempted on a non-disputed transaction.
-    /// - `ReferredTransactionNoAmount`: If the original transaction does not have an amount.
-    fn check_transaction_semantic(
-        tx: &Transaction,
-        original_tx: &Transaction,
-    ) -> Result<Decimal, EngineError> {
-        if original_tx.client != tx.client {
-            return Err(EngineError::DifferentClient);
-        }
-        match tx.ty {
-            TransactionType::Dispute => {
-                if original_tx.disputed {
-                    return Err(EngineError::TransactionAlreadyDisputed);
+    /// - `Ok(())` if all transactions are processed without errors.
+    /// - `Err(TransactionProcessingError)` if any errors occur during processing or reading.
+    ///   If a stream is found out of order, processing stops and the error is reported along
+    ///   with the errors collected so far.
+    pub fn process_merged<R: Read>(
+        &self,
+        readers: Vec<R>,
+        buffer_size: usize,
+    ) -> Result<(), TransactionProcessingError> {
+        let mut streams: Vec<_> = readers
+            .into_iter()
+            .map(|stream| {
+                transactions_reader_builder(&self.config)
+                    .from_reader(BufReader::with_capacity(buffer_size, stream))
+                    .into_deserialize::<Transaction>()
+            })
+            .collect();
+
+        let num_streams = streams.len();
+        let mut errors = Vec::new();
+        let mut heap = BinaryHeap::with_capacity(num_streams);
+        let mut last_tx: Vec<Option<TxId>> = vec![None; num_streams];
+
+        // Pulls the next valid record of the stream `idx` into the heap, reading errors are collected.
+        let mut pull = |idx: usize,
+                        heap: &mut BinaryHeap<Reverse<(TxId, usize, MergeEntry)>>,
+                        errors: &mut Vec<TransactionError>|
+         -> Result<(), TransactionError> {
+            for result in streams[idx].by_ref() {
+                match result {
+                    Ok(record) => {
+                        if let Some(last) = last_tx[idx] {
+                            if record.tx < last {
+                                return Err(TransactionError::other(format!(
+                                    "Stream {} out of order: tx {} found after tx {}",
+                                    idx, record.tx, last
+                                )));
+                            }
+                        }
+                        last_tx[idx] = Some(record.tx);
+                        heap.push(Reverse((record.tx, idx, MergeEntry(record))));
+                        return Ok(());
+                    }
+                    Err(e) => errors.push(Engine::format_read_error(&e)),
                 }
             }
-            TransactionType::Resolve | TransactionType::Chargeback => {
-                if !original_tx.disputed {
-                    return Err(EngineError::TransactionNotDisputed);
-                }
+            Ok(())
+        };
+
+        for idx in 0..num_streams {
+            if let Err(e) = pull(idx, &mut heap, &mut errors) {
+                errors.push(e);
+                return Err(TransactionProcessingError::MultipleErrors(errors));
             }
-            _ => {}
         }
-        // This error condition should never happen as it is guaranteed only deposits and withdrawals
-        // with valid amount are stored in the transactions_log. But in general the process could read
-        // the content from a previous session from file if the file is corrupted some deposits or
-        // withdrawals without amount could occur.
-        let mut amount = original_tx
-            .amount
-            .ok_or(EngineError::ReferredTransactionNoAmount)?;
 
-        if original_tx.ty == TransactionType::Withdrawal {
-            amount = -amount;
+        while let Some(Reverse((_, idx, MergeEntry(record)))) = heap.pop() {
+            if let Err(e) = self.process_transaction(&record) {
+                errors.push(TransactionError::processing(&record, e));
+            }
+            if let Err(e) = pull(idx, &mut heap, &mut errors) {
+                errors.push(e);
+                return Err(TransactionProcessingError::MultipleErrors(errors));
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(TransactionProcessingError::MultipleErrors(errors))
+        } else {
+            Ok(())
         }
-        Ok(amount)
     }
 
     /// Performs a safe addition of two decimal numbers.
@@ -278,39 +3313,7 @@ impl EngineFunctions for Engine {
         stream: R,
         buffer_size: usize,
     ) -> Result<(), TransactionProcessingError> {
-        let reader = BufReader::with_capacity(buffer_size, stream);
-
-        let mut csv_reader = ReaderBuilder::new().has_headers(true).from_reader(reader);
-
-        let mut errors = Vec::with_capacity(1000);
-        loop {
-            match csv_reader.deserialize::<Transaction>().next() {
-                Some(Ok(record)) => {
-                    if let Err(e) = self.process_transaction(&record) {
-                        errors.push(format!("Error processing {:?}: {}", record, e));
-                    }
-                }
-                Some(Err(e)) => {
-                    let error_message = e.to_string();
-                    if let Some(pos) = error_message.find("Unknown transaction type") {
-                        errors.push(format!(
-                            "Error reading transaction record: {}",
-                            &error_message[pos..]
-                        ));
-                    } else {
-                        errors.push(format!("Error reading transaction record: {}", e));
-                    }
-                    continue;
-                }
-                None => break,
-            }
-        }
-
-        if !errors.is_empty() {
-            Err(TransactionProcessingError::MultipleErrors(errors))
-        } else {
-            Ok(())
-        }
+        self.read_and_process_transactions_with_sink(stream, buffer_size, |_, _| {})
     }
 
     /// Reads transactions from a CSV file and processes them using the Engine.
@@ -326,13 +3329,11 @@ impl EngineFunctions for Engine {
         input_path: &str,
         buffer_size: usize,
     ) -> Result<(), TransactionProcessingError> {
-        let file = File::open(input_path).map_err(|e| {
-            TransactionProcessingError::MultipleErrors(vec![format!("Error opening file: {}", e)])
-        })?;
-        let reader = BufReader::new(file);
-
-        // Call the method from the Engine struct
-        self.read_and_process_transactions(reader, buffer_size)
+        self.process_file_once(input_path, |engine, content| match content {
+            Some(content) => engine.process_transactions_content(input_path, content, buffer_size),
+            None => engine
+                .read_and_process_transactions(open_transactions_file(input_path)?, buffer_size),
+        })
     }
 
     /// Loads transactions and accounts from CSV files dumped from a previous session to populate the internal maps.
@@ -364,10 +3365,25 @@ impl EngineFunctions for Engine {
                 .trim(Trim::All)
                 .from_reader(BufReader::new(file));
 
-            for result in rdr.deserialize::<Transaction>() {
+            let headers = rdr.headers().map_err(EngineSerDeserError::Csv)?.clone();
+            let raw_amount_idx = headers.iter().position(|h| h == "raw_amount");
+            for result in rdr.records() {
+                let result = result.and_then(|record| {
+                    let transaction = record.deserialize::<Transaction>(Some(&headers))?;
+                    Ok((transaction, record))
+                });
                 match result {
-                    Ok(transaction) => {
-                        self.transaction_log.insert(transaction.tx, transaction);
+                    Ok((transaction, record)) => {
+                        if let Some(raw_amount) = raw_amount_idx
+                            .and_then(|idx| record.get(idx))
+                            .filter(|raw| !raw.is_empty())
+                        {
+                            self.raw_amounts
+                                .insert(self.tx_key(&transaction), raw_amount.to_string());
+                        }
+                        let key = self.tx_key(&transaction);
+                        self.historical_transactions.insert(key);
+                        self.transaction_log.insert(key, transaction);
                     }
                     Err(e) => {
                         eprintln!("Error parsing CSV record: {:?}", e);
@@ -391,30 +3407,23 @@ impl EngineFunctions for Engine {
         // Load accounts from CSV
         {
             let file = File::open(accounts_path).map_err(EngineSerDeserError::Io)?;
+            // The trailing overdraft_limit column is optional
             let mut rdr = ReaderBuilder::new()
                 .has_headers(true)
+                .flexible(true)
                 .trim(Trim::All)
+                .comment(Some(b'#'))
                 .from_reader(BufReader::new(file));
+            let has_overdraft_limit = rdr.headers().map_err(EngineSerDeserError::Csv)?.get(5)
+                == Some(OVERDRAFT_LIMIT_COLUMN);
 
             for result in rdr.records() {
                 let record = result.map_err(EngineSerDeserError::Csv)?;
-                let client_id: u16 = record[0]
-                    .parse()
-                    .map_err(|_| EngineSerDeserError::InvalidClientId)?;
-                let account = Account {
-                    available: record[1]
-                        .parse()
-                        .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
-                    held: record[2]
-                        .parse()
-                        .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
-                    total: record[3]
-                        .parse()
-                        .map_err(|_| EngineSerDeserError::InvalidDecimal)?,
-                    locked: record[4]
-                        .parse()
-                        .map_err(|_| EngineSerDeserError::InvalidBool)?,
-                };
+                let (client_id, mut account) = Engine::parse_account_record(&record, 0)?;
+                if has_overdraft_limit {
+                    account.overdraft_limit = parse_overdraft_limit(record.get(5))
+                        .ok_or(EngineSerDeserError::InvalidDecimal)?;
+                }
                 self.accounts.insert(client_id, account);
             }
         }
@@ -437,13 +3446,22 @@ impl EngineFunctions for Engine {
         writer: W,
         buffer_size: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
         // Wrap the writer with a buffered writer
 
         let mut buf_writer = BufWriter::with_capacity(buffer_size, writer);
-        writeln!(buf_writer, "client,available,held,total,locked")?;
+        writeln!(
+            buf_writer,
+            "{}",
+            account_csv_header(&self.config.dump).join(",")
+        )?;
         buf_writer.flush()?; // Ensure the header is written
 
-        serialize_account_balances_csv(&self.accounts, &mut buf_writer)?;
+        serialize_account_balances_csv_with_options(
+            &self.accounts,
+            &mut buf_writer,
+            &self.config.dump,
+        )?;
 
         buf_writer.flush()?;
 
@@ -466,15 +3484,33 @@ impl EngineFunctions for Engine {
         transactions_path: &str,
         buffer_size: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_before_dump()?;
         // Dump transactions
 
         let file = File::create(transactions_path)?;
         let mut buf_writer = BufWriter::with_capacity(buffer_size, file);
 
-        writeln!(buf_writer, "type,client,tx,amount,disputed")?;
-        buf_writer.flush()?; // Ensure the header is written
+        if self.config.preserve_raw_amount {
+            writeln!(
+                buf_writer,
+                "type,client,tx,amount,disputed,raw_amount,charged_back,reversed,released"
+            )?;
+            buf_writer.flush()?; // Ensure the header is written
 
-        serialize_transcation_log_csv(&self.transaction_log, &mut buf_writer)?;
+            serialize_transcation_log_csv_with_raw_amounts(
+                &self.transaction_log,
+                &self.raw_amounts,
+                &mut buf_writer,
+            )?;
+        } else {
+            writeln!(
+                buf_writer,
+                "type,client,tx,amount,disputed,charged_back,reversed,released"
+            )?;
+            buf_writer.flush()?; // Ensure the header is written
+
+            serialize_transcation_log_csv(&self.transaction_log, &mut buf_writer)?;
+        }
 
         buf_writer.flush()?;
 
@@ -486,14 +3522,39 @@ impl EngineStateTransitionFunctions for Engine {
     /// Process a transaction. This function is a dispatch to the correct processing function
     /// for the given transaction type.
     fn process_transaction(&self, tx: &Transaction) -> Result<(), EngineError> {
-        match tx.ty {
-            TransactionType::Deposit => self.process_deposit(tx)?,
-            TransactionType::Withdrawal => self.process_withdrawal(tx)?,
-            TransactionType::Dispute => self.process_dispute(tx)?,
-            TransactionType::Resolve => self.process_resolve(tx)?,
-            TransactionType::Chargeback => self.process_chargeback(tx)?,
-        }
-        Ok(())
+        #[cfg(feature = "timing")]
+        let start = Instant::now();
+
+        #[cfg(feature = "jsonschema")]
+        self.validate_schema(tx)
+            .inspect_err(|e| self.metrics.record_rejection(e))?;
+        let input = tx;
+        let tx =
+            normalize_input(tx, &self.config).inspect_err(|e| self.metrics.record_rejection(e))?;
+        let tx = tx.as_ref();
+        let result = match tx.ty {
+            TransactionType::Deposit => self.process_deposit(tx),
+            TransactionType::Withdrawal => self.process_withdrawal(tx),
+            TransactionType::Dispute => self.process_dispute(tx),
+            TransactionType::Resolve => self.process_resolve(tx),
+            TransactionType::Chargeback => self.process_chargeback(tx),
+            TransactionType::Hold => self.process_hold(tx),
+            TransactionType::Release => self.process_release(tx),
+            TransactionType::Reversal => self.process_reversal(tx),
+            TransactionType::Freeze => self.process_freeze(tx),
+            TransactionType::Unfreeze => self.process_unfreeze(tx),
+            TransactionType::Fee => Err(EngineError::FeeNotProcessable),
+        };
+        self.metrics.record(&tx.ty, &result);
+        let result = result.and_then(|()| self.append_to_wal(input));
+
+        #[cfg(feature = "timing")]
+        self.timings
+            .entry(tx.ty.clone())
+            .or_default()
+            .record(start.elapsed());
+
+        result
     }
 
     /// Process a deposit transaction.
@@ -506,29 +3567,88 @@ impl EngineStateTransitionFunctions for Engine {
     /// - `Err(EngineError)`: If the transaction is invalid or if the account is locked.
     ///
     /// # Errors
+    /// The checks are performed in this order (see `DepositCheckOrder` to check the lock
+    /// before the repeated tx id):
     /// - `NoAmount`: If the transaction does not have an amount.
     /// - `DepositAmountInvalid`: If the transaction amount is not greater than 0.
+    /// - `AmountExceedsLimit`: If the transaction amount exceeds the limit set with `with_max_amount`.
+    /// - `UnknownClient`: If the account does not exist, strict mode is enabled
+    ///   (`with_strict_clients`) and the client is not registered.
+    /// - `AccountCapacityReached`: If the account does not exist and the limit set with
+    ///   `with_max_accounts` is reached (with `AccountCapacityPolicy::Reject`).
     /// - `TransactionRepeated`: If the transaction id has already been processed in this session.
+    ///   The account is not created in this case.
     /// - `AccountLocked`: If the account is already locked.
+    /// - `InsufficientFundsForFee`: If the available funds after the deposit cannot cover the
+    ///   transaction fee. The account is not created in this case.
     fn process_deposit(&self, tx: &Transaction) -> Result<(), EngineError> {
         let amount = tx.amount.ok_or(EngineError::NoAmount)?;
         if amount <= Decimal::from(0) {
             return Err(EngineError::DepositAmountInvalid);
         }
-        if self.transaction_log.contains_key(&tx.tx) {
+        self.check_amount_limit(amount)?;
+        if self.config.strict_clients
+            && !self.accounts.contains_key(&tx.client)
+            && !self.registered_clients.contains(&tx.client)
+        {
+            return Err(EngineError::UnknownClient);
+        }
+        let overflow;
+        let tx = if self.config.max_accounts.is_some() && !self.accounts.contains_key(&tx.client) {
+            match new_account_deposit_client(tx, self.accounts.len(), &self.config)? {
+                client if client != tx.client => {
+                    overflow = Transaction {
+                        client,
+                        ..tx.clone()
+                    };
+                    &overflow
+                }
+                _ => tx,
+            }
+        } else {
+            tx
+        };
+        if self.config.deposit_check_order == DepositCheckOrder::LockedFirst
+            && self.accounts.get(&tx.client).is_some_and(|a| a.locked)
+        {
+            return Err(EngineError::AccountLocked);
+        }
+        if self.transaction_log.contains_key(&self.tx_key(tx)) {
             return Err(EngineError::TransactionRepeated);
         }
+        let fee = self.config.transaction_fee;
 
-        let mut account = self.accounts.entry(tx.client).or_default();
+        let mut account = match self.accounts.entry(tx.client) {
+            Entry::Occupied(entry) => entry.into_ref(),
+            // The account is not created if the deposit cannot cover the fee
+            Entry::Vacant(_) if amount < fee => return Err(EngineError::InsufficientFundsForFee),
+            Entry::Vacant(entry) => entry.insert(Account::default()),
+        };
 
         if account.locked {
             return Err(EngineError::AccountLocked);
         }
 
-        account.available = Engine::safe_add(&account.available, &amount)?;
-        account.total = Engine::safe_add(&account.total, &amount)?;
+        let mut available = Engine::safe_add(&account.available, &amount)?;
+        let mut total = Engine::safe_add(&account.total, &amount)?;
+        if fee > Decimal::ZERO {
+            if available < fee {
+                return Err(EngineError::InsufficientFundsForFee);
+            }
+            available = Engine::safe_sub(&available, &fee)?;
+            total = Engine::safe_sub(&total, &fee)?;
+        }
+        let before = account.clone();
+        account.available = available;
+        account.total = total;
+        self.publish_delta(tx.client, &before, &account);
 
-        self.transaction_log.insert(tx.tx, tx.clone());
+        self.transaction_log.insert(self.tx_key(tx), tx.clone());
+        self.record_fee(tx);
+        self.amount_stats
+            .entry(tx.ty.clone())
+            .or_default()
+            .record(amount);
         Ok(())
     }
 
@@ -544,29 +3664,51 @@ impl EngineStateTransitionFunctions for Engine {
     /// # Errors
     /// - `NoAmount`: If the transaction does not have an amount.
     /// - `WithdrawalAmountInvalid`: If the transaction amount is not greater than 0.
+    /// - `AmountExceedsLimit`: If the transaction amount exceeds the limit set with `with_max_amount`.
     /// - `TransactionRepeated`: If the transaction id has already been processed in this session.
     /// - `AccountLocked`: If the account is already locked.
     /// - `InsufficientFunds`: If the account does not have enough available funds.
+    /// - `InsufficientFundsForFee`: If the available funds after the withdrawal cannot cover the
+    ///   transaction fee.
     /// - `AccountNotFound`: If the account does not exist.
+    /// - `NegativeTotal`: If the total would go negative and the policy is `NegativeTotalPolicy::Reject`.
     fn process_withdrawal(&self, tx: &Transaction) -> Result<(), EngineError> {
         let amount = tx.amount.ok_or(EngineError::NoAmount)?;
         if amount <= Decimal::from(0) {
             return Err(EngineError::WithdrawalAmountInvalid);
         }
-        if self.transaction_log.contains_key(&tx.tx) {
+        self.check_amount_limit(amount)?;
+        if self.transaction_log.contains_key(&self.tx_key(tx)) {
             return Err(EngineError::TransactionRepeated);
         }
 
         let mut account = self.try_get_account(tx.client)?;
 
-        if account.available >= amount {
-            account.available = Engine::safe_sub(&account.available, &amount)?;
-            account.total = Engine::safe_sub(&account.total, &amount)?;
+        // The available funds can go down to the overdraft limit of the account
+        let available = Engine::safe_sub(&account.available, &amount)?;
+        if available >= -account.overdraft_limit {
+            let fee = self.config.transaction_fee;
+            let available = Engine::safe_sub(&available, &fee)?;
+            if available < -account.overdraft_limit {
+                return Err(EngineError::InsufficientFundsForFee);
+            }
+            let total = Engine::safe_sub(&account.total, &amount)?;
+            let total = Engine::safe_sub(&total, &fee)?;
+            self.check_total(&total)?;
+            let before = account.clone();
+            account.available = available;
+            account.total = total;
+            self.publish_delta(tx.client, &before, &account);
         } else {
             return Err(EngineError::InsufficientFunds);
         }
 
-        self.transaction_log.insert(tx.tx, tx.clone());
+        self.transaction_log.insert(self.tx_key(tx), tx.clone());
+        self.record_fee(tx);
+        self.amount_stats
+            .entry(tx.ty.clone())
+            .or_default()
+            .record(amount);
         Ok(())
     }
 
@@ -581,17 +3723,40 @@ impl EngineStateTransitionFunctions for Engine {
     ///
     /// # Errors
     /// - `TransactionNotFound`: If the transaction id is not found in the transaction log.
+    /// - `TxIdCollision`: Instead of `TransactionNotFound` if the transaction has an amount
+    ///   (it looks like a new transaction) and `with_tx_id_collision_detection` is enabled.
     /// - `AccountNotFound`: If the client id is not found in the accounts map.
-    /// - `AccountLocked`: If the account is already locked.
+    /// - `DifferentClient`: If the referred transaction belongs to a different client (checked
+    ///   before the account).
+    /// - `AccountLocked`: If the account is already locked, unless the `LockedAccountPolicy` is
+    ///   `AllowDisputes`.
+    /// - `HistoricalTransaction`: If the transaction was loaded from a previous session and
+    ///   the `HistoricalDisputePolicy` is `Reject`.
+    /// - `NegativeHeld`: If the held funds would go below zero (dispute of a withdrawal) and the
+    ///   policy is `NegativeHeldPolicy::Strict`.
+    /// - `WithdrawalNotDisputable`: If the transaction is a withdrawal and the `DisputePolicy`
+    ///   does not allow withdrawal disputes.
     fn process_dispute(&self, tx: &Transaction) -> Result<(), EngineError> {
-        let mut account = self.try_get_account(tx.client)?;
-        if let Some(mut original_tx) = self.transaction_log.get_mut(&tx.tx) {
+        self.check_reference_client(tx)?;
+        let mut account = self.try_get_dispute_account(tx.client)?;
+        if self.config.historical_dispute_policy == HistoricalDisputePolicy::Reject
+            && self.historical_transactions.contains(&self.tx_key(tx))
+        {
+            return Err(EngineError::HistoricalTransaction);
+        }
+        if let Some(mut original_tx) = self.transaction_log.get_mut(&self.tx_key(tx)) {
             let amount = Engine::check_transaction_semantic(tx, &original_tx)?;
-            account.available = Engine::safe_sub(&account.available, &amount)?;
-            account.held = Engine::safe_add(&account.held, &amount)?;
+            check_disputable(&original_tx, &self.config)?;
+            let available = Engine::safe_sub(&account.available, &amount)?;
+            let held = Engine::safe_add(&account.held, &amount)?;
+            self.check_held(tx, &account.held, &held)?;
+            let before = account.clone();
+            account.available = available;
+            account.held = held;
             original_tx.disputed = true;
+            self.publish_delta(tx.client, &before, &account);
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
         }
         Ok(())
     }
@@ -602,22 +3767,42 @@ impl EngineStateTransitionFunctions for Engine {
     /// - `tx`: The resolve transaction to be processed.
     ///
     /// # Returns
-    /// - `Ok(())`: If the transaction is successfully processed.
+    /// - `Ok(())`: If the transaction is successfully processed, or if it refers to a
+    ///   non-disputed transaction and the `ResolvePolicy` is `IgnoreIfNotDisputed` (no-op).
     /// - `Err(EngineError)`: If the transaction is invalid or if the account is locked.
     ///
     /// # Errors
     /// - `TransactionNotFound`: If the transaction id is not found in the transaction log.
+    /// - `TxIdCollision`: Instead of `TransactionNotFound` if the transaction has an amount
+    ///   (it looks like a new transaction) and `with_tx_id_collision_detection` is enabled.
     /// - `AccountNotFound`: If the client id is not found in the accounts map.
-    /// - `AccountLocked`: If the account is already locked.
+    /// - `DifferentClient`: If the referred transaction belongs to a different client (checked
+    ///   before the account).
+    /// - `AccountLocked`: If the account is already locked, unless the `LockedAccountPolicy` is
+    ///   `AllowDisputes`.
+    /// - `NegativeHeld`: If the held funds would go below zero and the policy is
+    ///   `NegativeHeldPolicy::Reject` or `Strict`.
     fn process_resolve(&self, tx: &Transaction) -> Result<(), EngineError> {
-        let mut account = self.try_get_account(tx.client)?;
-        if let Some(mut original_tx) = self.transaction_log.get_mut(&tx.tx) {
+        self.check_reference_client(tx)?;
+        let mut account = self.try_get_dispute_account(tx.client)?;
+        if let Some(mut original_tx) = self.transaction_log.get_mut(&self.tx_key(tx)) {
+            if self.config.resolve_policy == ResolvePolicy::IgnoreIfNotDisputed
+                && original_tx.client == tx.client
+                && !original_tx.disputed
+            {
+                return Ok(());
+            }
             let amount = Engine::check_transaction_semantic(tx, &original_tx)?;
-            account.available = Engine::safe_add(&account.available, &amount)?;
-            account.held = Engine::safe_sub(&account.held, &amount)?;
+            let available = Engine::safe_add(&account.available, &amount)?;
+            let held = Engine::safe_sub(&account.held, &amount)?;
+            self.check_held(tx, &account.held, &held)?;
+            let before = account.clone();
+            account.available = available;
+            account.held = held;
             original_tx.disputed = false;
+            self.publish_delta(tx.client, &before, &account);
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
         }
         Ok(())
     }
@@ -627,24 +3812,211 @@ impl EngineStateTransitionFunctions for Engine {
     /// # Parameters
     /// - `tx`: The chargeback transaction to be processed.
     ///
+    /// A chargeback with an amount smaller than the disputed amount is partial: only that portion
+    /// is reversed, the account stays unlocked and the logged transaction stays disputed for the
+    /// remainder (its amount is unchanged, the portion is added to its `charged_back`), to be
+    /// resolved or charged back later.
+    /// Once its whole amount is charged back, the logged transaction (which keeps its type) cannot
+    /// be disputed, resolved or charged back again, see `LockedAccountPolicy::AllowDisputes`.
+    ///
     /// # Returns
     /// - `Ok(())`: If the transaction is successfully processed.
     /// - `Err(EngineError)`: If the transaction is invalid or if the account is locked.
     ///
     /// # Errors
     /// - `TransactionNotFound`: If the transaction id is not found in the transaction log.
+    /// - `TxIdCollision`: Instead of `TransactionNotFound` if the transaction has an amount
+    ///   (it looks like a new transaction) and `with_tx_id_collision_detection` is enabled.
     /// - `AccountNotFound`: If the client id is not found in the accounts map.
-    /// - `AccountLocked`: If the account is already locked.
+    /// - `DifferentClient`: If the referred transaction belongs to a different client (checked
+    ///   before the account).
+    /// - `AccountLocked`: If the account is already locked, unless the `LockedAccountPolicy` is
+    ///   `AllowDisputes`.
+    /// - `ChargebackAmountInvalid`: If the amount is not greater than 0.
+    /// - `ChargebackExceedsHeld`: If the amount is greater than the disputed amount.
+    /// - `NegativeTotal`: If the total would go negative and the policy is `NegativeTotalPolicy::Reject`.
     fn process_chargeback(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx)?;
+        let mut account = self.try_get_dispute_account(tx.client)?;
+        if let Some(mut original_tx) = self.transaction_log.get_mut(&self.tx_key(tx)) {
+            let disputed = Engine::check_transaction_semantic(tx, &original_tx)?;
+            let (amount, partial) = chargeback_amount(tx, disputed)?;
+            let total = Engine::safe_sub(&account.total, &amount)?;
+            let held = Engine::safe_sub(&account.held, &amount)?;
+            self.check_total(&total)?;
+            let before = account.clone();
+            account.total = total;
+            account.held = held;
+            original_tx.charged_back += amount.abs();
+            if !partial {
+                account.locked = true;
+            }
+            self.publish_delta(tx.client, &before, &account);
+        } else {
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+    /// Process a hold transaction, moving its amount from the available to the held funds.
+    ///
+    /// # Parameters
+    /// - `tx`: The hold transaction to be processed.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the transaction is successfully processed.
+    /// - `Err(EngineError)`: If the transaction is invalid or if the account is locked.
+    ///
+    /// # Errors
+    /// - `NoAmount`: If the transaction does not have an amount.
+    /// - `HoldAmountInvalid`: If the transaction amount is not greater than 0.
+    /// - `TransactionRepeated`: If the transaction id has already been processed in this session.
+    /// - `AccountNotFound`: If the account does not exist.
+    /// - `AccountLocked`: If the account is already locked.
+    /// - `InsufficientFunds`: If the account does not have enough available funds.
+    fn process_hold(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let amount = tx.amount.ok_or(EngineError::NoAmount)?;
+        if amount <= Decimal::ZERO {
+            return Err(EngineError::HoldAmountInvalid);
+        }
+        if self.transaction_log.contains_key(&self.tx_key(tx)) {
+            return Err(EngineError::TransactionRepeated);
+        }
+
         let mut account = self.try_get_account(tx.client)?;
-        if let Some(original_tx) = self.transaction_log.get(&tx.tx) {
-            let amount = Engine::check_transaction_semantic(tx, &original_tx)?;
-            account.total = Engine::safe_sub(&account.total, &amount)?;
-            account.held = Engine::safe_sub(&account.held, &amount)?;
-            account.locked = true;
+        if account.available < amount {
+            return Err(EngineError::InsufficientFunds);
+        }
+        let available = Engine::safe_sub(&account.available, &amount)?;
+        let held = Engine::safe_add(&account.held, &amount)?;
+        let before = account.clone();
+        account.available = available;
+        account.held = held;
+        self.publish_delta(tx.client, &before, &account);
+
+        self.transaction_log.insert(self.tx_key(tx), tx.clone());
+        self.amount_stats
+            .entry(tx.ty.clone())
+            .or_default()
+            .record(amount);
+        Ok(())
+    }
+
+    /// Process a release transaction, moving the amount of the referred hold back to the
+    /// available funds. The logged hold is flagged as `released`, so it cannot be released twice.
+    ///
+    /// # Parameters
+    /// - `tx`: The release transaction to be processed.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the transaction is successfully processed.
+    /// - `Err(EngineError)`: If the transaction is invalid or if the account is locked.
+    ///
+    /// # Errors
+    /// - `TransactionNotFound`: If the transaction id is not found in the transaction log.
+    /// - `TxIdCollision`: Instead of `TransactionNotFound` if the transaction has an amount
+    ///   (it looks like a new transaction) and `with_tx_id_collision_detection` is enabled.
+    /// - `AccountNotFound`: If the client id is not found in the accounts map.
+    /// - `AccountLocked`: If the account is already locked.
+    /// - `DifferentClient`: If the hold belongs to a different client.
+    /// - `NotAHold`: If the referred transaction is not a hold.
+    /// - `HoldAlreadyReleased`: If the hold has already been released.
+    fn process_release(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx)?;
+        let mut account = self.try_get_account(tx.client)?;
+        if let Some(mut original_tx) = self.transaction_log.get_mut(&self.tx_key(tx)) {
+            let amount = Engine::check_release(tx, &original_tx)?;
+            let available = Engine::safe_add(&account.available, &amount)?;
+            let held = Engine::safe_sub(&account.held, &amount)?;
+            let before = account.clone();
+            account.available = available;
+            account.held = held;
+            original_tx.released = true;
+            self.publish_delta(tx.client, &before, &account);
         } else {
-            return Err(EngineError::TransactionNotFound);
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+
+    /// Process a reversal transaction, undoing the effect of the referred deposit or withdrawal
+    /// on the available and total funds (a deposit is subtracted, a withdrawal added back). The
+    /// logged transaction keeps its type and is flagged as `reversed`, so it cannot be disputed
+    /// or reversed again.
+    /// A transaction charged back cannot be reversed, as its account is locked.
+    ///
+    /// # Parameters
+    /// - `tx`: The reversal transaction to be processed.
+    ///
+    /// # Errors
+    /// - `TransactionNotFound`: If the transaction id is not found in the transaction log.
+    /// - `TxIdCollision`: Instead of `TransactionNotFound` if the transaction has an amount
+    ///   (it looks like a new transaction) and `with_tx_id_collision_detection` is enabled.
+    /// - `DifferentClient`: If the referred transaction belongs to a different client.
+    /// - `AccountNotFound`: If the client id is not found in the accounts map.
+    /// - `AccountLocked`: If the account is already locked.
+    /// - `AlreadyReversed`: If the referred transaction has already been reversed.
+    /// - `CannotReverseDisputed`: If the referred transaction is disputed.
+    /// - `HoldNotReversible`: If the referred transaction is a hold.
+    /// - `NegativeTotal`: If the total would go negative and the policy is `NegativeTotalPolicy::Reject`.
+    fn process_reversal(&self, tx: &Transaction) -> Result<(), EngineError> {
+        self.check_reference_client(tx)?;
+        let mut account = self.try_get_account(tx.client)?;
+        if let Some(mut original_tx) = self.transaction_log.get_mut(&self.tx_key(tx)) {
+            let amount = Engine::check_reversal(tx, &original_tx)?;
+            let available = Engine::safe_sub(&account.available, &amount)?;
+            let total = Engine::safe_sub(&account.total, &amount)?;
+            self.check_total(&total)?;
+            let before = account.clone();
+            account.available = available;
+            account.total = total;
+            original_tx.reversed = true;
+            self.publish_delta(tx.client, &before, &account);
+        } else {
+            return Err(reference_not_found(tx, &self.config));
+        }
+        Ok(())
+    }
+
+    /// Process a freeze transaction, locking the account of the client (deposits, withdrawals
+    /// and every other transaction are then rejected with `AccountLocked`) without touching its
+    /// balances.
+    ///
+    /// # Parameters
+    /// - `tx`: The freeze transaction to be processed.
+    ///
+    /// # Errors
+    /// - `AccountNotFound`: If the client id is not found in the accounts map.
+    /// - `AccountAlreadyFrozen`: If the account is already locked (frozen or charged back).
+    fn process_freeze(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let mut account = self
+            .accounts
+            .get_mut(&tx.client)
+            .ok_or(EngineError::AccountNotFound)?;
+        if account.locked {
+            return Err(EngineError::AccountAlreadyFrozen);
+        }
+        account.locked = true;
+        Ok(())
+    }
+
+    /// Process an unfreeze transaction, unlocking the account of the client without touching its
+    /// balances. An account locked by a chargeback is unlocked as well.
+    ///
+    /// # Parameters
+    /// - `tx`: The unfreeze transaction to be processed.
+    ///
+    /// # Errors
+    /// - `AccountNotFound`: If the client id is not found in the accounts map.
+    /// - `AccountNotFrozen`: If the account is not locked.
+    fn process_unfreeze(&self, tx: &Transaction) -> Result<(), EngineError> {
+        let mut account = self
+            .accounts
+            .get_mut(&tx.client)
+            .ok_or(EngineError::AccountNotFound)?;
+        if !account.locked {
+            return Err(EngineError::AccountNotFrozen);
         }
+        account.locked = false;
         Ok(())
     }
 }
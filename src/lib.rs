@@ -1,5 +1,6 @@
 pub mod asyncengine;
 pub mod basics;
+pub mod config;
 pub mod datastr;
 pub mod engine;
 pub mod utility;
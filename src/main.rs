@@ -1,17 +1,215 @@
 use std::env;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use sysinfo::System;
 
 use tempfile::NamedTempFile;
 use txn_engine::asyncengine::{AsyncEngine, AsyncEngineFunctions};
+use txn_engine::config::{AccountColumn, AmountUnits, ConfigFile, EngineConfig, Rounding};
+use txn_engine::datastr::account::AccountDelta;
+use txn_engine::datastr::deser::normalize_amount;
+use txn_engine::datastr::transaction::{TransactionProcessingError, TransactionType};
 use txn_engine::engine::{Engine, EngineFunctions};
-use txn_engine::utility::{generate_random_transactions, get_current_memory};
+use txn_engine::utility::{
+    bytes_to_mb, generate_random_transactions, generate_random_transactions_seeded, MemoryReporter,
+};
 
 const BUFFER_SIZE: usize = 16_384;
 
+/// Input path reading the transactions from stdin, e.g. `producer | cargo run -- - > accounts.csv`.
+const STDIN_INPUT: &str = "-";
+
+/// Directory of the `-dump` checkpoint in sync mode, see `Engine::checkpoint`.
+const CHECKPOINT_DIR: &str = "checkpoint";
+
+/// Command line flags (arguments starting with `-`, except the `-` stdin input), accepted in any
+/// position.
+struct CliOptions {
+    /// `-dump`: dump the transaction log after processing (a checkpoint of the whole engine
+    /// state in sync mode).
+    dump: bool,
+    /// `--group-errors`: report identical errors once, with their count.
+    group_errors: bool,
+    /// `-validate`: only report the errors of the transactions, without processing them or
+    /// printing the accounts, and exit with an error if there are any (sync mode).
+    validate: bool,
+    /// `--delta-feed`: print the balance changes instead of the accounts (sync mode).
+    delta_feed: bool,
+    /// `--ndjson`: print the accounts as newline-delimited JSON instead of CSV.
+    ndjson: bool,
+    /// `-json`: print the accounts as JSON Lines with the amounts as strings instead of CSV.
+    json: bool,
+    /// `--inline-errors`: also write the processing errors as `#` comment lines above the
+    /// accounts CSV header (ignored with `--ndjson`, `-json` and `--delta-feed`).
+    inline_errors: bool,
+    /// `-explain`: print the input amounts altered by the rounding to the precision to stderr
+    /// before processing them (input files only, not stdin).
+    explain: bool,
+    /// Worker threads of the async runtime, from the `--config=PATH` file.
+    worker_threads: Option<usize>,
+    /// Shards of the async engine maps, from the `--config=PATH` file.
+    shards: Option<usize>,
+    /// Engine configuration read from the `--config=PATH` file (default if not given),
+    /// overridden by the flags:
+    /// - `--omit-zero`: skip the zero-balance, unlocked accounts in the dump.
+    /// - `--columns=LIST`: write only the listed account columns, in order (e.g. `client,total`).
+    /// - `--preserve-raw-amount`: keep the input amount strings in the dumped transaction log (sync mode).
+    /// - `--amount-minor-units[=SCALE]`: input amounts are integer minor units (default scale 2).
+    /// - `--signed-amounts`: a deposit with a negative amount is a withdrawal.
+    /// - `--max-duration=SECONDS`: stop processing after the budget, dumping the partial accounts.
+    /// - `--dump-scale=N`: dump the amounts with `N` decimal places.
+    /// - `--dump-rounding=STRATEGY`: rounding of the amounts with `--dump-scale` (e.g. `half-even`).
+    /// - `-rounding=STRATEGY`: rounding of the input amounts to the precision (e.g. `half-even`).
+    /// - `-only=LIST`: process only the records of the listed types (e.g. `deposit,withdrawal`),
+    ///   skipping the others.
+    config: EngineConfig,
+}
+
+impl CliOptions {
+    fn from_flags(flags: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config_file = ConfigFile::default();
+        for flag in flags {
+            if let Some(path) = flag.strip_prefix("--config=") {
+                config_file = ConfigFile::load(path)?;
+            }
+        }
+        let mut config = config_file.engine;
+        for flag in flags {
+            match flag.as_str() {
+                "--omit-zero" => config.dump.omit_zero = true,
+                "--preserve-raw-amount" => config.preserve_raw_amount = true,
+                "--signed-amounts" => config.signed_amounts = true,
+                "--amount-minor-units" => config.amount_units = AmountUnits::Minor(2),
+                _ => {
+                    if let Some(scale) = flag.strip_prefix("--amount-minor-units=") {
+                        config.amount_units = AmountUnits::Minor(scale.parse()?);
+                    } else if let Some(list) = flag.strip_prefix("--columns=") {
+                        config.dump.columns = Some(AccountColumn::parse_list(list)?);
+                    } else if let Some(scale) = flag.strip_prefix("--dump-scale=") {
+                        config.dump.scale = Some(scale.parse()?);
+                    } else if let Some(rounding) = flag.strip_prefix("--dump-rounding=") {
+                        config.dump.rounding = Rounding::parse(rounding)?;
+                    } else if let Some(rounding) = flag.strip_prefix("-rounding=") {
+                        config.rounding = Rounding::parse(rounding)?;
+                    } else if let Some(list) = flag.strip_prefix("-only=") {
+                        config.only_types = Some(TransactionType::parse_list(list)?);
+                    } else if let Some(seconds) = flag.strip_prefix("--max-duration=") {
+                        config.max_duration = Some(Duration::try_from_secs_f64(seconds.parse()?)?);
+                    }
+                }
+            }
+        }
+        Ok(CliOptions {
+            dump: flags.iter().any(|f| f == "-dump"),
+            group_errors: flags.iter().any(|f| f == "--group-errors"),
+            validate: flags.iter().any(|f| f == "-validate"),
+            delta_feed: flags.iter().any(|f| f == "--delta-feed"),
+            ndjson: flags.iter().any(|f| f == "--ndjson"),
+            json: flags.iter().any(|f| f == "-json"),
+            inline_errors: flags.iter().any(|f| f == "--inline-errors"),
+            explain: flags.iter().any(|f| f == "-explain"),
+            worker_threads: config_file.worker_threads.map(NonZeroUsize::get),
+            shards: config_file.shards.map(NonZeroUsize::get),
+            config,
+        })
+    }
+}
+
+/// Splits the command line into the flags and the positional arguments, folding the
+/// space-separated `--config PATH` form into the `--config=PATH` flag.
+fn split_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(Vec<String>, Vec<String>), Box<dyn std::error::Error>> {
+    let mut flags = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let path = args
+                .next()
+                .ok_or("--config requires the path of a config file")?;
+            flags.push(format!("--config={path}"));
+        } else if arg.starts_with('-') && arg != STDIN_INPUT {
+            flags.push(arg);
+        } else {
+            positional.push(arg);
+        }
+    }
+    Ok((flags, positional))
+}
+
+/// Returns `true` if `e` is a write to a closed pipe, e.g. stdout piped into `head`.
+fn is_broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    let io_error = if let Some(e) = e.downcast_ref::<std::io::Error>() {
+        Some(e)
+    } else if let Some(e) = e.downcast_ref::<csv::Error>() {
+        match e.kind() {
+            csv::ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    } else if let Some(e) = e.downcast_ref::<csv_async::Error>() {
+        match e.kind() {
+            csv_async::ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    io_error.is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+/// Drops the output silently if its reader went away (broken pipe): there is nobody left to
+/// report to, so this is not an error of the engine.
+fn ignore_broken_pipe(
+    result: Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match result {
+        Err(e) if is_broken_pipe(e.as_ref()) => Ok(()),
+        result => result,
+    }
+}
+
+/// Prints the processing errors to stderr, grouped by cause if `--group-errors` is set.
+fn report_processing_errors(e: &TransactionProcessingError, options: &CliOptions) {
+    if options.group_errors {
+        eprintln!("Errors:");
+        for (message, count) in e.grouped() {
+            eprintln!("  {} (x{})", message, count);
+        }
+    } else {
+        eprintln!("Error: {}", e);
+    }
+}
+
+/// Writes the processing errors as `#` comment lines (grouped by cause if `--group-errors` is
+/// set), so that CSV readers honoring comments skip them. Line breaks in a message are replaced
+/// by spaces, as they would end the comment.
+fn write_inline_errors<W: Write>(
+    mut writer: W,
+    e: &TransactionProcessingError,
+    options: &CliOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lines: Vec<String> = if options.group_errors {
+        e.grouped()
+            .into_iter()
+            .map(|(message, count)| format!("{} (x{})", message, count))
+            .collect()
+    } else {
+        let TransactionProcessingError::MultipleErrors(errors) = e;
+        errors.iter().map(ToString::to_string).collect()
+    };
+    for line in lines {
+        writeln!(writer, "# {}", line.replace(['\r', '\n'], " "))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
 /// Main entry point of the transaction engine.
 ///
 /// The transaction engine processes transactions from a provided CSV file and updates account states accordingly.
@@ -21,24 +219,31 @@ const BUFFER_SIZE: usize = 16_384;
 /// Async mode supports normal processing and stress testing modes.
 ///
 /// Sync usage:
-///   Normal mode:     cargo run -- transactions.csv [-dump] > accounts.csv
-///   Stress test mode: cargo run -- stress-test <number_of_transactions> > accounts.csv
+///   Normal mode:     cargo run -- <transactions.csv|-> [-dump] [-validate] [--group-errors] [--inline-errors] [--omit-zero] [--columns=LIST] [--amount-minor-units[=SCALE]] [--signed-amounts] [--max-duration=SECONDS] [--dump-scale=N] [--dump-rounding=STRATEGY] [-rounding=STRATEGY] [-only=LIST] [--config PATH] [--preserve-raw-amount] [--delta-feed] [--ndjson] [-json] [-explain] > accounts.csv
+///   Stress test mode: cargo run -- stress-test <number_of_transactions> [seed] [-explain] > accounts.csv
 ///
 /// Async usage:
-///   Normal mode:     cargo run -- async transactions.csv [-dump] > accounts.csv
-///   Stress test mode: cargo run -- async stress-test <number_of_transactions> > accounts.csv
+///   Normal mode:     cargo run -- async <transactions.csv|-> [-dump] [--group-errors] [--inline-errors] [--ndjson] [-json] [--omit-zero] [--columns=LIST] [--amount-minor-units[=SCALE]] [--signed-amounts] [--max-duration=SECONDS] [--dump-scale=N] [--dump-rounding=STRATEGY] [-rounding=STRATEGY] [-only=LIST] [--config PATH] [-explain] > accounts.csv
+///   Stress test mode: cargo run -- async stress-test <number_of_transactions> [seed] [-explain] > accounts.csv
+///
+/// Validate usage (dry run, prints a pass/fail summary and exits with an error if invalid):
+///   cargo run -- validate <transactions.csv|-> [--config PATH]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args) = split_args(env::args())?;
+    let options = CliOptions::from_flags(&flags)?;
     if args.len() < 2 || args.len() > 5 {
         eprintln!("Sync Usage:");
-        eprintln!("  Normal mode:     cargo run -- transactions.csv [-dump] > accounts.csv");
+        eprintln!("  Normal mode:     cargo run -- <transactions.csv|-> [-dump] [-validate] [--group-errors] [--inline-errors] [--omit-zero] [--columns=LIST] [--amount-minor-units[=SCALE]] [--signed-amounts] [--max-duration=SECONDS] [--dump-scale=N] [--dump-rounding=STRATEGY] [-rounding=STRATEGY] [-only=LIST] [--config PATH] [--preserve-raw-amount] [--delta-feed] [--ndjson] [-json] [-explain] > accounts.csv");
         eprintln!(
-            "  Stress test mode: cargo run -- stress-test <number_of_transactions> > accounts.csv"
+            "  Stress test mode: cargo run -- stress-test <number_of_transactions> [seed] [-explain] > accounts.csv"
         );
 
         eprintln!("Async Usage:");
-        eprintln!("  Normal mode:     cargo run -- async transactions.csv [-dump] > accounts.csv");
-        eprintln!("  Stress test mode: cargo run -- async stress-test <number_of_transactions> > accounts.csv");
+        eprintln!("  Normal mode:     cargo run -- async <transactions.csv|-> [-dump] [--group-errors] [--inline-errors] [--ndjson] [-json] [--omit-zero] [--columns=LIST] [--amount-minor-units[=SCALE]] [--signed-amounts] [--max-duration=SECONDS] [--dump-scale=N] [--dump-rounding=STRATEGY] [-rounding=STRATEGY] [-only=LIST] [--config PATH] [-explain] > accounts.csv");
+        eprintln!("  Stress test mode: cargo run -- async stress-test <number_of_transactions> [seed] [-explain] > accounts.csv");
+
+        eprintln!("Validate Usage:");
+        eprintln!("  cargo run -- validate <transactions.csv|-> [--config PATH]");
 
         return Err("Incorrect number of arguments".into());
     }
@@ -50,25 +255,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             if args.len() <= 2 {
                 eprintln!("Usage:");
                 eprintln!(
-                    "  Normal mode:     cargo run -- async transactions.csv [-dump] > accounts.csv"
+                    "  Normal mode:     cargo run -- async <transactions.csv|-> [-dump] [--group-errors] [--inline-errors] [--ndjson] [-json] [--omit-zero] [--columns=LIST] [--amount-minor-units[=SCALE]] [--signed-amounts] [--max-duration=SECONDS] [--dump-scale=N] [--dump-rounding=STRATEGY] [-rounding=STRATEGY] [-only=LIST] [--config PATH] [-explain] > accounts.csv"
                 );
-                eprintln!("  Stress test mode: cargo run -- async stress-test <number_of_transactions> > accounts.csv");
+                eprintln!("  Stress test mode: cargo run -- async stress-test <number_of_transactions> [seed] [-explain] > accounts.csv");
                 return Err("Insufficient arguments for async mode".into());
             }
             eprint!("{} ", args[2]);
-            let tokio_runtime = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()?;
+            let tokio_runtime = match options.worker_threads {
+                Some(worker_threads) => tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(worker_threads)
+                    .enable_all()
+                    .build()?,
+                None => tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?,
+            };
             if args[2] == "stress-test" {
                 // asyc stress test mode
                 eprintln!("stress test mode ");
-                if args.len() != 4 {
+                if args.len() != 4 && args.len() != 5 {
                     return Err("Stress test requires a number of transactions to generate".into());
                 }
                 let num_transactions: usize = args[3].parse()?;
+                let seed = args.get(4).map(|seed| seed.parse::<u64>()).transpose()?;
 
                 tokio_runtime.block_on(async {
-                    match process_stress_test_async(num_transactions).await {
+                    match process_stress_test_async(num_transactions, seed, options.explain).await {
                         Ok(()) => {}
                         Err(e) => eprintln!("Error: {}", e),
                     };
@@ -77,14 +289,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // normal async processing
                 tokio_runtime.block_on(async {
                     let input_path = &args[2];
-                    let mut engine = AsyncEngine::default();
-                    match process_normal_async(
-                        &mut engine,
-                        input_path,
-                        args.contains(&"-dump".to_string()),
-                    )
-                    .await
-                    {
+                    let mut engine = AsyncEngine::default().with_config(options.config.clone());
+                    if let Some(shards) = options.shards {
+                        engine = engine.with_shards(shards);
+                    }
+                    match process_normal_async(&mut engine, input_path, &options).await {
                         Ok(()) => {}
                         Err(e) => eprintln!("Error: {}", e),
                     };
@@ -92,20 +301,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        "validate" => {
+            // validation mode
+            if args.len() != 3 {
+                return Err("Validate mode requires a transactions file".into());
+            }
+            process_validate(&args[2], &options)?;
+        }
+
         _ => {
             // sync mode
             if args[1] == "stress-test" {
                 // sync stress test mode
-                if args.len() != 3 {
+                if args.len() != 3 && args.len() != 4 {
                     return Err("Stress test requires a number of transactions to generate".into());
                 }
                 let num_transactions: usize = args[2].parse()?;
-                process_stress_test(num_transactions)?;
+                let seed = args.get(3).map(|seed| seed.parse::<u64>()).transpose()?;
+                process_stress_test(num_transactions, seed, options.explain)?;
             } else {
                 // normal sync processing
                 let input_path = &args[1];
-                let mut engine = Engine::default();
-                process_normal(&mut engine, input_path, args.contains(&"-dump".to_string()))?;
+                let mut engine = Engine::default().with_config(options.config.clone());
+                process_normal(&mut engine, input_path, &options)?;
             }
         }
     }
@@ -113,44 +331,211 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Process transactions from a CSV file and optionally dump the session state.
+/// Reads and processes the transactions of the file at `input_path` through a memory map.
+#[cfg(feature = "mmap")]
+fn process_file(engine: &mut Engine, input_path: &str) -> Result<(), TransactionProcessingError> {
+    engine.read_and_process_transactions_from_mmap(input_path, BUFFER_SIZE)
+}
+
+/// Reads and processes the transactions of the file at `input_path`.
+#[cfg(not(feature = "mmap"))]
+fn process_file(engine: &mut Engine, input_path: &str) -> Result<(), TransactionProcessingError> {
+    engine.read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+}
+
+/// Prints to stderr every amount of the transactions file at `input_path` that is altered by
+/// the rounding to the precision of `config` (see `normalize_amount`), as `row N: raw -> rounded`
+/// with `N` the 1-based index of the data row. Amounts that cannot be parsed are left to the
+/// processing to report.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if the file cannot be read or has no header.
+fn explain_rounding(
+    input_path: &str,
+    config: &EngineConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .delimiter(config.csv_dialect.delimiter)
+        .from_path(input_path)?;
+    let Some(amount_idx) = csv_reader.headers()?.iter().position(|h| h == "amount") else {
+        return Ok(());
+    };
+    for (row, record) in csv_reader.records().enumerate() {
+        let Some(raw) = record
+            .ok()
+            .and_then(|record| record.get(amount_idx).map(String::from))
+        else {
+            continue;
+        };
+        let (Ok(amount), Ok(rounded)) = (
+            raw.parse::<rust_decimal::Decimal>(),
+            normalize_amount(&raw, config.precision, config.rounding.strategy()),
+        ) else {
+            continue;
+        };
+        if rounded != amount {
+            eprintln!("row {}: {} -> {}", row + 1, raw, rounded);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the balance changes received from `deltas` to stdout as CSV.
+fn write_deltas(deltas: Receiver<AccountDelta>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv_writer = csv::Writer::from_writer(std::io::stdout());
+    for delta in deltas.try_iter() {
+        csv_writer.serialize(delta)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Process transactions from a CSV file (or stdin) and optionally dump the session state.
 ///
 /// # Parameters
 /// - `engine`: Mutable reference to the Engine that processes transactions.
-/// - `input_path`: Path to the CSV file containing transactions.
-/// - `should_dump`: Boolean indicating whether to dump the session state after processing.
+/// - `input_path`: Path to the CSV file containing transactions, `-` to stream them from stdin.
+/// - `options`: Command line flags (session dump, error grouping).
 ///
 /// # Errors
 /// - `Box<dyn std::error::Error>` if any errors occur while reading from the file, processing transactions, or writing the dump.
 fn process_normal(
     engine: &mut Engine,
     input_path: &str,
-    should_dump: bool,
+    options: &CliOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    match engine.read_and_process_transactions_from_csv(input_path, BUFFER_SIZE) {
-        Ok(()) => {}
-        Err(e) => eprintln!("Error: {}", e),
+    if options.validate {
+        return validate(engine, input_path, options);
     }
 
-    engine.dump_account_to_csv(std::io::stdout(), BUFFER_SIZE)?;
+    if options.explain && input_path != STDIN_INPUT {
+        explain_rounding(input_path, &options.config)?;
+    }
 
-    if should_dump {
-        let now: DateTime<Utc> = Utc::now();
-        let timestamp = now.format("%Y%m%d_%H%M%S").to_string();
+    let deltas = options.delta_feed.then(|| engine.subscribe_deltas());
 
-        let transactions_file = format!("{}_transaction_log.csv", timestamp);
+    let result = if input_path == STDIN_INPUT {
+        engine.read_and_process_transactions(std::io::stdin().lock(), BUFFER_SIZE)
+    } else {
+        process_file(engine, input_path)
+    };
 
-        engine.dump_transaction_log_to_csv(&transactions_file, BUFFER_SIZE)?;
+    if let Err(e) = &result {
+        report_processing_errors(e, options);
+    }
+
+    ignore_broken_pipe(match deltas {
+        Some(deltas) => write_deltas(deltas),
+        None if options.ndjson => engine.dump_account_to_ndjson(std::io::stdout(), BUFFER_SIZE),
+        None if options.json => engine.dump_account_to_json(std::io::stdout(), BUFFER_SIZE),
+        None => match &result {
+            Err(e) if options.inline_errors => write_inline_errors(std::io::stdout(), e, options),
+            _ => Ok(()),
+        }
+        .and_then(|()| engine.dump_account_to_csv(std::io::stdout(), BUFFER_SIZE)),
+    })?;
+
+    if options.dump {
+        engine.checkpoint(Path::new(CHECKPOINT_DIR))?;
+    }
+
+    for mismatch in engine.pre_dump_warnings() {
+        eprintln!("Warning: the transaction log and the accounts disagree: {mismatch}");
     }
 
     Ok(())
 }
 
+/// Validates the transactions of the file at `input_path` (or stdin) without changing the
+/// engine state, printing the errors to stderr (see `Engine::validate_transactions`).
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if the file cannot be opened or any transaction is invalid.
+fn validate(
+    engine: &Engine,
+    input_path: &str,
+    options: &CliOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match validate_input(engine, input_path) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            report_processing_errors(&e, options);
+            Err("Validation failed".into())
+        }
+    }
+}
+
+/// Validates the transactions of the file at `input_path`, or of stdin if it is `-`.
+fn validate_input(engine: &Engine, input_path: &str) -> Result<(), TransactionProcessingError> {
+    if input_path == STDIN_INPUT {
+        engine.validate_transactions(std::io::stdin().lock(), BUFFER_SIZE)
+    } else {
+        engine.validate_transactions_from_csv(input_path, BUFFER_SIZE)
+    }
+}
+
+/// Validates the transactions of the file at `input_path` (or stdin) for CI gates, printing a
+/// pass/fail summary with the error counts by cause to stdout (see
+/// `Engine::validate_transactions`). No account is printed.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any transaction is invalid.
+fn process_validate(
+    input_path: &str,
+    options: &CliOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let engine = Engine::default().with_config(options.config.clone());
+    let result = validate_input(&engine, input_path);
+    let mut stdout = std::io::stdout().lock();
+    match result {
+        Ok(()) => {
+            writeln!(stdout, "{}: PASS (0 errors)", input_path)?;
+            Ok(())
+        }
+        Err(e) => {
+            let TransactionProcessingError::MultipleErrors(errors) = &e;
+            writeln!(stdout, "{}: FAIL ({} errors)", input_path, errors.len())?;
+            for (message, count) in e.grouped() {
+                writeln!(stdout, "  {}: {}", message, count)?;
+            }
+            Err("Validation failed".into())
+        }
+    }
+}
+
+/// Prints the memory consumption delta of a stress test (from memory values in bytes, see
+/// `MemoryReporter`), or that it is unavailable if the process memory could not be read
+/// (rather than a misleading delta from 0).
+fn report_memory_delta(start_memory: Option<u64>, end_memory: Option<u64>) {
+    match (start_memory, end_memory) {
+        (Some(start_memory), Some(end_memory)) => {
+            let memory_delta_mb = bytes_to_mb(end_memory.saturating_sub(start_memory));
+            eprintln!("Memory consumption delta: {:.3} MB", memory_delta_mb);
+        }
+        _ => eprintln!("Memory consumption delta: memory unavailable"),
+    }
+}
+
+/// Generates the transactions of a stress test into `temp_file`, from `seed` if given.
+fn generate_stress_test_transactions(
+    num_transactions: usize,
+    seed: Option<u64>,
+    temp_file: &NamedTempFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match seed {
+        Some(seed) => generate_random_transactions_seeded(num_transactions, seed, temp_file),
+        None => generate_random_transactions(num_transactions, temp_file),
+    }
+}
+
 /// Process a specified number of random transactions and print performance metrics.
 ///
 /// # Parameters
 /// - `num_transactions`: The number of random transactions to generate and process.
-/// - `start_memory`: The current memory consumption at the start of the stress test.
+/// - `seed`: The seed of the generated transactions, for a reproducible dataset (random if `None`).
+/// - `explain`: Prints the generated amounts altered by the rounding to the precision (`-explain`).
 ///
 /// # Errors
 /// - `Box<dyn std::error::Error>` if any errors occur while generating or processing transactions.
@@ -158,16 +543,23 @@ fn process_normal(
 /// # Notes
 /// - The temporary file is automatically deleted when the function returns.
 /// - The performance metrics are printed to stderr.
-fn process_stress_test(num_transactions: usize) -> Result<(), Box<dyn std::error::Error>> {
+fn process_stress_test(
+    num_transactions: usize,
+    seed: Option<u64>,
+    explain: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Use NamedTempFile for automatic cleanup
     // The temporary file is automatically deleted when temp_file goes out of scope
     let temp_file = NamedTempFile::new()?;
-    generate_random_transactions(num_transactions, &temp_file)?;
+    generate_stress_test_transactions(num_transactions, seed, &temp_file)?;
+    if explain {
+        explain_rounding(temp_file.path().to_str().unwrap(), &EngineConfig::default())?;
+    }
 
     let mut engine = Engine::default();
     let start_time = Instant::now();
-    let mut system = System::new_all();
-    let start_memory = get_current_memory(&mut system);
+    let mut memory = MemoryReporter::system();
+    let start_memory = memory.current_bytes();
 
     // Process transactions directly from the temporary file
     // Error are not printed on the stderr during the stress test as it may affect the performance of the engine
@@ -180,13 +572,43 @@ fn process_stress_test(num_transactions: usize) -> Result<(), Box<dyn std::error
     {
         // let's measure the resoruces before creating the dump to properly measure the engine performance:
         let elapsed_time = start_time.elapsed();
-        let memory_delta = get_current_memory(&mut system).saturating_sub(start_memory);
+        let end_memory = memory.current_bytes();
 
-        let memory_delta_mb = (memory_delta as f64) / (1024.0 * 1024.0);
-        let engine_memory_mb = (engine.size_of() as f64) / (1024.0 * 1024.0);
+        let engine_memory_mb = bytes_to_mb(engine.size_of() as u64);
         eprintln!("Elapsed time: {:?}", elapsed_time);
         eprintln!("Engine Memory size: {:.3} MB", engine_memory_mb);
-        eprintln!("Memory consumption delta: {:.3} MB", memory_delta_mb);
+        report_memory_delta(start_memory, end_memory);
+    }
+
+    if let Err(violations) = engine.verify_invariants() {
+        eprintln!("Invariant violations: {:?}", violations);
+    }
+
+    let amount_stats = engine.amount_stats();
+    for ty in [TransactionType::Deposit, TransactionType::Withdrawal] {
+        if let Some(stats) = amount_stats.get(&ty) {
+            eprintln!(
+                "Amount stats {}: count {} sum {} min {} max {} mean {:.4}",
+                ty,
+                stats.count,
+                stats.sum,
+                stats.min.unwrap_or_default(),
+                stats.max.unwrap_or_default(),
+                stats.mean().unwrap_or_default()
+            );
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    {
+        // Compare against the memory-mapped read path on the same input.
+        let mmap_engine = Engine::default();
+        let start_time = Instant::now();
+        let _ = mmap_engine.read_and_process_transactions_from_mmap(
+            temp_file.path().to_str().unwrap(),
+            BUFFER_SIZE,
+        );
+        eprintln!("Elapsed time (mmap): {:?}", start_time.elapsed());
     }
 
     engine.dump_account_to_csv(std::io::stdout(), BUFFER_SIZE)?;
@@ -194,12 +616,12 @@ fn process_stress_test(num_transactions: usize) -> Result<(), Box<dyn std::error
     Ok(())
 }
 
-/// Process transactions from a CSV file and optionally dump the session state.
+/// Process transactions from a CSV file (or stdin) and optionally dump the session state.
 ///
 /// # Parameters
 /// - `engine`: Mutable reference to the AsycEngine that processes transactions.
-/// - `input_path`: Path to the CSV file containing transactions.
-/// - `should_dump`: Boolean indicating whether to dump the session state after processing.
+/// - `input_path`: Path to the CSV file containing transactions, `-` to stream them from stdin.
+/// - `options`: Command line flags (session dump, error grouping).
 ///
 /// # Errors
 /// - `Box<dyn std::error::Error>` if any errors occur while reading from the file, processing transactions, or writing the dump.
@@ -209,26 +631,54 @@ fn process_stress_test(num_transactions: usize) -> Result<(), Box<dyn std::error
 async fn process_normal_async(
     engine: &mut AsyncEngine,
     input_path: &str,
-    should_dump: bool,
+    options: &CliOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if options.explain && input_path != STDIN_INPUT {
+        explain_rounding(input_path, &options.config)?;
+    }
+
     let engine = Arc::new(engine);
-    match engine
-        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
-        .await
-    {
-        Ok(()) => {}
-        Err(e) => eprintln!("Error: {}", e),
+    let result = if input_path == STDIN_INPUT {
+        engine
+            .read_and_process_transactions(tokio::io::stdin(), BUFFER_SIZE)
+            .await
+    } else {
+        engine
+            .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+            .await
+    };
+    if let Err(e) = &result {
+        report_processing_errors(e, options);
     }
 
-    match engine
-        .dump_account_to_csv(tokio::io::stdout(), BUFFER_SIZE)
-        .await
-    {
+    let dumped = if options.ndjson {
+        engine
+            .dump_account_to_ndjson(tokio::io::stdout(), BUFFER_SIZE)
+            .await
+    } else if options.json {
+        engine
+            .dump_account_to_json(tokio::io::stdout(), BUFFER_SIZE)
+            .await
+    } else {
+        let inlined = match &result {
+            Err(e) if options.inline_errors => write_inline_errors(std::io::stdout(), e, options),
+            _ => Ok(()),
+        };
+        match inlined {
+            Ok(()) => {
+                engine
+                    .dump_account_to_csv(tokio::io::stdout(), BUFFER_SIZE)
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    };
+    match ignore_broken_pipe(dumped) {
         Ok(()) => {}
         Err(e) => eprintln!("Error: {}", e),
     };
 
-    if should_dump {
+    if options.dump {
         let now: DateTime<Utc> = Utc::now();
         let timestamp = now.format("%Y%m%d_%H%M%S").to_string();
 
@@ -250,7 +700,8 @@ async fn process_normal_async(
 ///
 /// # Parameters
 /// - `num_transactions`: The number of random transactions to generate and process.
-/// - `start_memory`: The current memory consumption at the start of the stress test.
+/// - `seed`: The seed of the generated transactions, for a reproducible dataset (random if `None`).
+/// - `explain`: Prints the generated amounts altered by the rounding to the precision (`-explain`).
 ///
 /// # Errors
 /// - `Box<dyn std::error::Error>` if any errors occur while generating or processing transactions.
@@ -261,16 +712,21 @@ async fn process_normal_async(
 /// - This function is `async` and must be awaited or executed within an `async` context.
 async fn process_stress_test_async(
     num_transactions: usize,
+    seed: Option<u64>,
+    explain: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Use NamedTempFile for automatic cleanup
     // The temporary file is automatically deleted when temp_file goes out of scope
     let temp_file = NamedTempFile::new()?;
-    generate_random_transactions(num_transactions, &temp_file)?;
+    generate_stress_test_transactions(num_transactions, seed, &temp_file)?;
+    if explain {
+        explain_rounding(temp_file.path().to_str().unwrap(), &EngineConfig::default())?;
+    }
 
     let engine = Arc::new(AsyncEngine::default());
     let start_time = Instant::now();
-    let mut system = System::new_all();
-    let start_memory = get_current_memory(&mut system);
+    let mut memory = MemoryReporter::system();
+    let start_memory = memory.current_bytes();
 
     // Process transactions directly from the temporary file
     // Error are not printed on the stderr during the stress test as it may affect the performance of the engine
@@ -283,13 +739,16 @@ async fn process_stress_test_async(
     {
         // let's measure the resoruces before creating the dump to properly measure the engine performance:
         let elapsed_time = start_time.elapsed();
-        let memory_delta = get_current_memory(&mut system).saturating_sub(start_memory);
+        let end_memory = memory.current_bytes();
 
-        let memory_delta_mb = (memory_delta as f64) / (1024.0 * 1024.0);
-        let engine_memory_mb = (engine.size_of().await as f64) / (1024.0 * 1024.0);
+        let engine_memory_mb = bytes_to_mb(engine.size_of().await as u64);
         eprintln!("Elapsed time: {:?}", elapsed_time);
         eprintln!("Engine Memory size: {:.3} MB", engine_memory_mb);
-        eprintln!("Memory consumption delta: {:.3} MB", memory_delta_mb);
+        report_memory_delta(start_memory, end_memory);
+    }
+
+    if let Err(violations) = engine.verify_invariants().await {
+        eprintln!("Invariant violations: {:?}", violations);
     }
 
     match engine
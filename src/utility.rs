@@ -1,5 +1,6 @@
 use csv::Writer;
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
 use rust_decimal::prelude::*;
 use std::{collections::HashMap, fs::File, process};
 use sysinfo::{Pid, System};
@@ -18,13 +19,44 @@ use tempfile::NamedTempFile;
 pub fn generate_random_transactions(
     num_transactions: usize,
     temp_file: &NamedTempFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_random_transactions(num_transactions, temp_file, &mut thread_rng())
+}
+
+/// Generates random transactions like `generate_random_transactions`, drawing them from a
+/// generator seeded with `seed`: the same seed always produces the same file, e.g. to compare
+/// stress runs against a fixed dataset.
+///
+/// # Parameters
+/// - `num_transactions`: The number of transactions to generate.
+/// - `seed`: The seed of the random generator.
+/// - `temp_file`: A temporary file created with `NamedTempFile::new()`. This file will be overwritten.
+///
+/// # Errors
+/// - `Box<dyn std::error::Error>` if any errors occur while writing to the file.
+pub fn generate_random_transactions_seeded(
+    num_transactions: usize,
+    seed: u64,
+    temp_file: &NamedTempFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_random_transactions(
+        num_transactions,
+        temp_file,
+        &mut StdRng::seed_from_u64(seed),
+    )
+}
+
+/// Writes `num_transactions` random transactions drawn from `rng` to `temp_file`.
+fn write_random_transactions<G: Rng>(
+    num_transactions: usize,
+    temp_file: &NamedTempFile,
+    rng: &mut G,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let file = File::create(temp_file.path())?;
     let mut writer = csv::Writer::from_writer(file);
-    let mut rng = thread_rng();
     writer.write_record(["type", "client", "tx", "amount"])?;
     for _ in 0..num_transactions {
-        let ty = match rand::random::<u8>() % 5 {
+        let ty = match rng.gen::<u8>() % 5 {
             0 => "deposit",
             1 => "withdrawal",
             2 => "dispute",
@@ -148,24 +180,104 @@ pub fn generate_random_transaction_concurrent_stream(
     Ok(temp_file)
 }
 
-/// Retrieves the memory usage of the current process.
+/// Unit of the memory values returned by a `MemorySource`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryUnit {
+    Bytes,
+    Kilobytes,
+}
+
+impl MemoryUnit {
+    /// Converts `value`, expressed in this unit, into bytes (saturating).
+    pub fn to_bytes(self, value: u64) -> u64 {
+        match self {
+            MemoryUnit::Bytes => value,
+            MemoryUnit::Kilobytes => value.saturating_mul(1024),
+        }
+    }
+}
+
+/// Source of the memory usage of a process, implemented by `sysinfo::System` and mockable in
+/// tests.
+pub trait MemorySource {
+    /// Returns the memory used by the process `pid`, in `unit()`, `None` if it cannot be read.
+    fn process_memory(&mut self, pid: u32) -> Option<u64>;
+
+    /// Unit of the values returned by `process_memory` (bytes unless overridden).
+    fn unit(&self) -> MemoryUnit {
+        MemoryUnit::Bytes
+    }
+}
+
+impl MemorySource for System {
+    fn process_memory(&mut self, pid: u32) -> Option<u64> {
+        self.refresh_all();
+        self.processes()
+            .get(&Pid::from_u32(pid))
+            .map(|process| process.memory())
+    }
+
+    /// `sysinfo` reports the process memory in bytes on every platform since 0.30 (older
+    /// versions reported kilobytes).
+    fn unit(&self) -> MemoryUnit {
+        MemoryUnit::Bytes
+    }
+}
+
+/// Retrieves the memory usage of the current process, in bytes.
 ///
 /// This function refreshes the system's process information to find and return
 /// the memory used by the current process.
 ///
 /// # Parameters
-/// - `system`: A mutable reference to a `System` instance that will be refreshed
+/// - `source`: The memory source, typically a `System` instance that will be refreshed
 ///   to obtain up-to-date process information.
 ///
 /// # Returns
-/// - `u64`: The memory consumption of the current process, in kilobytes.
-pub fn get_current_memory(system: &mut System) -> u64 {
-    system.refresh_all();
+/// - `Some(u64)`: The memory consumption of the current process, in bytes whatever the unit
+///   of `source`.
+/// - `None`: If the memory of the current process cannot be read (e.g. the process is not
+///   found on platforms where sysinfo cannot list processes).
+pub fn get_current_memory<S: MemorySource>(source: &mut S) -> Option<u64> {
     let current_pid = process::id(); // process::id() returns a u32
+    let unit = source.unit();
+    source
+        .process_memory(current_pid)
+        .map(|memory| unit.to_bytes(memory))
+}
+
+/// Reports the memory usage of the current process in bytes on every platform, normalizing the
+/// unit of its `MemorySource`.
+pub struct MemoryReporter<S: MemorySource = System> {
+    source: S,
+}
+
+impl MemoryReporter<System> {
+    /// Creates a reporter reading the process memory through `sysinfo`.
+    pub fn system() -> Self {
+        MemoryReporter::new(System::new_all())
+    }
+}
+
+impl<S: MemorySource> MemoryReporter<S> {
+    pub fn new(source: S) -> Self {
+        MemoryReporter { source }
+    }
+
+    /// Returns the memory used by the current process in bytes, `None` if it cannot be read.
+    pub fn current_bytes(&mut self) -> Option<u64> {
+        get_current_memory(&mut self.source)
+    }
+
+    /// Returns the memory used by the current process in mebibytes (`bytes / 1024^2`), `None`
+    /// if it cannot be read.
+    pub fn current_mb(&mut self) -> Option<f64> {
+        self.current_bytes().map(bytes_to_mb)
+    }
+}
 
-    system
-        .processes()
-        .get(&Pid::from_u32(current_pid))
-        .map(|process| process.memory())
-        .unwrap_or(0)
+/// Converts a number of bytes into mebibytes (`bytes / 1024^2`), the unit of the stress test
+/// reports.
+pub fn bytes_to_mb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
 }
@@ -5,15 +5,28 @@ use tempfile::NamedTempFile;
 use tokio::fs::File;
 use txn_engine::{
     asyncengine::{AsyncEngine, AsyncEngineFunctions},
-    datastr::transaction::TransactionProcessingError,
+    config::{
+        AccountCapacityPolicy, AccountColumn, AmountUnits, CsvDialect, DepositCheckOrder,
+        DisputePolicy, EngineConfig, HistoricalDisputePolicy, LockedAccountPolicy,
+        NegativeHeldPolicy, NegativeTotalPolicy, ResolvePolicy, Rounding, TxUniqueness,
+    },
+    datastr::account::Account,
+    datastr::transaction::{TransactionError, TransactionErrorKind, TransactionProcessingError},
+    engine::{Engine, EngineError, EngineFunctions, InvariantViolation},
     utility::generate_random_transaction_concurrent_stream,
 };
 
+use futures_util::StreamExt;
 use std::io::Write;
-use txn_engine::datastr::transaction::TransactionType;
+use txn_engine::datastr::transaction::{Transaction, TransactionType, TxKey};
 
 const BUFFER_SIZE: usize = 16_384;
 
+/// Returns the messages of the errors collected by a processing call.
+fn messages(errors: &[TransactionError]) -> Vec<&str> {
+    errors.iter().map(TransactionError::message).collect()
+}
+
 #[tokio::test]
 async fn unit_test_deposit_and_withdrawal_async() {
     let mut temp_file = NamedTempFile::new().unwrap();
@@ -668,48 +681,38 @@ async fn unit_test_decimal_precision_async() {
 
 #[tokio::test]
 async fn unit_test_subtraction_overflow_async() {
-    let mut transactions_file = NamedTempFile::new().unwrap();
-    let mut accounts_file = NamedTempFile::new().unwrap();
-
-    transactions_file
-        .write_all(
-            b"type,client,tx,amount\n\
-              deposit,1,1,10.0000\n\
-              deposit,2,2,5.0000\n\
-              deposit,3,3,100.0000\n\
-              withdrawal,1,4,5.0000\n",
-        )
-        .unwrap();
-
-    let huge_negative = rust_decimal::Decimal::MIN.to_string();
-    let accounts_csv = format!(
-        r#"client,available,held,total,locked
-            1,5.0000,0.0000,5.0000,false
-            2,5.0000,0.0000,5.0000,false
-            3,{0},{0},{0},false"#,
-        huge_negative
-    );
-
-    write!(accounts_file, "{}", accounts_csv).unwrap();
-
-    let transactions_path = transactions_file.path().to_str().unwrap().to_owned();
-    let accounts_path = accounts_file.path().to_str().unwrap().to_owned();
-
-    let engine = Arc::new(AsyncEngine::new());
-
-    engine
-        .load_from_previous_session_csvs(&transactions_path, &accounts_path)
-        .await
-        .expect("Failed to load from previous-session CSVs");
+    let huge_negative = Decimal::MIN;
+    let account = |amount: Decimal| Account {
+        available: amount,
+        held: Decimal::ZERO,
+        total: amount,
+        ..Account::default()
+    };
+    let engine = AsyncEngine::from_state(
+        vec![
+            (1, account(Decimal::from(5))),
+            (2, account(Decimal::from(5))),
+            (
+                3,
+                Account {
+                    held: huge_negative,
+                    ..account(huge_negative)
+                },
+            ),
+        ],
+        vec![
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(2, 2, Decimal::from(5)),
+            Transaction::deposit(3, 3, Decimal::from(100)),
+            Transaction::withdrawal(1, 4, Decimal::from(5)),
+        ],
+    )
+    .await;
 
-    let mut dispute_file = NamedTempFile::new().unwrap();
     let dispute_csv = "type,client,tx,amount\n\
                        dispute,3,3,\n";
-    write!(dispute_file, "{}", dispute_csv).unwrap();
-    let dispute_path = dispute_file.path().to_str().unwrap().to_owned();
-
     let result = engine
-        .read_and_process_transactions_from_csv(&dispute_path, BUFFER_SIZE)
+        .read_and_process_transactions(dispute_csv.as_bytes(), BUFFER_SIZE)
         .await;
 
     assert!(
@@ -724,6 +727,124 @@ async fn unit_test_subtraction_overflow_async() {
     );
 }
 
+/// Tests that an `AsyncEngine` created with `AsyncEngine::from_state` behaves as one loaded
+/// with `load_from_previous_session_csvs` from the same accounts and transactions.
+#[tokio::test]
+async fn unit_test_from_state_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    transactions_file
+        .write_all(b"type,client,tx,amount\ndeposit,1,1,10.0000\nwithdrawal,1,2,4.0000\n")
+        .unwrap();
+    accounts_file
+        .write_all(b"client,available,held,total,locked\n1,6.0000,0.0000,6.0000,false\n")
+        .unwrap();
+    let loaded = AsyncEngine::new();
+    loaded
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let from_state = AsyncEngine::from_state(
+        vec![(
+            1,
+            Account {
+                available: Decimal::from(6),
+                total: Decimal::from(6),
+                ..Account::default()
+            },
+        )],
+        vec![
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::withdrawal(1, 2, Decimal::from(4)),
+        ],
+    )
+    .await;
+
+    let csv = "type,client,tx,amount\ndispute,1,1,\n";
+    for engine in [&loaded, &from_state] {
+        engine
+            .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+            .await
+            .unwrap();
+    }
+    let account = from_state.get_account(1).await.unwrap();
+    assert_eq!(Some(account.clone()), loaded.get_account(1).await);
+    assert_eq!(account.available, Decimal::from(-4));
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(from_state.total_transactions().await, 2);
+}
+
+/// Writer counting the flushes requested by the async dumps.
+#[derive(Default)]
+struct FlushCounter {
+    bytes: Vec<u8>,
+    flushes: usize,
+}
+
+impl tokio::io::AsyncWrite for FlushCounter {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.bytes.extend_from_slice(buf);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        self.flushes += 1;
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// Tests that the async `dump_account_to_csv` flushes every `flush_every` records written, also
+/// when no client id is a multiple of 1000 (e.g. few clients with high-valued ids).
+#[tokio::test]
+async fn unit_test_dump_flush_every_async() {
+    let accounts: Vec<(u16, Account)> = (0..2500u16)
+        .map(|i| (50_001 + 2 * i, Account::default()))
+        .collect();
+    let dump_flushes = |flush_every: Option<usize>| {
+        let accounts = accounts.clone();
+        async move {
+            let mut engine = AsyncEngine::from_state(accounts, vec![]).await;
+            if let Some(flush_every) = flush_every {
+                engine = engine.with_dump_flush_every(flush_every);
+            }
+            let mut writer = FlushCounter::default();
+            engine
+                .dump_account_to_csv(&mut writer, BUFFER_SIZE)
+                .await
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(writer.bytes).unwrap().lines().count(),
+                2501
+            );
+            writer.flushes
+        }
+    };
+
+    // With `0` the writer is flushed only once all the records are written.
+    let final_flushes = dump_flushes(Some(0)).await;
+    // 1000 records by default: intermediate flushes after 1000 and 2000 records.
+    assert_eq!(dump_flushes(None).await, final_flushes + 2);
+    assert_eq!(dump_flushes(Some(500)).await, final_flushes + 5);
+}
+
 #[tokio::test]
 async fn reg_test_from_csv_file_basic_async() {
     let input_path = "tests/transactions_basic.csv";
@@ -876,24 +997,24 @@ async fn reg_test_from_csv_file_disputed_async() {
 }
 
 ///Tests the handling of several erroneous transactions from a CSV file.
-
+///
 /// type       ,client,tx   ,amount
-
+///
 /// deposit    ,6     ,9    ,0.0000
 /// withdrawal ,6     ,10   ,-5.0000       # Negative amount, should fail
 /// deposit    ,6     ,11   ,79228162514264337593543950330  # Large amount
 /// deposit    ,6     ,12   ,5000.0000     # Addition overflowed
 /// withdrawal ,6     ,13   ,              # Empty amount --> fail
-
+///
 /// deposit    ,7     ,14   ,              # Empty amount --> fail
 /// deposit    ,7     ,15   ,10.0
 /// deposit    ,7     ,15   ,10.0          # Duplicate tx
 /// dispute    ,7     ,16   ,              # Dispute on non-existent or invalid tx
-
+///
 /// resolve    ,6     ,9999 ,              # Resolve on non-existent tx
-
+///
 /// chargeback ,7     ,16   ,              # Chargeback on non-existent tx
-
+///
 /// dispute    ,7     ,15   ,
 /// dispute    ,7     ,15   ,              # Transaction already disputed
 /// chargeback ,7     ,15   ,
@@ -904,6 +1025,15 @@ async fn reg_test_from_csv_file_disputed_async() {
 /// deposit    ,9     ,20  ,100
 /// withdrawal ,9     ,21  ,200            # Insufficient funds : the transaction does NOT gets into the transaction log.
 /// dispute    ,9     ,21  ,               # Dispute on non-existent or invalid tx
+///
+/// deposit    ,10    ,22  ,50
+/// freeze     ,10    ,23  ,
+/// freeze     ,10    ,24  ,               # Account already frozen
+/// withdrawal ,10    ,25  ,10             # Account is locked
+/// unfreeze   ,10    ,26  ,
+/// unfreeze   ,10    ,27  ,               # Account not frozen
+/// withdrawal ,10    ,28  ,10
+/// freeze     ,99    ,29  ,               # Account not found
 /// The test checks that the correct errors are reported.
 ///
 #[tokio::test]
@@ -935,6 +1065,10 @@ async fn reg_test_from_csv_file_error_conditions_async() {
                 "Error processing Transaction { ty: Resolve, client: 8, tx: 18, amount: None, disputed: false }: Transaction not disputed",
                 "Error processing Transaction { ty: Withdrawal, client: 9, tx: 21, amount: Some(200), disputed: false }: Insufficient funds",
                 "Error processing Transaction { ty: Dispute, client: 9, tx: 21, amount: None, disputed: false }: Transaction not found",
+                "Error processing Transaction { ty: Freeze, client: 10, tx: 24, amount: None, disputed: false }: Account already frozen",
+                "Error processing Transaction { ty: Withdrawal, client: 10, tx: 25, amount: Some(10), disputed: false }: Account is locked",
+                "Error processing Transaction { ty: Unfreeze, client: 10, tx: 27, amount: None, disputed: false }: Account not frozen",
+                "Error processing Transaction { ty: Freeze, client: 99, tx: 29, amount: None, disputed: false }: Account not found",
             ];
 
             let mut actual_errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
@@ -950,7 +1084,10 @@ async fn reg_test_from_csv_file_error_conditions_async() {
                 "Errors do not match expected errors"
             );
 
-            assert_eq!(engine.accounts.len().await, 4);
+            assert_eq!(engine.accounts.len().await, 5);
+            let account = engine.get_account(10).await.unwrap();
+            assert!(!account.locked);
+            assert_eq!(account.available, Decimal::from(40));
         }
     }
 }
@@ -980,7 +1117,7 @@ async fn reg_test_from_csv_file_malformed_async() {
             ];
 
             // Compare the sorted errors to ensure the order doesn't matter
-            let mut actual_errors = errors.clone();
+            let mut actual_errors = messages(&errors);
             actual_errors.sort();
             let mut expected_errors_sorted = expected_errors;
             expected_errors_sorted.sort();
@@ -1007,7 +1144,6 @@ async fn reg_test_from_csv_file_malformed_async() {
 /// The test expects that:
 /// - The transaction log contains three transactions with correct details.
 /// - The accounts map contains two accounts with expected balances and states.
-
 #[tokio::test]
 async fn reg_test_load_from_previous_session_csv_async() {
     // Create temporary files for transactions and accounts
@@ -1058,15 +1194,15 @@ async fn reg_test_load_from_previous_session_csv_async() {
             // Check if transactions were loaded correctly
             assert_eq!(engine.transaction_log.len().await, 3);
 
-            let tx1_guard = engine.transaction_log.get(1).await.unwrap();
-            let tx1 = tx1_guard.get(&1).unwrap();
+            let tx1_guard = engine.transaction_log.get(TxKey::global(1)).await.unwrap();
+            let tx1 = tx1_guard.get(&TxKey::global(1)).unwrap();
             assert_eq!(tx1.ty, TransactionType::Deposit);
             assert_eq!(tx1.client, 1);
             assert_eq!(tx1.tx, 1);
             assert_eq!(tx1.amount, Some(Decimal::new(10_0000, 4))); // 10.0000
 
-            let tx2_guard = engine.transaction_log.get(3).await.unwrap();
-            let tx2 = tx2_guard.get(&3).unwrap();
+            let tx2_guard = engine.transaction_log.get(TxKey::global(3)).await.unwrap();
+            let tx2 = tx2_guard.get(&TxKey::global(3)).unwrap();
             assert_eq!(tx2.ty, TransactionType::Withdrawal);
             assert_eq!(tx2.client, 1);
             assert_eq!(tx2.tx, 3);
@@ -1087,6 +1223,26 @@ async fn reg_test_load_from_previous_session_csv_async() {
     handle.await.unwrap();
 }
 
+/// Regression test of `ShardedIter`: every key of the map is returned exactly once, also when
+/// a shard holds several keys (the iterator used to return only the first key of each shard, so
+/// the async dumps silently dropped accounts).
+#[tokio::test]
+async fn reg_test_sharded_iter_returns_every_key() {
+    let map = txn_engine::basics::hmap::ShardedRwLockMap::new();
+    for client in 0..1000u16 {
+        map.insert(client, client).await;
+    }
+
+    let mut keys = Vec::new();
+    let mut iter = map.iter().await;
+    while let Some((key, shard_guard)) = iter.next().await {
+        assert_eq!(shard_guard.get(&key), Some(&key));
+        keys.push(key);
+    }
+    keys.sort_unstable();
+    assert_eq!(keys, (0..1000u16).collect::<Vec<_>>());
+}
+
 /// Tests serialization and deserialization of the `Engine` to and from CSV files.
 ///
 /// This test creates a temporary file for transactions and accounts,
@@ -1236,27 +1392,26 @@ async fn reg_test_engine_consistency_with_concurrent_processing_async(
     let engine_seq = Arc::new(AsyncEngine::new());
     let engine_concurrent = Arc::new(AsyncEngine::new());
 
-    // Process files sequentially with engine1
-    match engine_seq
-        .read_and_process_transactions(File::open(temp_file1.path()).await.unwrap(), BUF_SIZE)
-        .await
-    {
-        Ok(()) => {}
-        Err(..) => {}
-    }
-    match engine_seq
-        .read_and_process_transactions(File::open(temp_file2.path()).await.unwrap(), BUF_SIZE)
-        .await
-    {
-        Ok(()) => {}
-        Err(..) => {}
+    // Number of errors of a processing call: the files have disjoint clients and tx ids, so a
+    // file fails on the same transactions whether processed sequentially or concurrently.
+    fn error_count(result: Result<(), TransactionProcessingError>) -> usize {
+        match result {
+            Ok(()) => 0,
+            Err(TransactionProcessingError::MultipleErrors(errors)) => errors.len(),
+        }
     }
-    match engine_seq
-        .read_and_process_transactions(File::open(temp_file3.path()).await.unwrap(), BUF_SIZE)
-        .await
-    {
-        Ok(()) => {}
-        Err(..) => {}
+
+    // Process files sequentially with engine1
+    let mut sequential_errors = Vec::new();
+    for temp_file in [&temp_file1, &temp_file2, &temp_file3] {
+        sequential_errors.push(error_count(
+            engine_seq
+                .read_and_process_transactions(
+                    File::open(temp_file.path()).await.unwrap(),
+                    BUF_SIZE,
+                )
+                .await,
+        ));
     }
 
     // Process files concurrently with engine2
@@ -1265,47 +1420,46 @@ async fn reg_test_engine_consistency_with_concurrent_processing_async(
             let engine_concurrent = Arc::clone(&engine_concurrent);
             let file = File::open(temp_file1.path()).await.unwrap();
             tokio::spawn(async move {
-                match engine_concurrent
-                    .read_and_process_transactions(file, BUF_SIZE)
-                    .await
-                {
-                    Ok(()) => {}
-                    Err(..) => {}
-                };
+                error_count(
+                    engine_concurrent
+                        .read_and_process_transactions(file, BUF_SIZE)
+                        .await,
+                )
             })
         },
         {
             let engine_concurrent = Arc::clone(&engine_concurrent);
             let file = File::open(temp_file2.path()).await.unwrap();
             tokio::spawn(async move {
-                match engine_concurrent
-                    .read_and_process_transactions(file, BUF_SIZE)
-                    .await
-                {
-                    Ok(()) => {}
-                    Err(..) => {}
-                };
+                error_count(
+                    engine_concurrent
+                        .read_and_process_transactions(file, BUF_SIZE)
+                        .await,
+                )
             })
         },
         {
             let engine_concurrent = Arc::clone(&engine_concurrent);
             let file = File::open(temp_file3.path()).await.unwrap();
             tokio::spawn(async move {
-                match engine_concurrent
-                    .read_and_process_transactions(file, BUF_SIZE)
-                    .await
-                {
-                    Ok(()) => {}
-                    Err(..) => {}
-                };
+                error_count(
+                    engine_concurrent
+                        .read_and_process_transactions(file, BUF_SIZE)
+                        .await,
+                )
             })
         },
     ];
 
     // Wait for all threads to complete
+    let mut concurrent_errors = Vec::new();
     for handle in handles {
-        handle.await.unwrap();
+        concurrent_errors.push(handle.await.unwrap());
     }
+    assert_eq!(
+        concurrent_errors, sequential_errors,
+        "Error counts differ between sequential and concurrent processing"
+    );
 
     /*  debug
     writer.write_record(["client", "available", "held", "total", "locked"]).unwrap();
@@ -1337,3 +1491,2169 @@ async fn reg_test_engine_consistency_with_concurrent_processing_async(
 
     Ok(())
 }
+
+/// Tests the `NegativeTotalPolicy` on the async engine: with `Reject` a chargeback driving the
+/// total negative is refused and the account is left untouched.
+#[tokio::test]
+async fn unit_test_negative_total_policy_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,10.0\n\
+                       dispute,1,1,\n\
+                       chargeback,1,1,\n";
+
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("Default policy should allow a negative total");
+    {
+        let guard = engine.accounts.get(1).await.unwrap();
+        let account = guard.get(&1).unwrap();
+        assert_eq!(account.total, Decimal::from(-10));
+        assert!(account.locked);
+    }
+
+    let engine = AsyncEngine::new().with_negative_total_policy(NegativeTotalPolicy::Reject);
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the chargeback to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Chargeback, client: 1, tx: 1, amount: None, disputed: false }: Operation would drive the account total negative".to_string()]
+            );
+        }
+    }
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::ZERO);
+    assert_eq!(account.held, Decimal::from(10));
+    assert!(!account.locked);
+}
+
+/// Tests that the async loader accepts the `1`/`0` and `Y`/`N` representations of `locked`.
+#[tokio::test]
+async fn reg_test_load_locked_formats_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    writeln!(transactions_file, "type,client,tx,amount,disputed").unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,1\n2,5.0000,0.0000,5.0000,0\n3,1.0000,0.0000,1.0000,Y\n4,1.0000,0.0000,1.0000,N\n"
+    )
+    .unwrap();
+
+    let engine = AsyncEngine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .expect("Failed to load accounts");
+
+    for (client, locked) in [(1, true), (2, false), (3, true), (4, false)] {
+        let guard = engine.accounts.get(client).await.unwrap();
+        assert_eq!(
+            guard.get(&client).unwrap().locked,
+            locked,
+            "client {}",
+            client
+        );
+    }
+}
+
+/// Tests the `HistoricalDisputePolicy` on the async engine: with `Reject` a dispute on a
+/// transaction loaded from a previous session is refused.
+#[tokio::test]
+async fn unit_test_historical_dispute_policy_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    write!(
+        transactions_file,
+        "type,client,tx,amount,disputed\n\
+         deposit,1,1,10.0,false\n"
+    )
+    .unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,10.0,0.0,10.0,false\n"
+    )
+    .unwrap();
+
+    let engine = AsyncEngine::new().with_historical_dispute_policy(HistoricalDisputePolicy::Reject);
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .expect("Failed to load from CSV");
+
+    let csv_content = "type,client,tx,amount\n\
+                       dispute,1,1,\n";
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the dispute on the historical transaction to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Dispute, client: 1, tx: 1, amount: None, disputed: false }: Transaction loaded from a previous session cannot be disputed".to_string()]
+            );
+        }
+    }
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.available, Decimal::from(10));
+}
+
+/// Measures the shard occupancy of `Shardable` for sequential, strided and random keys:
+/// every shard must be used and hold between half and twice the average number of keys.
+#[test]
+fn unit_test_shard_distribution() {
+    use rand::Rng;
+    use txn_engine::basics::hmap::{Shardable, NUM_SHARDS};
+
+    fn assert_balanced(name: &str, shards: impl Iterator<Item = usize>) {
+        let mut occupancy = [0usize; NUM_SHARDS];
+        let mut total = 0;
+        for shard in shards {
+            occupancy[shard] += 1;
+            total += 1;
+        }
+        let average = total / NUM_SHARDS;
+        for (shard, count) in occupancy.iter().enumerate() {
+            assert!(
+                *count >= average / 2 && *count <= average * 2,
+                "{} keys: shard {} holds {} keys (average {})",
+                name,
+                shard,
+                count,
+                average
+            );
+        }
+    }
+
+    let num_keys = (NUM_SHARDS * 100) as u32;
+    let shard = |hash: usize| hash % NUM_SHARDS;
+    assert_balanced("sequential u32", (0..num_keys).map(|k| shard(k.shard())));
+    assert_balanced(
+        "sequential u16",
+        (0..num_keys as u16).map(|k| shard(k.shard())),
+    );
+    assert_balanced(
+        "strided u32",
+        (0..num_keys).map(|k| shard((k * NUM_SHARDS as u32).shard())),
+    );
+    let mut rng = rand::thread_rng();
+    assert_balanced(
+        "random u32",
+        (0..num_keys).map(|_| shard(rng.gen::<u32>().shard())),
+    );
+}
+
+/// Tests reading amounts as integer minor units on the async engine and dumping them back.
+#[tokio::test]
+async fn unit_test_amount_minor_units_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,1050\n\
+                       withdrawal,1,2,25\n";
+
+    let engine = AsyncEngine::new()
+        .with_amount_units(AmountUnits::Minor(2))
+        .with_dump_amount_units(AmountUnits::Minor(2));
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("Failed to process minor-unit amounts");
+    {
+        let guard = engine.accounts.get(1).await.unwrap();
+        assert_eq!(
+            guard.get(&1).unwrap().available,
+            Decimal::from_str("10.25").unwrap()
+        );
+    }
+
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv(&mut output, BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1025,0,1025,false\n"
+    );
+}
+
+/// Tests that the async dump omits zero-balance, unlocked accounts when requested and
+/// dumps every account otherwise.
+#[tokio::test]
+async fn unit_test_dump_omit_zero_accounts_async() {
+    let mut csv_content = String::from(
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         withdrawal,1,2,10.0\n",
+    );
+    // Enough clients to share shards.
+    for client in 2..200 {
+        csv_content.push_str(&format!("deposit,{},{},5.0\n", client, client + 1000));
+    }
+
+    for (omit_zero, expected) in [(false, 199), (true, 198)] {
+        let engine = AsyncEngine::new().with_omit_zero_accounts(omit_zero);
+        engine
+            .read_and_process_transactions(
+                std::io::Cursor::new(csv_content.clone().into_bytes()),
+                BUFFER_SIZE,
+            )
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        engine
+            .dump_account_to_csv(&mut output, BUFFER_SIZE)
+            .await
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let clients: Vec<&str> = output
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(clients.len(), expected);
+        assert_eq!(clients.contains(&"1"), !omit_zero);
+    }
+}
+
+/// Hammers a single shard while its write lock is held and checks that the contention counters
+/// report the waits on that shard only.
+#[cfg(feature = "contention")]
+#[tokio::test]
+async fn unit_test_shard_contention_report() {
+    use txn_engine::basics::hmap::ShardedRwLockMap;
+
+    let map: Arc<ShardedRwLockMap<u32, u64>> = Arc::new(ShardedRwLockMap::new());
+    let hot_key = 7u32;
+    assert!(map.contention_report().iter().all(|count| *count == 0));
+
+    let guard = map.entry(hot_key).await;
+    let handles: Vec<_> = (0..10u64)
+        .map(|value| {
+            let map = Arc::clone(&map);
+            tokio::spawn(async move {
+                map.insert(hot_key, value).await;
+            })
+        })
+        .collect();
+    // Let every task try (and fail) to acquire the write lock held by `guard`.
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    drop(guard);
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let report = map.contention_report();
+    assert_eq!(report[map.shard_for(&hot_key)], 10);
+    assert_eq!(report.iter().sum::<u64>(), 10);
+}
+
+/// Tests `ResolvePolicy::IgnoreIfNotDisputed` on the async engine.
+#[tokio::test]
+async fn unit_test_resolve_policy_ignore_if_not_disputed_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       resolve,1,1,\n\
+                       dispute,1,1,\n\
+                       resolve,1,1,\n\
+                       resolve,1,1,\n";
+
+    let engine = AsyncEngine::new().with_resolve_policy(ResolvePolicy::IgnoreIfNotDisputed);
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("Resolves on non-disputed transactions should be ignored");
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::ZERO);
+}
+
+/// Tests `DepositCheckOrder::LockedFirst` on the async engine: a repeated deposit on a locked
+/// account is reported as locked.
+#[tokio::test]
+async fn unit_test_deposit_check_order_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       dispute,1,1,\n\
+                       chargeback,1,1,\n\
+                       deposit,1,1,5.0\n";
+
+    let engine = AsyncEngine::new().with_deposit_check_order(DepositCheckOrder::LockedFirst);
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the repeated deposit to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Deposit, client: 1, tx: 1, amount: Some(5.0), disputed: false }: Account is locked".to_string()]
+            );
+        }
+    }
+}
+
+/// Tests `with_checkpointing`: a run interrupted after part of the input, then restarted with a
+/// fresh engine on the whole input, ends in the same state as an uninterrupted run, fee log
+/// included; a restart with a different input is rejected.
+#[tokio::test]
+async fn unit_test_checkpoint_resume_async() {
+    let rows = [
+        "deposit,1,1,10.0",
+        "deposit,2,2,20.0",
+        "withdrawal,1,3,3.0",
+        "dispute,1,1,",
+        "deposit,2,4,5.0",
+        "resolve,1,1,",
+        "dispute,2,2,",
+        "chargeback,2,2,",
+        "deposit,1,5,1.0",
+        "withdrawal,2,6,1.0",
+    ];
+    let input = |rows: &[&str]| {
+        std::io::Cursor::new(format!("type,client,tx,amount\n{}\n", rows.join("\n")).into_bytes())
+    };
+    async fn dump(engine: &AsyncEngine) -> (Vec<String>, Vec<String>) {
+        let mut accounts = Vec::new();
+        engine
+            .dump_account_to_csv(&mut accounts, BUFFER_SIZE)
+            .await
+            .unwrap();
+        let transactions_file = NamedTempFile::new().unwrap();
+        let transactions_path = transactions_file.path().to_str().unwrap();
+        engine
+            .dump_transaction_log_to_csv(transactions_path, BUFFER_SIZE)
+            .await
+            .unwrap();
+        let transactions = std::fs::read_to_string(transactions_path).unwrap();
+
+        let mut accounts: Vec<String> = String::from_utf8(accounts)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let mut transactions: Vec<String> = transactions.lines().map(str::to_string).collect();
+        accounts.sort();
+        transactions.sort();
+        (accounts, transactions)
+    }
+
+    let uninterrupted = AsyncEngine::new();
+    let uninterrupted_result = uninterrupted
+        .read_and_process_transactions(input(&rows), BUFFER_SIZE)
+        .await;
+
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    let interrupted = AsyncEngine::new().with_checkpointing(checkpoint_dir.path(), 3);
+    interrupted
+        .read_and_process_transactions(input(&rows[..7]), BUFFER_SIZE)
+        .await
+        .expect("The first part of the input should be processed without errors");
+    assert!(checkpoint_dir
+        .path()
+        .join(txn_engine::asyncengine::CHECKPOINT_FILE)
+        .exists());
+
+    let restarted = AsyncEngine::new().with_checkpointing(checkpoint_dir.path(), 3);
+    let restarted_result = restarted
+        .read_and_process_transactions(input(&rows), BUFFER_SIZE)
+        .await;
+
+    // Only the error after the checkpoint is reported (by both runs)
+    match (uninterrupted_result, restarted_result) {
+        (
+            Err(TransactionProcessingError::MultipleErrors(uninterrupted_errors)),
+            Err(TransactionProcessingError::MultipleErrors(restarted_errors)),
+        ) => {
+            assert_eq!(uninterrupted_errors, restarted_errors);
+            assert_eq!(
+                messages(&restarted_errors),
+                vec!["Error processing Transaction { ty: Withdrawal, client: 2, tx: 6, amount: Some(1.0), disputed: false }: Account is locked".to_string()]
+            );
+        }
+        _ => panic!("Expected the withdrawal on the locked account to fail"),
+    }
+    assert_eq!(dump(&restarted).await, dump(&uninterrupted).await);
+
+    let mut other_rows = rows[1..].to_vec();
+    other_rows.push("deposit,3,7,1.0");
+    let mismatching = AsyncEngine::new().with_checkpointing(checkpoint_dir.path(), 3);
+    match mismatching
+        .read_and_process_transactions(input(&other_rows), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the restart on a different input to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec![
+                    "Error resuming from checkpoint: transaction 7 read where 6 was expected"
+                        .to_string()
+                ]
+            );
+        }
+    }
+
+    // The fees charged before the checkpoint are restored with the balances including them
+    let fee = Decimal::from_str("0.5").unwrap();
+    let uninterrupted = AsyncEngine::new().with_transaction_fee(fee);
+    uninterrupted
+        .read_and_process_transactions(input(&rows[..6]), BUFFER_SIZE)
+        .await
+        .unwrap();
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    AsyncEngine::new()
+        .with_transaction_fee(fee)
+        .with_checkpointing(checkpoint_dir.path(), 2)
+        .read_and_process_transactions(input(&rows[..4]), BUFFER_SIZE)
+        .await
+        .unwrap();
+    let restarted = AsyncEngine::new()
+        .with_transaction_fee(fee)
+        .with_checkpointing(checkpoint_dir.path(), 2);
+    restarted
+        .read_and_process_transactions(input(&rows[..6]), BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(dump(&restarted).await, dump(&uninterrupted).await);
+    assert_eq!(restarted.fee_log.len().await, 4);
+    assert_eq!(
+        restarted.fee_log.len().await,
+        uninterrupted.fee_log.len().await
+    );
+    let mut fees = restarted.fee_log.iter().await;
+    while let Some((key, shard_guard)) = fees.next().await {
+        let fee_tx = shard_guard.get(&key).unwrap();
+        assert_eq!(fee_tx.ty, TransactionType::Fee);
+        assert_eq!(fee_tx.amount, Some(fee));
+    }
+}
+
+/// Tests `log_type_counts` after loading a session with deposits and withdrawals.
+#[tokio::test]
+async fn unit_test_log_type_counts_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    write!(
+        transactions_file,
+        "type,client,tx,amount,disputed\n\
+         deposit,1,1,10.0,false\n\
+         deposit,2,2,20.0,true\n\
+         withdrawal,1,3,5.0,false\n\
+         deposit,1,4,1.0,false\n"
+    )
+    .unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,6.0,0.0,6.0,false\n\
+         2,0.0,20.0,20.0,false\n"
+    )
+    .unwrap();
+
+    let engine = AsyncEngine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .expect("Failed to load from CSV");
+
+    let counts = engine.log_type_counts().await;
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[&TransactionType::Deposit], 3);
+    assert_eq!(counts[&TransactionType::Withdrawal], 1);
+}
+
+/// Tests `with_signed_amounts` on the async engine: `deposit,1,2,-5` is a withdrawal of 5.
+#[tokio::test]
+async fn unit_test_signed_amounts_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       deposit,1,2,-5.0\n";
+
+    let engine = AsyncEngine::new().with_signed_amounts(true);
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The negative deposit should be processed as a withdrawal");
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(5));
+    drop(guard);
+    let guard = engine.transaction_log.get(TxKey::global(2)).await.unwrap();
+    assert_eq!(
+        guard.get(&TxKey::global(2)).unwrap().ty,
+        TransactionType::Withdrawal
+    );
+}
+
+/// Tests that `state_checksum` tracks the sum of the totals and the number of accounts.
+#[tokio::test]
+async fn unit_test_state_checksum_async() {
+    let engine = AsyncEngine::new();
+    assert_eq!(engine.state_checksum().await, (Decimal::ZERO, 0));
+
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       deposit,2,2,2.5\n\
+                       withdrawal,1,3,4.0\n";
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        engine.state_checksum().await,
+        (Decimal::from_str("8.5").unwrap(), 2)
+    );
+}
+
+/// Tests `with_transaction_fee` on the async engine: balances reflect the fees and a withdrawal
+/// that cannot cover the fee is rejected.
+#[tokio::test]
+async fn unit_test_transaction_fee_async() {
+    let fee = Decimal::from_str("0.5").unwrap();
+    let engine = AsyncEngine::new().with_transaction_fee(fee);
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,4.0\n\
+                       withdrawal,1,3,5.0\n";
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the withdrawal not covering the fee to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Withdrawal, client: 1, tx: 3, amount: Some(5.0), disputed: false }: Insufficient funds to cover the transaction fee".to_string()]
+            );
+        }
+    }
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(5));
+    drop(guard);
+    assert_eq!(engine.fee_log.len().await, 2);
+}
+
+/// Tests `with_tx_id_collision_detection` on the async engine: only the dispute with an amount
+/// fails with `TxIdCollision`.
+#[tokio::test]
+async fn unit_test_tx_id_collision_detection_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       dispute,1,5,\n\
+                       dispute,1,6,3.0\n";
+    let engine = AsyncEngine::new().with_tx_id_collision_detection(true);
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the disputes of missing transactions to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec![
+                    "Error processing Transaction { ty: Dispute, client: 1, tx: 5, amount: None, disputed: false }: Transaction not found".to_string(),
+                    "Error processing Transaction { ty: Dispute, client: 1, tx: 6, amount: Some(3.0), disputed: false }: Transaction id not found and the dispute/resolve/chargeback has an amount - it looks like a new transaction".to_string(),
+                ]
+            );
+        }
+    }
+}
+
+/// Contrasts `len` and `len_consistent` while a key moves from a low shard to a high one:
+/// both scans are blocked on a middle shard when the key moves, so `len` counts the key twice
+/// (a total that never existed) while `len_consistent` holds the shards already read, delays
+/// the move and returns a true total.
+#[tokio::test]
+async fn unit_test_len_consistent_async() {
+    use txn_engine::basics::hmap::{Shardable, ShardedRwLockMap, NUM_SHARDS};
+
+    let key_in = |shards: std::ops::Range<usize>| {
+        (0u32..)
+            .find(|key| shards.contains(&(key.shard() % NUM_SHARDS)))
+            .unwrap()
+    };
+    let low = key_in(0..8);
+    let middle = key_in(28..36);
+    let high = key_in(56..64);
+
+    for consistent in [false, true] {
+        let map: Arc<ShardedRwLockMap<u32, u64>> = Arc::new(ShardedRwLockMap::new());
+        map.insert(low, 0).await;
+        map.insert(middle, 0).await;
+        assert_eq!(map.len().await, 2);
+        assert_eq!(map.len_consistent().await, 2);
+
+        // Block the scan on the middle shard
+        let middle_guard = map.get_mut(middle).await.unwrap();
+        let counter = {
+            let map = map.clone();
+            tokio::spawn(async move {
+                if consistent {
+                    map.len_consistent().await
+                } else {
+                    map.len().await
+                }
+            })
+        };
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        // Move the key from the low shard (already counted) to the high shard (not yet counted)
+        let mover = {
+            let map = map.clone();
+            tokio::spawn(async move {
+                map.remove(low).await;
+                map.insert(high, 0).await;
+            })
+        };
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        drop(middle_guard);
+
+        let counted = counter.await.unwrap();
+        mover.await.unwrap();
+        if consistent {
+            assert_eq!(counted, 2);
+        } else {
+            assert_eq!(counted, 3, "len should count the moved key twice");
+        }
+        assert_eq!(map.len_consistent().await, 2);
+    }
+}
+
+/// Tests that input with Windows-style `\r\n` line endings is parsed like `\n` input by the
+/// async engine, for every transaction type and for rows with an empty amount column.
+#[tokio::test]
+async fn unit_test_crlf_line_endings_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\r\n\
+                       deposit,1,1,10.5\r\n\
+                       deposit,1,2,4.25\r\n\
+                       withdrawal,1,3,2.5\r\n\
+                       dispute,1,1,\r\n\
+                       resolve,1,1,\r\n\
+                       deposit, 2 , 4 , 7.0 \r\n\
+                       dispute,2,4,\r\n\
+                       chargeback,2,4,\r\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+    let engine = Arc::new(AsyncEngine::default());
+
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("CRLF input should be processed without errors");
+
+    assert_eq!(
+        engine.accounts.len().await,
+        2,
+        "There should be two accounts"
+    );
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("12.25").unwrap());
+    assert_eq!(account.held, Decimal::ZERO);
+    assert!(!account.locked);
+    drop(account_guard);
+    let account_guard = engine.accounts.get(2).await.unwrap();
+    let account = account_guard.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::ZERO);
+    assert!(account.locked, "Account 2 must be locked after chargeback");
+}
+
+/// Tests `with_max_duration`: reading from a slow stream with a short budget stops early,
+/// reports the number of processed records and keeps the partial accounts.
+#[tokio::test]
+async fn unit_test_max_duration_async() {
+    use tokio::io::AsyncWriteExt;
+
+    let (mut writer, reader) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        writer.write_all(b"type,client,tx,amount\n").await?;
+        for tx in 1..=200 {
+            writer
+                .write_all(format!("deposit,1,{tx},1.0\n").as_bytes())
+                .await?;
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let engine = AsyncEngine::default().with_max_duration(std::time::Duration::from_millis(100));
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(reader, BUFFER_SIZE)
+        .await
+        .expect_err("Processing should stop at the deadline");
+
+    let processed = engine.transaction_log.len().await;
+    assert!(
+        processed > 0 && processed < 200,
+        "Processed {processed} records"
+    );
+    assert_eq!(
+        messages(&errors),
+        vec![format!(
+            "Processing stopped after {processed} records: maximum duration of 100ms exceeded"
+        )]
+    );
+    assert_eq!(errors[0].kind, TransactionErrorKind::Stopped);
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    assert_eq!(
+        account_guard.get(&1).unwrap().total,
+        Decimal::from(processed)
+    );
+}
+
+/// Tests the `hold`/`release` authorization flow with the async engine: a hold moves funds from
+/// available to held (so they cannot be withdrawn), a release moves them back once, and holds
+/// cannot be disputed.
+#[tokio::test]
+async fn unit_test_hold_and_release_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       hold,1,2,4.0\n\
+                       withdrawal,1,3,8.0\n\
+                       withdrawal,1,4,6.0\n\
+                       hold,1,5,1.0\n\
+                       dispute,1,2,\n\
+                       release,1,2,\n\
+                       release,1,2,\n\
+                       release,1,1,\n\
+                       hold,1,6,0\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+    let engine = Arc::new(AsyncEngine::default());
+
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect_err("The invalid rows should be reported");
+
+    let causes: Vec<&str> = errors
+        .iter()
+        .map(|e| e.message().split_once("}: ").unwrap().1)
+        .collect();
+    assert_eq!(
+        causes,
+        vec![
+            "Insufficient funds",
+            "Insufficient funds",
+            "Holds cannot be disputed/resolved/chargedback",
+            "Hold already released",
+            "Released transaction is not a hold",
+            "Hold amount must be greater than 0",
+        ]
+    );
+
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(4));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(4));
+    assert!(!account.locked);
+}
+
+/// Tests `with_max_amount` with the async engine: deposits and withdrawals below or at the
+/// limit are processed, the ones above it fail with `AmountExceedsLimit`.
+#[tokio::test]
+async fn unit_test_max_amount_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,99.9999\n\
+                       deposit,1,2,100\n\
+                       deposit,1,3,100.0001\n\
+                       withdrawal,1,4,50\n\
+                       withdrawal,1,5,100\n\
+                       withdrawal,1,6,101\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+    let engine = Arc::new(AsyncEngine::default().with_max_amount(Decimal::from(100)));
+
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect_err("The amounts above the limit should be rejected");
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message().contains("tx: 3"));
+    assert!(errors[1].message().contains("tx: 6"));
+    assert!(errors.iter().all(|e| e
+        .message()
+        .ends_with("Amount exceeds the maximum single-transaction amount")));
+
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("49.9999").unwrap());
+    assert_eq!(account.total, Decimal::from_str("49.9999").unwrap());
+}
+
+/// Tests `dump_account_to_ndjson` with the async engine: every line is a valid JSON object and
+/// the accounts match the CSV dump.
+#[tokio::test]
+async fn unit_test_dump_account_to_ndjson_async() {
+    let engine = AsyncEngine::default();
+    let _ = engine
+        .read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE)
+        .await;
+
+    let mut ndjson = Vec::new();
+    engine
+        .dump_account_to_ndjson(&mut ndjson, BUFFER_SIZE)
+        .await
+        .expect("NDJSON dump should succeed");
+    let mut from_ndjson: Vec<(u16, Decimal, Decimal, Decimal, bool)> = String::from_utf8(ndjson)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value =
+                serde_json::from_str(line).expect("Each line should be valid JSON");
+            let amount = |field: &str| Decimal::from_str(&value[field].to_string()).unwrap();
+            (
+                value["client"].as_u64().unwrap() as u16,
+                amount("available"),
+                amount("held"),
+                amount("total"),
+                value["locked"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+
+    let mut csv = Vec::new();
+    engine
+        .dump_account_to_csv(&mut csv, BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut from_csv: Vec<(u16, Decimal, Decimal, Decimal, bool)> =
+        csv::Reader::from_reader(&csv[..])
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+    from_ndjson.sort_by_key(|account| account.0);
+    from_csv.sort_by_key(|account| account.0);
+    assert_eq!(from_ndjson.len(), engine.accounts.len().await);
+    assert_eq!(from_ndjson, from_csv);
+}
+
+/// Tests `with_idempotent_file_reruns` with the async engine: processing the same file twice is
+/// a no-op the second time.
+#[tokio::test]
+async fn unit_test_idempotent_file_reruns_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,4.0\n\
+                       dispute,1,1,\n\
+                       resolve,1,1,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+
+    let engine = AsyncEngine::default().with_idempotent_file_reruns(true);
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("The first run should succeed");
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("Re-running the same file should be a no-op");
+
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(6));
+}
+
+/// Tests `max_chargeback_exposure` with the async engine: the sum of the amounts of the
+/// transactions currently disputed, across accounts.
+#[tokio::test]
+async fn unit_test_max_chargeback_exposure_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.5\n\
+                       deposit,1,2,20\n\
+                       deposit,2,3,7\n\
+                       withdrawal,2,4,2\n\
+                       deposit,3,5,100\n\
+                       dispute,1,1,\n\
+                       dispute,1,2,\n\
+                       dispute,2,4,\n\
+                       dispute,3,5,\n\
+                       resolve,1,2,\n\
+                       chargeback,3,5,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+    let engine = AsyncEngine::default();
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+
+    assert_eq!(
+        engine.max_chargeback_exposure().await,
+        Decimal::from_str("12.5").unwrap()
+    );
+}
+
+/// Tests `NegativeHeldPolicy::Reject` on resolves with the async engine: resolving a disputed
+/// deposit while a disputed withdrawal keeps held negative fails with `NegativeHeld`, resolving
+/// the disputed withdrawal is accepted.
+#[tokio::test]
+async fn unit_test_negative_held_policy_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       withdrawal,1,2,5\n\
+                       dispute,1,1,\n\
+                       dispute,1,2,\n\
+                       resolve,1,1,\n\
+                       resolve,1,2,\n\
+                       resolve,1,1,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+
+    let engine = AsyncEngine::default().with_negative_held_policy(NegativeHeldPolicy::Reject);
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect_err("The resolve overdrawing held should be rejected");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message().contains("tx: 1"));
+    assert!(errors[0]
+        .message()
+        .ends_with("Dispute or resolve would drive the account held funds negative"));
+
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.available, Decimal::from(5));
+    drop(account_guard);
+
+    let engine = AsyncEngine::default();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect_err("By default held is overdrawn, so the last resolve is not disputed anymore");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message().contains("tx: 1"));
+    assert!(errors[0].message().ends_with("Transaction not disputed"));
+}
+
+/// Tests `state_merkle_root` with the async engine: the root equals the sync engine's for the
+/// same input, and changes after a mutation.
+#[tokio::test]
+async fn unit_test_state_merkle_root_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.5\n\
+                       deposit,2,2,7\n\
+                       withdrawal,2,3,2\n\
+                       deposit,3,4,100\n\
+                       dispute,1,1,\n\
+                       dispute,3,4,\n\
+                       chargeback,3,4,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+
+    let engine = AsyncEngine::default();
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+    let mut sync_engine = Engine::default();
+    sync_engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .expect("The transactions should be processed");
+    let root = engine.state_merkle_root().await;
+    assert_eq!(root, sync_engine.state_merkle_root());
+
+    let mut mutation = NamedTempFile::new().unwrap();
+    write!(mutation, "type,client,tx,amount\nresolve,1,1,\n").unwrap();
+    engine
+        .read_and_process_transactions_from_csv(mutation.path().to_str().unwrap(), BUFFER_SIZE)
+        .await
+        .expect("The resolve should be applied");
+    assert_ne!(engine.state_merkle_root().await, root);
+}
+
+/// Tests `EngineConfig::precision` with the async engine: with a precision of 2 a `0.0045`
+/// withdrawal rounds to zero and is rejected, while `0.005` rounds up to `0.01`.
+#[tokio::test]
+async fn unit_test_configurable_precision_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,1.125\n\
+                       withdrawal,1,2,0.005\n\
+                       withdrawal,1,3,0.0045\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+
+    let engine = AsyncEngine::default().with_config(EngineConfig {
+        precision: 2,
+        ..EngineConfig::default()
+    });
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect_err("The withdrawal rounding to zero should be rejected");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message().contains("tx: 3"));
+    assert!(errors[0]
+        .message()
+        .ends_with("Withdrawal amount must be greater than 0"));
+
+    let account_guard = engine.accounts.get(1).await.unwrap();
+    let account = account_guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("1.12").unwrap());
+}
+
+/// Tests `disputed_transactions` with the async engine: after disputing two of three deposits,
+/// their ids are returned sorted.
+#[tokio::test]
+async fn unit_test_disputed_transactions_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,30,10\n\
+                       deposit,2,10,20\n\
+                       deposit,1,20,30\n\
+                       dispute,1,30,\n\
+                       dispute,2,10,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap().to_owned();
+    let engine = AsyncEngine::default();
+    engine
+        .read_and_process_transactions_from_csv(&input_path, BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+
+    assert_eq!(engine.disputed_transactions().await, vec![10, 30]);
+}
+
+/// Tests dumping a subset of the account columns in a given order with the async engine.
+#[tokio::test]
+async fn unit_test_dump_account_columns_subset_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.5\n";
+    let engine =
+        AsyncEngine::new().with_account_columns(vec![AccountColumn::Total, AccountColumn::Client]);
+    engine
+        .read_and_process_transactions(
+            std::io::Cursor::new(csv_content.as_bytes().to_vec()),
+            BUFFER_SIZE,
+        )
+        .await
+        .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv(&mut output, BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "total,client\n10.5,1\n");
+}
+
+/// Tests the structured processing errors with the async engine: the engine error, transaction
+/// and client of a failed record can be matched without parsing the message.
+#[tokio::test]
+async fn unit_test_structured_transaction_errors_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,50.0\n\
+                       transfer,1,3,1.0\n";
+    let engine = AsyncEngine::new();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(
+            std::io::Cursor::new(csv_content.as_bytes().to_vec()),
+            BUFFER_SIZE,
+        )
+        .await
+        .expect_err("The failing records should be reported");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].tx, Some(2));
+    assert_eq!(errors[0].client, Some(1));
+    assert_eq!(
+        errors[0].engine_error(),
+        Some(&EngineError::InsufficientFunds)
+    );
+    assert!(errors[0].to_string().ends_with("Insufficient funds"));
+    assert_eq!(errors[1].kind, TransactionErrorKind::Read);
+    assert_eq!(errors[1].record_line, Some(4));
+}
+
+/// Tests the `-` input of the CLI in async mode: the transactions are streamed from stdin and
+/// the accounts printed on stdout.
+#[test]
+fn unit_test_cli_stdin_input_async() {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_txn_engine"))
+        .args(["async", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("The binary should start");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,4.5\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,5.5,0,5.5,false\n"
+    );
+}
+
+/// Tests `dump_account_to_json` with the async engine: the amounts are JSON strings and every
+/// line parses back into the `Account` of the engine.
+#[tokio::test]
+async fn unit_test_dump_account_to_json_async() {
+    let engine = AsyncEngine::default();
+    let _ = engine
+        .read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE)
+        .await;
+
+    let mut json = Vec::new();
+    engine
+        .dump_account_to_json(&mut json, BUFFER_SIZE)
+        .await
+        .expect("JSON dump should succeed");
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(json.lines().count(), engine.accounts.len().await);
+    for line in json.lines() {
+        let value: serde_json::Value =
+            serde_json::from_str(line).expect("Each line should be valid JSON");
+        assert!(value["available"].is_string());
+        let client = value["client"].as_u64().unwrap() as u16;
+        let account: Account = serde_json::from_value(value).unwrap();
+        let guard = engine.accounts.get(client).await.unwrap();
+        assert_eq!(Some(&account), guard.get(&client));
+    }
+}
+
+/// Tests the bulk `load_from_previous_session_csvs` of the async engine on a 1M-row session:
+/// the loaded state matches a record-by-record load (async CSV reader and one `insert`, and so
+/// one shard lock acquisition, per record), and both load times are reported.
+#[tokio::test]
+async fn reg_test_bulk_load_session_async() -> Result<(), Box<dyn std::error::Error>> {
+    use futures_util::stream::StreamExt;
+    use std::time::Instant;
+    use txn_engine::datastr::transaction::Transaction;
+
+    const ROWS: u32 = 1_000_000;
+    const CLIENTS: u32 = 50_000;
+
+    let mut transactions_file = std::io::BufWriter::new(NamedTempFile::new()?);
+    writeln!(transactions_file, "type,client,tx,amount,disputed")?;
+    for tx in 1..=ROWS {
+        writeln!(
+            transactions_file,
+            "deposit,{},{},{}.5,{}",
+            tx % CLIENTS,
+            tx,
+            tx % 100,
+            tx % 10 == 0
+        )?;
+    }
+    let transactions_file = transactions_file.into_inner()?;
+    let mut accounts_file = std::io::BufWriter::new(NamedTempFile::new()?);
+    writeln!(accounts_file, "client,available,held,total,locked")?;
+    for client in 0..CLIENTS {
+        writeln!(
+            accounts_file,
+            "{},{}.25,0,{}.25,false",
+            client, client, client
+        )?;
+    }
+    let accounts_file = accounts_file.into_inner()?;
+    let transactions_path = transactions_file.path().to_str().unwrap();
+    let accounts_path = accounts_file.path().to_str().unwrap();
+
+    let bulk = AsyncEngine::new();
+    let start = Instant::now();
+    bulk.load_from_previous_session_csvs(transactions_path, accounts_path)
+        .await?;
+    let bulk_elapsed = start.elapsed();
+
+    let naive = AsyncEngine::new();
+    let start = Instant::now();
+    let mut reader = csv_async::AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .create_deserializer(tokio::io::BufReader::new(
+            File::open(transactions_path).await?,
+        ));
+    let mut records = reader.deserialize::<Transaction>();
+    while let Some(record) = records.next().await {
+        let tx = record?;
+        naive.transaction_log.insert(TxKey::global(tx.tx), tx).await;
+    }
+    let mut reader = csv::Reader::from_path(accounts_path)?;
+    for record in reader.deserialize::<(u16, Decimal, Decimal, Decimal, bool)>() {
+        let (client, available, held, total, locked) = record?;
+        let account = Account {
+            available,
+            held,
+            total,
+            locked,
+            ..Account::default()
+        };
+        naive.accounts.insert(client, account).await;
+    }
+    let naive_elapsed = start.elapsed();
+    eprintln!(
+        "Session load of {} rows: bulk {:?}, record by record {:?}",
+        ROWS, bulk_elapsed, naive_elapsed
+    );
+
+    assert_eq!(bulk.transaction_log.len().await, ROWS as usize);
+    assert_eq!(bulk.accounts.len().await, CLIENTS as usize);
+    for tx in [1, 10, ROWS / 2, ROWS] {
+        let key = TxKey::global(tx);
+        let bulk_guard = bulk.transaction_log.get(key).await.unwrap();
+        let naive_guard = naive.transaction_log.get(key).await.unwrap();
+        assert_eq!(bulk_guard.get(&key), naive_guard.get(&key));
+    }
+    let mut accounts = bulk.accounts.iter().await;
+    while let Some((client, guard)) = accounts.next().await {
+        let naive_guard = naive.accounts.get(client).await.unwrap();
+        assert_eq!(guard.get(&client), naive_guard.get(&client));
+    }
+    Ok(())
+}
+
+/// Tests `with_shards`: the same files processed concurrently by engines with 8 and 256 shards
+/// (not only the default 64) give identical final balances.
+#[tokio::test]
+async fn reg_test_shard_count_consistency_async() -> Result<(), Box<dyn std::error::Error>> {
+    let files = [
+        generate_random_transaction_concurrent_stream(100_000, 0, 1, 10)?,
+        generate_random_transaction_concurrent_stream(100_000, 100_001, 200, 300)?,
+        generate_random_transaction_concurrent_stream(100_000, 200_001, 400, 500)?,
+    ];
+
+    let mut snapshots = Vec::new();
+    for shards in [8, 256] {
+        let engine = Arc::new(AsyncEngine::new().with_shards(shards));
+        assert_eq!(engine.accounts.shard_count(), shards);
+        let mut handles = Vec::new();
+        for file in &files {
+            let engine = Arc::clone(&engine);
+            let file = File::open(file.path()).await?;
+            handles.push(tokio::spawn(async move {
+                let _ = engine
+                    .read_and_process_transactions(file, BUFFER_SIZE)
+                    .await;
+            }));
+        }
+        for handle in handles {
+            handle.await?;
+        }
+
+        let mut accounts = Vec::new();
+        let mut iter = engine.accounts.iter().await;
+        while let Some((client, guard)) = iter.next().await {
+            if let Some(account) = guard.get(&client) {
+                accounts.push((client, account.clone()));
+            }
+        }
+        accounts.sort_by_key(|(client, _)| *client);
+        assert!(!accounts.is_empty());
+        snapshots.push(accounts);
+    }
+    assert_eq!(snapshots[0], snapshots[1]);
+    Ok(())
+}
+
+/// Tests `get_account`, `total_clients` and `total_transactions` with the async engine.
+#[tokio::test]
+async fn unit_test_get_account_async() {
+    let engine = AsyncEngine::new();
+    assert_eq!(engine.get_account(1).await, None);
+    assert_eq!(engine.total_clients().await, 0);
+
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,5.0\n\
+               withdrawal,1,3,4.0\n\
+               dispute,1,1,\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+        .unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, Decimal::from_str("-4.0").unwrap());
+    assert_eq!(account.held, Decimal::from_str("10.0").unwrap());
+    assert_eq!(account.total, Decimal::from_str("6.0").unwrap());
+    assert_eq!(engine.get_account(3).await, None);
+    assert_eq!(engine.total_clients().await, 2);
+    assert_eq!(engine.total_transactions().await, 3);
+}
+
+/// Tests `TxUniqueness::PerClient` with the async engine: two clients can use the same tx id,
+/// and disputes and chargebacks refer to the transaction of their own client.
+#[tokio::test]
+async fn unit_test_tx_uniqueness_per_client_async() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,1,5.0\n\
+               dispute,2,1,\n\
+               chargeback,2,1,\n\
+               dispute,1,1,\n\
+               deposit,1,1,3.0\n";
+
+    let engine = AsyncEngine::new();
+    assert!(engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+        .is_err());
+    assert_eq!(engine.get_account(2).await, None);
+    assert_eq!(engine.total_transactions().await, 1);
+
+    let engine = AsyncEngine::new().with_tx_uniqueness(TxUniqueness::PerClient);
+    match engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the repeated deposit to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].client, Some(1));
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::TransactionRepeated)
+            );
+        }
+    }
+    let account1 = engine.get_account(1).await.unwrap();
+    assert_eq!(account1.available, Decimal::ZERO);
+    assert_eq!(account1.held, Decimal::from_str("10.0").unwrap());
+    let account2 = engine.get_account(2).await.unwrap();
+    assert_eq!(account2.total, Decimal::ZERO);
+    assert!(account2.locked);
+
+    assert_eq!(engine.total_transactions().await, 2);
+    assert!(engine.transaction_log.get(TxKey::global(1)).await.is_none());
+    let key = TxKey::per_client(2, 1);
+    let guard = engine.transaction_log.get(key).await.unwrap();
+    assert!(guard.get(&key).unwrap().disputed);
+}
+
+/// Tests the account overdraft limit with the async engine: a withdrawal within the limit loaded
+/// from the trailing `overdraft_limit` column succeeds, one exceeding it fails.
+#[tokio::test]
+async fn unit_test_overdraft_limit_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    writeln!(transactions_file, "type,client,tx,amount,disputed").unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked,overdraft_limit\n\
+         1,10.0,0,10.0,false,50\n\
+         2,10.0,0,10.0,false\n"
+    )
+    .unwrap();
+
+    let engine = AsyncEngine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .expect("The accounts should be loaded");
+
+    let csv = "type,client,tx,amount\n\
+               withdrawal,1,1,40.0\n\
+               withdrawal,1,2,25.0\n\
+               withdrawal,2,3,15.0\n";
+    match engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the withdrawals beyond the limit to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                errors.iter().map(|e| e.tx).collect::<Vec<_>>(),
+                vec![Some(2), Some(3)]
+            );
+        }
+    }
+    assert_eq!(
+        engine.get_account(1).await.unwrap().available,
+        Decimal::from_str("-30.0").unwrap()
+    );
+    assert_eq!(
+        engine.get_account(2).await.unwrap().available,
+        Decimal::from_str("10.0").unwrap()
+    );
+}
+
+/// Tests the `AccountCapacityPolicy` of `AsyncEngine::with_max_accounts` at the capacity
+/// boundary, see `unit_test_max_accounts_policies`.
+#[tokio::test]
+async fn unit_test_max_accounts_policies_async() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,20.0\n\
+               deposit,3,3,30.0\n\
+               deposit,1,4,5.0\n";
+
+    let engine = AsyncEngine::new().with_max_accounts(2, AccountCapacityPolicy::Reject);
+    match engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the deposit beyond the limit to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].tx, Some(3));
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::AccountCapacityReached)
+            );
+        }
+    }
+    assert_eq!(engine.total_clients().await, 2);
+    assert_eq!(
+        engine.get_account(1).await.unwrap().available,
+        Decimal::from(15)
+    );
+
+    let engine = AsyncEngine::new().with_max_accounts(2, AccountCapacityPolicy::Overflow(999));
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert!(engine.get_account(3).await.is_none());
+    assert_eq!(
+        engine.get_account(999).await.unwrap().available,
+        Decimal::from(30)
+    );
+
+    let engine = AsyncEngine::new().with_max_accounts(2, AccountCapacityPolicy::Drop);
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect_err("The dropped deposit should be reported");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].tx, Some(3));
+    assert_eq!(errors[0].engine_error(), Some(&EngineError::DepositDropped));
+    assert_eq!(engine.total_clients().await, 2);
+    assert!(engine.get_account(3).await.is_none());
+}
+
+/// Tests the transparent decompression of gzip input files with the async engine, see
+/// `unit_test_gzip_input`.
+#[tokio::test]
+async fn unit_test_gzip_input_async() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,20.0\n\
+               withdrawal,1,3,4.5\n\
+               dispute,2,2,\n";
+    let mut gz_file = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .unwrap();
+    let mut encoder = GzEncoder::new(gz_file.as_file_mut(), Compression::default());
+    encoder.write_all(csv.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions_from_csv(gz_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .await
+        .unwrap();
+    let plain = AsyncEngine::new();
+    plain
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    for client in [1, 2] {
+        assert_eq!(
+            engine.get_account(client).await,
+            plain.get_account(client).await
+        );
+    }
+    assert_eq!(
+        engine.get_account(1).await.unwrap().available,
+        Decimal::from_str("5.5").unwrap()
+    );
+}
+
+/// Tests the rounding of the input amounts with the async engine, see
+/// `unit_test_input_rounding`.
+#[tokio::test]
+async fn unit_test_input_rounding_async() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,1.12345\n";
+    for (rounding, expected) in [(Rounding::HalfUp, "1.1235"), (Rounding::HalfEven, "1.1234")] {
+        let engine = AsyncEngine::new().with_rounding(rounding);
+        engine
+            .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.get_account(1).await.unwrap().available,
+            Decimal::from_str(expected).unwrap()
+        );
+    }
+}
+
+/// Tests `AsyncEngine::verify_invariants` on accounts loaded from a previous session, see
+/// `unit_test_verify_invariants`.
+#[tokio::test]
+async fn unit_test_verify_invariants_async() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    writeln!(transactions_file, "type,client,tx,amount,disputed").unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,10.0,5.0,15.0,false\n\
+         2,10.0,5.0,20.0,false\n"
+    )
+    .unwrap();
+
+    let engine = AsyncEngine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        engine.verify_invariants().await,
+        Err(vec![InvariantViolation {
+            client: 2,
+            discrepancy: Some(Decimal::from(-5)),
+        }])
+    );
+}
+
+/// Tests `prune_transactions` with the async engine: pruning is rejected while an older
+/// transaction is disputed, and disputes on pruned tx ids then fail with `TransactionNotFound`.
+#[tokio::test]
+async fn unit_test_prune_transactions_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       deposit,1,2,20\n\
+                       deposit,1,3,30\n\
+                       dispute,1,2,\n";
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+
+    assert_eq!(
+        engine.prune_transactions(3).await,
+        Err(EngineError::TransactionUnsettled)
+    );
+    assert_eq!(engine.transaction_log.len().await, 3);
+
+    let csv_content = "type,client,tx,amount\n\
+                       resolve,1,2,\n";
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The resolve should be applied");
+    assert_eq!(engine.prune_transactions(3).await, Ok(2));
+    assert_eq!(engine.transaction_log.len().await, 1);
+
+    let csv_content = "type,client,tx,amount\n\
+                       dispute,1,1,\n\
+                       dispute,1,3,\n";
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the dispute on the pruned transaction to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].tx, Some(1));
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::TransactionNotFound)
+            );
+        }
+    }
+    let guard = engine.accounts.get(1).await.unwrap();
+    assert_eq!(guard.get(&1).unwrap().held, Decimal::from(30));
+}
+
+/// Tests partial chargebacks with the async engine: the partial ones leave the account unlocked
+/// and the remainder disputed, the final one (without amount) reverses the remainder and locks
+/// the account.
+#[tokio::test]
+async fn unit_test_partial_chargeback_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,100\n\
+                       dispute,1,1,\n\
+                       chargeback,1,1,30\n\
+                       chargeback,1,1,80\n";
+    let engine = AsyncEngine::new();
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Ok(()) => panic!("Expected the chargeback exceeding the held funds to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::ChargebackExceedsHeld)
+            );
+        }
+    }
+    {
+        let guard = engine.accounts.get(1).await.unwrap();
+        let account = guard.get(&1).unwrap();
+        assert_eq!(account.held, Decimal::from(70));
+        assert_eq!(account.total, Decimal::from(70));
+        assert!(!account.locked);
+    }
+    {
+        let guard = engine.transaction_log.get(TxKey::global(1)).await.unwrap();
+        let logged = guard.get(&TxKey::global(1)).unwrap();
+        assert_eq!(logged.amount, Some(Decimal::from(100)));
+        assert_eq!(logged.charged_back, Decimal::from(30));
+    }
+    assert_eq!(engine.max_chargeback_exposure().await, Decimal::from(70));
+
+    let csv_content = "type,client,tx,amount\n\
+                       chargeback,1,1,20\n\
+                       chargeback,1,1,\n";
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The chargebacks should be applied");
+    let guard = engine.accounts.get(1).await.unwrap();
+    let account = guard.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::ZERO);
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::ZERO);
+    assert!(account.locked);
+}
+
+/// Tests `process_files`: two files are processed concurrently into the same engine, the
+/// accounts of both are created and the malformed row of the second file is reported once,
+/// prefixed with its path.
+#[tokio::test]
+async fn unit_test_process_files_async() {
+    let mut good_file = NamedTempFile::new().unwrap();
+    write!(
+        good_file,
+        "type,client,tx,amount\n\
+         deposit,1,1,10\n\
+         deposit,2,2,20\n\
+         withdrawal,1,3,4\n"
+    )
+    .unwrap();
+    let mut bad_file = NamedTempFile::new().unwrap();
+    write!(
+        bad_file,
+        "type,client,tx,amount\n\
+         deposit,3,4,30\n\
+         deposit,not_a_client,5,1\n\
+         deposit,4,6,40\n"
+    )
+    .unwrap();
+    let paths = vec![
+        good_file.path().to_str().unwrap().to_owned(),
+        bad_file.path().to_str().unwrap().to_owned(),
+    ];
+
+    let engine = AsyncEngine::new();
+    match engine.process_files(&paths, BUFFER_SIZE).await {
+        Ok(()) => panic!("Expected the malformed row to be reported"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].kind, TransactionErrorKind::Read);
+            assert!(
+                errors[0]
+                    .message()
+                    .starts_with(&format!("{}: Error reading transaction record", paths[1])),
+                "{}",
+                errors[0]
+            );
+        }
+    }
+
+    for (client, total) in [(1, 6), (2, 20), (3, 30), (4, 40)] {
+        let account = engine.get_account(client).await.unwrap();
+        assert_eq!(account.total, Decimal::from(total), "client {}", client);
+    }
+    assert_eq!(engine.total_clients().await, 4);
+}
+
+/// Tests reading a tab-delimited, BOM-prefixed file with the async engine and
+/// `CsvDialect::tab_separated`.
+#[tokio::test]
+async fn unit_test_csv_dialect_async() {
+    let csv_content = "\u{feff}type\tclient\ttx\tamount\n\
+                       deposit\t1\t1\t10.5\n\
+                       withdrawal\t1\t2\t4\n";
+    let engine = AsyncEngine::new().with_csv_dialect(CsvDialect::tab_separated());
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.total, Decimal::from_str("6.5").unwrap());
+}
+
+/// Tests composing the stream of the accounts with `StreamExt` combinators: only the locked
+/// accounts are collected, as copies.
+#[tokio::test]
+async fn unit_test_accounts_stream_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       deposit,2,2,20\n\
+                       deposit,3,3,30\n\
+                       dispute,2,2,\n\
+                       chargeback,2,2,\n\
+                       dispute,3,3,\n";
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+
+    let locked: Vec<(u16, Account)> = engine
+        .accounts
+        .stream()
+        .filter(|(_, account)| std::future::ready(account.locked))
+        .collect()
+        .await;
+    assert_eq!(locked.len(), 1);
+    assert_eq!(locked[0].0, 2);
+    assert_eq!(locked[0].1.total, Decimal::ZERO);
+
+    let mut clients: Vec<u16> = engine
+        .accounts
+        .stream()
+        .map(|(client, _)| client)
+        .collect()
+        .await;
+    clients.sort_unstable();
+    assert_eq!(clients, vec![1, 2, 3]);
+}
+
+/// Tests that a dispute of client 2 referring to a transaction of client 1 is reported as
+/// `DifferentClient`, whether or not client 2 has an account.
+#[tokio::test]
+async fn unit_test_cross_client_dispute_async() {
+    for csv_content in [
+        "type,client,tx,amount\ndeposit,1,1,10\ndispute,2,1,\n",
+        "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,5\ndispute,2,1,\n",
+    ] {
+        let engine = AsyncEngine::new();
+        match engine
+            .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+            .await
+        {
+            Err(TransactionProcessingError::MultipleErrors(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(
+                    errors[0].engine_error(),
+                    Some(&EngineError::DifferentClient),
+                    "{}",
+                    csv_content
+                );
+            }
+            Ok(()) => panic!("Expected DifferentClient for {}", csv_content),
+        }
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.held, Decimal::ZERO);
+    }
+}
+
+/// Tests `NegativeHeldPolicy::Strict` with the async engine: disputing a withdrawal fails with
+/// `NegativeHeld` and leaves the account unchanged, while by default held goes to -5.
+#[tokio::test]
+async fn unit_test_negative_held_policy_strict_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       withdrawal,1,2,5\n\
+                       dispute,1,2,\n";
+
+    let engine = AsyncEngine::default();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("By default a withdrawal can be disputed");
+    assert_eq!(engine.get_account(1).await.unwrap().held, Decimal::from(-5));
+
+    let engine = AsyncEngine::default().with_negative_held_policy(NegativeHeldPolicy::Strict);
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].engine_error(), Some(&EngineError::NegativeHeld));
+        }
+        Ok(()) => panic!("Expected the dispute of the withdrawal to be rejected"),
+    }
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::ZERO);
+}
+
+/// Tests that the async engine maps the records by header name: reordered columns and unknown
+/// extra columns are read correctly.
+#[tokio::test]
+async fn unit_test_header_order_agnostic_async() {
+    let csv_content = "note,client,amount,type,tx\n\
+                       first,1,10.5,deposit,1\n\
+                       ,1,4,withdrawal,2\n\
+                       x,1,,dispute,2\n";
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The transactions should be processed");
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, Decimal::from_str("10.5").unwrap());
+    assert_eq!(account.held, Decimal::from(-4));
+    assert_eq!(account.total, Decimal::from_str("6.5").unwrap());
+}
+
+/// Tests `DisputePolicy` with the async engine: disputing a withdrawal fails with
+/// `WithdrawalNotDisputable` when withdrawal disputes are not allowed.
+#[tokio::test]
+async fn unit_test_dispute_policy_withdrawals_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       withdrawal,1,2,4\n\
+                       dispute,1,2,\n";
+
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("By default withdrawals can be disputed");
+    assert_eq!(engine.get_account(1).await.unwrap().held, Decimal::from(-4));
+
+    let engine = AsyncEngine::new().with_dispute_policy(DisputePolicy {
+        allow_withdrawal_disputes: false,
+    });
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::WithdrawalNotDisputable)
+            );
+        }
+        Ok(()) => panic!("Expected the dispute of the withdrawal to be rejected"),
+    }
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.held, Decimal::ZERO);
+}
+
+/// Tests `dump_account_to_csv_sorted` with the async engine: the rows are in strictly
+/// increasing client order.
+#[tokio::test]
+async fn unit_test_dump_account_to_csv_sorted_async() {
+    let mut csv_content = String::from("type,client,tx,amount\n");
+    for i in 0..300u32 {
+        csv_content.push_str(&format!("deposit,{},{},1\n", (i * 7919) % 1000, i));
+    }
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(std::io::Cursor::new(csv_content), BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv_sorted(&mut output, BUFFER_SIZE)
+        .await
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let clients: Vec<u16> = output
+        .lines()
+        .skip(1)
+        .map(|row| row.split(',').next().unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(clients.len(), 300);
+    assert!(clients.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+/// Tests reversals with the async engine: a deposit is undone (its logged transaction keeps its
+/// type and is flagged as reversed), a reversed transaction cannot be reversed again, and a
+/// disputed one cannot be reversed.
+#[tokio::test]
+async fn unit_test_reversal_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       deposit,1,2,100\n\
+                       deposit,1,3,5\n\
+                       reversal,1,2,\n\
+                       reversal,1,2,\n\
+                       dispute,1,3,\n\
+                       reversal,1,3,\n";
+    let engine = AsyncEngine::new();
+    let errors = match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => errors,
+        Ok(()) => panic!("Expected the invalid reversals to be reported"),
+    };
+    let kinds: Vec<_> = errors.iter().map(|e| e.engine_error().cloned()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            Some(EngineError::AlreadyReversed),
+            Some(EngineError::CannotReverseDisputed),
+        ]
+    );
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(15));
+
+    let tx2_guard = engine.transaction_log.get(TxKey::global(2)).await.unwrap();
+    let tx2 = tx2_guard.get(&TxKey::global(2)).unwrap();
+    assert_eq!(tx2.ty, TransactionType::Deposit);
+    assert_eq!(tx2.amount, Some(Decimal::from(100)));
+    assert!(tx2.reversed);
+}
+
+/// Tests `LockedAccountPolicy` with the async engine: the resolve of a disputed deposit on an
+/// account locked by a chargeback is rejected by default and applied under `AllowDisputes`.
+#[tokio::test]
+async fn unit_test_locked_account_policy_resolve_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       deposit,1,2,5\n\
+                       dispute,1,1,\n\
+                       dispute,1,2,\n\
+                       chargeback,1,1,\n\
+                       resolve,1,2,\n";
+
+    let engine = AsyncEngine::new();
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].engine_error(), Some(&EngineError::AccountLocked));
+        }
+        Ok(()) => panic!("Expected the resolve on the locked account to be rejected"),
+    }
+    assert_eq!(engine.get_account(1).await.unwrap().held, Decimal::from(5));
+
+    let engine = AsyncEngine::new().with_locked_account_policy(LockedAccountPolicy::AllowDisputes);
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("The resolve is applied on the locked account");
+    let account = engine.get_account(1).await.unwrap();
+    assert!(account.locked);
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(5));
+
+    let csv_content = "type,client,tx,amount\n\
+                       chargeback,1,1,\n\
+                       deposit,1,3,1\n";
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::AlreadyChargedBack)
+            );
+            assert_eq!(errors[1].engine_error(), Some(&EngineError::AccountLocked));
+        }
+        Ok(()) => panic!("Expected the chargeback and the deposit to be rejected"),
+    }
+    assert_eq!(engine.get_account(1).await.unwrap(), account);
+}
+
+/// Tests strict mode with the async engine: the deposit of a registered client is applied, the
+/// one of an unregistered client fails with `UnknownClient` without creating an account.
+#[tokio::test]
+async fn unit_test_strict_clients_async() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10\n\
+                       deposit,2,2,5\n";
+
+    let engine = AsyncEngine::new().with_strict_clients(true);
+    engine.register_client(1).await;
+    match engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].engine_error(), Some(&EngineError::UnknownClient));
+        }
+        Ok(()) => panic!("Expected the deposit of client 2 to be rejected"),
+    }
+    assert_eq!(
+        engine.get_account(1).await.unwrap().total,
+        Decimal::from(10)
+    );
+    assert!(engine.get_account(2).await.is_none());
+
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .await
+        .expect("By default any client can deposit");
+    assert_eq!(engine.get_account(2).await.unwrap().total, Decimal::from(5));
+}
+
+/// Tests `with_only_types` with the async engine on a mixed file: the dispute, resolve and
+/// chargeback rows are skipped and the balances reflect only the deposits and withdrawals.
+#[tokio::test]
+async fn unit_test_only_types_async() {
+    let engine = AsyncEngine::new().with_only_types(Some(
+        [TransactionType::Deposit, TransactionType::Withdrawal].into(),
+    ));
+    // Only the zero deposit and withdrawal of the file fail.
+    match engine
+        .read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(errors.iter().all(|e| e.client == Some(11)));
+        }
+        Ok(()) => panic!("Expected the zero deposit and withdrawal to be rejected"),
+    }
+
+    let account = engine.get_account(10).await.unwrap();
+    assert_eq!(account.available, Decimal::from(30));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert!(!account.locked);
+    let account = engine.get_account(8).await.unwrap();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::ZERO);
+}
+
+/// Tests the empty inputs with the async engine: a file with only the header is a successful
+/// no-op, a zero-byte file fails with a single "missing header" error.
+#[tokio::test]
+async fn unit_test_empty_input_async() {
+    let mut header_only = NamedTempFile::new().unwrap();
+    writeln!(header_only, "type,client,tx,amount").unwrap();
+    let engine = AsyncEngine::new();
+    engine
+        .read_and_process_transactions_from_csv(header_only.path().to_str().unwrap(), BUFFER_SIZE)
+        .await
+        .expect("A file with only the header is a no-op");
+    assert_eq!(engine.accounts.len().await, 0);
+
+    let zero_bytes = NamedTempFile::new().unwrap();
+    let engine = AsyncEngine::new();
+    match engine
+        .read_and_process_transactions_from_csv(zero_bytes.path().to_str().unwrap(), BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].message(), "empty input: missing header");
+        }
+        Ok(()) => panic!("Expected the zero-byte file to be rejected"),
+    }
+    assert_eq!(engine.accounts.len().await, 0);
+}
+
+/// Tests the timestamp ordering with the async engine: a dispute preceding its deposit in the
+/// file fails by default, but is processed after the deposit when the records are ordered by
+/// their ISO-8601 `timestamp` column.
+#[tokio::test]
+async fn unit_test_timestamp_ordering_async() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(
+        temp_file,
+        "type,client,tx,amount,timestamp\n\
+         dispute,1,1,,2024-05-01T10:00:00Z\n\
+         deposit,1,1,10.0,2024-05-01T09:00:00Z\n\
+         deposit,1,2,5.0,2024-05-01T11:00:00Z\n"
+    )
+    .unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let engine = AsyncEngine::new();
+    match engine
+        .read_and_process_transactions_from_csv(path, BUFFER_SIZE)
+        .await
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::AccountNotFound)
+            );
+        }
+        Ok(()) => panic!("Expected the dispute to fail"),
+    }
+    assert_eq!(engine.get_account(1).await.unwrap().held, Decimal::ZERO);
+
+    let engine = AsyncEngine::new().with_timestamp_ordering(2);
+    engine
+        .read_and_process_transactions_from_csv(path, BUFFER_SIZE)
+        .await
+        .expect("The dispute should be processed after its deposit");
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.available, Decimal::from(5));
+
+    let mut no_timestamps = NamedTempFile::new().unwrap();
+    write!(no_timestamps, "type,client,tx,amount\ndeposit,1,1,10.0\n").unwrap();
+    let engine = AsyncEngine::new().with_timestamp_ordering(16);
+    assert!(engine
+        .read_and_process_transactions_from_csv(no_timestamps.path().to_str().unwrap(), BUFFER_SIZE)
+        .await
+        .is_err());
+    assert_eq!(engine.accounts.len().await, 0);
+}
@@ -1,17 +1,41 @@
+use chrono::DateTime;
 use csv::Writer;
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::str::FromStr;
-use txn_engine::datastr::account::serialize_account_balances_csv;
-use txn_engine::datastr::transaction::{TransactionProcessingError, TransactionType};
-use txn_engine::engine::{Engine, EngineFunctions};
-use txn_engine::utility::generate_random_transaction_concurrent_stream;
+use txn_engine::basics::feed::FeedOverflowPolicy;
+use txn_engine::config::{
+    AccountCapacityPolicy, AccountColumn, AmountUnits, BoolFormat, ConfigFile, CsvDialect,
+    DepositCheckOrder, DisputePolicy, EngineConfig, HistoricalDisputePolicy, LockedAccountPolicy,
+    NegativeHeldPolicy, NegativeTotalPolicy, PreDumpVerification, ResolvePolicy, Rounding,
+    TxUniqueness,
+};
+use txn_engine::datastr::account::{serialize_account_balances_csv, Account};
+use txn_engine::datastr::deser::{normalize_amount, DeserError};
+use txn_engine::datastr::transaction::{
+    Transaction, TransactionError, TransactionErrorKind, TransactionProcessingError,
+    TransactionType, TxKey,
+};
+use txn_engine::engine::{
+    Engine, EngineError, EngineFunctions, EngineSerDeserError, HeldMismatch, InvariantViolation,
+    MergeConflict, CHECKPOINT_ACCOUNTS, CHECKPOINT_TRANSACTIONS,
+};
+use txn_engine::utility::{
+    bytes_to_mb, generate_random_transaction_concurrent_stream, get_current_memory, MemoryReporter,
+    MemorySource, MemoryUnit,
+};
 
 use std::io::Write;
 use tempfile::NamedTempFile;
 
 const BUFFER_SIZE: usize = 16_384;
 
+/// Returns the messages of the errors collected by a processing call.
+fn messages(errors: &[TransactionError]) -> Vec<&str> {
+    errors.iter().map(TransactionError::message).collect()
+}
+
 #[test]
 fn unit_test_deposit_and_withdrawal() {
     let mut temp_file = NamedTempFile::new().unwrap();
@@ -282,10 +306,7 @@ fn unit_test_deposit_and_dispute_chargeback() {
         Decimal::from_str("0.0000").unwrap(),
         "Held should be 10 after dispute"
     );
-    assert_eq!(
-        account.locked, true,
-        "Account must be locked after chargeback"
-    );
+    assert!(account.locked, "Account must be locked after chargeback");
 }
 
 #[test]
@@ -515,7 +536,7 @@ fn unit_test_addition_overflow() {
             panic!("Engine::read_and_process_transactions_from_csv should fail due to overflow")
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("{}", e);
             assert!(
                 e.to_string().contains("Addition overflow"),
                 "Expected `Addition overflow` error"
@@ -540,13 +561,13 @@ fn unit_test_addition_overflow() {
 /// 1.12 => 1.1200
 /// 1.1 => 1.1000
 /// 1 => 1.0000
-
+///
 /// Sum of deposits = 1.1235 + 1.1235 + 1.1234 + 1.1230 + 1.1200 + 1.1000 + 1.0000 = 7.7134
 /// Withdrawals:
 /// 0.00045 => 0.0005 (rounded up)
 /// 0.000045 => 0.0000 (rounded down to zero due to 4-digit precision)
 /// 0.0000045 => 0.0000 (rounded down to zero due to 4-digit precision)
-
+///
 /// Sum of withdrawals = 0.0005 + 0.0000 + 0.0000 = 0.0005
 /// Net Balance Calculation:
 /// Net Balance = Sum of Deposits - Sum of Withdrawals
@@ -596,70 +617,51 @@ fn unit_test_decimal_precision() {
 /// Tests the handling of subtraction overflow during transaction processing.
 ///
 /// This test simulates a scenario where a dispute transaction causes a subtraction
-/// overflow. It creates temporary CSV files for transactions and accounts dumps, loads them
-/// into an `Engine` instance, and then processes a transaction that should trigger
-/// a subtraction overflow error.
+/// overflow. It creates an `Engine` from an in-memory state with `Engine::from_state`
+/// and then processes a transaction that should trigger a subtraction overflow error.
 /// This is necessary as the engine cannot generate a status on the Engine such that a
 /// transaction can generate a subtraction overflow just processing transactions. So we need to populate the Engine state
-/// from an ad-hoc "corrupted" input file.
+/// from an ad-hoc "corrupted" state.
 ///
-/// The test expects the `Engine::read_and_process_transactions_from_csv` function to return an error
+/// The test expects the `Engine::read_and_process_transactions` function to return an error
 /// indicating a `Subtraction overflow`. If no error occurs or a different error
 /// is returned, the test will fail.
 #[test]
 fn unit_test_subtraction_overflow() {
-    // Create temporary files for transactions and accounts
-    let mut transactions_file = NamedTempFile::new().expect("Failed to create temporary file");
-    let mut accounts_file = NamedTempFile::new().expect("Failed to create temporary file");
-
-    // Write transaction data
-    transactions_file
-        .write_all(
-            b"type,client,tx,amount\n
-                                        deposit,1,1,10.0000\n
-                                        deposit,2,2,5.0000\n
-                                        deposit,3,3,100.0000\n
-                                        withdrawal,1,4,5.0000",
-        )
-        .unwrap();
-
-    let large_neg_amount = (Decimal::MIN + Decimal::from(1)).to_string();
-    let csv_content = format!(
-        r#"client,available,held,total,locked,\n
-        1,5.0000,0.0000,5.0000,false,\n
-        2,5.0000,0.0000,5.0000,false,\n
-        3,{},{},{},false,\n"#,
-        large_neg_amount, large_neg_amount, large_neg_amount
-    );
-
-    // Write account data
-    write!(accounts_file, "{}", csv_content).unwrap();
-
-    // Create an instance of Engine
-    let mut engine = Engine::new();
-
-    // Load data from CSV files
-    engine
-        .load_from_previous_session_csvs(
-            transactions_file.path().to_str().unwrap(),
-            accounts_file.path().to_str().unwrap(),
-        )
-        .expect("Failed to load from CSV");
-
-    let mut temp_file = NamedTempFile::new().unwrap();
-    let csv_content = format!(
-        r#"type,client,tx,amount,\n
-           dispute,3,3,,\n"#
+    let large_neg_amount = Decimal::MIN + Decimal::from(1);
+    let account = |amount: Decimal| Account {
+        available: amount,
+        held: Decimal::ZERO,
+        total: amount,
+        ..Account::default()
+    };
+    let engine = Engine::from_state(
+        vec![
+            (1, account(Decimal::from(5))),
+            (2, account(Decimal::from(5))),
+            (
+                3,
+                Account {
+                    held: large_neg_amount,
+                    ..account(large_neg_amount)
+                },
+            ),
+        ],
+        vec![
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(2, 2, Decimal::from(5)),
+            Transaction::deposit(3, 3, Decimal::from(100)),
+            Transaction::withdrawal(1, 4, Decimal::from(5)),
+        ],
     );
-    write!(temp_file, "{}", csv_content).unwrap();
-    let input_path = temp_file.path().to_str().unwrap();
 
-    match engine.read_and_process_transactions_from_csv(input_path, BUFFER_SIZE) {
+    let csv = "type,client,tx,amount\ndispute,3,3,\n";
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
         Ok(()) => {
-            panic!("Engine::read_and_process_transactions_from_csv should fail due to overflow")
+            panic!("Engine::read_and_process_transactions should fail due to overflow")
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("{}", e);
             assert!(
                 e.to_string().contains("Subtraction overflow"),
                 "Expected `Subtraction overflow` error"
@@ -668,6 +670,65 @@ fn unit_test_subtraction_overflow() {
     }
 }
 
+/// Tests that an `Engine` created with `Engine::from_state` behaves as one loaded with
+/// `load_from_previous_session_csvs` from the same accounts and transactions: a subsequent
+/// dispute leaves both engines in the same state.
+#[test]
+fn unit_test_from_state() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    transactions_file
+        .write_all(b"type,client,tx,amount\ndeposit,1,1,10.0000\nwithdrawal,1,2,4.0000\n")
+        .unwrap();
+    accounts_file
+        .write_all(b"client,available,held,total,locked\n1,6.0000,0.0000,6.0000,false\n")
+        .unwrap();
+    let mut loaded = Engine::new();
+    loaded
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .unwrap();
+
+    let from_state = Engine::from_state(
+        vec![(
+            1,
+            Account {
+                available: Decimal::from(6),
+                total: Decimal::from(6),
+                ..Account::default()
+            },
+        )],
+        vec![
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::withdrawal(1, 2, Decimal::from(4)),
+        ],
+    );
+
+    for engine in [&loaded, &from_state] {
+        engine.process_slice(&[Transaction::dispute(1, 1)]).unwrap();
+    }
+    let account = from_state.accounts.get(&1).unwrap().clone();
+    assert_eq!(account, *loaded.accounts.get(&1).unwrap());
+    assert_eq!(account.available, Decimal::from(-4));
+    assert_eq!(account.held, Decimal::from(10));
+    for tx in [1, 2] {
+        let key = TxKey::global(tx);
+        assert_eq!(
+            *from_state.transaction_log.get(&key).unwrap(),
+            *loaded.transaction_log.get(&key).unwrap()
+        );
+    }
+    assert!(
+        from_state
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .disputed
+    );
+}
+
 /// Test that transactions are processed correctly from a CSV file.
 ///
 /// The CSV file `tests/transactions_basic.csv` contains three deposits and two withdrawal.
@@ -796,24 +857,24 @@ fn reg_test_from_csv_file_disputed() {
 
 #[test]
 ///Tests the handling of several erroneous transactions from a CSV file.
-
+///
 /// type       ,client,tx   ,amount
-
+///
 /// deposit    ,6     ,9    ,0.0000
 /// withdrawal ,6     ,10   ,-5.0000       # Negative amount, should fail
 /// deposit    ,6     ,11   ,79228162514264337593543950330  # Large amount
 /// deposit    ,6     ,12   ,5000.0000     # Addition overflowed
 /// withdrawal ,6     ,13   ,              # Empty amount --> fail
-
+///
 /// deposit    ,7     ,14   ,              # Empty amount --> fail
 /// deposit    ,7     ,15   ,10.0
 /// deposit    ,7     ,15   ,10.0          # Duplicate tx
 /// dispute    ,7     ,16   ,              # Dispute on non-existent or invalid tx
-
+///
 /// resolve    ,6     ,9999 ,              # Resolve on non-existent tx
-
+///
 /// chargeback ,7     ,16   ,              # Chargeback on non-existent tx
-
+///
 /// dispute    ,7     ,15   ,
 /// dispute    ,7     ,15   ,              # Transaction already disputed
 /// chargeback ,7     ,15   ,
@@ -824,6 +885,15 @@ fn reg_test_from_csv_file_disputed() {
 /// deposit    ,9     ,20  ,100
 /// withdrawal ,9     ,21  ,200            # Insufficient funds : the transaction does NOT gets into the transaction log.
 /// dispute    ,9     ,21  ,               # Dispute on non-existent or invalid tx
+///
+/// deposit    ,10    ,22  ,50
+/// freeze     ,10    ,23  ,
+/// freeze     ,10    ,24  ,               # Account already frozen
+/// withdrawal ,10    ,25  ,10             # Account is locked
+/// unfreeze   ,10    ,26  ,
+/// unfreeze   ,10    ,27  ,               # Account not frozen
+/// withdrawal ,10    ,28  ,10
+/// freeze     ,99    ,29  ,               # Account not found
 /// The test checks that the correct errors are reported.
 ///
 fn reg_test_from_csv_file_error_conditions() {
@@ -833,7 +903,7 @@ fn reg_test_from_csv_file_error_conditions() {
         Ok(()) => panic!("Expected an error, but got success"),
         Err(TransactionProcessingError::MultipleErrors(errors)) => {
             let expected_errors = vec![
-                "Error reading transaction record: Unknown transaction type: DEPOSIT",
+                "Error reading transaction record: CSV deserialize error: record 18 (line: 19, byte: 335): Unknown transaction type: DEPOSIT",
                 "Error processing Transaction { ty: Deposit, client: 6, tx: 9, amount: Some(0.0000), disputed: false }: Deposit amount must be greater than 0",
                 "Error processing Transaction { ty: Withdrawal, client: 6, tx: 10, amount: Some(-5.0000), disputed: false }: Withdrawal amount must be greater than 0",
                 "Error processing Transaction { ty: Deposit, client: 6, tx: 12, amount: Some(5000.0000), disputed: false }: Addition overflow",
@@ -848,10 +918,14 @@ fn reg_test_from_csv_file_error_conditions() {
                 "Error processing Transaction { ty: Resolve, client: 8, tx: 18, amount: None, disputed: false }: Transaction not disputed",
                 "Error processing Transaction { ty: Withdrawal, client: 9, tx: 21, amount: Some(200), disputed: false }: Insufficient funds",
                 "Error processing Transaction { ty: Dispute, client: 9, tx: 21, amount: None, disputed: false }: Transaction not found",
+                "Error processing Transaction { ty: Freeze, client: 10, tx: 24, amount: None, disputed: false }: Account already frozen",
+                "Error processing Transaction { ty: Withdrawal, client: 10, tx: 25, amount: Some(10), disputed: false }: Account is locked",
+                "Error processing Transaction { ty: Unfreeze, client: 10, tx: 27, amount: None, disputed: false }: Account not frozen",
+                "Error processing Transaction { ty: Freeze, client: 99, tx: 29, amount: None, disputed: false }: Account not found",
             ];
 
             // Compare the sorted errors to ensure the order doesn't matter
-            let mut actual_errors = errors.clone();
+            let mut actual_errors = messages(&errors);
             actual_errors.sort();
             let mut expected_errors_sorted = expected_errors;
             expected_errors_sorted.sort();
@@ -862,7 +936,10 @@ fn reg_test_from_csv_file_error_conditions() {
             );
         }
     }
-    assert_eq!(engine.accounts.len(), 4);
+    assert_eq!(engine.accounts.len(), 5);
+    let account = engine.get_account(10).unwrap();
+    assert!(!account.locked);
+    assert_eq!(account.available, Decimal::from(40));
 }
 
 /// Tests that processing a CSV file with malformed records results in the expected errors.
@@ -880,13 +957,13 @@ fn reg_test_from_csv_file_malformed() {
         Err(TransactionProcessingError::MultipleErrors(errors)) => {
             let expected_errors = vec![
                 "Error reading transaction record: CSV deserialize error: record 1 (line: 2, byte: 22): invalid digit found in string",
-                "Error reading transaction record: Unknown transaction type: deposi",
-                "Error reading transaction record: Unknown transaction type: witawal",
+                "Error reading transaction record: CSV deserialize error: record 2 (line: 3, byte: 46): Unknown transaction type: deposi",
+                "Error reading transaction record: CSV deserialize error: record 4 (line: 5, byte: 94): Unknown transaction type: witawal",
                 "Error processing Transaction { ty: Withdrawal, client: 1, tx: 3, amount: Some(5.0000), disputed: false }: Account not found",
             ];
 
             // Compare the sorted errors to ensure the order doesn't matter
-            let mut actual_errors = errors.clone();
+            let mut actual_errors = messages(&errors);
             actual_errors.sort();
             let mut expected_errors_sorted = expected_errors;
             expected_errors_sorted.sort();
@@ -913,7 +990,6 @@ fn reg_test_from_csv_file_malformed() {
 /// The test expects that:
 /// - The transaction log contains three transactions with correct details.
 /// - The accounts map contains two accounts with expected balances and states.
-
 #[test]
 fn reg_test_load_from_previous_session_csv() {
     // Create temporary files for transactions and accounts
@@ -952,13 +1028,13 @@ fn reg_test_load_from_previous_session_csv() {
 
     // Check if transactions were loaded correctly
     assert_eq!(engine.transaction_log.len(), 3);
-    let tx1 = engine.transaction_log.get(&1).unwrap();
+    let tx1 = engine.transaction_log.get(&TxKey::global(1)).unwrap();
     assert_eq!(tx1.ty, TransactionType::Deposit);
     assert_eq!(tx1.client, 1);
     assert_eq!(tx1.tx, 1);
     assert_eq!(tx1.amount, Some(Decimal::new(10_0000, 4))); // 10.0000
 
-    let tx2 = engine.transaction_log.get(&3).unwrap();
+    let tx2 = engine.transaction_log.get(&TxKey::global(3)).unwrap();
     assert_eq!(tx2.ty, TransactionType::Withdrawal);
     assert_eq!(tx2.client, 1);
     assert_eq!(tx2.tx, 3);
@@ -1053,11 +1129,11 @@ fn reg_test_serdesr_engine() {
             if let Some(transaction2) = engine2.transaction_log.get(&tx_id) {
                 assert_eq!(
                     *transaction, *transaction2,
-                    "Transaction mismatch for tx_id {}",
+                    "Transaction mismatch for tx_id {:?}",
                     tx_id
                 );
             } else {
-                panic!("Transaction with tx_id {} not found in engine2", tx_id);
+                panic!("Transaction with tx_id {:?} not found in engine2", tx_id);
             }
         }
     }
@@ -1102,58 +1178,60 @@ fn reg_test_engine_consistency_with_concurrent_processing() {
     let engine1 = Engine::new();
     let engine2 = Arc::new(Engine::new());
 
-    // Process files sequentially with engine1
-    match engine1.read_and_process_transactions(File::open(temp_file1.path()).unwrap(), BUF_SIZE) {
-        Ok(()) => {}
-        Err(..) => {}
-    }
-    match engine1.read_and_process_transactions(File::open(temp_file2.path()).unwrap(), BUF_SIZE) {
-        Ok(()) => {}
-        Err(..) => {}
-    }
-    match engine1.read_and_process_transactions(File::open(temp_file3.path()).unwrap(), BUF_SIZE) {
-        Ok(()) => {}
-        Err(..) => {}
+    // Number of errors of a processing call: the files have disjoint clients and tx ids, so a
+    // file fails on the same transactions whether processed sequentially or concurrently.
+    fn error_count(result: Result<(), TransactionProcessingError>) -> usize {
+        match result {
+            Ok(()) => 0,
+            Err(TransactionProcessingError::MultipleErrors(errors)) => errors.len(),
+        }
     }
 
+    // Process files sequentially with engine1
+    let sequential_errors: Vec<usize> = [&temp_file1, &temp_file2, &temp_file3]
+        .iter()
+        .map(|temp_file| {
+            error_count(
+                engine1
+                    .read_and_process_transactions(File::open(temp_file.path()).unwrap(), BUF_SIZE),
+            )
+        })
+        .collect();
+
     // Process files concurrently with engine2
     let handles = vec![
         {
             let engine2 = Arc::clone(&engine2);
             let file = File::open(temp_file1.path()).unwrap();
-            thread::spawn(
-                move || match engine2.read_and_process_transactions(file, BUF_SIZE) {
-                    Ok(()) => {}
-                    Err(..) => {}
-                },
-            )
+            thread::spawn(move || {
+                error_count(engine2.read_and_process_transactions(file, BUF_SIZE))
+            })
         },
         {
             let engine2 = Arc::clone(&engine2);
             let file = File::open(temp_file2.path()).unwrap();
-            thread::spawn(
-                move || match engine2.read_and_process_transactions(file, BUF_SIZE) {
-                    Ok(()) => {}
-                    Err(..) => {}
-                },
-            )
+            thread::spawn(move || {
+                error_count(engine2.read_and_process_transactions(file, BUF_SIZE))
+            })
         },
         {
             let engine2 = Arc::clone(&engine2);
             let file = File::open(temp_file3.path()).unwrap();
-            thread::spawn(
-                move || match engine2.read_and_process_transactions(file, BUF_SIZE) {
-                    Ok(()) => {}
-                    Err(..) => {}
-                },
-            )
+            thread::spawn(move || {
+                error_count(engine2.read_and_process_transactions(file, BUF_SIZE))
+            })
         },
     ];
 
     // Wait for all threads to complete
-    for handle in handles {
-        handle.join().unwrap();
-    }
+    let concurrent_errors: Vec<usize> = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+    assert_eq!(
+        concurrent_errors, sequential_errors,
+        "Error counts differ between sequential and concurrent processing"
+    );
 
     /*  debug
     writer.write_record(["client", "available", "held", "total", "locked"]).unwrap();
@@ -1198,3 +1276,4294 @@ fn reg_test_engine_consistency_with_concurrent_processing() {
         }
     }
 }
+
+/// Tests that the timing report contains an entry for every processed transaction type
+/// and that the collected statistics are coherent (min <= avg <= max).
+#[cfg(feature = "timing")]
+#[test]
+fn unit_test_timing_report() {
+    let mut engine = Engine::default();
+    let input_path = "tests/transactions_disputed.csv";
+    let _ = engine.read_and_process_transactions_from_csv(input_path, BUFFER_SIZE);
+
+    let report = engine.timing_report();
+    for ty in [
+        TransactionType::Deposit,
+        TransactionType::Withdrawal,
+        TransactionType::Dispute,
+        TransactionType::Resolve,
+        TransactionType::Chargeback,
+    ] {
+        let stats = report
+            .get(&ty)
+            .unwrap_or_else(|| panic!("Missing timing entry for {}", ty));
+        assert!(stats.count > 0, "No samples recorded for {}", ty);
+        assert!(stats.min <= stats.avg() && stats.avg() <= stats.max);
+    }
+    assert_eq!(report[&TransactionType::Deposit].count, 7);
+}
+
+/// Tests merging two streams sorted by transaction id with interleaved ids.
+///
+/// The withdrawals of the second stream can only succeed if they are processed after the
+/// deposits of the first stream with lower transaction ids, i.e. in global tx id order.
+#[test]
+fn unit_test_process_merged_interleaved_streams() {
+    let stream_a = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,3,5.0\n\
+                    dispute,1,3,\n";
+    let stream_b = "type,client,tx,amount\n\
+                    withdrawal,1,2,8.0\n\
+                    withdrawal,1,4,2.0\n";
+
+    let engine = Engine::new();
+    engine
+        .process_merged(vec![stream_a.as_bytes(), stream_b.as_bytes()], BUFFER_SIZE)
+        .expect("Merged processing should succeed");
+
+    let account = engine.accounts.get(&1).expect("Account 1 should exist");
+    assert_eq!(account.available, Decimal::from_str("0").unwrap());
+    assert_eq!(account.held, Decimal::from_str("5").unwrap());
+    assert_eq!(account.total, Decimal::from_str("5").unwrap());
+    assert_eq!(engine.transaction_log.len(), 4);
+}
+
+/// Tests that a stream not sorted by transaction id is reported as an error.
+#[test]
+fn unit_test_process_merged_out_of_order_stream() {
+    let stream_a = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,3,5.0\n";
+    let stream_b = "type,client,tx,amount\n\
+                    deposit,2,4,8.0\n\
+                    deposit,2,2,2.0\n";
+
+    let engine = Engine::new();
+    match engine.process_merged(vec![stream_a.as_bytes(), stream_b.as_bytes()], BUFFER_SIZE) {
+        Ok(()) => panic!("Expected an out of order error"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Stream 1 out of order: tx 2 found after tx 4".to_string()]
+            );
+        }
+    }
+    assert!(engine.transaction_log.get(&TxKey::global(2)).is_none());
+}
+
+/// Tests the `NegativeTotalPolicy`: charging back a deposit whose funds were already withdrawn
+/// drives the total negative. The default policy applies the chargeback, `Reject` refuses it and
+/// leaves the account untouched.
+#[test]
+fn unit_test_negative_total_policy() {
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,10.0\n\
+                       dispute,1,1,\n\
+                       chargeback,1,1,\n";
+
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .expect("Default policy should allow a negative total");
+    {
+        let account = engine.accounts.get(&1).expect("Account 1 should exist");
+        assert_eq!(account.total, Decimal::from(-10));
+        assert_eq!(account.held, Decimal::ZERO);
+        assert!(account.locked);
+    }
+
+    let engine = Engine::new().with_negative_total_policy(NegativeTotalPolicy::Reject);
+    match engine.read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the chargeback to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Chargeback, client: 1, tx: 1, amount: None, disputed: false }: Operation would drive the account total negative".to_string()]
+            );
+        }
+    }
+    let account = engine.accounts.get(&1).expect("Account 1 should exist");
+    assert_eq!(account.total, Decimal::ZERO);
+    assert_eq!(account.available, Decimal::from(-10));
+    assert_eq!(account.held, Decimal::from(10));
+    assert!(!account.locked);
+}
+
+/// Tests the amount statistics collected while processing `tests/transactions_basic.csv`:
+/// deposits of 10.0001 (rounded from 10.00008), 20 and 5; withdrawals of 5 and 15.
+#[test]
+fn unit_test_amount_stats() {
+    let mut engine = Engine::default();
+    engine
+        .read_and_process_transactions_from_csv("tests/transactions_basic.csv", BUFFER_SIZE)
+        .expect("Basic file should be processed without errors");
+
+    let stats = engine.amount_stats();
+    assert_eq!(stats.len(), 2, "Only deposits and withdrawals are tracked");
+
+    let deposits = stats[&TransactionType::Deposit];
+    assert_eq!(deposits.count, 3);
+    assert_eq!(deposits.sum, Decimal::from_str("35.0001").unwrap());
+    assert_eq!(deposits.min, Some(Decimal::from(5)));
+    assert_eq!(deposits.max, Some(Decimal::from(20)));
+    assert_eq!(
+        deposits.mean(),
+        Some(Decimal::from_str("35.0001").unwrap() / Decimal::from(3))
+    );
+
+    let withdrawals = stats[&TransactionType::Withdrawal];
+    assert_eq!(withdrawals.count, 2);
+    assert_eq!(withdrawals.sum, Decimal::from(20));
+    assert_eq!(withdrawals.min, Some(Decimal::from(5)));
+    assert_eq!(withdrawals.max, Some(Decimal::from(15)));
+    assert_eq!(withdrawals.mean(), Some(Decimal::from(10)));
+}
+
+/// Tests that the `locked` column can be dumped as `1`/`0` and that such a dump can be
+/// loaded back through `load_from_previous_session_csvs` into identical accounts.
+#[test]
+fn reg_test_locked_format_round_trip() {
+    let mut engine = Engine::new().with_locked_format(BoolFormat::OneZero);
+    let _ = engine
+        .read_and_process_transactions_from_csv("tests/transactions_disputed.csv", BUFFER_SIZE);
+
+    let accounts_file = NamedTempFile::new().expect("Failed to create temporary file");
+    engine
+        .dump_account_to_csv(accounts_file.reopen().unwrap(), BUFFER_SIZE)
+        .expect("Failed to dump accounts");
+    let transactions_file = NamedTempFile::new().expect("Failed to create temporary file");
+    engine
+        .dump_transaction_log_to_csv(transactions_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect("Failed to dump transactions");
+
+    let dump = std::fs::read_to_string(accounts_file.path()).unwrap();
+    let mut locked_values: Vec<&str> = dump
+        .lines()
+        .skip(1)
+        .map(|line| line.rsplit(',').next().unwrap())
+        .collect();
+    locked_values.sort();
+    locked_values.dedup();
+    assert_eq!(locked_values, vec!["0", "1"]);
+
+    let mut engine2 = Engine::new();
+    engine2
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .expect("Failed to load the 1/0 dump");
+
+    assert_eq!(engine.accounts.len(), engine2.accounts.len());
+    for entry in engine.accounts.iter() {
+        let account2 = engine2.accounts.get(entry.key()).unwrap();
+        assert_eq!(
+            *entry.value(),
+            *account2,
+            "Mismatch for client {}",
+            entry.key()
+        );
+    }
+}
+
+/// Tests `log_diff` comparing two engines that processed the same transactions in different
+/// orders: processing the dispute before the deposit and the withdrawal before any deposit
+/// makes both fail, so tx 1 differs by disputed state and tx 3 is missing.
+#[test]
+fn unit_test_log_diff() {
+    let in_order = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,2,5.0\n\
+                    withdrawal,1,3,5.0\n\
+                    dispute,1,1,\n";
+    let reordered = "type,client,tx,amount\n\
+                     withdrawal,1,3,5.0\n\
+                     dispute,1,1,\n\
+                     deposit,1,1,10.0\n\
+                     deposit,1,2,5.0\n";
+
+    let engine1 = Engine::new();
+    let _ = engine1.read_and_process_transactions(in_order.as_bytes(), BUFFER_SIZE);
+    let engine2 = Engine::new();
+    let _ = engine2.read_and_process_transactions(reordered.as_bytes(), BUFFER_SIZE);
+
+    assert_eq!(engine1.log_diff(&engine2), vec![1, 3]);
+    assert_eq!(engine2.log_diff(&engine1), vec![1, 3]);
+    assert!(engine1.log_diff(&engine1).is_empty());
+}
+
+/// Tests `merge` of two engines with disjoint clients: the result is the union of their
+/// accounts and transaction logs, and a dispute opened before the merge can be resolved after it.
+#[test]
+fn unit_test_merge_disjoint_clients() {
+    let mut engine1 = Engine::new();
+    engine1
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::dispute(1, 1),
+        ])
+        .unwrap();
+    let engine2 = Engine::new();
+    engine2
+        .process_slice(&[
+            Transaction::deposit(2, 2, Decimal::from(7)),
+            Transaction::withdrawal(2, 3, Decimal::from(2)),
+        ])
+        .unwrap();
+
+    engine1.merge(engine2).unwrap();
+
+    assert_eq!(engine1.accounts.len(), 2);
+    assert_eq!(engine1.transaction_log.len(), 3);
+    let account1 = engine1.accounts.get(&1).unwrap().clone();
+    assert_eq!(account1.available, Decimal::from(0));
+    assert_eq!(account1.held, Decimal::from(10));
+    let account2 = engine1.accounts.get(&2).unwrap().clone();
+    assert_eq!(account2.available, Decimal::from(5));
+    assert_eq!(account2.total, Decimal::from(5));
+
+    engine1
+        .process_slice(&[Transaction::resolve(1, 1)])
+        .unwrap();
+    assert_eq!(
+        engine1.accounts.get(&1).unwrap().available,
+        Decimal::from(10)
+    );
+}
+
+/// Tests that `merge` rejects a transaction id logged by both engines, with a different or the
+/// same content (which would be counted twice), leaving the merging engine untouched.
+#[test]
+fn unit_test_merge_conflicting_tx() {
+    let mut engine1 = Engine::new();
+    engine1
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(10))])
+        .unwrap();
+    let engine2 = Engine::new();
+    engine2
+        .process_slice(&[
+            Transaction::deposit(2, 1, Decimal::from(3)),
+            Transaction::deposit(2, 2, Decimal::from(4)),
+        ])
+        .unwrap();
+
+    assert_eq!(engine1.merge(engine2), Err(MergeConflict { tx: 1 }));
+    assert_eq!(engine1.accounts.len(), 1);
+    assert_eq!(engine1.transaction_log.len(), 1);
+    assert_eq!(engine1.accounts.get(&1).unwrap().total, Decimal::from(10));
+
+    let engine2 = Engine::new();
+    engine2
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(10))])
+        .unwrap();
+    assert_eq!(engine1.merge(engine2), Err(MergeConflict { tx: 1 }));
+    assert_eq!(engine1.accounts.get(&1).unwrap().total, Decimal::from(10));
+}
+
+/// Tests that the memory-mapped read path produces the same state as the buffered one.
+#[cfg(feature = "mmap")]
+#[test]
+fn unit_test_mmap_matches_buffered_path() {
+    let temp_file = NamedTempFile::new().unwrap();
+    txn_engine::utility::generate_random_transactions(10_000, &temp_file).unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut buffered_engine = Engine::new();
+    let buffered_result = buffered_engine.read_and_process_transactions_from_csv(path, BUFFER_SIZE);
+    let mmap_engine = Engine::new();
+    let mmap_result = mmap_engine.read_and_process_transactions_from_mmap(path, BUFFER_SIZE);
+
+    assert_eq!(buffered_result.is_ok(), mmap_result.is_ok());
+    assert!(buffered_engine.log_diff(&mmap_engine).is_empty());
+
+    let mut buffered_dump = Vec::new();
+    buffered_engine
+        .dump_account_to_csv(&mut buffered_dump, BUFFER_SIZE)
+        .unwrap();
+    let mut mmap_dump = Vec::new();
+    mmap_engine
+        .dump_account_to_csv(&mut mmap_dump, BUFFER_SIZE)
+        .unwrap();
+    let mut buffered_lines: Vec<_> = String::from_utf8(buffered_dump)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    let mut mmap_lines: Vec<_> = String::from_utf8(mmap_dump)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    buffered_lines.sort();
+    mmap_lines.sort();
+    assert_eq!(buffered_lines, mmap_lines);
+}
+
+/// Tests the `HistoricalDisputePolicy`: with `Reject` a dispute on a transaction loaded from a
+/// previous session is refused, while transactions of the current session stay disputable.
+#[test]
+fn unit_test_historical_dispute_policy() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    write!(
+        transactions_file,
+        "type,client,tx,amount,disputed\n\
+         deposit,1,1,10.0,false\n"
+    )
+    .unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,10.0,0.0,10.0,false\n"
+    )
+    .unwrap();
+    let transactions_path = transactions_file.path().to_str().unwrap();
+    let accounts_path = accounts_file.path().to_str().unwrap();
+
+    let csv = "type,client,tx,amount\n\
+               deposit,1,2,5.0\n\
+               dispute,1,1,\n\
+               dispute,1,2,\n";
+
+    let mut engine = Engine::new();
+    engine
+        .load_from_previous_session_csvs(transactions_path, accounts_path)
+        .expect("Failed to load from CSV");
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("Default policy should allow disputing historical transactions");
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().held,
+        Decimal::from_str("15.0").unwrap()
+    );
+
+    let mut engine = Engine::new().with_historical_dispute_policy(HistoricalDisputePolicy::Reject);
+    engine
+        .load_from_previous_session_csvs(transactions_path, accounts_path)
+        .expect("Failed to load from CSV");
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the dispute on the historical transaction to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Dispute, client: 1, tx: 1, amount: None, disputed: false }: Transaction loaded from a previous session cannot be disputed".to_string()]
+            );
+        }
+    }
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::from_str("5.0").unwrap());
+    assert_eq!(account.available, Decimal::from_str("10.0").unwrap());
+    assert!(
+        !engine
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .disputed
+    );
+}
+
+/// Tests the dispute reorder window: a dispute one row before its deposit fails by default and
+/// is applied once the deposit is processed when the window is enabled, while a dispute falling
+/// out of the window is reported as not found.
+#[test]
+fn unit_test_dispute_reorder_window() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               dispute,1,2,\n\
+               deposit,1,2,5.0\n";
+
+    let engine = Engine::new();
+    assert!(engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .is_err());
+    assert!(
+        !engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .disputed
+    );
+
+    let engine = Engine::new().with_dispute_reorder_window(1);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("The dispute should be retried after its deposit");
+    assert!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .disputed
+    );
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("10.0").unwrap());
+    assert_eq!(account.held, Decimal::from_str("5.0").unwrap());
+    drop(account);
+
+    let csv = "type,client,tx,amount\n\
+               dispute,1,2,\n\
+               deposit,1,1,10.0\n\
+               deposit,1,2,5.0\n";
+    let engine = Engine::new().with_dispute_reorder_window(1);
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the dispute to fall out of the reorder window"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Dispute, client: 1, tx: 2, amount: None, disputed: false }: Transaction not found".to_string()]
+            );
+        }
+    }
+    assert!(
+        !engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .disputed
+    );
+}
+
+/// Tests `process_slice` with transactions built through the `Transaction` constructors.
+#[test]
+fn unit_test_process_slice_with_constructors() {
+    let engine = Engine::new();
+    let transactions = vec![
+        Transaction::deposit(1, 1, Decimal::from(10)),
+        Transaction::deposit(2, 2, Decimal::from(20)),
+        Transaction::withdrawal(1, 3, Decimal::from(4)),
+        Transaction::dispute(2, 2),
+        Transaction::resolve(2, 2),
+        Transaction::dispute(1, 1),
+        Transaction::chargeback(1, 1),
+        Transaction::withdrawal(2, 4, Decimal::from(50)),
+    ];
+    assert_eq!(transactions[3].amount, None);
+    assert!(!transactions[0].disputed);
+
+    match engine.process_slice(&transactions) {
+        Ok(()) => panic!("Expected the withdrawal exceeding the funds to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Withdrawal, client: 2, tx: 4, amount: Some(50), disputed: false }: Insufficient funds".to_string()]
+            );
+        }
+    }
+
+    let account1 = engine.accounts.get(&1).unwrap();
+    assert_eq!(account1.available, Decimal::from(-4));
+    assert_eq!(account1.total, Decimal::from(-4));
+    assert!(account1.locked);
+    let account2 = engine.accounts.get(&2).unwrap();
+    assert_eq!(account2.available, Decimal::from(20));
+    assert_eq!(account2.held, Decimal::ZERO);
+    assert!(!account2.locked);
+}
+
+/// Tests `process_as_of` reconstructing the account state at two different cutoffs from the
+/// same timestamped file, with epoch seconds and ISO-8601 timestamps: later transactions are
+/// skipped without errors, and a record without a timestamp is reported.
+#[test]
+fn unit_test_process_as_of() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(
+        temp_file,
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,10.0,100\n\
+         deposit,2,2,20.0,1970-01-01T00:03:20Z\n\
+         withdrawal,1,3,4.0,300\n\
+         dispute,2,2,,1970-01-01T00:06:40\n\
+         chargeback,2,2,,500\n"
+    )
+    .unwrap();
+    let input_path = temp_file.path().to_str().unwrap();
+
+    let engine = Engine::new();
+    engine
+        .process_as_of(input_path, DateTime::from_timestamp(250, 0).unwrap())
+        .expect("Processing up to the cutoff should not fail");
+    assert_eq!(engine.transaction_log.len(), 2);
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("10.0").unwrap()
+    );
+    assert_eq!(
+        engine.accounts.get(&2).unwrap().available,
+        Decimal::from_str("20.0").unwrap()
+    );
+
+    let engine = Engine::new();
+    engine
+        .process_as_of(input_path, DateTime::from_timestamp(400, 0).unwrap())
+        .expect("Processing up to the cutoff should not fail");
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("6.0").unwrap()
+    );
+    let account2 = engine.accounts.get(&2).unwrap();
+    assert_eq!(account2.held, Decimal::from_str("20.0").unwrap());
+    assert!(!account2.locked);
+    drop(account2);
+
+    let cutoff = DateTime::from_timestamp(400, 0).unwrap();
+    let mut no_timestamps = NamedTempFile::new().unwrap();
+    write!(no_timestamps, "type,client,tx,amount\ndeposit,1,1,10.0\n").unwrap();
+    assert!(Engine::new()
+        .process_as_of(no_timestamps.path().to_str().unwrap(), cutoff)
+        .is_err());
+
+    let mut missing_timestamp = NamedTempFile::new().unwrap();
+    write!(
+        missing_timestamp,
+        "type,client,tx,amount,timestamp\ndeposit,1,1,10.0,100\ndeposit,1,2,5.0,\n"
+    )
+    .unwrap();
+    let engine = Engine::new();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_as_of(missing_timestamp.path().to_str().unwrap(), cutoff)
+        .expect_err("A record without a timestamp should be reported");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains("missing timestamp"));
+    assert_eq!(engine.transaction_log.len(), 1);
+}
+
+/// Tests that `TransactionProcessingError::grouped` aggregates many identical errors of
+/// different transactions into `(message, count)` pairs, in order of first occurrence.
+#[test]
+fn unit_test_grouped_errors() {
+    let mut csv = String::from(
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         dispute,1,1,\n\
+         chargeback,1,1,\n\
+         withdrawal,2,2,1.0\n",
+    );
+    for tx in 3..1003 {
+        csv.push_str(&format!("deposit,1,{},1.0\n", tx));
+    }
+    csv.push_str("unknown,1,1003,1.0\n");
+
+    let engine = Engine::new();
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected errors on the locked account"),
+        Err(e) => {
+            let TransactionProcessingError::MultipleErrors(errors) = &e;
+            assert_eq!(errors.len(), 1002);
+            assert_eq!(
+                e.grouped(),
+                vec![
+                    ("Account not found".to_string(), 1),
+                    ("Account is locked".to_string(), 1000),
+                    (
+                        "Error reading transaction record: CSV deserialize error: record 1005 (line: 1006, byte: 17986): Unknown transaction type: unknown"
+                            .to_string(),
+                        1
+                    ),
+                ]
+            );
+        }
+    }
+}
+
+/// Tests reading amounts as integer minor units: `1050` at scale 2 is `10.50`, non-integer
+/// amounts are rejected and the dump can emit minor units too.
+#[test]
+fn unit_test_amount_minor_units() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,1050\n\
+               withdrawal,1,2,25\n\
+               deposit,1,3,10.5\n";
+
+    let engine = Engine::new()
+        .with_amount_units(AmountUnits::Minor(2))
+        .with_dump_amount_units(AmountUnits::Minor(2));
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the non-integer minor-unit amount to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Deposit, client: 1, tx: 3, amount: Some(10.5), disputed: false }: Amount in minor units must be an integer".to_string()]
+            );
+        }
+    }
+    assert_eq!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .amount
+            .unwrap()
+            .to_string(),
+        "10.50"
+    );
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("10.25").unwrap());
+    drop(account);
+
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv(&mut output, BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,1025,0,1025,false\n"
+    );
+
+    let engine = Engine::new().with_amount_units(AmountUnits::Minor(2));
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap_err();
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv(&mut output, BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,10.25,0,10.25,false\n"
+    );
+}
+
+/// Tests `consistent_snapshot` while transactions are processed concurrently: every account
+/// of every snapshot must satisfy `available + held == total`.
+#[test]
+fn reg_test_consistent_snapshot_concurrent() {
+    let engine = Engine::new();
+    let mut transactions = Vec::new();
+    for tx in 0..20_000u32 {
+        let client = (tx % 8) as u16;
+        transactions.push(Transaction::deposit(client, 2 * tx, Decimal::from(3)));
+        transactions.push(Transaction::withdrawal(client, 2 * tx + 1, Decimal::ONE));
+        if tx % 3 == 0 {
+            transactions.push(Transaction::dispute(client, 2 * tx));
+        }
+    }
+
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let _ = engine.process_slice(&transactions);
+            done.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+        scope.spawn(|| {
+            let mut snapshots = 0;
+            while !done.load(std::sync::atomic::Ordering::SeqCst) || snapshots == 0 {
+                for (client, account) in engine.consistent_snapshot() {
+                    assert_eq!(
+                        account.available + account.held,
+                        account.total,
+                        "Torn read for client {}",
+                        client
+                    );
+                }
+                snapshots += 1;
+            }
+        });
+    });
+
+    assert_eq!(engine.consistent_snapshot().len(), 8);
+}
+
+/// Tests that zero-balance, unlocked accounts are omitted from the dump only when requested,
+/// while locked zero accounts are always kept.
+#[test]
+fn unit_test_dump_omit_zero_accounts() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               withdrawal,1,2,10.0\n\
+               deposit,2,3,5.0\n\
+               deposit,3,4,1.0\n\
+               dispute,3,4,\n\
+               chargeback,3,4,\n";
+
+    let dumped_clients = |engine: &Engine| {
+        let mut output = Vec::new();
+        engine
+            .dump_account_to_csv(&mut output, BUFFER_SIZE)
+            .unwrap();
+        let mut clients: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect();
+        clients.sort();
+        clients
+    };
+
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(dumped_clients(&engine), vec!["1", "2", "3"]);
+
+    let engine = Engine::new().with_omit_zero_accounts(true);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(dumped_clients(&engine), vec!["2", "3"]);
+}
+
+/// Tests that the raw input amount is preserved alongside the rounded amount applied, dumped
+/// in the transaction log and loaded back.
+#[test]
+fn reg_test_preserve_raw_amount_round_trip() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,1.123456\n\
+               withdrawal,1,2, 0.5\n";
+
+    let engine = Engine::new().with_preserve_raw_amount(true);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .amount,
+        Some(Decimal::from_str("1.1235").unwrap())
+    );
+    assert_eq!(engine.raw_amount(1, 1), Some("1.123456".to_string()));
+    assert_eq!(engine.raw_amount(1, 2), Some("0.5".to_string()));
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("0.6235").unwrap()
+    );
+
+    let transactions_file = NamedTempFile::new().unwrap();
+    let transactions_path = transactions_file.path().to_str().unwrap();
+    engine
+        .dump_transaction_log_to_csv(transactions_path, BUFFER_SIZE)
+        .unwrap();
+    let dump = std::fs::read_to_string(transactions_path).unwrap();
+    assert!(dump
+        .starts_with("type,client,tx,amount,disputed,raw_amount,charged_back,reversed,released\n"));
+    assert!(dump.contains("deposit,1,1,1.1235,false,1.123456,0,false,false\n"));
+
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n1,0.6235,0,0.6235,false\n"
+    )
+    .unwrap();
+    let mut loaded = Engine::new();
+    loaded
+        .load_from_previous_session_csvs(transactions_path, accounts_file.path().to_str().unwrap())
+        .unwrap();
+    assert_eq!(loaded.raw_amount(1, 1), Some("1.123456".to_string()));
+    assert_eq!(
+        loaded
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .amount,
+        Some(Decimal::from_str("1.1235").unwrap())
+    );
+
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(engine.raw_amount(1, 1), None);
+}
+
+/// Tests `ResolvePolicy::IgnoreIfNotDisputed`: repeated resolves are a successful no-op,
+/// while resolves of unknown transactions or from other clients still fail.
+#[test]
+fn unit_test_resolve_policy_ignore_if_not_disputed() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               resolve,1,1,\n\
+               dispute,1,1,\n\
+               resolve,1,1,\n\
+               resolve,1,1,\n";
+
+    let engine = Engine::new();
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the default policy to reject resolves on non-disputed txs"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => assert_eq!(errors.len(), 2),
+    }
+
+    let engine = Engine::new().with_resolve_policy(ResolvePolicy::IgnoreIfNotDisputed);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("Resolves on non-disputed transactions should be ignored");
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("10.0").unwrap());
+    assert_eq!(account.held, Decimal::ZERO);
+    drop(account);
+
+    let csv = "type,client,tx,amount\n\
+               deposit,2,2,5.0\n\
+               resolve,1,2,\n\
+               resolve,1,3,\n";
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected resolves of other clients/unknown txs to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec![
+                    "Error processing Transaction { ty: Resolve, client: 1, tx: 2, amount: None, disputed: false }: Cannot dispute/resolve/chargeback transaction from a different client".to_string(),
+                    "Error processing Transaction { ty: Resolve, client: 1, tx: 3, amount: None, disputed: false }: Transaction not found".to_string(),
+                ]
+            );
+        }
+    }
+}
+
+/// Tests the round trip of the engine state through a single combined CSV
+/// (`dump_combined_csv` / `load_combined_csv`).
+#[test]
+fn reg_test_combined_csv_round_trip() {
+    let mut engine = Engine::new();
+    let _ = engine
+        .read_and_process_transactions_from_csv("tests/transactions_disputed.csv", BUFFER_SIZE);
+
+    let mut combined = Vec::new();
+    engine.dump_combined_csv(&mut combined).unwrap();
+    let dump = String::from_utf8(combined.clone()).unwrap();
+    assert!(dump
+        .lines()
+        .all(|l| l.starts_with("A,") || l.starts_with("T,")));
+    assert_eq!(
+        dump.lines().filter(|l| l.starts_with("A,")).count(),
+        engine.accounts.len()
+    );
+
+    let mut loaded = Engine::new();
+    loaded.load_combined_csv(combined.as_slice()).unwrap();
+    assert!(engine.log_diff(&loaded).is_empty());
+    assert_eq!(engine.consistent_snapshot(), loaded.consistent_snapshot());
+
+    let mut invalid = Engine::new();
+    assert!(invalid
+        .load_combined_csv("X,1,2,3,4,5\n".as_bytes())
+        .is_err());
+}
+
+/// Pins the order of the deposit checks: by default a repeated tx id is reported before the
+/// account lock (and does not create a nonexistent account), with `LockedFirst` the lock wins.
+#[test]
+fn unit_test_deposit_check_order() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               dispute,1,1,\n\
+               chargeback,1,1,\n\
+               deposit,1,1,5.0\n\
+               deposit,2,1,5.0\n";
+    let errors_of =
+        |engine: &Engine| match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+            Ok(()) => panic!("Expected the repeated deposits to fail"),
+            Err(TransactionProcessingError::MultipleErrors(errors)) => {
+                errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+            }
+        };
+
+    let engine = Engine::new();
+    assert_eq!(
+        errors_of(&engine),
+        vec![
+            "Error processing Transaction { ty: Deposit, client: 1, tx: 1, amount: Some(5.0), disputed: false }: Transaction id already processed in this session - cannot be repeated.".to_string(),
+            "Error processing Transaction { ty: Deposit, client: 2, tx: 1, amount: Some(5.0), disputed: false }: Transaction id already processed in this session - cannot be repeated.".to_string(),
+        ]
+    );
+    assert!(engine.accounts.get(&2).is_none());
+
+    let engine = Engine::new().with_deposit_check_order(DepositCheckOrder::LockedFirst);
+    assert_eq!(
+        errors_of(&engine),
+        vec![
+            "Error processing Transaction { ty: Deposit, client: 1, tx: 1, amount: Some(5.0), disputed: false }: Account is locked".to_string(),
+            "Error processing Transaction { ty: Deposit, client: 2, tx: 1, amount: Some(5.0), disputed: false }: Transaction id already processed in this session - cannot be repeated.".to_string(),
+        ]
+    );
+    assert!(engine.accounts.get(&2).is_none());
+}
+
+/// Tests `log_type_counts` after loading a session with deposits and withdrawals, some of
+/// them disputed.
+#[test]
+fn unit_test_log_type_counts() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    write!(
+        transactions_file,
+        "type,client,tx,amount,disputed\n\
+         deposit,1,1,10.0,false\n\
+         deposit,2,2,20.0,true\n\
+         withdrawal,1,3,5.0,false\n\
+         deposit,1,4,1.0,false\n"
+    )
+    .unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,6.0,0.0,6.0,false\n\
+         2,0.0,20.0,20.0,false\n"
+    )
+    .unwrap();
+
+    let mut engine = Engine::new();
+    assert!(engine.log_type_counts().is_empty());
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .expect("Failed to load from CSV");
+
+    let counts = engine.log_type_counts();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[&TransactionType::Deposit], 3);
+    assert_eq!(counts[&TransactionType::Withdrawal], 1);
+}
+
+/// Tests `with_signed_amounts`: `deposit,1,2,-5` is a withdrawal of 5 (logged as such, so it
+/// can be disputed as a withdrawal) when enabled, and fails with `DepositAmountInvalid` otherwise.
+#[test]
+fn unit_test_signed_amounts() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,1,2,-5.0\n";
+
+    let engine = Engine::new().with_signed_amounts(true);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("The negative deposit should be processed as a withdrawal");
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(5));
+    drop(account);
+    let logged = engine.transaction_log.get(&TxKey::global(2)).unwrap();
+    assert_eq!(logged.ty, TransactionType::Withdrawal);
+    assert_eq!(logged.amount, Some(Decimal::from(5)));
+
+    let engine = Engine::new();
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the negative deposit to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec!["Error processing Transaction { ty: Deposit, client: 1, tx: 2, amount: Some(-5.0), disputed: false }: Deposit amount must be greater than 0".to_string()]
+            );
+        }
+    }
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from(10)
+    );
+}
+
+/// Tests that `state_checksum` tracks the sum of the totals and the number of accounts.
+#[test]
+fn unit_test_state_checksum() {
+    let engine = Engine::new();
+    assert_eq!(engine.state_checksum(), (Decimal::ZERO, 0));
+
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,2.5\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(
+        engine.state_checksum(),
+        (Decimal::from_str("12.5").unwrap(), 2)
+    );
+
+    let csv = "type,client,tx,amount\n\
+               withdrawal,1,3,4.0\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(
+        engine.state_checksum(),
+        (Decimal::from_str("8.5").unwrap(), 2)
+    );
+}
+
+/// Memory source mock returning a fixed reading.
+struct FixedMemorySource(Option<u64>);
+
+impl MemorySource for FixedMemorySource {
+    fn process_memory(&mut self, _pid: u32) -> Option<u64> {
+        self.0
+    }
+}
+
+/// Tests that `get_current_memory` reports `None` when the process memory cannot be read,
+/// rather than 0.
+#[test]
+fn unit_test_get_current_memory_unavailable() {
+    assert_eq!(get_current_memory(&mut FixedMemorySource(None)), None);
+    assert_eq!(
+        get_current_memory(&mut FixedMemorySource(Some(1024))),
+        Some(1024)
+    );
+}
+
+/// Tests `with_transaction_fee`: balances reflect deposits and withdrawals minus the fees,
+/// each fee is recorded in the `fee_log`, and transactions that cannot cover the fee are
+/// rejected without changing the balances.
+#[test]
+fn unit_test_transaction_fee() {
+    let fee = Decimal::from_str("0.5").unwrap();
+    let engine = Engine::new().with_transaction_fee(fee);
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               withdrawal,1,2,4.0\n\
+               withdrawal,1,3,5.0\n\
+               deposit,2,4,0.25\n\
+               dispute,1,1,\n";
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the transactions not covering the fee to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                messages(&errors),
+                vec![
+                    "Error processing Transaction { ty: Withdrawal, client: 1, tx: 3, amount: Some(5.0), disputed: false }: Insufficient funds to cover the transaction fee".to_string(),
+                    "Error processing Transaction { ty: Deposit, client: 2, tx: 4, amount: Some(0.25), disputed: false }: Insufficient funds to cover the transaction fee".to_string(),
+                ]
+            );
+        }
+    }
+
+    // 10 - 0.5 - 4 - 0.5 = 5, then the deposit of 10 is disputed (the fee is not refunded)
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(-5));
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.total, Decimal::from(5));
+    drop(account);
+    assert!(engine.accounts.get(&2).is_none());
+
+    assert_eq!(engine.fee_log.len(), 2);
+    assert_eq!(
+        *engine.fee_log.get(&TxKey::global(1)).unwrap(),
+        Transaction::fee(1, 1, fee)
+    );
+    assert_eq!(
+        *engine.fee_log.get(&TxKey::global(2)).unwrap(),
+        Transaction::fee(1, 2, fee)
+    );
+
+    assert!(matches!(
+        engine.process_slice(&[Transaction::fee(1, 5, fee)]),
+        Err(TransactionProcessingError::MultipleErrors(errors)) if errors[0].message().ends_with("Fees are charged by the engine and cannot be processed as transactions")
+    ));
+}
+
+/// Tests that a negative transaction fee, which would credit the client on every withdrawal, is
+/// rejected by the builder and in a config file.
+#[test]
+#[should_panic(expected = "The transaction fee must not be negative")]
+fn unit_test_negative_transaction_fee() {
+    assert!(ConfigFile::from_toml_str("[engine]\ntransaction_fee = \"-0.5\"\n").is_err());
+    assert!(ConfigFile::from_toml_str("[engine]\ntransaction_fee = \"0\"\n").is_ok());
+
+    let _ = Engine::new().with_transaction_fee(Decimal::from_str("-0.5").unwrap());
+}
+
+/// Tests an engine set up from a TOML config file: fee, minor units, resolve and negative
+/// total policies are applied; unknown settings are rejected.
+#[test]
+fn unit_test_config_file() {
+    let config = ConfigFile::from_toml_str(
+        r#"
+        worker_threads = 2
+        shards = 256
+
+        [engine]
+        negative_total_policy = "reject"
+        resolve_policy = "ignore_if_not_disputed"
+        amount_units = { minor = 2 }
+        transaction_fee = "0.5"
+
+        [engine.dump]
+        locked_format = "one_zero"
+        "#,
+    )
+    .expect("The config should be parsed");
+    assert_eq!(config.worker_threads.map(|threads| threads.get()), Some(2));
+    assert_eq!(config.shards.map(|shards| shards.get()), Some(256));
+    assert_eq!(
+        config.engine.negative_total_policy,
+        NegativeTotalPolicy::Reject
+    );
+    assert_eq!(config.engine.dump.locked_format, BoolFormat::OneZero);
+    assert_eq!(
+        config.engine.historical_dispute_policy,
+        HistoricalDisputePolicy::Allow
+    );
+
+    let engine = Engine::new().with_config(config.engine);
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,1000\n\
+               resolve,1,1,\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("The resolve of a non disputed transaction should be ignored");
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("9.5").unwrap());
+    assert_eq!(account.total, Decimal::from_str("9.5").unwrap());
+
+    assert!(ConfigFile::from_toml_str("[engine]\nunknown_policy = true\n").is_err());
+    assert!(ConfigFile::from_toml_str("shards = 0\n").is_err());
+    assert!(ConfigFile::from_toml_str("worker_threads = 0\n").is_err());
+    assert_eq!(
+        ConfigFile::from_toml_str("").unwrap(),
+        ConfigFile::default()
+    );
+}
+
+/// Tests `subscribe_deltas`: one delta per successful transaction, and the sum of the deltas of
+/// each client equals its final balances.
+#[test]
+fn unit_test_delta_feed() {
+    let engine = Engine::new().with_transaction_fee(Decimal::from_str("0.1").unwrap());
+    let deltas = engine.subscribe_deltas();
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,20.0\n\
+               withdrawal,1,3,3.0\n\
+               dispute,1,1,\n\
+               resolve,1,1,\n\
+               dispute,2,2,\n\
+               chargeback,2,2,\n\
+               withdrawal,1,4,100.0\n";
+    assert!(engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .is_err());
+
+    let deltas: Vec<_> = deltas.try_iter().collect();
+    assert_eq!(deltas.len(), 7);
+    assert_eq!(deltas[3].client, 1);
+    assert_eq!(deltas[3].delta_available, Decimal::from(-10));
+    assert_eq!(deltas[3].delta_held, Decimal::from(10));
+    assert_eq!(deltas[3].delta_total, Decimal::ZERO);
+
+    for entry in engine.accounts.iter() {
+        let client_deltas = deltas.iter().filter(|d| d.client == *entry.key());
+        let (available, held, total) = client_deltas.fold(
+            (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO),
+            |(available, held, total), d| {
+                (
+                    available + d.delta_available,
+                    held + d.delta_held,
+                    total + d.delta_total,
+                )
+            },
+        );
+        assert_eq!(available, entry.value().available);
+        assert_eq!(held, entry.value().held);
+        assert_eq!(total, entry.value().total);
+    }
+}
+
+/// Tests `with_tx_id_collision_detection`: a dispute without amount referring to a missing
+/// transaction fails with `TransactionNotFound`, while a dispute structured as a new
+/// transaction (with an amount) fails with `TxIdCollision`. Without the option both are
+/// `TransactionNotFound`.
+#[test]
+fn unit_test_tx_id_collision_detection() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               dispute,1,5,\n\
+               dispute,1,6,3.0\n\
+               chargeback,1,7,3.0\n";
+    let errors_of =
+        |engine: &Engine| match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+            Ok(()) => panic!("Expected the disputes of missing transactions to fail"),
+            Err(TransactionProcessingError::MultipleErrors(errors)) => {
+                errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+            }
+        };
+
+    let engine = Engine::new().with_tx_id_collision_detection(true);
+    assert_eq!(
+        errors_of(&engine),
+        vec![
+            "Error processing Transaction { ty: Dispute, client: 1, tx: 5, amount: None, disputed: false }: Transaction not found".to_string(),
+            "Error processing Transaction { ty: Dispute, client: 1, tx: 6, amount: Some(3.0), disputed: false }: Transaction id not found and the dispute/resolve/chargeback has an amount - it looks like a new transaction".to_string(),
+            "Error processing Transaction { ty: Chargeback, client: 1, tx: 7, amount: Some(3.0), disputed: false }: Transaction id not found and the dispute/resolve/chargeback has an amount - it looks like a new transaction".to_string(),
+        ]
+    );
+
+    let engine = Engine::new();
+    assert!(errors_of(&engine)
+        .iter()
+        .all(|e| e.ends_with("Transaction not found")));
+}
+
+/// Tests `export_parquet`: the accounts and the transaction log read back from Parquet have
+/// the expected rows and amounts (as `Decimal128`).
+#[cfg(feature = "parquet")]
+#[test]
+fn unit_test_export_parquet() {
+    use arrow_array::{Array, BooleanArray, Decimal128Array, RecordBatch, UInt16Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let read_back = |path: &std::path::Path| -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        batches[0].clone()
+    };
+
+    let engine = Engine::new();
+    let csv = "type,client,tx,amount\n\
+               deposit,2,1,10.5\n\
+               deposit,1,2,3.1234\n\
+               withdrawal,2,3,0.5\n\
+               dispute,1,2,\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+
+    let accounts_file = NamedTempFile::new().unwrap();
+    let transactions_file = NamedTempFile::new().unwrap();
+    engine
+        .export_parquet(
+            accounts_file.path().to_str().unwrap(),
+            Some(transactions_file.path().to_str().unwrap()),
+        )
+        .expect("Parquet export should succeed");
+
+    let accounts = read_back(accounts_file.path());
+    assert_eq!(accounts.num_rows(), 2);
+    let clients = accounts
+        .column_by_name("client")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<UInt16Array>()
+        .unwrap();
+    assert_eq!(clients.values(), &[1, 2]);
+    let held = accounts
+        .column_by_name("held")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Decimal128Array>()
+        .unwrap();
+    assert_eq!(held.scale(), 4);
+    assert_eq!(held.value_as_string(0), "3.1234");
+    assert_eq!(held.value_as_string(1), "0.0000");
+    let total = accounts
+        .column_by_name("total")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Decimal128Array>()
+        .unwrap();
+    assert_eq!(total.value_as_string(1), "10.0000");
+
+    let transactions = read_back(transactions_file.path());
+    assert_eq!(transactions.num_rows(), 3);
+    let disputed = transactions
+        .column_by_name("disputed")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<BooleanArray>()
+        .unwrap();
+    assert!(!disputed.value(0));
+    assert!(disputed.value(1));
+    assert!(!disputed.is_null(2));
+}
+
+/// Tests that input with Windows-style `\r\n` line endings is parsed like `\n` input:
+/// every transaction type is recognised, no field keeps a trailing `\r` (including the
+/// last `amount` column and rows with an empty amount), and the same holds when loading
+/// a previous session's transaction log and account snapshot.
+#[test]
+fn unit_test_crlf_line_endings() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\r\n\
+                       deposit,1,1,10.5\r\n\
+                       deposit,1,2,4.25\r\n\
+                       withdrawal,1,3,2.5\r\n\
+                       dispute,1,1,\r\n\
+                       resolve,1,1,\r\n\
+                       deposit, 2 , 4 , 7.0 \r\n\
+                       dispute,2,4,\r\n\
+                       chargeback,2,4,\r\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+
+    let mut engine = Engine::default();
+    engine
+        .read_and_process_transactions_from_csv(temp_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect("CRLF input should be processed without errors");
+
+    assert_eq!(engine.accounts.len(), 2, "There should be two accounts");
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("12.25").unwrap());
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from_str("12.25").unwrap());
+    assert!(!account.locked);
+    let account = engine.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::ZERO);
+    assert!(account.locked, "Account 2 must be locked after chargeback");
+    assert_eq!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(3))
+            .unwrap()
+            .amount,
+        Some(Decimal::from_str("2.5").unwrap())
+    );
+
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    transactions_file
+        .write_all(b"type,client,tx,amount,disputed\r\ndeposit,1,1,10.0000,true\r\nwithdrawal,1,2,5.0000,false\r\n")
+        .unwrap();
+    accounts_file
+        .write_all(b"client,available,held,total,locked\r\n1,0.0000,5.0000,5.0000,true\r\n")
+        .unwrap();
+
+    let mut engine = Engine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .expect("CRLF session files should load");
+    assert_eq!(engine.transaction_log.len(), 2);
+    assert!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(1))
+            .unwrap()
+            .disputed
+    );
+    assert_eq!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .amount,
+        Some(Decimal::new(5_0000, 4))
+    );
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::new(5_0000, 4));
+    assert!(account.locked);
+}
+
+/// Reader yielding at most `chunk` bytes per read, each read taking `delay`.
+struct SlowReader {
+    data: std::io::Cursor<Vec<u8>>,
+    chunk: usize,
+    delay: std::time::Duration,
+}
+
+impl std::io::Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::thread::sleep(self.delay);
+        let len = buf.len().min(self.chunk);
+        self.data.read(&mut buf[..len])
+    }
+}
+
+/// Tests `with_max_duration`: reading from a slow stream with a short budget stops early,
+/// reports the number of processed records and keeps the partial accounts.
+#[test]
+fn unit_test_max_duration() {
+    let mut csv_content = String::from("type,client,tx,amount\n");
+    for tx in 1..=200 {
+        csv_content.push_str(&format!("deposit,1,{tx},1.0\n"));
+    }
+    let reader = SlowReader {
+        data: std::io::Cursor::new(csv_content.into_bytes()),
+        chunk: 20,
+        delay: std::time::Duration::from_millis(5),
+    };
+
+    let engine = Engine::default().with_max_duration(std::time::Duration::from_millis(100));
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(reader, BUFFER_SIZE)
+        .expect_err("Processing should stop at the deadline");
+
+    let processed = engine.transaction_log.len();
+    assert!(
+        processed > 0 && processed < 200,
+        "Processed {processed} records"
+    );
+    assert_eq!(
+        messages(&errors),
+        vec![format!(
+            "Processing stopped after {processed} records: maximum duration of 100ms exceeded"
+        )]
+    );
+    assert_eq!(errors[0].kind, TransactionErrorKind::Stopped);
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(processed));
+}
+
+/// Tests the `hold`/`release` authorization flow: a hold moves funds from available to held
+/// (so they cannot be withdrawn), a release moves them back once, and holds cannot be disputed.
+/// The released hold stays logged as a hold flagged as released, also through a dump.
+#[test]
+fn unit_test_hold_and_release() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       hold,1,2,4.0\n\
+                       withdrawal,1,3,8.0\n\
+                       withdrawal,1,4,6.0\n\
+                       hold,1,5,1.0\n\
+                       dispute,1,2,\n\
+                       release,1,2,\n\
+                       release,1,2,\n\
+                       release,1,1,\n\
+                       hold,1,6,0\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+
+    let mut engine = Engine::default();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(temp_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect_err("The invalid rows should be reported");
+
+    let causes: Vec<&str> = errors
+        .iter()
+        .map(|e| e.message().split_once("}: ").unwrap().1)
+        .collect();
+    assert_eq!(
+        causes,
+        vec![
+            "Insufficient funds",
+            "Insufficient funds",
+            "Holds cannot be disputed/resolved/chargedback",
+            "Hold already released",
+            "Released transaction is not a hold",
+            "Hold amount must be greater than 0",
+        ]
+    );
+    assert!(
+        errors[0].message().contains("tx: 3"),
+        "The withdrawal of held funds must fail"
+    );
+    assert!(
+        errors[1].message().contains("tx: 5"),
+        "The hold of withdrawn funds must fail"
+    );
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(4));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(4));
+    assert!(!account.locked);
+    drop(account);
+    let logged = engine
+        .transaction_log
+        .get(&TxKey::global(2))
+        .unwrap()
+        .clone();
+    assert_eq!(logged.ty, TransactionType::Hold);
+    assert_eq!(logged.amount, Some(Decimal::from(4)));
+    assert!(logged.released);
+    assert!(engine.held_mismatches().is_empty());
+
+    let mut dump = Vec::new();
+    engine.dump_combined_csv(&mut dump).unwrap();
+    let mut loaded = Engine::new();
+    loaded.load_combined_csv(dump.as_slice()).unwrap();
+    assert!(
+        loaded
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .released
+    );
+    let TransactionProcessingError::MultipleErrors(errors) = loaded
+        .process_slice(&[Transaction::release(1, 2)])
+        .expect_err("A released hold cannot be released after a reload");
+    assert_eq!(
+        errors[0].engine_error(),
+        Some(&EngineError::HoldAlreadyReleased)
+    );
+}
+
+/// Tests `with_max_amount`: deposits and withdrawals below or at the limit are processed, the
+/// ones above it fail with `AmountExceedsLimit` and leave the account unchanged.
+#[test]
+fn unit_test_max_amount() {
+    let limit = Decimal::from(100);
+    let engine = Engine::default().with_max_amount(limit);
+    let transactions = vec![
+        Transaction::deposit(1, 1, Decimal::from_str("99.9999").unwrap()),
+        Transaction::deposit(1, 2, limit),
+        Transaction::deposit(1, 3, Decimal::from_str("100.0001").unwrap()),
+        Transaction::withdrawal(1, 4, Decimal::from(50)),
+        Transaction::withdrawal(1, 5, limit),
+        Transaction::withdrawal(1, 6, Decimal::from(101)),
+    ];
+
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&transactions)
+        .expect_err("The amounts above the limit should be rejected");
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message().contains("tx: 3"));
+    assert!(errors[1].message().contains("tx: 6"));
+    assert!(errors.iter().all(|e| e
+        .message()
+        .ends_with("Amount exceeds the maximum single-transaction amount")));
+
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from_str("49.9999").unwrap());
+    assert_eq!(account.total, Decimal::from_str("49.9999").unwrap());
+    assert!(!engine.transaction_log.contains_key(&TxKey::global(3)));
+    assert!(!engine.transaction_log.contains_key(&TxKey::global(6)));
+
+    let unlimited = Engine::default();
+    assert!(unlimited
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(1_000_000_000))])
+        .is_ok());
+}
+
+/// Tests `dump_account_to_ndjson`: every line is a valid JSON object and the accounts match the
+/// CSV dump.
+#[test]
+fn unit_test_dump_account_to_ndjson() {
+    let mut engine = Engine::default();
+    let _ =
+        engine.read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE);
+
+    let mut ndjson = Vec::new();
+    engine
+        .dump_account_to_ndjson(&mut ndjson, BUFFER_SIZE)
+        .expect("NDJSON dump should succeed");
+    let mut from_ndjson: Vec<(u16, Decimal, Decimal, Decimal, bool)> = String::from_utf8(ndjson)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value =
+                serde_json::from_str(line).expect("Each line should be valid JSON");
+            let amount = |field: &str| Decimal::from_str(&value[field].to_string()).unwrap();
+            (
+                value["client"].as_u64().unwrap() as u16,
+                amount("available"),
+                amount("held"),
+                amount("total"),
+                value["locked"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+
+    let mut csv = Vec::new();
+    engine.dump_account_to_csv(&mut csv, BUFFER_SIZE).unwrap();
+    let mut from_csv: Vec<(u16, Decimal, Decimal, Decimal, bool)> =
+        csv::Reader::from_reader(&csv[..])
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+    from_ndjson.sort_by_key(|account| account.0);
+    from_csv.sort_by_key(|account| account.0);
+    assert_eq!(from_ndjson.len(), engine.accounts.len());
+    assert_eq!(from_ndjson, from_csv);
+}
+
+/// Tests `trace_client`: replaying a deposit-dispute-resolve sequence records the client account
+/// after each of its transactions, including the failed ones, and ignores the other clients.
+#[test]
+fn unit_test_trace_client() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       deposit,2,2,5.0\n\
+                       dispute,1,1,\n\
+                       withdrawal,1,3,1.0\n\
+                       dispute,2,2,\n\
+                       resolve,1,1,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+
+    let engine = Engine::default();
+    let steps = engine
+        .trace_client(1, temp_file.path().to_str().unwrap())
+        .expect("The trace should be produced");
+
+    let account = |available: i64, held: i64| {
+        Some(Account {
+            available: Decimal::from(available),
+            held: Decimal::from(held),
+            total: Decimal::from(10),
+            locked: false,
+            ..Account::default()
+        })
+    };
+    assert_eq!(
+        steps.iter().map(|s| s.transaction.tx).collect::<Vec<_>>(),
+        vec![1, 1, 3, 1]
+    );
+    assert_eq!(steps[0].account, account(10, 0));
+    assert_eq!(steps[1].account, account(0, 10));
+    assert_eq!(steps[2].error.as_deref(), Some("Insufficient funds"));
+    assert_eq!(steps[2].account, account(0, 10));
+    assert_eq!(steps[3].account, account(10, 0));
+    assert!(steps
+        .iter()
+        .enumerate()
+        .all(|(i, s)| s.error.is_none() || i == 2));
+    assert!(
+        engine.accounts.is_empty(),
+        "The engine must not be modified"
+    );
+}
+
+/// Tests `with_idempotent_file_reruns`: processing the same file twice is a no-op the second
+/// time, while a different file is still processed (and the default still reports the repeats).
+#[test]
+fn unit_test_idempotent_file_reruns() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.0\n\
+                       withdrawal,1,2,4.0\n\
+                       dispute,1,1,\n\
+                       resolve,1,1,\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap();
+
+    let mut engine = Engine::default().with_idempotent_file_reruns(true);
+    engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect("The first run should succeed");
+    engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect("Re-running the same file should be a no-op");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(6));
+
+    let mut other_file = NamedTempFile::new().unwrap();
+    write!(other_file, "type,client,tx,amount\ndeposit,1,3,1.0\n").unwrap();
+    engine
+        .read_and_process_transactions_from_csv(other_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect("A new file should be processed");
+    assert_eq!(engine.accounts.get(&1).unwrap().total, Decimal::from(7));
+
+    // A gzip-compressed file is decompressed from the bytes read for the fingerprint
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(b"type,client,tx,amount\ndeposit,2,4,5.0\n")
+        .unwrap();
+    let mut gz_file = NamedTempFile::new().unwrap();
+    gz_file.write_all(&encoder.finish().unwrap()).unwrap();
+    for _ in 0..2 {
+        engine
+            .read_and_process_transactions_from_csv(gz_file.path().to_str().unwrap(), BUFFER_SIZE)
+            .expect("The gzip file should be processed once");
+    }
+    assert_eq!(engine.accounts.get(&2).unwrap().total, Decimal::from(5));
+
+    let mut engine = Engine::default();
+    engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .unwrap();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect_err("Without the mode the re-run should report the repeated transactions");
+    assert!(errors[0].message().ends_with("cannot be repeated."));
+}
+
+/// Tests `max_chargeback_exposure`: the sum of the amounts of the transactions currently
+/// disputed, across accounts (resolved and charged back disputes no longer count). The disputes
+/// of an account locked by a chargeback still count under `LockedAccountPolicy::AllowDisputes`.
+#[test]
+fn unit_test_max_chargeback_exposure() {
+    let engine = Engine::default();
+    assert_eq!(engine.max_chargeback_exposure(), Decimal::ZERO);
+
+    let transactions = vec![
+        Transaction::deposit(1, 1, Decimal::from_str("10.5").unwrap()),
+        Transaction::deposit(1, 2, Decimal::from(20)),
+        Transaction::deposit(2, 3, Decimal::from(7)),
+        Transaction::withdrawal(2, 4, Decimal::from(2)),
+        Transaction::deposit(3, 5, Decimal::from(100)),
+        Transaction::dispute(1, 1),
+        Transaction::dispute(1, 2),
+        Transaction::dispute(2, 4),
+        Transaction::dispute(3, 5),
+        Transaction::resolve(1, 2),
+        Transaction::chargeback(3, 5),
+    ];
+    engine
+        .process_slice(&transactions)
+        .expect("The transactions should be processed");
+
+    assert_eq!(
+        engine.max_chargeback_exposure(),
+        Decimal::from_str("12.5").unwrap()
+    );
+
+    let engine = Engine::default().with_locked_account_policy(LockedAccountPolicy::AllowDisputes);
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(1, 2, Decimal::from(5)),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::chargeback(1, 1),
+        ])
+        .expect("The transactions should be processed");
+    assert!(engine.accounts.get(&1).unwrap().locked);
+    assert_eq!(engine.max_chargeback_exposure(), Decimal::from(5));
+}
+
+/// Tests `NegativeHeldPolicy` on resolves while a withdrawal is disputed (held driven negative):
+/// by default resolving the disputed deposit overdraws held; with `Reject` it fails with
+/// `NegativeHeld`, while resolving the disputed withdrawal (raising held) is still accepted.
+#[test]
+fn unit_test_negative_held_policy() {
+    let disputes = vec![
+        Transaction::deposit(1, 1, Decimal::from(10)),
+        Transaction::withdrawal(1, 2, Decimal::from(5)),
+        Transaction::dispute(1, 1),
+        Transaction::dispute(1, 2),
+    ];
+
+    let engine = Engine::default();
+    engine
+        .process_slice(&disputes)
+        .expect("The disputes should be applied");
+    engine
+        .process_slice(&[Transaction::resolve(1, 1)])
+        .expect("By default held can be overdrawn");
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(-5));
+
+    let engine = Engine::default().with_negative_held_policy(NegativeHeldPolicy::Reject);
+    engine
+        .process_slice(&disputes)
+        .expect("The disputes should be applied");
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(5));
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&[Transaction::resolve(1, 1)])
+        .expect_err("The resolve overdrawing held should be rejected");
+    assert!(errors[0]
+        .message()
+        .ends_with("Dispute or resolve would drive the account held funds negative"));
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(5));
+    assert_eq!(engine.accounts.get(&1).unwrap().available, Decimal::ZERO);
+
+    engine
+        .process_slice(&[Transaction::resolve(1, 2), Transaction::resolve(1, 1)])
+        .expect("Resolving the withdrawal first keeps held non negative");
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.available, Decimal::from(5));
+}
+
+/// Memory source reporting a fixed amount of memory in kilobytes, like older sysinfo versions.
+struct KilobyteMemorySource(u64);
+
+impl MemorySource for KilobyteMemorySource {
+    fn process_memory(&mut self, _pid: u32) -> Option<u64> {
+        Some(self.0)
+    }
+
+    fn unit(&self) -> MemoryUnit {
+        MemoryUnit::Kilobytes
+    }
+}
+
+/// Tests that `MemoryReporter` normalizes the memory of its source to bytes, whatever the unit
+/// of the source.
+#[test]
+fn unit_test_memory_reporter_normalizes_to_bytes() {
+    let mut reporter = MemoryReporter::new(KilobyteMemorySource(2048));
+    assert_eq!(reporter.current_bytes(), Some(2 * 1024 * 1024));
+    assert_eq!(reporter.current_mb(), Some(2.0));
+
+    let mut reporter = MemoryReporter::new(FixedMemorySource(Some(3 * 1024 * 1024)));
+    assert_eq!(reporter.current_bytes(), Some(3 * 1024 * 1024));
+    assert_eq!(reporter.current_mb(), Some(3.0));
+
+    assert_eq!(
+        MemoryReporter::new(FixedMemorySource(None)).current_mb(),
+        None
+    );
+    assert_eq!(MemoryUnit::Kilobytes.to_bytes(u64::MAX), u64::MAX);
+    assert_eq!(bytes_to_mb(512 * 1024), 0.5);
+}
+
+/// Tests `state_merkle_root`: engines with the same account state have the same root, whatever
+/// the processing order and the scale of the amounts, and the root changes after a mutation.
+#[test]
+fn unit_test_state_merkle_root() {
+    let engine = Engine::default();
+    let empty_root = engine.state_merkle_root();
+    assert_eq!(empty_root, Engine::default().state_merkle_root());
+
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from_str("1.50").unwrap()),
+            Transaction::deposit(2, 2, Decimal::from(3)),
+            Transaction::deposit(3, 3, Decimal::from(4)),
+            Transaction::dispute(3, 3),
+        ])
+        .expect("The transactions should be processed");
+    let other = Engine::default();
+    other
+        .process_slice(&[
+            Transaction::deposit(3, 30, Decimal::from(4)),
+            Transaction::deposit(2, 20, Decimal::from_str("3.000").unwrap()),
+            Transaction::dispute(3, 30),
+            Transaction::deposit(1, 10, Decimal::from_str("1.5").unwrap()),
+        ])
+        .expect("The transactions should be processed");
+    assert_eq!(engine.state_merkle_root(), other.state_merkle_root());
+    assert_ne!(engine.state_merkle_root(), empty_root);
+
+    other
+        .process_slice(&[Transaction::resolve(3, 30)])
+        .expect("The resolve should be applied");
+    assert_ne!(engine.state_merkle_root(), other.state_merkle_root());
+}
+
+/// Tests `process_slice_parallel` with `with_per_client_ordering` on same-client hazards: every
+/// client deposits, withdraws, disputes and charges back, with the transactions of a client
+/// spread across the whole slice. Routing each client to a fixed worker keeps the dispute after
+/// its deposit and the chargeback after its dispute, so the result equals sequential processing.
+#[test]
+fn unit_test_process_slice_parallel_per_client_ordering() {
+    const CLIENTS: u16 = 200;
+    let mut transactions = Vec::new();
+    for client in 1..=CLIENTS {
+        transactions.push(Transaction::deposit(
+            client,
+            client as u32,
+            Decimal::from(100),
+        ));
+    }
+    for client in 1..=CLIENTS {
+        let tx = 1_000 + client as u32;
+        transactions.push(Transaction::withdrawal(client, tx, Decimal::from(30)));
+    }
+    for client in 1..=CLIENTS {
+        transactions.push(Transaction::dispute(client, client as u32));
+    }
+    for client in (1..=CLIENTS).filter(|client| client % 2 == 0) {
+        transactions.push(Transaction::chargeback(client, client as u32));
+    }
+
+    let sequential = Engine::default();
+    sequential
+        .process_slice(&transactions)
+        .expect("The transactions should be processed sequentially");
+
+    for workers in [1, 3, 8] {
+        let engine = Engine::default().with_per_client_ordering(true);
+        engine
+            .process_slice_parallel(&transactions, workers)
+            .expect("The transactions of each client should be processed in order");
+        assert_eq!(engine.state_merkle_root(), sequential.state_merkle_root());
+        let account = engine.accounts.get(&2).unwrap();
+        assert!(account.locked);
+        assert_eq!(account.total, Decimal::from(-30));
+    }
+}
+
+/// Tests `EngineConfig::precision`: with a precision of 2 a `0.0045` withdrawal rounds to zero
+/// and is rejected, the same way the default precision of 4 rejects `0.0000045`, while `0.005`
+/// rounds up to `0.01`. A precision of 8 keeps `0.0000045` unchanged.
+#[test]
+fn unit_test_configurable_precision() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,1.125\n\
+                       withdrawal,1,2,0.005\n\
+                       withdrawal,1,3,0.0045\n\
+                       withdrawal,1,4,0.0000045\n";
+    write!(temp_file, "{}", csv_content).unwrap();
+    let input_path = temp_file.path().to_str().unwrap();
+
+    let mut engine = Engine::default().with_config(EngineConfig {
+        precision: 2,
+        ..EngineConfig::default()
+    });
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect_err("The withdrawals rounding to zero should be rejected");
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message().contains("tx: 3"));
+    assert!(errors[0]
+        .message()
+        .ends_with("Withdrawal amount must be greater than 0"));
+    assert!(errors[1].message().contains("tx: 4"));
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("1.12").unwrap()
+    );
+
+    let mut engine = Engine::default();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect_err("The default precision should reject the last withdrawal");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message().contains("tx: 4"));
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("1.1155").unwrap()
+    );
+
+    let mut engine = Engine::default().with_precision(8);
+    engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect("A precision of 8 should keep every withdrawal");
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().available,
+        Decimal::from_str("1.1154955").unwrap()
+    );
+}
+
+/// Tests `disputed_transactions`: after disputing two of three deposits, their ids are returned
+/// sorted; a resolved dispute is no longer listed.
+#[test]
+fn unit_test_disputed_transactions() {
+    let engine = Engine::default();
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 3, Decimal::from(10)),
+            Transaction::deposit(2, 1, Decimal::from(20)),
+            Transaction::deposit(1, 2, Decimal::from(30)),
+            Transaction::dispute(1, 3),
+            Transaction::dispute(2, 1),
+        ])
+        .expect("The transactions should be processed");
+    assert_eq!(engine.disputed_transactions(), vec![1, 3]);
+
+    engine
+        .process_slice(&[Transaction::resolve(2, 1)])
+        .expect("The resolve should be applied");
+    assert_eq!(engine.disputed_transactions(), vec![3]);
+}
+
+/// Tests dumping a subset of the account columns in a given order (`client,total,locked`), and
+/// the validation of the requested column names.
+#[test]
+fn unit_test_dump_account_columns_subset() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.5\n\
+               deposit,2,2,3\n\
+               dispute,2,2,\n\
+               chargeback,2,2,\n";
+    let columns = AccountColumn::parse_list("client,total,locked").unwrap();
+    assert_eq!(
+        columns,
+        vec![
+            AccountColumn::Client,
+            AccountColumn::Total,
+            AccountColumn::Locked
+        ]
+    );
+    let engine = Engine::new().with_account_columns(columns);
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_csv(&mut output, BUFFER_SIZE)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let mut lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.remove(0), "client,total,locked");
+    lines.sort();
+    assert_eq!(lines, vec!["1,10.5,false", "2,0,true"]);
+
+    assert!(AccountColumn::parse_list("client,balance")
+        .unwrap_err()
+        .contains("Unknown account column `balance`"));
+    assert!(AccountColumn::parse_list("total,client,total")
+        .unwrap_err()
+        .contains("repeated"));
+    assert!(
+        ConfigFile::from_toml_str("[engine.dump]\ncolumns = [\"client\", \"balance\"]\n").is_err()
+    );
+}
+
+/// Tests the structured processing errors: the engine error, transaction, client and input line
+/// of every failed record can be matched without parsing the messages, which are unchanged.
+#[test]
+fn unit_test_structured_transaction_errors() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               withdrawal,1,2,50.0\n\
+               transfer,1,3,1.0\n\
+               dispute,2,1,\n";
+    let engine = Engine::new();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect_err("The failing records should be reported");
+    assert_eq!(errors.len(), 3);
+
+    assert_eq!(errors[0].record_line, Some(3));
+    assert_eq!(errors[0].tx, Some(2));
+    assert_eq!(errors[0].client, Some(1));
+    assert_eq!(
+        errors[0].kind,
+        TransactionErrorKind::Engine(EngineError::InsufficientFunds)
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "Error processing Transaction { ty: Withdrawal, client: 1, tx: 2, amount: Some(50.0), disputed: false }: Insufficient funds"
+    );
+
+    assert_eq!(errors[1].record_line, Some(4));
+    assert_eq!(errors[1].kind, TransactionErrorKind::Read);
+    assert_eq!(errors[1].tx, None);
+
+    assert_eq!(errors[2].record_line, Some(5));
+    assert_eq!(
+        errors[2].engine_error(),
+        Some(&EngineError::DifferentClient)
+    );
+    assert_eq!(errors[2].client, Some(2));
+}
+
+/// Runs the `txn_engine` binary with `args`, writing `input` to its stdin, and returns its
+/// output. If `close_stdout` is set, the read end of stdout is closed before writing the input.
+fn run_cli(args: &[&str], input: &str, close_stdout: bool) -> std::process::Output {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_txn_engine"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("The binary should start");
+    if close_stdout {
+        drop(child.stdout.take());
+    }
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Tests the `-` input of the CLI: the transactions are streamed from stdin and the accounts
+/// printed on stdout; a closed stdout (broken pipe) is not reported as a failure.
+#[test]
+fn unit_test_cli_stdin_input() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 withdrawal,1,2,4.5\n";
+    let output = run_cli(&["-"], input, false);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,5.5,0,5.5,false\n"
+    );
+
+    let output = run_cli(&["-", "--omit-zero"], input, true);
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("panicked"));
+}
+
+/// Tests `with_only_types` on a mixed file: with only deposits and withdrawals, the dispute,
+/// resolve and chargeback rows are skipped without errors and the balances reflect only the
+/// deposits and withdrawals. The `-only=LIST` flag of the CLI does the same.
+#[test]
+fn unit_test_only_types() {
+    let mut engine = Engine::new().with_only_types(Some(
+        [TransactionType::Deposit, TransactionType::Withdrawal].into(),
+    ));
+    let result =
+        engine.read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE);
+    // Only the zero deposit and withdrawal of the file fail.
+    let Err(TransactionProcessingError::MultipleErrors(errors)) = result else {
+        panic!("The zero deposit and withdrawal should fail");
+    };
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|e| e.client == Some(11)));
+
+    assert!(engine.transaction_log.iter().all(|tx| !tx.disputed));
+    let account = engine.accounts.get(&10).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(30));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert!(!account.locked);
+    let account = engine.accounts.get(&12).unwrap().clone();
+    assert_eq!(account.available, Decimal::from_str("99998.9999").unwrap());
+    assert_eq!(account.held, Decimal::ZERO);
+
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 dispute,1,1,\n\
+                 withdrawal,1,2,4.5\n";
+    let output = run_cli(&["-", "-only=deposit,withdrawal"], input, false);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,5.5,0,5.5,false\n"
+    );
+    assert!(!run_cli(&["-", "-only=deposit,refund"], "", false)
+        .status
+        .success());
+}
+
+/// Tests the `--config PATH` form of the CLI flag (as `--config=PATH`): the settings of the file
+/// are applied in both modes, including the shard count of the async engine.
+#[test]
+fn unit_test_cli_config_file() {
+    let mut config_file = NamedTempFile::new().unwrap();
+    write!(
+        config_file,
+        "shards = 8\n\n[engine]\ntransaction_fee = \"0.5\"\n"
+    )
+    .unwrap();
+    let config_path = config_file.path().to_str().unwrap();
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n";
+
+    for args in [
+        vec!["-", "--config", config_path],
+        vec!["async", "-", "--config", config_path],
+        vec!["-", &format!("--config={config_path}")],
+    ] {
+        let output = run_cli(&args, input, false);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "client,available,held,total,locked\n1,9.5,0,9.5,false\n"
+        );
+    }
+    assert!(!run_cli(&["-", "--config"], "", false).status.success());
+}
+
+/// Tests the pre-dump verification: an engine loaded with a disputed deposit whose funds are not
+/// held by the account dumps with warnings, or refuses to dump in strict mode, and
+/// `held_mismatches` reports the disagreement.
+#[test]
+fn unit_test_pre_dump_verification() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    write!(
+        transactions_file,
+        "type,client,tx,amount,disputed\n\
+         deposit,1,1,10.0,true\n\
+         deposit,2,2,5.0,true\n"
+    )
+    .unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,10.0,0.0,10.0,false\n\
+         2,0.0,5.0,5.0,false\n"
+    )
+    .unwrap();
+    let transactions_path = transactions_file.path().to_str().unwrap();
+    let accounts_path = accounts_file.path().to_str().unwrap();
+
+    let mut engine = Engine::new();
+    engine
+        .load_from_previous_session_csvs(transactions_path, accounts_path)
+        .expect("Failed to load from CSV");
+    assert_eq!(
+        engine.held_mismatches(),
+        vec![HeldMismatch {
+            client: 1,
+            held: Decimal::from_str("0.0").unwrap(),
+            expected: Decimal::from_str("10.0").unwrap(),
+        }]
+    );
+    engine
+        .dump_account_to_ndjson(Vec::new(), BUFFER_SIZE)
+        .expect("The dump should not be verified by default");
+
+    let mut engine = Engine::new().with_pre_dump_verification(PreDumpVerification::Warn);
+    engine
+        .load_from_previous_session_csvs(transactions_path, accounts_path)
+        .expect("Failed to load from CSV");
+    let mut output = Vec::new();
+    engine
+        .dump_account_to_ndjson(&mut output, BUFFER_SIZE)
+        .expect("The dump should be written despite the warnings");
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 2);
+    assert_eq!(engine.pre_dump_warnings(), engine.held_mismatches());
+    assert_eq!(
+        engine.pre_dump_warnings()[0].to_string(),
+        "client 1 held 0.0 expected 10.0"
+    );
+
+    let mut engine = Engine::new().with_pre_dump_verification(PreDumpVerification::Strict);
+    engine
+        .load_from_previous_session_csvs(transactions_path, accounts_path)
+        .expect("Failed to load from CSV");
+    let error = engine
+        .dump_account_to_ndjson(Vec::new(), BUFFER_SIZE)
+        .expect_err("The inconsistent state should not be dumped");
+    assert!(error
+        .to_string()
+        .contains("client 1 held 0.0 expected 10.0"));
+    assert!(engine.dump_combined_csv(Vec::new()).is_err());
+
+    let engine = Engine::new().with_pre_dump_verification(PreDumpVerification::Strict);
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               withdrawal,1,2,4.0\n\
+               dispute,1,1,\n\
+               dispute,1,2,\n";
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert!(engine.held_mismatches().is_empty());
+    engine
+        .dump_account_to_ndjson(Vec::new(), BUFFER_SIZE)
+        .expect("A consistent state should be dumped");
+    assert!(engine.pre_dump_warnings().is_empty());
+}
+
+/// Tests the `--inline-errors` flag of the CLI: the processing errors are written as `#` comment
+/// lines preceding the accounts CSV header, which a reader honoring comments skips.
+#[test]
+fn unit_test_cli_inline_errors() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 withdrawal,1,2,40.0\n\
+                 dispute,1,9,\n";
+    let output = run_cli(&["-", "--inline-errors"], input, false);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("# Error processing Transaction { ty: Withdrawal"));
+    assert!(lines[0].ends_with("Insufficient funds"));
+    assert!(lines[1].starts_with("# Error processing Transaction { ty: Dispute"));
+    assert_eq!(
+        &lines[2..],
+        ["client,available,held,total,locked", "1,10.0,0,10.0,false"]
+    );
+
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(stdout.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["client", "available", "held", "total", "locked"]
+    );
+    assert_eq!(reader.records().count(), 1);
+
+    let output = run_cli(&["-"], input, false);
+    assert!(!String::from_utf8(output.stdout).unwrap().contains('#'));
+}
+
+/// Tests `dump_account_to_json`: the amounts are JSON strings keeping their scale and every line
+/// parses back into the `Account` of the engine.
+#[test]
+fn unit_test_dump_account_to_json() {
+    let mut engine = Engine::default();
+    let _ =
+        engine.read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE);
+
+    let mut json = Vec::new();
+    engine
+        .dump_account_to_json(&mut json, BUFFER_SIZE)
+        .expect("JSON dump should succeed");
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(json.lines().count(), engine.accounts.len());
+    for line in json.lines() {
+        let value: serde_json::Value =
+            serde_json::from_str(line).expect("Each line should be valid JSON");
+        for field in ["available", "held", "total"] {
+            assert!(value[field].is_string());
+        }
+        let client = value["client"].as_u64().unwrap() as u16;
+        let account: Account = serde_json::from_value(value).unwrap();
+        assert_eq!(account, *engine.accounts.get(&client).unwrap());
+    }
+
+    let engine = Engine::default();
+    engine
+        .process_slice(&[Transaction::deposit(
+            1,
+            1,
+            Decimal::from_str("5.0000").unwrap(),
+        )])
+        .unwrap();
+    let mut json = Vec::new();
+    engine.dump_account_to_json(&mut json, BUFFER_SIZE).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{\"client\":1,\"available\":\"5.0000\",\"held\":\"0\",\"total\":\"5.0000\",\"locked\":false}\n"
+    );
+}
+
+/// Tests `with_reference_precheck`: a stream with a dangling dispute reference is refused up
+/// front, without processing any record, while references to earlier or logged transactions
+/// pass the check.
+#[test]
+fn unit_test_reference_precheck() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               dispute,1,1,\n\
+               dispute,1,7,\n\
+               deposit,1,2,5.0\n\
+               chargeback,1,3,\n\
+               deposit,1,3,1.0\n";
+
+    let engine = Engine::new().with_reference_precheck(true);
+    match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the dangling references to be reported"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert_eq!(
+                errors
+                    .iter()
+                    .map(|e| (e.tx, e.record_line))
+                    .collect::<Vec<_>>(),
+                vec![(Some(7), Some(4)), (Some(3), Some(6))]
+            );
+            assert!(errors
+                .iter()
+                .all(|e| e.engine_error() == Some(&EngineError::TransactionNotFound)));
+        }
+    }
+    assert!(engine.accounts.is_empty());
+    assert!(engine.transaction_log.is_empty());
+
+    // Without the precheck, the dangling references fail mid-stream.
+    let engine = Engine::new();
+    assert!(engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .is_err());
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().total,
+        Decimal::from_str("16.0").unwrap()
+    );
+
+    // A reference to a transaction already in the log passes the check.
+    let engine = Engine::new().with_reference_precheck(true);
+    engine
+        .read_and_process_transactions("type,client,tx,amount\ndeposit,1,1,10.0\n".as_bytes(), 16)
+        .unwrap();
+    engine
+        .read_and_process_transactions("type,client,tx,amount\ndispute,1,1,\n".as_bytes(), 16)
+        .unwrap();
+    assert_eq!(
+        engine.accounts.get(&1).unwrap().held,
+        Decimal::from_str("10.0").unwrap()
+    );
+}
+
+/// Tests `get_account`, `total_clients` and `total_transactions`: the account is a copy, so
+/// processing the client while holding it does not block.
+#[test]
+fn unit_test_get_account() {
+    let engine = Engine::new();
+    assert_eq!(engine.get_account(1), None);
+    assert_eq!(engine.total_clients(), 0);
+    assert_eq!(engine.total_transactions(), 0);
+
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(2, 2, Decimal::from(5)),
+            Transaction::withdrawal(1, 3, Decimal::from(4)),
+        ])
+        .unwrap();
+    let account = engine.get_account(1).unwrap();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.total, Decimal::from(6));
+
+    engine.process_slice(&[Transaction::dispute(1, 1)]).unwrap();
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(engine.get_account(1).unwrap().held, Decimal::from(10));
+    assert_eq!(engine.get_account(3), None);
+    assert_eq!(engine.total_clients(), 2);
+    assert_eq!(engine.total_transactions(), 3);
+}
+
+/// Tests `subscribe_deltas_bounded` with a slow consumer: with `DropOldest` the processing
+/// never waits and only the latest deltas are kept, with `Block` the processing waits for the
+/// consumer and every delta is delivered in order.
+#[test]
+fn unit_test_bounded_delta_feed() {
+    let deposits: Vec<Transaction> = (1..=5)
+        .map(|tx| Transaction::deposit(1, tx, Decimal::from(tx)))
+        .collect();
+
+    let engine = Engine::new();
+    let deltas = engine.subscribe_deltas_bounded(2, FeedOverflowPolicy::DropOldest);
+    engine.process_slice(&deposits).unwrap();
+    let received: Vec<Decimal> = deltas.try_iter().map(|d| d.delta_total).collect();
+    assert_eq!(received, vec![Decimal::from(4), Decimal::from(5)]);
+    assert_eq!(deltas.dropped(), 3);
+
+    let engine = Engine::new();
+    std::thread::scope(|scope| {
+        // Owned by the scope closure, so that a failed assertion drops it and unblocks the
+        // processing thread instead of hanging the test.
+        let deltas = engine.subscribe_deltas_bounded(1, FeedOverflowPolicy::Block);
+        let processing = scope.spawn(|| engine.process_slice(&deposits));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        // One delta is buffered, the second deposit waits for room before being logged.
+        assert!(!processing.is_finished());
+        assert_eq!(engine.transaction_log.len(), 1);
+
+        let mut received = Vec::new();
+        while let Some(delta) = deltas.recv() {
+            received.push(delta.delta_total);
+            if received.len() == deposits.len() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        processing.join().unwrap().unwrap();
+        assert_eq!(received, (1..=5).map(Decimal::from).collect::<Vec<_>>());
+        assert_eq!(deltas.dropped(), 0);
+    });
+}
+
+/// Tests `read_and_process_transactions_with_sink`: the sink sees the outcome of every
+/// transaction in processing order (a held back dispute after the deposit it waited for), and
+/// the errors are still returned at the end.
+#[test]
+fn unit_test_read_and_process_transactions_with_sink() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               withdrawal,1,2,50.0\n\
+               dispute,1,3,\n\
+               deposit,1,3,5.0\n\
+               bogus,1,4,1.0\n\
+               dispute,1,9,\n";
+    let engine = Engine::new().with_dispute_reorder_window(2);
+    let mut outcomes = Vec::new();
+    let result = engine.read_and_process_transactions_with_sink(
+        csv.as_bytes(),
+        BUFFER_SIZE,
+        |tx, result| outcomes.push((tx.ty.clone(), tx.tx, result)),
+    );
+    assert_eq!(
+        outcomes,
+        vec![
+            (TransactionType::Deposit, 1, Ok(())),
+            (
+                TransactionType::Withdrawal,
+                2,
+                Err(EngineError::InsufficientFunds)
+            ),
+            (TransactionType::Deposit, 3, Ok(())),
+            (TransactionType::Dispute, 3, Ok(())),
+            (
+                TransactionType::Dispute,
+                9,
+                Err(EngineError::TransactionNotFound)
+            ),
+        ]
+    );
+    match result {
+        Ok(()) => panic!("Expected the failed transactions to be reported"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => assert_eq!(errors.len(), 3),
+    }
+}
+
+/// Tests `TxUniqueness::PerClient`: two clients can use the same tx id, and disputes,
+/// resolves and chargebacks refer to the transaction of their own client, while by default the
+/// second deposit is rejected as repeated.
+#[test]
+fn unit_test_tx_uniqueness_per_client() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,1,5.0\n\
+               dispute,2,1,\n\
+               chargeback,2,1,\n\
+               dispute,1,1,\n\
+               resolve,1,1,\n\
+               deposit,1,1,3.0\n";
+    let errors_of =
+        |engine: &Engine| match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+            Ok(()) => panic!("Expected the repeated deposit to fail"),
+            Err(TransactionProcessingError::MultipleErrors(errors)) => errors
+                .iter()
+                .map(|e| (e.tx, e.client, e.engine_error().cloned()))
+                .collect::<Vec<_>>(),
+        };
+
+    let engine = Engine::new();
+    assert_eq!(
+        errors_of(&engine),
+        vec![
+            (Some(1), Some(2), Some(EngineError::TransactionRepeated)),
+            (Some(1), Some(2), Some(EngineError::DifferentClient)),
+            (Some(1), Some(2), Some(EngineError::DifferentClient)),
+            (Some(1), Some(1), Some(EngineError::TransactionRepeated)),
+        ]
+    );
+    assert!(engine.accounts.get(&2).is_none());
+    assert_eq!(engine.total_transactions(), 1);
+
+    let engine = Engine::new().with_tx_uniqueness(TxUniqueness::PerClient);
+    assert_eq!(
+        errors_of(&engine),
+        vec![(Some(1), Some(1), Some(EngineError::TransactionRepeated))]
+    );
+    let account1 = engine.get_account(1).unwrap();
+    assert_eq!(account1.available, Decimal::from_str("10.0").unwrap());
+    assert_eq!(account1.held, Decimal::ZERO);
+    assert!(!account1.locked);
+    let account2 = engine.get_account(2).unwrap();
+    assert_eq!(account2.total, Decimal::ZERO);
+    assert_eq!(account2.held, Decimal::ZERO);
+    assert!(account2.locked);
+
+    assert_eq!(engine.total_transactions(), 2);
+    assert!(engine.transaction_log.get(&TxKey::global(1)).is_none());
+    let tx1 = engine
+        .transaction_log
+        .get(&TxKey::per_client(1, 1))
+        .unwrap();
+    assert_eq!(tx1.amount, Some(Decimal::from_str("10.0").unwrap()));
+    assert!(!tx1.disputed);
+    let tx2 = engine
+        .transaction_log
+        .get(&TxKey::per_client(2, 1))
+        .unwrap();
+    assert_eq!(tx2.amount, Some(Decimal::from_str("5.0").unwrap()));
+    assert!(tx2.disputed);
+}
+
+/// Tests `process_streams` on concurrent streams sharing the same clients, with operations whose
+/// outcome depends on the order (withdrawals needing a deposit of another stream, disputes and
+/// chargebacks of deposits of another stream): over several runs the final state is always the
+/// one of processing the streams sequentially, in order.
+#[test]
+fn reg_test_process_streams_deterministic() {
+    const CLIENTS: u32 = 300;
+    let mut deposits = String::from("type,client,tx,amount\n");
+    let mut withdrawals = String::from("type,client,tx,amount\n");
+    let mut disputes = String::from("type,client,tx,amount\n");
+    for client in 1..=CLIENTS {
+        // Refers to a deposit of a later stream: always fails
+        withdrawals.push_str(&format!("dispute,{},{},\n", client, 2_000 + client));
+        deposits.push_str(&format!("deposit,{},{},100.0\n", client, client));
+        withdrawals.push_str(&format!("withdrawal,{},{},60.0\n", client, 1_000 + client));
+        withdrawals.push_str(&format!("withdrawal,{},{},50.0\n", client, 2_000 + client));
+        disputes.push_str(&format!("dispute,{},{},\n", client, client));
+        if client % 2 == 0 {
+            disputes.push_str(&format!("chargeback,{},{},\n", client, client));
+        } else {
+            disputes.push_str(&format!("resolve,{},{},\n", client, client));
+        }
+    }
+    let streams = [deposits, withdrawals, disputes];
+
+    let sequential = Engine::new();
+    for stream in &streams {
+        let _ = sequential.read_and_process_transactions(stream.as_bytes(), BUFFER_SIZE);
+    }
+    let account = sequential.accounts.get(&2).unwrap();
+    assert!(account.locked);
+    assert_eq!(account.total, Decimal::from(-60));
+    drop(account);
+
+    for run in 0..5 {
+        let engine = Engine::new();
+        let errors = match engine
+            .process_streams(streams.iter().map(|stream| stream.as_bytes()).collect(), 4)
+        {
+            Ok(()) => panic!("Expected the dangling disputes and the overdrafts to fail"),
+            Err(TransactionProcessingError::MultipleErrors(errors)) => errors,
+        };
+        assert_eq!(errors.len(), 2 * CLIENTS as usize, "run {}", run);
+        assert_eq!(
+            engine.state_merkle_root(),
+            sequential.state_merkle_root(),
+            "run {}",
+            run
+        );
+        assert!(engine.log_diff(&sequential).is_empty(), "run {}", run);
+    }
+}
+
+/// Tests the `--dump-scale` and `--dump-rounding` flags of the CLI: the dumped amounts are
+/// rounded and padded to the requested scale, independently of the processing precision.
+#[test]
+fn unit_test_cli_dump_scale_and_rounding() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,1.125\n\
+                 deposit,2,2,10\n";
+    let accounts = |args: &[&str]| {
+        let output = run_cli(args, input, false);
+        assert!(output.status.success());
+        let mut lines: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    assert_eq!(
+        accounts(&["-"]),
+        ["1,1.125,0,1.125,false", "2,10,0,10,false"]
+    );
+    assert_eq!(
+        accounts(&["-", "--dump-scale=2"]),
+        ["1,1.13,0.00,1.13,false", "2,10.00,0.00,10.00,false"]
+    );
+    assert_eq!(
+        accounts(&["-", "--dump-scale=2", "--dump-rounding=half-even"]),
+        ["1,1.12,0.00,1.12,false", "2,10.00,0.00,10.00,false"]
+    );
+    assert_eq!(
+        accounts(&["async", "-", "--dump-scale=0", "--dump-rounding=ceiling"]),
+        ["1,2,0,2,false", "2,10,0,10,false"]
+    );
+
+    let output = run_cli(&["-", "--dump-rounding=nearest"], input, false);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Unknown rounding `nearest`"));
+}
+
+/// Tests `checkpoint` and `restore`: a checkpoint round-trips the engine state, fee log included,
+/// and records the row counts in its manifest; a row deleted from a checkpoint file, or a file
+/// left from another checkpoint with the same row counts, is detected on restore.
+#[test]
+fn unit_test_checkpoint_restore() {
+    let mut engine = Engine::default().with_transaction_fee(Decimal::new(1, 2));
+    let _ =
+        engine.read_and_process_transactions_from_csv("tests/transactions_mixed.csv", BUFFER_SIZE);
+    let dir = tempfile::tempdir().unwrap();
+
+    let manifest = engine
+        .checkpoint(dir.path())
+        .expect("Checkpoint should succeed");
+    assert_eq!(manifest.accounts, engine.total_clients());
+    assert_eq!(manifest.transactions, engine.total_transactions());
+    let names: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names.len(), 4, "Only the checkpoint files should be left");
+    assert!(manifest.fees > 0);
+    assert_eq!(manifest.fees, engine.fee_log.len());
+
+    let mut restored = Engine::default();
+    assert_eq!(restored.restore(dir.path()).unwrap(), manifest);
+    assert_eq!(restored.state_merkle_root(), engine.state_merkle_root());
+    assert!(restored.log_diff(&engine).is_empty());
+    assert_eq!(restored.fee_log.len(), engine.fee_log.len());
+    for entry in engine.fee_log.iter() {
+        assert_eq!(
+            restored.fee_log.get(entry.key()).unwrap().amount,
+            entry.value().amount
+        );
+    }
+    let old_accounts = std::fs::read(dir.path().join(CHECKPOINT_ACCOUNTS)).unwrap();
+
+    // A second checkpoint replaces the first one
+    engine
+        .process_slice(&[Transaction::deposit(999, 999_999, Decimal::ONE)])
+        .unwrap();
+    let manifest = engine.checkpoint(dir.path()).unwrap();
+    assert_eq!(manifest.accounts, engine.total_clients());
+
+    let transactions_path = dir.path().join(CHECKPOINT_TRANSACTIONS);
+    let content = std::fs::read_to_string(&transactions_path).unwrap();
+    let truncated: Vec<&str> = content.lines().take(content.lines().count() - 1).collect();
+    std::fs::write(&transactions_path, truncated.join("\n") + "\n").unwrap();
+    match Engine::default().restore(dir.path()) {
+        Err(EngineSerDeserError::CheckpointChecksumMismatch(name)) => {
+            assert_eq!(name, CHECKPOINT_TRANSACTIONS)
+        }
+        other => panic!("Expected a checkpoint mismatch, got {:?}", other),
+    }
+
+    // Accounts left from the previous checkpoint, with the same row count, are rejected too
+    let manifest = engine.checkpoint(dir.path()).unwrap();
+    std::fs::write(dir.path().join(CHECKPOINT_ACCOUNTS), &old_accounts).unwrap();
+    let mut restored = Engine::default();
+    match restored.restore(dir.path()) {
+        Err(EngineSerDeserError::CheckpointChecksumMismatch(name)) => {
+            assert_eq!(name, CHECKPOINT_ACCOUNTS)
+        }
+        other => panic!("Expected a checkpoint mismatch, got {:?}", other),
+    }
+    assert_eq!(restored.total_transactions(), 0, "Nothing should be loaded");
+    assert!(manifest.accounts > 0);
+}
+
+/// Tests `with_json_schema` with a schema requiring an amount between 0 and 1000 on every
+/// transaction: the violating records are rejected before any processing, and a valid schema
+/// is required.
+#[cfg(feature = "jsonschema")]
+#[test]
+fn unit_test_json_schema_validation() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "required": ["type", "client", "tx", "amount"],
+        "properties": {
+            "amount": { "type": "number", "exclusiveMinimum": 0, "maximum": 1000 }
+        }
+    });
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,1,2,5000.0\n\
+               withdrawal,1,3,4.0\n\
+               dispute,1,1,\n";
+
+    let engine = Engine::new().with_json_schema(&schema).unwrap();
+    let errors = match engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the records violating the schema to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => errors,
+    };
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].tx, Some(2));
+    assert_eq!(errors[1].tx, Some(1));
+    assert_eq!(errors[1].record_line, Some(5));
+    for error in &errors {
+        assert!(matches!(
+            error.engine_error(),
+            Some(EngineError::SchemaViolation(_))
+        ));
+    }
+    let account = engine.get_account(1).unwrap();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.held, Decimal::ZERO);
+
+    // Without a schema every record is processed
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    assert_eq!(engine.get_account(1).unwrap().held, Decimal::from(10));
+
+    assert!(Engine::new()
+        .with_json_schema(&serde_json::json!({ "type": "no-such-type" }))
+        .is_err());
+}
+
+/// Tests the account overdraft limit loaded from the optional trailing `overdraft_limit` column:
+/// a withdrawal driving `available` negative within the limit succeeds, one exceeding it fails,
+/// and accounts without the column (or with an empty one) have no overdraft.
+#[test]
+fn unit_test_overdraft_limit() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    writeln!(transactions_file, "type,client,tx,amount,disputed").unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked,overdraft_limit\n\
+         1,10.0,0,10.0,false,50\n\
+         2,10.0,0,10.0,false\n\
+         3,10.0,0,10.0,false,\n"
+    )
+    .unwrap();
+
+    let mut engine = Engine::new();
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .expect("The accounts should be loaded");
+    assert_eq!(
+        engine.get_account(1).unwrap().overdraft_limit,
+        Decimal::from(50)
+    );
+    assert_eq!(
+        engine.get_account(2).unwrap().overdraft_limit,
+        Decimal::ZERO
+    );
+
+    let results: Vec<_> = [
+        Transaction::withdrawal(1, 1, Decimal::from(40)),
+        Transaction::withdrawal(1, 2, Decimal::from(25)),
+        Transaction::withdrawal(1, 3, Decimal::from(20)),
+        Transaction::withdrawal(2, 4, Decimal::from(15)),
+        Transaction::withdrawal(3, 5, Decimal::from(15)),
+    ]
+    .iter()
+    .map(|tx| engine.process_slice(std::slice::from_ref(tx)).is_ok())
+    .collect();
+    assert_eq!(results, [true, false, true, false, false]);
+
+    let account = engine.get_account(1).unwrap();
+    assert_eq!(account.available, Decimal::from(-50));
+    assert_eq!(account.total, Decimal::from(-50));
+    assert_eq!(engine.get_account(2).unwrap().available, Decimal::from(10));
+}
+
+/// Tests `Engine::validate_transactions`: every error of the batch is reported, disputes are
+/// checked against the current state and the earlier transactions of the batch, and neither the
+/// accounts nor the transaction log are changed.
+#[test]
+fn unit_test_validate_transactions() {
+    let engine = Engine::new();
+    engine
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(10))])
+        .unwrap();
+
+    let batch = "type,client,tx,amount\n\
+                 dispute,1,1,\n\
+                 deposit,2,2,5.0\n\
+                 withdrawal,2,3,7.0\n\
+                 resolve,1,1,\n\
+                 dispute,1,9,\n\
+                 withdrawal,1,4,10.0\n";
+    match engine.validate_transactions(batch.as_bytes(), BUFFER_SIZE) {
+        Ok(()) => panic!("Expected the invalid transactions to be reported"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                errors
+                    .iter()
+                    .map(|e| (e.tx, e.engine_error()))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (Some(3), Some(&EngineError::InsufficientFunds)),
+                    (Some(9), Some(&EngineError::TransactionNotFound)),
+                ]
+            );
+        }
+    }
+
+    assert_eq!(engine.total_clients(), 1);
+    assert_eq!(engine.total_transactions(), 1);
+    let account = engine.get_account(1).unwrap();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::ZERO);
+
+    let valid = "type,client,tx,amount\n\
+                 dispute,1,1,\n\
+                 resolve,1,1,\n";
+    assert!(engine
+        .validate_transactions(valid.as_bytes(), BUFFER_SIZE)
+        .is_ok());
+}
+
+/// Tests the `-validate` flag of the CLI: the errors are printed to stderr, no account is
+/// printed and the exit status reports whether the transactions are valid.
+#[test]
+fn unit_test_cli_validate() {
+    let invalid = "type,client,tx,amount\n\
+                   deposit,1,1,10.0\n\
+                   withdrawal,1,2,15.0\n";
+    let output = run_cli(&["-", "-validate"], invalid, false);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Insufficient funds"), "{}", stderr);
+    assert!(stderr.contains("Validation failed"), "{}", stderr);
+
+    let valid = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 withdrawal,1,2,5.0\n";
+    let output = run_cli(&["-", "-validate"], valid, false);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+/// Tests `Engine::net_flow`: without disputes it matches the balance of the account, while a
+/// dispute moves the balance to the held funds without changing the net flow.
+#[test]
+fn unit_test_net_flow() {
+    let engine = Engine::new();
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(100)),
+            Transaction::withdrawal(1, 2, Decimal::from_str("30.5").unwrap()),
+            Transaction::deposit(1, 3, Decimal::from(20)),
+            Transaction::deposit(2, 4, Decimal::from(50)),
+            Transaction::withdrawal(2, 5, Decimal::from(10)),
+        ])
+        .unwrap();
+
+    for client in [1, 2] {
+        assert_eq!(
+            engine.net_flow(client),
+            Some(engine.get_account(client).unwrap().total)
+        );
+    }
+    assert_eq!(engine.net_flow(1), Some(Decimal::from_str("89.5").unwrap()));
+    assert_eq!(engine.net_flow(3), None);
+
+    engine.process_slice(&[Transaction::dispute(2, 4)]).unwrap();
+    assert_eq!(engine.get_account(2).unwrap().available, Decimal::from(-10));
+    assert_eq!(engine.net_flow(2), Some(Decimal::from(40)));
+}
+
+/// Tests the `AccountCapacityPolicy` of `Engine::with_max_accounts` at the capacity boundary:
+/// existing accounts keep accepting deposits, while a deposit creating one more account is
+/// rejected, credited to the overflow account (created beyond the limit) or dropped.
+#[test]
+fn unit_test_max_accounts_policies() {
+    let deposits = |engine: &Engine| {
+        engine
+            .process_slice(&[
+                Transaction::deposit(1, 1, Decimal::from(10)),
+                Transaction::deposit(2, 2, Decimal::from(20)),
+            ])
+            .unwrap();
+        [
+            Transaction::deposit(3, 3, Decimal::from(30)),
+            Transaction::deposit(1, 4, Decimal::from(5)),
+            Transaction::deposit(4, 5, Decimal::from(40)),
+        ]
+        .iter()
+        .map(|tx| {
+            engine.process_slice(std::slice::from_ref(tx)).map_err(
+                |TransactionProcessingError::MultipleErrors(errors)| {
+                    errors[0].engine_error().cloned()
+                },
+            )
+        })
+        .collect::<Vec<_>>()
+    };
+
+    let engine = Engine::new().with_max_accounts(2, AccountCapacityPolicy::Reject);
+    assert_eq!(
+        deposits(&engine),
+        [
+            Err(Some(EngineError::AccountCapacityReached)),
+            Ok(()),
+            Err(Some(EngineError::AccountCapacityReached)),
+        ]
+    );
+    assert_eq!(engine.total_clients(), 2);
+    assert_eq!(engine.total_transactions(), 3);
+    assert_eq!(engine.get_account(1).unwrap().available, Decimal::from(15));
+
+    let engine = Engine::new().with_max_accounts(2, AccountCapacityPolicy::Overflow(999));
+    assert_eq!(deposits(&engine), [Ok(()), Ok(()), Ok(())]);
+    assert_eq!(engine.total_clients(), 3);
+    assert!(engine.get_account(3).is_none());
+    assert_eq!(
+        engine.get_account(999).unwrap().available,
+        Decimal::from(70)
+    );
+    assert_eq!(
+        engine
+            .transaction_log
+            .get(&TxKey::global(5))
+            .unwrap()
+            .client,
+        999
+    );
+
+    let engine = Engine::new().with_max_accounts(2, AccountCapacityPolicy::Drop);
+    assert_eq!(
+        deposits(&engine),
+        [
+            Err(Some(EngineError::DepositDropped)),
+            Ok(()),
+            Err(Some(EngineError::DepositDropped))
+        ]
+    );
+    assert_eq!(engine.total_clients(), 2);
+    assert_eq!(engine.total_transactions(), 3);
+    assert!(engine.get_account(3).is_none());
+
+    let config = ConfigFile::from_toml_str(
+        "[engine]\nmax_accounts = 2\naccount_capacity_policy = { overflow = 999 }\n",
+    )
+    .unwrap();
+    assert_eq!(config.engine.max_accounts, Some(2));
+    assert_eq!(
+        config.engine.account_capacity_policy,
+        AccountCapacityPolicy::Overflow(999)
+    );
+}
+
+/// Tests the transparent decompression of gzip input files: a gzip-compressed CSV (detected by
+/// its `.gz` suffix or by its magic bytes) gives the same accounts as the plain one.
+#[test]
+fn unit_test_gzip_input() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.0\n\
+               deposit,2,2,20.0\n\
+               withdrawal,1,3,4.5\n\
+               dispute,2,2,\n";
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(csv.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let accounts = |path: &str| {
+        let mut engine = Engine::new();
+        engine
+            .read_and_process_transactions_from_csv(path, BUFFER_SIZE)
+            .unwrap();
+        let mut output = Vec::new();
+        engine
+            .dump_account_to_csv(&mut output, BUFFER_SIZE)
+            .unwrap();
+        let mut lines: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    let mut plain_file = NamedTempFile::new().unwrap();
+    plain_file.write_all(csv.as_bytes()).unwrap();
+    let mut gz_file = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .unwrap();
+    gz_file.write_all(&compressed).unwrap();
+    let mut unnamed_gz_file = NamedTempFile::new().unwrap();
+    unnamed_gz_file.write_all(&compressed).unwrap();
+
+    let expected = accounts(plain_file.path().to_str().unwrap());
+    assert!(expected.contains(&"1,5.5,0,5.5,false".to_string()));
+    assert_eq!(accounts(gz_file.path().to_str().unwrap()), expected);
+    assert_eq!(accounts(unnamed_gz_file.path().to_str().unwrap()), expected);
+}
+
+/// Tests the `validate` subcommand of the CLI on a valid and an invalid file: a pass/fail
+/// summary with the error counts by cause is printed instead of the accounts, and the exit
+/// status fails on any error.
+#[test]
+fn unit_test_cli_validate_subcommand() {
+    let mut valid_file = NamedTempFile::new().unwrap();
+    write!(
+        valid_file,
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         withdrawal,1,2,5.0\n"
+    )
+    .unwrap();
+    let valid_path = valid_file.path().to_str().unwrap();
+    let output = run_cli(&["validate", valid_path], "", false);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}: PASS (0 errors)\n", valid_path)
+    );
+
+    let mut invalid_file = NamedTempFile::new().unwrap();
+    write!(
+        invalid_file,
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         withdrawal,1,2,15.0\n\
+         withdrawal,1,3,20.0\n\
+         dispute,1,9,\n"
+    )
+    .unwrap();
+    let invalid_path = invalid_file.path().to_str().unwrap();
+    let output = run_cli(&["validate", invalid_path], "", false);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{}: FAIL (3 errors)\n  Insufficient funds: 2\n  Transaction not found: 1\n",
+            invalid_path
+        )
+    );
+}
+
+/// Tests the rounding of the input amounts to the precision: `1.12345` is rounded to `1.1235`
+/// by default (midpoint away from zero) and to `1.1234` with banker's rounding, set with
+/// `with_rounding` or the `-rounding=` flag of the CLI.
+#[test]
+fn unit_test_input_rounding() {
+    let deposit = [Transaction::deposit(
+        1,
+        1,
+        Decimal::from_str("1.12345").unwrap(),
+    )];
+
+    let engine = Engine::new();
+    engine.process_slice(&deposit).unwrap();
+    assert_eq!(
+        engine.get_account(1).unwrap().available,
+        Decimal::from_str("1.1235").unwrap()
+    );
+
+    let engine = Engine::new().with_rounding(Rounding::HalfEven);
+    engine.process_slice(&deposit).unwrap();
+    assert_eq!(
+        engine.get_account(1).unwrap().available,
+        Decimal::from_str("1.1234").unwrap()
+    );
+
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,1.12345\n";
+    let output = run_cli(&["-", "-rounding=half-even"], input, false);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,1.1234,0,1.1234,false\n"
+    );
+    let output = run_cli(&["-", "-rounding=banker"], input, false);
+    assert!(!output.status.success());
+}
+
+/// Tests `Engine::verify_invariants`: accounts loaded from a previous session with
+/// `available + held != total` are flagged with their discrepancy, while a discrepancy below
+/// the precision and the accounts of processed transactions are not.
+#[test]
+fn unit_test_verify_invariants() {
+    let mut transactions_file = NamedTempFile::new().unwrap();
+    writeln!(transactions_file, "type,client,tx,amount,disputed").unwrap();
+    let mut accounts_file = NamedTempFile::new().unwrap();
+    write!(
+        accounts_file,
+        "client,available,held,total,locked\n\
+         1,10.0,5.0,15.0,false\n\
+         2,10.0,5.0,20.0,false\n\
+         3,10.00001,0,10.0,false\n\
+         4,10.0,-5.0,10.0,true\n"
+    )
+    .unwrap();
+
+    let mut engine = Engine::new();
+    assert_eq!(engine.verify_invariants(), Ok(()));
+    engine
+        .load_from_previous_session_csvs(
+            transactions_file.path().to_str().unwrap(),
+            accounts_file.path().to_str().unwrap(),
+        )
+        .unwrap();
+    engine
+        .process_slice(&[
+            Transaction::deposit(5, 1, Decimal::from(100)),
+            Transaction::withdrawal(5, 2, Decimal::from(30)),
+            Transaction::dispute(5, 1),
+        ])
+        .unwrap();
+
+    assert_eq!(
+        engine.verify_invariants(),
+        Err(vec![
+            InvariantViolation {
+                client: 2,
+                discrepancy: Some(Decimal::from(-5)),
+            },
+            InvariantViolation {
+                client: 4,
+                discrepancy: Some(Decimal::from(-5)),
+            },
+        ])
+    );
+}
+
+/// Tests `read_and_process_transactions_parallel` against the serial path on a generated file:
+/// whatever the number of workers, the final state, the transaction log and the errors (with
+/// their input lines) are the ones of the serial processing. The timings are printed as a rough
+/// benchmark. The dispute reorder window and the reference precheck are rejected.
+#[test]
+fn unit_test_read_and_process_transactions_parallel() {
+    let temp_file = NamedTempFile::new().unwrap();
+    txn_engine::utility::generate_random_transactions(50_000, &temp_file).unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let sorted_errors = |result: Result<(), TransactionProcessingError>| {
+        let mut errors = match result {
+            Ok(()) => Vec::new(),
+            Err(TransactionProcessingError::MultipleErrors(errors)) => errors,
+        };
+        errors.sort_by_key(|e| e.record_line);
+        errors
+    };
+
+    let mut serial = Engine::new().with_tx_uniqueness(TxUniqueness::PerClient);
+    let start = std::time::Instant::now();
+    let serial_errors =
+        sorted_errors(serial.read_and_process_transactions_from_csv(path, BUFFER_SIZE));
+    println!("serial: {:?}", start.elapsed());
+    assert!(!serial_errors.is_empty());
+    assert!(serial_errors.iter().all(|e| e.record_line.is_some()));
+
+    for workers in [1, 2, 4, 7] {
+        let engine = Engine::new().with_tx_uniqueness(TxUniqueness::PerClient);
+        let start = std::time::Instant::now();
+        let errors = sorted_errors(engine.read_and_process_transactions_parallel(
+            File::open(path).unwrap(),
+            BUFFER_SIZE,
+            workers,
+        ));
+        println!("{} workers: {:?}", workers, start.elapsed());
+        assert_eq!(errors, serial_errors, "{} workers", workers);
+        assert_eq!(
+            engine.state_merkle_root(),
+            serial.state_merkle_root(),
+            "{} workers",
+            workers
+        );
+        assert!(engine.log_diff(&serial).is_empty(), "{} workers", workers);
+    }
+
+    for engine in [
+        Engine::new().with_dispute_reorder_window(10),
+        Engine::new().with_reference_precheck(true),
+    ] {
+        let TransactionProcessingError::MultipleErrors(errors) = engine
+            .read_and_process_transactions_parallel(File::open(path).unwrap(), BUFFER_SIZE, 4)
+            .expect_err("The unsupported options should be rejected");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, TransactionErrorKind::Other);
+        assert_eq!(engine.total_transactions(), 0);
+    }
+}
+
+/// Tests `prune_transactions`: pruning is rejected while an older transaction is disputed on an
+/// unlocked account, charged back transactions can be pruned, and disputes on pruned tx ids
+/// then fail with `TransactionNotFound`.
+#[test]
+fn unit_test_prune_transactions() {
+    let engine = Engine::new();
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(1, 2, Decimal::from(20)),
+            Transaction::deposit(1, 3, Decimal::from(30)),
+            Transaction::deposit(2, 4, Decimal::from(40)),
+            Transaction::deposit(1, 5, Decimal::from(50)),
+            Transaction::dispute(1, 2),
+            Transaction::dispute(2, 4),
+            Transaction::chargeback(2, 4),
+        ])
+        .expect("The transactions should be processed");
+
+    assert_eq!(
+        engine.prune_transactions(4),
+        Err(EngineError::TransactionUnsettled)
+    );
+    assert_eq!(engine.transaction_log.len(), 5);
+
+    engine
+        .process_slice(&[Transaction::resolve(1, 2)])
+        .expect("The resolve should be applied");
+    assert_eq!(engine.prune_transactions(4), Ok(3));
+    // Tx 4 is still flagged as disputed, but it was charged back.
+    assert_eq!(engine.prune_transactions(5), Ok(1));
+    assert_eq!(engine.prune_transactions(5), Ok(0));
+    assert_eq!(engine.transaction_log.len(), 1);
+
+    match engine.process_slice(&[
+        Transaction::dispute(1, 1),
+        Transaction::dispute(1, 3),
+        Transaction::dispute(1, 5),
+    ]) {
+        Ok(()) => panic!("Expected the disputes on the pruned transactions to fail"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            let failed: Vec<_> = errors
+                .iter()
+                .map(|e| (e.tx, e.engine_error().cloned()))
+                .collect();
+            assert_eq!(
+                failed,
+                vec![
+                    (Some(1), Some(EngineError::TransactionNotFound)),
+                    (Some(3), Some(EngineError::TransactionNotFound)),
+                ]
+            );
+        }
+    }
+    let account = engine.accounts.get(&1).unwrap();
+    assert_eq!(account.available, Decimal::from(60));
+    assert_eq!(account.held, Decimal::from(50));
+}
+
+/// Tests `generate_random_transactions_seeded`: the same seed produces byte-identical files,
+/// a different seed a different file.
+#[test]
+fn unit_test_generate_random_transactions_seeded() {
+    let generate = |seed| {
+        let temp_file = NamedTempFile::new().unwrap();
+        txn_engine::utility::generate_random_transactions_seeded(1_000, seed, &temp_file).unwrap();
+        std::fs::read(temp_file.path()).unwrap()
+    };
+    let first = generate(42);
+    assert_eq!(first, generate(42));
+    assert_ne!(first, generate(43));
+    assert_eq!(first.iter().filter(|&&b| b == b'\n').count(), 1_001);
+}
+
+/// Tests partial chargebacks: a chargeback with an amount only reverses that portion of the
+/// disputed funds, leaving the account unlocked and the remainder disputed, to be charged back
+/// (locking the account) or resolved later. Amounts above the disputed remainder are rejected.
+/// The logged amount is unchanged, the portion being tracked in `charged_back`.
+#[test]
+fn unit_test_partial_chargeback() {
+    let partial = |client, tx, amount| Transaction {
+        amount: Some(Decimal::from(amount)),
+        ..Transaction::chargeback(client, tx)
+    };
+    let engine = Engine::new();
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(100)),
+            Transaction::deposit(2, 2, Decimal::from(100)),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(2, 2),
+            partial(1, 1, 30),
+            partial(2, 2, 40),
+        ])
+        .expect("The partial chargebacks should be applied");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::ZERO);
+    assert_eq!(account.held, Decimal::from(70));
+    assert_eq!(account.total, Decimal::from(70));
+    assert!(!account.locked);
+    let logged = engine
+        .transaction_log
+        .get(&TxKey::global(1))
+        .unwrap()
+        .clone();
+    assert!(logged.disputed);
+    assert_eq!(logged.amount, Some(Decimal::from(100)));
+    assert_eq!(logged.charged_back, Decimal::from(30));
+    assert_eq!(engine.max_chargeback_exposure(), Decimal::from(130));
+
+    // The charged back part survives a dump and a reload
+    let mut combined = Vec::new();
+    engine.dump_combined_csv(&mut combined).unwrap();
+    let mut loaded = Engine::new();
+    loaded.load_combined_csv(combined.as_slice()).unwrap();
+    assert!(engine.log_diff(&loaded).is_empty());
+    assert!(loaded.held_mismatches().is_empty());
+
+    match engine.process_slice(&[partial(1, 1, 80), partial(1, 1, 0)]) {
+        Ok(()) => panic!("Expected the invalid chargebacks to be rejected"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            let errors: Vec<_> = errors.iter().map(|e| e.engine_error().cloned()).collect();
+            assert_eq!(
+                errors,
+                vec![
+                    Some(EngineError::ChargebackExceedsHeld),
+                    Some(EngineError::ChargebackAmountInvalid),
+                ]
+            );
+        }
+    }
+
+    engine
+        .process_slice(&[
+            partial(1, 1, 20),
+            Transaction::chargeback(1, 1),
+            Transaction::resolve(2, 2),
+        ])
+        .expect("The final chargeback and the resolve should be applied");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::ZERO);
+    assert!(account.locked);
+    let account = engine.accounts.get(&2).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(60));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(60));
+    assert!(!account.locked);
+    assert!(engine.held_mismatches().is_empty());
+}
+
+/// Tests `metrics` on the error conditions file: the rejection counts by error match the errors
+/// returned by the processing (the unreadable record is not counted), and the Prometheus
+/// rendering exposes the counters.
+#[test]
+fn unit_test_metrics() {
+    let mut engine = Engine::default();
+    let errors = match engine
+        .read_and_process_transactions_from_csv("tests/transactions_errors.csv", BUFFER_SIZE)
+    {
+        Ok(()) => panic!("Expected an error, but got success"),
+        Err(TransactionProcessingError::MultipleErrors(errors)) => errors,
+    };
+    let mut expected_rejections: BTreeMap<String, u64> = BTreeMap::new();
+    for error in errors.iter().filter_map(TransactionError::engine_error) {
+        *expected_rejections
+            .entry(format!("{:?}", error))
+            .or_default() += 1;
+    }
+
+    let metrics = engine.metrics();
+    assert_eq!(metrics.rejected_by_kind, expected_rejections);
+    assert_eq!(metrics.rejected_by_kind["TransactionNotFound"], 4);
+    assert_eq!(metrics.rejected_by_kind.values().sum::<u64>(), 18);
+    assert_eq!(metrics.deposits_processed, 5);
+    assert_eq!(metrics.withdrawals_processed, 1);
+    assert_eq!(metrics.disputes, 1);
+    assert_eq!(metrics.resolves, 0);
+    assert_eq!(metrics.chargebacks, 1);
+    assert_eq!(metrics.accounts_locked, 1);
+
+    let rendered = metrics.render_prometheus();
+    assert!(rendered.contains("# TYPE txn_engine_transactions_processed_total counter\n"));
+    assert!(rendered.contains("txn_engine_transactions_processed_total{type=\"deposit\"} 5\n"));
+    assert!(rendered
+        .contains("txn_engine_transactions_rejected_total{kind=\"TransactionNotFound\"} 4\n"));
+    assert!(rendered.ends_with("txn_engine_accounts_locked 1\n"));
+}
+
+/// Tests the CSV input dialects: a BOM-prefixed comma-separated file and a tab-delimited file
+/// (with `CsvDialect::tab_separated`) parse into the same accounts. The delimiter can be set in
+/// the config file.
+#[test]
+fn unit_test_csv_dialect() {
+    let rows = [
+        ["type", "client", "tx", "amount"],
+        ["deposit", "1", "1", "10.5"],
+        ["deposit", "2", "2", "20"],
+        ["withdrawal", "1", "3", "4"],
+        ["dispute", "2", "2", ""],
+    ];
+    let write_file = |bom: &[u8], delimiter: &str| {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(bom).unwrap();
+        for row in rows {
+            writeln!(temp_file, "{}", row.join(delimiter)).unwrap();
+        }
+        temp_file
+    };
+    let process = |mut engine: Engine, temp_file: &NamedTempFile| {
+        engine
+            .read_and_process_transactions_from_csv(temp_file.path().to_str().unwrap(), BUFFER_SIZE)
+            .expect("The transactions should be processed");
+        let mut accounts: Vec<(u16, Account)> = engine
+            .accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        accounts.sort_by_key(|(client, _)| *client);
+        accounts
+    };
+
+    let bom_file = write_file(b"\xEF\xBB\xBF", ",");
+    let tab_file = write_file(b"", "\t");
+    let bom_accounts = process(Engine::new(), &bom_file);
+    let tab_accounts = process(
+        Engine::new().with_csv_dialect(CsvDialect::tab_separated()),
+        &tab_file,
+    );
+    assert_eq!(bom_accounts, tab_accounts);
+    assert_eq!(bom_accounts.len(), 2);
+    assert_eq!(bom_accounts[0].1.total, Decimal::from_str("6.5").unwrap());
+    assert_eq!(bom_accounts[1].1.held, Decimal::from(20));
+
+    // The tab-separated file is not readable as comma-separated.
+    assert!(Engine::new()
+        .read_and_process_transactions_from_csv(tab_file.path().to_str().unwrap(), BUFFER_SIZE)
+        .is_err());
+
+    let config = ConfigFile::from_toml_str("[engine.csv_dialect]\ndelimiter = \"\\t\"\n").unwrap();
+    assert_eq!(config.engine.csv_dialect, CsvDialect::tab_separated());
+    assert!(ConfigFile::from_toml_str("[engine.csv_dialect]\ndelimiter = \";;\"\n").is_err());
+}
+
+/// A dispute preceding its deposit in the file fails by default, but is processed after the
+/// deposit when the records are ordered by their `timestamp` column, given in seconds since the
+/// Unix epoch or in ISO-8601. Files without a timestamp column, and invalid or missing
+/// timestamps, are reported.
+#[test]
+fn unit_test_timestamp_ordering() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(
+        temp_file,
+        "type,client,tx,amount,timestamp\n\
+         dispute,1,1,,200\n\
+         deposit,1,1,10.0,100\n\
+         deposit,1,2,5.0,300\n"
+    )
+    .unwrap();
+    let path = temp_file.path().to_str().unwrap();
+
+    let mut engine = Engine::new();
+    match engine.read_and_process_transactions_from_csv(path, BUFFER_SIZE) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::AccountNotFound)
+            );
+            assert_eq!(errors[0].record_line, Some(2));
+        }
+        other => panic!("Expected the dispute to fail, got {:?}", other),
+    }
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::ZERO);
+
+    let mut engine = Engine::new().with_timestamp_ordering(2);
+    engine
+        .read_and_process_transactions_from_csv(path, BUFFER_SIZE)
+        .expect("The dispute should be processed after its deposit");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.available, Decimal::from(5));
+
+    let mut invalid = NamedTempFile::new().unwrap();
+    write!(
+        invalid,
+        "type,client,tx,amount,timestamp\ndeposit,1,1,10.0,soon\ndeposit,1,2,5.0,100\n"
+    )
+    .unwrap();
+    let mut engine = Engine::new().with_timestamp_ordering(16);
+    match engine
+        .read_and_process_transactions_from_csv(invalid.path().to_str().unwrap(), BUFFER_SIZE)
+    {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].message().contains("invalid timestamp 'soon'"));
+            assert_eq!(errors[0].record_line, Some(2));
+        }
+        other => panic!("Expected an invalid timestamp error, got {:?}", other),
+    }
+    assert_eq!(engine.accounts.get(&1).unwrap().total, Decimal::from(5));
+
+    let mut iso = NamedTempFile::new().unwrap();
+    write!(
+        iso,
+        "type,client,tx,amount,timestamp\n\
+         dispute,1,1,,2024-05-01T10:00:00+02:00\n\
+         deposit,1,1,10.0,2024-05-01T07:59:59Z\n\
+         deposit,1,2,5.0,\n\
+         deposit,1,3,1.0,2024-05-01T09:00:00\n"
+    )
+    .unwrap();
+    let mut engine = Engine::new().with_timestamp_ordering(16);
+    match engine.read_and_process_transactions_from_csv(iso.path().to_str().unwrap(), BUFFER_SIZE) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].message().contains("missing timestamp"));
+            assert_eq!(errors[0].record_line, Some(4));
+        }
+        other => panic!("Expected a missing timestamp error, got {:?}", other),
+    }
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.available, Decimal::from(1));
+
+    let mut no_timestamps = NamedTempFile::new().unwrap();
+    write!(no_timestamps, "type,client,tx,amount\ndeposit,1,1,10.0\n").unwrap();
+    let mut engine = Engine::new().with_timestamp_ordering(16);
+    assert!(engine
+        .read_and_process_transactions_from_csv(no_timestamps.path().to_str().unwrap(), BUFFER_SIZE)
+        .is_err());
+    assert!(engine.accounts.is_empty());
+}
+
+/// A dispute, resolve or chargeback of client 2 referring to a transaction of client 1 is
+/// reported as `DifferentClient`, whether or not client 2 has an account (the ownership of the
+/// referred transaction is checked before the account).
+#[test]
+fn unit_test_cross_client_dispute() {
+    let engine = Engine::new();
+    engine
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(10))])
+        .unwrap();
+    let cross_client = [
+        Transaction::dispute(2, 1),
+        Transaction::resolve(2, 1),
+        Transaction::chargeback(2, 1),
+    ];
+    for with_account in [false, true] {
+        if with_account {
+            engine
+                .process_slice(&[Transaction::deposit(2, 2, Decimal::from(5))])
+                .unwrap();
+        }
+        match engine.process_slice(&cross_client) {
+            Err(TransactionProcessingError::MultipleErrors(errors)) => {
+                assert_eq!(errors.len(), 3);
+                assert!(
+                    errors
+                        .iter()
+                        .all(|e| e.engine_error() == Some(&EngineError::DifferentClient)),
+                    "with_account: {}, {:?}",
+                    with_account,
+                    errors
+                );
+            }
+            other => panic!("Expected DifferentClient errors, got {:?}", other),
+        }
+    }
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::ZERO);
+
+    // A client without account disputing an unknown transaction still gets `AccountNotFound`.
+    match engine.process_slice(&[Transaction::dispute(3, 99)]) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::AccountNotFound)
+            );
+        }
+        other => panic!("Expected AccountNotFound, got {:?}", other),
+    }
+}
+
+/// Tests reading the transactions from a SQLite query: the balances equal the ones of the
+/// equivalent CSV, and rows that fail to map are collected with the processing errors.
+#[cfg(feature = "sqlite")]
+#[test]
+fn unit_test_read_and_process_transactions_from_sqlite() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10.5\n\
+               deposit,2,2,20\n\
+               withdrawal,1,3,4.25\n\
+               dispute,2,2,\n\
+               withdrawal,2,4,100\n";
+    let csv_engine = Engine::new();
+    let _ = csv_engine.read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE);
+
+    // A shared in-memory database lives as long as one of its connections is open.
+    let db_path = "file:unit_test_sqlite?mode=memory&cache=shared";
+    let connection = rusqlite::Connection::open(db_path).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE staged (id INTEGER PRIMARY KEY, kind TEXT, client INTEGER, tx INTEGER, amount TEXT);
+             INSERT INTO staged (kind, client, tx, amount) VALUES
+                 ('deposit', 1, 1, '10.5'),
+                 ('deposit', 2, 2, '20'),
+                 ('withdrawal', 1, 3, '4.25'),
+                 ('dispute', 2, 2, NULL),
+                 ('withdrawal', 2, 4, '100'),
+                 ('transfer', 1, 5, '1');",
+        )
+        .unwrap();
+
+    let engine = Engine::new();
+    match engine.read_and_process_transactions_from_sqlite(
+        db_path,
+        "SELECT kind AS type, client, tx, amount FROM staged ORDER BY id",
+    ) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 2, "{:?}", errors);
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::InsufficientFunds)
+            );
+            assert_eq!(errors[0].record_line, Some(5));
+            assert_eq!(errors[1].kind, TransactionErrorKind::Read);
+            assert_eq!(errors[1].record_line, Some(6));
+        }
+        Ok(()) => panic!("Expected the failing rows to be reported"),
+    }
+    let accounts = |engine: &Engine| {
+        let mut accounts: Vec<(u16, Account)> = engine
+            .accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        accounts.sort_by_key(|(client, _)| *client);
+        accounts
+    };
+    assert_eq!(accounts(&engine), accounts(&csv_engine));
+
+    assert!(Engine::new()
+        .read_and_process_transactions_from_sqlite(db_path, "SELECT * FROM missing")
+        .is_err());
+}
+
+/// Tests the single-transaction amount limit set from the config file (also as
+/// `max_transaction_amount`): a deposit above it is rejected early with `AmountExceedsLimit`,
+/// and an astronomically large deposit no longer ends in an addition overflow.
+#[test]
+fn reg_test_max_transaction_amount_config() {
+    let config =
+        ConfigFile::from_toml_str("[engine]\nmax_transaction_amount = \"1000\"\n").unwrap();
+    assert_eq!(config.engine.max_amount, Some(Decimal::from(1000)));
+    let engine = Engine::new().with_config(config.engine);
+    let transactions = [
+        Transaction::deposit(1, 1, Decimal::from(1000)),
+        Transaction::deposit(1, 2, Decimal::from(5000)),
+        Transaction::deposit(1, 3, Decimal::MAX),
+    ];
+    match engine.process_slice(&transactions) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(errors
+                .iter()
+                .all(|e| e.engine_error() == Some(&EngineError::AmountExceedsLimit)));
+        }
+        Ok(()) => panic!("Expected the deposits above the limit to be rejected"),
+    }
+    assert_eq!(engine.accounts.get(&1).unwrap().total, Decimal::from(1000));
+
+    let unbounded = Engine::new();
+    assert!(unbounded
+        .process_slice(&[Transaction::deposit(1, 1, Decimal::from(5000))])
+        .is_ok());
+}
+
+/// Tests `NegativeHeldPolicy::Strict` on the disputed withdrawal scenario: by default the
+/// dispute drives held to -5, with `Strict` it fails with `NegativeHeld` and leaves the account
+/// and the logged withdrawal unchanged, while disputing the deposit is still accepted.
+#[test]
+fn unit_test_negative_held_policy_strict() {
+    let transactions = [
+        Transaction::deposit(1, 1, Decimal::from(10)),
+        Transaction::withdrawal(1, 2, Decimal::from(5)),
+        Transaction::dispute(1, 2),
+    ];
+
+    let engine = Engine::default();
+    engine
+        .process_slice(&transactions)
+        .expect("By default a withdrawal can be disputed");
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(-5));
+
+    let engine = Engine::default().with_negative_held_policy(NegativeHeldPolicy::Strict);
+    match engine.process_slice(&transactions) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].tx, Some(2));
+            assert_eq!(errors[0].engine_error(), Some(&EngineError::NegativeHeld));
+        }
+        Ok(()) => panic!("Expected the dispute of the withdrawal to be rejected"),
+    }
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(5));
+    assert!(
+        !engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .disputed
+    );
+
+    engine
+        .process_slice(&[Transaction::dispute(1, 1)])
+        .expect("Disputing the deposit raises held");
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(10));
+}
+
+/// Tests that the records are mapped by header name: reordered columns, unknown extra columns
+/// (ignored), padded header names and a missing `amount` column are all read correctly.
+#[test]
+fn unit_test_header_order_agnostic() {
+    let baseline = "type,client,tx,amount\n\
+                    deposit,1,1,10.5\n\
+                    deposit,2,2,20\n\
+                    withdrawal,1,3,4\n\
+                    dispute,2,2,\n";
+    let variants = [
+        "client,type,tx,amount\n\
+         1,deposit,1,10.5\n\
+         2,deposit,2,20\n\
+         1,withdrawal,3,4\n\
+         2,dispute,2,\n",
+        "amount,note,tx,client,type\n\
+         10.5,first,1,1,deposit\n\
+         20,,2,2,deposit\n\
+         4,cash,3,1,withdrawal\n\
+         ,chargeback?,2,2,dispute\n",
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.5\n\
+         deposit, 2, 2, 20\n\
+         withdrawal, 1, 3, 4\n\
+         dispute, 2, 2,\n",
+    ];
+    let accounts = |csv: &str| {
+        let engine = Engine::new();
+        engine
+            .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+            .expect("The transactions should be processed");
+        let mut accounts: Vec<(u16, Account)> = engine
+            .accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        accounts.sort_by_key(|(client, _)| *client);
+        accounts
+    };
+    let expected = accounts(baseline);
+    assert_eq!(expected[0].1.available, Decimal::from_str("6.5").unwrap());
+    assert_eq!(expected[1].1.held, Decimal::from(20));
+    for csv in variants {
+        assert_eq!(accounts(csv), expected, "{}", csv);
+    }
+
+    // Disputes, resolves and chargebacks do not need an amount column.
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(baseline.as_bytes(), BUFFER_SIZE)
+        .unwrap();
+    engine
+        .read_and_process_transactions("tx,type,client\n2,resolve,2\n".as_bytes(), BUFFER_SIZE)
+        .expect("The resolve should be processed without an amount column");
+    assert_eq!(
+        engine.accounts.get(&2).unwrap().available,
+        Decimal::from(20)
+    );
+}
+
+/// Tests `read_and_process_transactions_tracked`: the returned clients are exactly the ones
+/// with a changed account, failed transactions (e.g. a dispute of an unknown transaction) do
+/// not touch their client, and a second batch only reports the clients it touched.
+#[test]
+fn unit_test_read_and_process_transactions_tracked() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10\n\
+               deposit,2,2,20\n\
+               withdrawal,1,3,4\n\
+               dispute,2,2,\n\
+               withdrawal,3,4,5\n\
+               dispute,4,99,\n\
+               deposit,5,5,7\n\
+               dispute,1,99,\n\
+               withdrawal,5,6,100\n";
+    let engine = Engine::new();
+    let (touched, result) =
+        engine.read_and_process_transactions_tracked(csv.as_bytes(), BUFFER_SIZE);
+    match result {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => assert_eq!(errors.len(), 4),
+        Ok(()) => panic!("Expected the failing transactions to be reported"),
+    }
+    let changed: HashSet<u16> = engine
+        .accounts
+        .iter()
+        .filter(|entry| *entry.value() != Account::default())
+        .map(|entry| *entry.key())
+        .collect();
+    assert_eq!(touched, changed);
+    assert_eq!(touched, HashSet::from([1, 2, 5]));
+
+    let (touched, result) = engine.read_and_process_transactions_tracked(
+        "type,client,tx,amount\nresolve,2,2,\ndispute,5,99,\n".as_bytes(),
+        BUFFER_SIZE,
+    );
+    assert!(result.is_err());
+    assert_eq!(touched, HashSet::from([2]));
+}
+
+/// Tests `DisputePolicy` on a disputed withdrawal: by default the dispute moves the amount to
+/// a negative held, when withdrawal disputes are not allowed it fails with
+/// `WithdrawalNotDisputable` and leaves the balances unchanged, while deposits stay disputable.
+#[test]
+fn unit_test_dispute_policy_withdrawals() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(
+        temp_file,
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         withdrawal,1,2,4.0\n\
+         dispute,1,2,\n"
+    )
+    .unwrap();
+    let input_path = temp_file.path().to_str().unwrap();
+
+    let mut engine = Engine::new();
+    engine
+        .read_and_process_transactions_from_csv(input_path, BUFFER_SIZE)
+        .expect("By default withdrawals can be disputed");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::from(-4));
+
+    let config =
+        ConfigFile::from_toml_str("[engine.dispute_policy]\nallow_withdrawal_disputes = false\n")
+            .unwrap();
+    assert_eq!(
+        config.engine.dispute_policy,
+        DisputePolicy {
+            allow_withdrawal_disputes: false
+        }
+    );
+    let mut engine = Engine::new().with_dispute_policy(config.engine.dispute_policy);
+    match engine.read_and_process_transactions_from_csv(input_path, BUFFER_SIZE) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].record_line, Some(4));
+            assert_eq!(
+                errors[0].engine_error(),
+                Some(&EngineError::WithdrawalNotDisputable)
+            );
+        }
+        Ok(()) => panic!("Expected the dispute of the withdrawal to be rejected"),
+    }
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(6));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert!(
+        !engine
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .disputed
+    );
+
+    engine
+        .process_slice(&[Transaction::dispute(1, 1)])
+        .expect("Deposits can still be disputed");
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(10));
+}
+
+/// Tests `dump_account_to_csv_sorted`: the rows are in strictly increasing client order and
+/// hold the same accounts as the unsorted dump.
+#[test]
+fn unit_test_dump_account_to_csv_sorted() {
+    let engine = Engine::new();
+    let transactions: Vec<Transaction> = (0..500u16)
+        .map(|i| {
+            let client = ((u32::from(i) * 7919) % 1000) as u16;
+            Transaction::deposit(client, u32::from(i), Decimal::from(i + 1))
+        })
+        .collect();
+    engine.process_slice(&transactions).unwrap();
+
+    let mut sorted = Vec::new();
+    engine
+        .dump_account_to_csv_sorted(&mut sorted, BUFFER_SIZE)
+        .unwrap();
+    let sorted = String::from_utf8(sorted).unwrap();
+    let mut lines = sorted.lines();
+    assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 500);
+    let clients: Vec<u16> = rows
+        .iter()
+        .map(|row| row.split(',').next().unwrap().parse().unwrap())
+        .collect();
+    assert!(clients.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let mut unsorted = Vec::new();
+    engine
+        .dump_account_to_csv(&mut unsorted, BUFFER_SIZE)
+        .unwrap();
+    let unsorted = String::from_utf8(unsorted).unwrap();
+    let mut unsorted_rows: Vec<&str> = unsorted.lines().skip(1).collect();
+    unsorted_rows.sort_by_key(|row| row.split(',').next().unwrap().parse::<u16>().unwrap());
+    assert_eq!(unsorted_rows, rows);
+}
+
+/// Tests reversals: a deposit reversal subtracts the amount from the available and total
+/// funds, a withdrawal reversal adds it back, and a reversed transaction (which keeps its type
+/// and amount, also through a dump) can be neither reversed again nor disputed. Holds cannot be
+/// reversed.
+#[test]
+fn unit_test_reversal() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,10\n\
+               deposit,1,2,100\n\
+               withdrawal,1,3,4\n\
+               reversal,1,2,\n\
+               reversal,1,3,\n";
+    let engine = Engine::new();
+    engine
+        .read_and_process_transactions(csv.as_bytes(), BUFFER_SIZE)
+        .expect("The reversals should be processed");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(10));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(10));
+    let logged = engine
+        .transaction_log
+        .get(&TxKey::global(2))
+        .unwrap()
+        .clone();
+    assert_eq!(logged.ty, TransactionType::Deposit);
+    assert_eq!(logged.amount, Some(Decimal::from(100)));
+    assert!(logged.reversed);
+    assert_eq!(engine.net_flow(1), Some(Decimal::from(10)));
+
+    let errors_of = |transactions: &[Transaction]| match engine.process_slice(transactions) {
+        Err(TransactionProcessingError::MultipleErrors(errors)) => errors
+            .iter()
+            .map(|e| e.engine_error().cloned())
+            .collect::<Vec<_>>(),
+        Ok(()) => panic!("Expected {:?} to fail", transactions),
+    };
+    assert_eq!(
+        errors_of(&[
+            Transaction::reversal(1, 2),
+            Transaction::dispute(1, 2),
+            Transaction::reversal(1, 99),
+            Transaction::reversal(2, 1),
+        ]),
+        vec![
+            Some(EngineError::AlreadyReversed),
+            Some(EngineError::AlreadyReversed),
+            Some(EngineError::TransactionNotFound),
+            Some(EngineError::DifferentClient),
+        ]
+    );
+    assert_eq!(engine.accounts.get(&1).unwrap().clone(), account);
+
+    engine
+        .process_slice(&[Transaction::hold(1, 4, Decimal::ONE)])
+        .unwrap();
+    assert_eq!(
+        errors_of(&[Transaction::reversal(1, 4)]),
+        vec![Some(EngineError::HoldNotReversible)]
+    );
+
+    let mut dump = Vec::new();
+    engine.dump_combined_csv(&mut dump).unwrap();
+    let mut loaded = Engine::new();
+    loaded.load_combined_csv(dump.as_slice()).unwrap();
+    assert!(
+        loaded
+            .transaction_log
+            .get(&TxKey::global(2))
+            .unwrap()
+            .reversed
+    );
+    let TransactionProcessingError::MultipleErrors(errors) = loaded
+        .process_slice(&[Transaction::reversal(1, 3)])
+        .expect_err("A reversed transaction cannot be reversed after a reload");
+    assert_eq!(
+        errors[0].engine_error(),
+        Some(&EngineError::AlreadyReversed)
+    );
+}
+
+/// Tests that a disputed transaction cannot be reversed (`CannotReverseDisputed`, the account
+/// is left unchanged) until it is resolved, and that a charged back one cannot be reversed as
+/// its account is locked.
+#[test]
+fn unit_test_reversal_of_disputed() {
+    let engine = Engine::new();
+    engine
+        .process_slice(&[
+            Transaction::deposit(1, 1, Decimal::from(10)),
+            Transaction::deposit(1, 2, Decimal::from(5)),
+            Transaction::dispute(1, 1),
+        ])
+        .unwrap();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&[Transaction::reversal(1, 1)])
+        .expect_err("A disputed transaction cannot be reversed");
+    assert_eq!(
+        errors[0].engine_error(),
+        Some(&EngineError::CannotReverseDisputed)
+    );
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::from(10));
+
+    engine
+        .process_slice(&[Transaction::resolve(1, 1), Transaction::reversal(1, 1)])
+        .expect("A resolved transaction can be reversed");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(5));
+
+    engine
+        .process_slice(&[Transaction::dispute(1, 2), Transaction::chargeback(1, 2)])
+        .unwrap();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&[Transaction::reversal(1, 2)])
+        .expect_err("A charged back transaction cannot be reversed");
+    assert_eq!(errors[0].engine_error(), Some(&EngineError::AccountLocked));
+}
+
+/// Tests `LockedAccountPolicy`: after a chargeback locks the account, the resolve of another
+/// disputed deposit is rejected by default (so that dispute can be pruned) and applied under
+/// `AllowDisputes` (where it cannot be pruned before), which still blocks deposits and a second
+/// chargeback of the charged back transaction, logged with its original type.
+#[test]
+fn unit_test_locked_account_policy_resolve() {
+    let transactions = [
+        Transaction::deposit(1, 1, Decimal::from(10)),
+        Transaction::deposit(1, 2, Decimal::from(5)),
+        Transaction::dispute(1, 1),
+        Transaction::dispute(1, 2),
+        Transaction::chargeback(1, 1),
+    ];
+    let resolve = [Transaction::resolve(1, 2)];
+
+    let engine = Engine::new();
+    engine.process_slice(&transactions).unwrap();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&resolve)
+        .expect_err("By default a locked account rejects resolves");
+    assert_eq!(errors[0].engine_error(), Some(&EngineError::AccountLocked));
+    assert_eq!(engine.accounts.get(&1).unwrap().held, Decimal::from(5));
+    // The dispute left on the locked account can no longer be settled: it can be pruned
+    assert_eq!(engine.prune_transactions(3), Ok(2));
+
+    let config =
+        ConfigFile::from_toml_str("[engine]\nlocked_account_policy = \"allow_disputes\"\n")
+            .unwrap();
+    assert_eq!(
+        config.engine.locked_account_policy,
+        LockedAccountPolicy::AllowDisputes
+    );
+    let engine = Engine::new().with_locked_account_policy(config.engine.locked_account_policy);
+    engine.process_slice(&transactions).unwrap();
+    let logged = engine
+        .transaction_log
+        .get(&TxKey::global(1))
+        .unwrap()
+        .clone();
+    assert_eq!(logged.ty, TransactionType::Deposit);
+    assert_eq!(logged.charged_back, Decimal::from(10));
+    assert_eq!(
+        engine.prune_transactions(3),
+        Err(EngineError::TransactionUnsettled)
+    );
+    engine
+        .process_slice(&resolve)
+        .expect("The resolve is applied on the locked account");
+    let account = engine.accounts.get(&1).unwrap().clone();
+    assert!(account.locked);
+    assert_eq!(account.available, Decimal::from(5));
+    assert_eq!(account.held, Decimal::ZERO);
+    assert_eq!(account.total, Decimal::from(5));
+
+    for (tx, expected) in [
+        (
+            Transaction::deposit(1, 3, Decimal::from(1)),
+            EngineError::AccountLocked,
+        ),
+        (
+            Transaction::chargeback(1, 1),
+            EngineError::AlreadyChargedBack,
+        ),
+        (Transaction::dispute(1, 1), EngineError::AlreadyChargedBack),
+    ] {
+        let TransactionProcessingError::MultipleErrors(errors) = engine
+            .process_slice(&[tx])
+            .expect_err("Expected the transaction to be rejected");
+        assert_eq!(errors[0].engine_error(), Some(&expected));
+    }
+    assert_eq!(*engine.accounts.get(&1).unwrap(), account);
+}
+
+/// Tests the write-ahead log: an engine replaying the log of a deposit, a dispute and a resolve
+/// (rejected transactions are not logged) recovers the account and the transaction log of the
+/// engine that wrote it.
+#[test]
+fn unit_test_wal_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let wal_path = dir.path().join("wal.csv");
+
+    let engine = Engine::new();
+    engine.enable_wal(&wal_path).unwrap();
+    let csv_content = "type,client,tx,amount\n\
+                       deposit,1,1,10.5\n\
+                       deposit,1,2,4\n\
+                       dispute,1,1,\n\
+                       withdrawal,1,3,100\n\
+                       resolve,1,1,\n\
+                       dispute,1,2,\n";
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions(csv_content.as_bytes(), BUFFER_SIZE)
+        .expect_err("The withdrawal exceeds the available funds");
+    assert_eq!(errors.len(), 1);
+
+    let wal = std::fs::read_to_string(&wal_path).unwrap();
+    assert_eq!(
+        wal,
+        "type,client,tx,amount\n\
+         deposit,1,1,10.5\n\
+         deposit,1,2,4\n\
+         dispute,1,1,\n\
+         resolve,1,1,\n\
+         dispute,1,2,\n"
+    );
+
+    let recovered = Engine::new();
+    recovered.recover_from_wal(&wal_path).unwrap();
+    let account = recovered.accounts.get(&1).unwrap().clone();
+    assert_eq!(account, *engine.accounts.get(&1).unwrap());
+    assert_eq!(account.available, Decimal::from_str("10.5").unwrap());
+    assert_eq!(account.held, Decimal::from(4));
+    for tx in [1, 2] {
+        let key = TxKey::global(tx);
+        assert_eq!(
+            *recovered.transaction_log.get(&key).unwrap(),
+            *engine.transaction_log.get(&key).unwrap()
+        );
+    }
+    assert_eq!(recovered.disputed_transactions(), vec![2]);
+}
+
+/// Tests `normalize_amount`: an amount beyond the precision is rounded (up, or down to zero),
+/// an amount within it is returned unchanged and invalid input is reported.
+#[test]
+fn unit_test_normalize_amount() {
+    let half_up = RoundingStrategy::MidpointAwayFromZero;
+    assert_eq!(
+        normalize_amount("1.12345", 4, half_up),
+        Ok(Decimal::from_str("1.1235").unwrap())
+    );
+    let rounded = normalize_amount(" 0.00004 ", 4, half_up).unwrap();
+    assert!(rounded.is_zero());
+    assert_eq!(rounded.scale(), 4);
+    assert_eq!(
+        normalize_amount("2.5", 4, half_up),
+        Ok(Decimal::from_str("2.5").unwrap())
+    );
+    assert_eq!(
+        normalize_amount("1.125", 2, RoundingStrategy::MidpointNearestEven),
+        Ok(Decimal::from_str("1.12").unwrap())
+    );
+    assert_eq!(
+        normalize_amount("  ", 4, half_up),
+        Err(DeserError::EmptyAmount)
+    );
+    assert_eq!(
+        normalize_amount("1.2.3", 4, half_up),
+        Err(DeserError::InvalidAmount("1.2.3".to_string()))
+    );
+}
+
+/// Tests strict mode: a deposit of a registered client (or of a client with a loaded account)
+/// opens or credits its account, a deposit of an unregistered client fails with
+/// `UnknownClient` without creating an account. By default any client can deposit.
+#[test]
+fn unit_test_strict_clients() {
+    let deposits = [
+        Transaction::deposit(1, 1, Decimal::from(10)),
+        Transaction::deposit(2, 2, Decimal::from(5)),
+        Transaction::deposit(3, 3, Decimal::from(7)),
+    ];
+
+    let engine = Engine::new();
+    engine.process_slice(&deposits).unwrap();
+    assert_eq!(engine.accounts.len(), 3);
+
+    let engine = Engine::from_state(
+        vec![(
+            3,
+            Account {
+                available: Decimal::from(1),
+                total: Decimal::from(1),
+                ..Account::default()
+            },
+        )],
+        vec![],
+    )
+    .with_strict_clients(true);
+    engine.register_client(1);
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .process_slice(&deposits)
+        .expect_err("Client 2 is not registered");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].engine_error(), Some(&EngineError::UnknownClient));
+    assert!(errors[0].message().contains("tx: 2"));
+    assert_eq!(engine.accounts.get(&1).unwrap().total, Decimal::from(10));
+    assert_eq!(engine.accounts.get(&3).unwrap().total, Decimal::from(8));
+    assert!(engine.accounts.get(&2).is_none());
+    assert!(engine.transaction_log.get(&TxKey::global(2)).is_none());
+}
+
+/// Tests the empty inputs: a file with only the header is a successful no-op, a zero-byte file
+/// fails with a single "missing header" error.
+#[test]
+fn unit_test_empty_input() {
+    let mut header_only = NamedTempFile::new().unwrap();
+    writeln!(header_only, "type,client,tx,amount").unwrap();
+    let mut engine = Engine::new();
+    engine
+        .read_and_process_transactions_from_csv(header_only.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect("A file with only the header is a no-op");
+    assert_eq!(engine.total_clients(), 0);
+
+    let zero_bytes = NamedTempFile::new().unwrap();
+    let mut engine = Engine::new();
+    let TransactionProcessingError::MultipleErrors(errors) = engine
+        .read_and_process_transactions_from_csv(zero_bytes.path().to_str().unwrap(), BUFFER_SIZE)
+        .expect_err("A zero-byte file has no header");
+    assert_eq!(messages(&errors), vec!["empty input: missing header"]);
+    assert_eq!(engine.total_clients(), 0);
+}